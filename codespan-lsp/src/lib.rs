@@ -3,18 +3,22 @@
 #![forbid(unsafe_code)]
 #![no_std]
 
-#[cfg(test)]
 extern crate alloc;
 
+use alloc::{string::ToString, vec::Vec};
 use core::ops::Range;
 
+use codespan_reporting::diagnostic::{Diagnostic, LabelStyle, Severity};
 use codespan_reporting::files::{Error, Files};
 
 // WARNING: Be extremely careful when adding new imports here, as it could break
 // the compatible version range that we claim in our `Cargo.toml`. This could
 // potentially break down-stream builds on a `cargo update`. This is an
 // absolute no-no, breaking much of what we enjoy about Cargo!
-use lsp_types::{Position as LspPosition, Range as LspRange};
+use lsp_types::{
+    Diagnostic as LspDiagnostic, DiagnosticRelatedInformation, DiagnosticSeverity,
+    Location as LspLocation, NumberOrString, Position as LspPosition, Range as LspRange, Url,
+};
 
 fn location_to_position(
     line_str: &str,
@@ -141,10 +145,75 @@ where
         ..position_to_byte_index(files, file_id, &range.end)?)
 }
 
+fn severity_to_lsp(severity: Severity) -> DiagnosticSeverity {
+    match severity {
+        Severity::Bug | Severity::Error => DiagnosticSeverity::ERROR,
+        Severity::Warning => DiagnosticSeverity::WARNING,
+        Severity::Note => DiagnosticSeverity::INFORMATION,
+        Severity::Help => DiagnosticSeverity::HINT,
+    }
+}
+
+/// Convert a diagnostic into an LSP diagnostic.
+///
+/// The range of the LSP diagnostic is the range of the first primary label,
+/// and the other labels become its related information, which points to the
+/// files using the URLs returned by `file_url`. The notes are appended to the
+/// message, separated by newlines. Positions count UTF-16 code units, as
+/// required by the protocol.
+pub fn diagnostic_to_lsp<'a, F>(
+    files: &'a F,
+    diagnostic: &Diagnostic<F::FileId>,
+    mut file_url: impl FnMut(F::FileId) -> Url,
+) -> Result<LspDiagnostic, Error>
+where
+    F: Files<'a> + ?Sized,
+{
+    let primary_index =
+        (diagnostic.labels.iter()).position(|label| label.style == LabelStyle::Primary);
+    let range = match primary_index {
+        Some(index) => {
+            let label = &diagnostic.labels[index];
+            byte_span_to_range(files, label.file_id, label.range.clone())?
+        }
+        None => LspRange::default(),
+    };
+
+    let related_information = (diagnostic.labels.iter().enumerate())
+        .filter(|(index, _)| Some(*index) != primary_index)
+        .map(|(_, label)| {
+            Ok(DiagnosticRelatedInformation {
+                location: LspLocation {
+                    uri: file_url(label.file_id),
+                    range: byte_span_to_range(files, label.file_id, label.range.clone())?,
+                },
+                message: label.message.clone(),
+            })
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    let mut message = diagnostic.message.clone();
+    for note in &diagnostic.notes {
+        message.push('\n');
+        message.push_str(&note.message);
+    }
+
+    Ok(LspDiagnostic::new(
+        range,
+        Some(severity_to_lsp(diagnostic.severity)),
+        (diagnostic.code.as_ref()).map(|code| NumberOrString::String(code.to_string())),
+        None,
+        message,
+        (!related_information.is_empty()).then_some(related_information),
+        None,
+    ))
+}
+
 #[cfg(test)]
 mod tests {
-    use alloc::string::ToString;
+    use alloc::{string::ToString, vec};
 
+    use codespan_reporting::diagnostic::Label;
     use codespan_reporting::files::{Location, SimpleFiles};
 
     use super::*;
@@ -240,4 +309,85 @@ test
             }
         );
     }
+
+    fn test_url(file_id: usize) -> Url {
+        Url::parse(&alloc::format!("file:///{file_id}.fun")).unwrap()
+    }
+
+    #[test]
+    fn diagnostic_with_astral_plane_characters() {
+        let mut files = SimpleFiles::new();
+        // `𐐀` and `🦀` each take up four bytes and two UTF-16 code units.
+        let file_id = files.add("astral", "let 𐐀 = \"🦀🦀\";\n𐐀 + 1\n");
+
+        let diagnostic = Diagnostic::error()
+            .with_code("E0308")
+            .with_message("mismatched types")
+            .with_labels(vec![
+                Label::primary(file_id, 23..27).with_message("expected `Int`"),
+                Label::secondary(file_id, 11..21).with_message("found `String`"),
+            ])
+            .with_notes(vec!["`+` requires integers".to_string()]);
+
+        let result = diagnostic_to_lsp(&files, &diagnostic, test_url).unwrap();
+
+        assert_eq!(
+            result.range,
+            LspRange {
+                start: LspPosition {
+                    line: 1,
+                    character: 0,
+                },
+                end: LspPosition {
+                    line: 1,
+                    character: 2,
+                },
+            }
+        );
+        assert_eq!(result.severity, Some(DiagnosticSeverity::ERROR));
+        assert_eq!(
+            result.code,
+            Some(NumberOrString::String("E0308".to_string()))
+        );
+        assert_eq!(result.message, "mismatched types\n`+` requires integers");
+        assert_eq!(
+            result.related_information,
+            Some(vec![DiagnosticRelatedInformation {
+                location: LspLocation {
+                    uri: test_url(file_id),
+                    range: LspRange {
+                        start: LspPosition {
+                            line: 0,
+                            character: 9,
+                        },
+                        end: LspPosition {
+                            line: 0,
+                            character: 15,
+                        },
+                    },
+                },
+                message: "found `String`".to_string(),
+            }])
+        );
+    }
+
+    #[test]
+    fn diagnostic_severities() {
+        let files = SimpleFiles::<&str, &str>::new();
+        let severities = [
+            (Diagnostic::bug(), DiagnosticSeverity::ERROR),
+            (Diagnostic::error(), DiagnosticSeverity::ERROR),
+            (Diagnostic::warning(), DiagnosticSeverity::WARNING),
+            (Diagnostic::note(), DiagnosticSeverity::INFORMATION),
+            (Diagnostic::help(), DiagnosticSeverity::HINT),
+        ];
+
+        for (diagnostic, expected) in severities {
+            let result = diagnostic_to_lsp(&files, &diagnostic, test_url).unwrap();
+
+            assert_eq!(result.severity, Some(expected));
+            assert_eq!(result.range, LspRange::default());
+            assert_eq!(result.related_information, None);
+        }
+    }
 }
@@ -7,6 +7,9 @@
 //! cargo run --example peg_calculator
 //! ```
 
+// The actions generated by `peg::parser!` are immediately-invoked closures.
+#![allow(clippy::redundant_closure_call)]
+
 use codespan_reporting::diagnostic::{Diagnostic, Label};
 use codespan_reporting::files::SimpleFile;
 use codespan_reporting::term::termcolor::{ColorChoice, StandardStream};
@@ -0,0 +1,198 @@
+//! [Checkstyle] XML back-end for emitting diagnostics.
+//!
+//! Many CI servers and dashboards aggregate lint results in the XML format of
+//! Checkstyle. The diagnostics are grouped by file, in the order that the files
+//! first appear in, and each diagnostic is emitted as an `error` element:
+//!
+//! - The message becomes the `message` attribute, with the notes appended to
+//!   it, separated by newlines.
+//! - The diagnostic code becomes the `source` attribute.
+//! - The [`Severity`] is mapped to the `severity` attribute, with bugs and
+//!   errors being `error`, warnings being `warning`, and notes and help
+//!   messages being `info`.
+//! - The first primary label becomes the `line` and `column` attributes, and
+//!   decides the file that the diagnostic is listed under. Diagnostics without
+//!   primary labels are skipped, because Checkstyle requires a file.
+//!
+//! [Checkstyle]: https://checkstyle.org/
+//!
+//! ```xml
+//! <?xml version="1.0" encoding="UTF-8"?>
+//! <checkstyle version="4.3">
+//!   <file name="src/main.fun">
+//!     <error line="2" column="9" severity="error" message="unexpected type" source="E0001"/>
+//!   </file>
+//! </checkstyle>
+//! ```
+
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+
+use std::io::{self, Write};
+
+use crate::diagnostic::{Diagnostic, LabelStyle, Severity};
+use crate::files::{Error, Files};
+
+fn severity(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Bug | Severity::Error => "error",
+        Severity::Warning => "warning",
+        Severity::Note | Severity::Help => "info",
+    }
+}
+
+/// Write `text` as the value of an XML attribute, escaping the characters
+/// that are special in attributes, including line breaks, which would
+/// otherwise be normalized to spaces by XML parsers.
+fn write_escaped(writer: &mut dyn Write, text: &str) -> io::Result<()> {
+    let mut start = 0;
+    for (index, ch) in text.char_indices() {
+        let escaped = match ch {
+            '&' => "&amp;",
+            '<' => "&lt;",
+            '>' => "&gt;",
+            '"' => "&quot;",
+            '\'' => "&apos;",
+            '\n' => "&#10;",
+            '\r' => "&#13;",
+            '\t' => "&#9;",
+            _ => continue,
+        };
+        writer.write_all(&text.as_bytes()[start..index])?;
+        writer.write_all(escaped.as_bytes())?;
+        start = index + ch.len_utf8();
+    }
+    writer.write_all(&text.as_bytes()[start..])
+}
+
+struct CheckstyleError<'diagnostic> {
+    line: usize,
+    column: usize,
+    severity: &'static str,
+    message: String,
+    source: Option<&'diagnostic str>,
+}
+
+/// Emit the diagnostics as a Checkstyle XML report.
+///
+/// The return value covers all error cases. These error case can arise if:
+/// * a file was removed from the file database.
+/// * a file was changed so that it is too small to have an index
+/// * IO fails
+pub fn render_checkstyle<'files, F: Files<'files> + ?Sized>(
+    writer: &mut dyn Write,
+    files: &'files F,
+    diagnostics: &[Diagnostic<F::FileId>],
+) -> Result<(), Error> {
+    let mut reported_files = Vec::<(F::FileId, Vec<CheckstyleError<'_>>)>::new();
+    for diagnostic in diagnostics {
+        let label =
+            match (diagnostic.labels.iter()).find(|label| label.style == LabelStyle::Primary) {
+                Some(label) => label,
+                None => continue,
+            };
+
+        let location = files.location(label.file_id, label.range.start)?;
+        let mut message = diagnostic.message.clone();
+        for note in &diagnostic.notes {
+            message.push('\n');
+            message.push_str(&note.message);
+        }
+        let error = CheckstyleError {
+            line: location.line_number,
+            column: location.column_number,
+            severity: severity(diagnostic.severity),
+            message,
+            source: diagnostic.code.as_deref().filter(|code| !code.is_empty()),
+        };
+
+        match (reported_files.iter_mut()).find(|(file_id, _)| *file_id == label.file_id) {
+            Some((_, errors)) => errors.push(error),
+            None => reported_files.push((label.file_id, alloc::vec![error])),
+        }
+    }
+
+    writeln!(writer, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
+    writeln!(writer, "<checkstyle version=\"4.3\">")?;
+    for (file_id, errors) in reported_files {
+        write!(writer, "  <file name=\"")?;
+        write_escaped(writer, &files.name(file_id)?.to_string())?;
+        writeln!(writer, "\">")?;
+        for error in errors {
+            write!(
+                writer,
+                "    <error line=\"{}\" column=\"{}\" severity=\"{}\" message=\"",
+                error.line, error.column, error.severity,
+            )?;
+            write_escaped(writer, &error.message)?;
+            write!(writer, "\"")?;
+            if let Some(source) = error.source {
+                write!(writer, " source=\"")?;
+                write_escaped(writer, source)?;
+                write!(writer, "\"")?;
+            }
+            writeln!(writer, "/>")?;
+        }
+        writeln!(writer, "  </file>")?;
+    }
+    writeln!(writer, "</checkstyle>")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use super::*;
+
+    use crate::diagnostic::Label;
+    use crate::files::SimpleFiles;
+
+    #[test]
+    fn checkstyle_report() {
+        let mut files = SimpleFiles::new();
+        let main = files.add("src/main.fun", "(+ 1\n(+ test \"\"))\n");
+        let lib = files.add("src/<lib>.fun", "let x = 1;\n");
+
+        let diagnostics = [
+            Diagnostic::error()
+                .with_code("E0001")
+                .with_message("unexpected type in `+` application")
+                .with_labels(vec![
+                    Label::secondary(main, 5..7),
+                    Label::primary(main, 13..15).with_message("expected `Int` but found `String`"),
+                ])
+                .with_notes(vec!["expected type `Int`\nfound type `String`".into()]),
+            Diagnostic::warning()
+                .with_message("unused variable \"x\" & friends")
+                .with_labels(vec![Label::primary(lib, 4..5)]),
+            Diagnostic::note()
+                .with_message("`+` is defined here")
+                .with_labels(vec![Label::primary(main, 0..4)]),
+            Diagnostic::help().with_message("consider adding a type annotation"),
+        ];
+
+        let mut buffer = Vec::new();
+        render_checkstyle(&mut buffer, &files, &diagnostics).unwrap();
+
+        assert_eq!(
+            String::from_utf8(buffer).unwrap(),
+            [
+                r#"<?xml version="1.0" encoding="UTF-8"?>"#,
+                r#"<checkstyle version="4.3">"#,
+                r#"  <file name="src/main.fun">"#,
+                r#"    <error line="2" column="9" severity="error" message="unexpected type in `+` application&#10;expected type `Int`&#10;found type `String`" source="E0001"/>"#,
+                r#"    <error line="1" column="1" severity="info" message="`+` is defined here"/>"#,
+                r#"  </file>"#,
+                r#"  <file name="src/&lt;lib&gt;.fun">"#,
+                r#"    <error line="1" column="5" severity="warning" message="unused variable &quot;x&quot; &amp; friends"/>"#,
+                r#"  </file>"#,
+                r#"</checkstyle>"#,
+                r#""#,
+            ]
+            .join("\n"),
+        );
+    }
+}
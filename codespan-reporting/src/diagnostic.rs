@@ -4,8 +4,17 @@ use alloc::{
     string::{String, ToString},
     vec::Vec,
 };
+use core::fmt;
 use core::ops::Range;
 
+use crate::files::{self, Files};
+
+#[cfg(feature = "std")]
+use std::error;
+
+#[cfg(not(feature = "std"))]
+use core::error;
+
 #[cfg(feature = "serialization")]
 use serde::{Deserialize, Serialize};
 
@@ -36,7 +45,7 @@ pub enum Severity {
     Bug,
 }
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd)]
+#[derive(Copy, Clone, Hash, Debug, PartialEq, Eq, PartialOrd, Ord)]
 #[cfg_attr(feature = "serialization", derive(Serialize, Deserialize))]
 pub enum LabelStyle {
     /// Labels that describe the primary cause of a diagnostic.
@@ -58,6 +67,156 @@ pub struct Label<FileId> {
     /// An optional message to provide some additional information for the
     /// underlined code. These should not include line breaks.
     pub message: String,
+    /// The number of lines before the label to include for context.
+    ///
+    /// Overrides [`Config::before_label_lines`] for this label if set.
+    ///
+    /// [`Config::before_label_lines`]: crate::term::Config::before_label_lines
+    #[cfg_attr(feature = "serialization", serde(default))]
+    pub before_context: Option<usize>,
+    /// The number of lines after the label to include for context.
+    ///
+    /// Overrides [`Config::after_label_lines`] for this label if set.
+    ///
+    /// [`Config::after_label_lines`]: crate::term::Config::after_label_lines
+    #[cfg_attr(feature = "serialization", serde(default))]
+    pub after_context: Option<usize>,
+    /// A suggested edit to the file of the label, which fixes the problem it
+    /// describes.
+    #[cfg_attr(feature = "serialization", serde(default))]
+    pub suggestion: Option<Suggestion>,
+    /// The priority of the message of a single-line label, when several
+    /// labels on the same line have messages.
+    ///
+    /// The messages hang underneath the carets, connected to the start of
+    /// their labels. The labels with a higher priority are listed closer to
+    /// the source line, and ties fall back to listing the labels that start
+    /// further right first, and then the labels that end further right. When
+    /// a label is listed closer to the source line than a label that starts
+    /// further right, its message is moved past the connection of that label:
+    ///
+    /// ```text
+    /// 1 │ let total = price * count;
+    ///   │             ^^^^^^^^^^^^^
+    ///   │             │       │
+    ///   │             ╰───────│─ expected `Int`, found `Float`
+    ///   │                     `count` is an `Int`
+    /// ```
+    ///
+    /// Defaults to `0`.
+    #[cfg_attr(feature = "serialization", serde(default))]
+    pub priority: i32,
+}
+
+/// A suggested edit to the source code, replacing a range of bytes with new
+/// text.
+///
+/// ```text
+/// help: suggested fix
+///   ┌─ test:2:9
+///   │
+/// 2 - (+ test "")
+/// 2 + (+ test 0)
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serialization", derive(Serialize, Deserialize))]
+pub struct Suggestion {
+    /// The range in bytes to replace.
+    pub range: Range<usize>,
+    /// The text to insert in place of the range.
+    pub replacement: String,
+}
+
+/// A note that is associated with a diagnostic, optionally prefixed with the
+/// name of a severity.
+///
+/// ```text
+/// = help: try converting the string with `parse`
+/// ```
+///
+/// With the `serialization` feature, notes without a severity are serialized
+/// as plain strings, as notes were before they could have a severity, and
+/// plain strings are deserialized as such notes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serialization", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serialization", serde(from = "NoteRepr", into = "NoteRepr"))]
+pub struct Note {
+    /// The severity of the note. Notes with a severity are rendered with the
+    /// name of the severity as a prefix, such as `= help: ...`, and styled like
+    /// the header of a diagnostic with that severity. Notes without a severity
+    /// are rendered as plain notes.
+    pub severity: Option<Severity>,
+    /// The message of the note. This can include line breaks for improved
+    /// formatting.
+    pub message: String,
+}
+
+impl Note {
+    /// Create a new note without a severity.
+    pub fn new(message: impl ToString) -> Note {
+        Note {
+            severity: None,
+            message: message.to_string(),
+        }
+    }
+
+    /// Set the severity of the note.
+    pub fn with_severity(mut self, severity: Severity) -> Note {
+        self.severity = Some(severity);
+        self
+    }
+}
+
+impl From<String> for Note {
+    fn from(message: String) -> Note {
+        Note {
+            severity: None,
+            message,
+        }
+    }
+}
+
+impl From<&str> for Note {
+    fn from(message: &str) -> Note {
+        Note::new(message)
+    }
+}
+
+/// The serialized form of a [`Note`], which is a plain string for notes
+/// without a severity.
+#[cfg(feature = "serialization")]
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum NoteRepr {
+    Message(String),
+    Note {
+        #[serde(default)]
+        severity: Option<Severity>,
+        message: String,
+    },
+}
+
+#[cfg(feature = "serialization")]
+impl From<NoteRepr> for Note {
+    fn from(repr: NoteRepr) -> Note {
+        match repr {
+            NoteRepr::Message(message) => Note::from(message),
+            NoteRepr::Note { severity, message } => Note { severity, message },
+        }
+    }
+}
+
+#[cfg(feature = "serialization")]
+impl From<Note> for NoteRepr {
+    fn from(note: Note) -> NoteRepr {
+        match note.severity {
+            None => NoteRepr::Message(note.message),
+            Some(severity) => NoteRepr::Note {
+                severity: Some(severity),
+                message: note.message,
+            },
+        }
+    }
 }
 
 impl<FileId> Label<FileId> {
@@ -72,6 +231,10 @@ impl<FileId> Label<FileId> {
             file_id,
             range: range.into(),
             message: String::new(),
+            before_context: None,
+            after_context: None,
+            suggestion: None,
+            priority: 0,
         }
     }
 
@@ -94,6 +257,38 @@ impl<FileId> Label<FileId> {
         self.message = message.to_string();
         self
     }
+
+    /// Set the number of lines of context to show before the label.
+    pub fn with_before_context(mut self, lines: usize) -> Label<FileId> {
+        self.before_context = Some(lines);
+        self
+    }
+
+    /// Set the number of lines of context to show after the label.
+    pub fn with_after_context(mut self, lines: usize) -> Label<FileId> {
+        self.after_context = Some(lines);
+        self
+    }
+
+    /// Suggest replacing the given range of the labeled file with
+    /// `replacement`.
+    pub fn with_suggestion(
+        mut self,
+        range: impl Into<Range<usize>>,
+        replacement: impl ToString,
+    ) -> Label<FileId> {
+        self.suggestion = Some(Suggestion {
+            range: range.into(),
+            replacement: replacement.to_string(),
+        });
+        self
+    }
+
+    /// Set the priority of the message of the label.
+    pub fn with_priority(mut self, priority: i32) -> Label<FileId> {
+        self.priority = priority;
+        self
+    }
 }
 
 /// Represents a diagnostic message that can provide information like errors and
@@ -113,13 +308,25 @@ pub struct Diagnostic<FileId> {
     /// diagnostic display mod, the message should be specific enough to make
     /// sense on its own, without additional context provided by labels and notes.
     pub message: String,
+    /// Source spans that are referenced in the message by their index, as
+    /// `{0}`, `{1}`, and so on.
+    ///
+    /// The terminal back-end replaces these references with the source text
+    /// of the spans, styled like primary labels, and `{{` and `}}` with literal
+    /// braces. References to spans that do not exist are left as they are.
+    /// Messages without any spans are displayed as they are, so they do not
+    /// need to escape their braces.
+    #[cfg_attr(feature = "serialization", serde(default))]
+    pub message_args: Vec<(FileId, Range<usize>)>,
     /// Source labels that describe the cause of the diagnostic.
-    /// The order of the labels inside the vector does not have any meaning.
-    /// The labels are always arranged in the order they appear in the source code.
+    /// The labels of each file are always arranged in the order they appear
+    /// in the source code. Labels in different files are rendered as separate
+    /// snippets, which are ordered by the first label that refers to each file.
+    /// Diagnostics without labels are rendered as just the header and the
+    /// notes, without any source snippet.
     pub labels: Vec<Label<FileId>>,
     /// Notes that are associated with the primary cause of the diagnostic.
-    /// These can include line breaks for improved formatting.
-    pub notes: Vec<String>,
+    pub notes: Vec<Note>,
 }
 
 impl<FileId> Diagnostic<FileId> {
@@ -129,6 +336,7 @@ impl<FileId> Diagnostic<FileId> {
             severity,
             code: None,
             message: String::new(),
+            message_args: Vec::new(),
             labels: Vec::new(),
             notes: Vec::new(),
         }
@@ -181,12 +389,84 @@ impl<FileId> Diagnostic<FileId> {
         self
     }
 
+    /// Add a source span that is referenced in the message by its index.
+    ///
+    /// ```rust
+    /// use codespan_reporting::diagnostic::Diagnostic;
+    /// use codespan_reporting::files::SimpleFile;
+    /// use codespan_reporting::term::{self, Config, DisplayStyle, PlainWriter};
+    ///
+    /// let file = SimpleFile::new("main.fun", "1 + \"2\"");
+    /// let diagnostic = Diagnostic::error()
+    ///     .with_message("cannot add `{1}` to `{0}`")
+    ///     .with_message_arg((), 0..1)
+    ///     .with_message_arg((), 4..7);
+    ///
+    /// let config = Config {
+    ///     display_style: DisplayStyle::Short,
+    ///     ..Config::default()
+    /// };
+    /// let mut writer = PlainWriter::new(Vec::new());
+    /// term::emit(&mut writer, &config, &file, &diagnostic).unwrap();
+    ///
+    /// assert_eq!(writer.into_inner(), b"error: cannot add `\"2\"` to `1`\n");
+    /// ```
+    ///
+    /// See [`Diagnostic::message_args`] for how they are displayed.
+    pub fn with_message_arg(
+        mut self,
+        file_id: FileId,
+        range: impl Into<Range<usize>>,
+    ) -> Diagnostic<FileId> {
+        self.message_args.push((file_id, range.into()));
+        self
+    }
+
     /// Add a label to the diagnostic.
     pub fn with_label(mut self, label: Label<FileId>) -> Diagnostic<FileId> {
         self.labels.push(label);
         self
     }
 
+    /// Add a primary label with the given message to the diagnostic.
+    ///
+    /// ```rust
+    /// use codespan_reporting::diagnostic::{Diagnostic, Label};
+    ///
+    /// let diagnostic = Diagnostic::error()
+    ///     .with_code("E0308")
+    ///     .with_message("mismatched types")
+    ///     .with_primary(0, 13..15, "expected `Int`, found `String`")
+    ///     .with_secondary(0, 5..7, "expected due to this")
+    ///     .with_note("expected type `Int`");
+    ///
+    /// assert_eq!(
+    ///     diagnostic.labels,
+    ///     [
+    ///         Label::primary(0, 13..15).with_message("expected `Int`, found `String`"),
+    ///         Label::secondary(0, 5..7).with_message("expected due to this"),
+    ///     ],
+    /// );
+    /// ```
+    pub fn with_primary(
+        self,
+        file_id: FileId,
+        range: impl Into<Range<usize>>,
+        message: impl ToString,
+    ) -> Diagnostic<FileId> {
+        self.with_label(Label::primary(file_id, range).with_message(message))
+    }
+
+    /// Add a secondary label with the given message to the diagnostic.
+    pub fn with_secondary(
+        self,
+        file_id: FileId,
+        range: impl Into<Range<usize>>,
+        message: impl ToString,
+    ) -> Diagnostic<FileId> {
+        self.with_label(Label::secondary(file_id, range).with_message(message))
+    }
+
     /// Add some labels to the diagnostic.
     pub fn with_labels(mut self, mut labels: Vec<Label<FileId>>) -> Diagnostic<FileId> {
         self.labels.append(&mut labels);
@@ -204,22 +484,317 @@ impl<FileId> Diagnostic<FileId> {
 
     /// Add a note to the diagnostic.
     pub fn with_note(mut self, note: impl ToString) -> Diagnostic<FileId> {
-        self.notes.push(note.to_string());
+        self.notes.push(Note::new(note));
         self
     }
 
-    /// Add some notes to the diagnostic.
-    pub fn with_notes(mut self, mut notes: Vec<String>) -> Diagnostic<FileId> {
-        self.notes.append(&mut notes);
+    /// Add a note to the diagnostic, prefixed with the name of the given
+    /// severity.
+    ///
+    /// ```rust
+    /// use codespan_reporting::diagnostic::{Diagnostic, Severity};
+    ///
+    /// let diagnostic = Diagnostic::<()>::error()
+    ///     .with_message("mismatched types")
+    ///     .with_note("expected `Int`, found `String`")
+    ///     .with_severity_note(Severity::Help, "try converting the string with `parse`");
+    ///
+    /// assert_eq!(diagnostic.notes[0].severity, None);
+    /// assert_eq!(diagnostic.notes[1].severity, Some(Severity::Help));
+    /// ```
+    pub fn with_severity_note(
+        mut self,
+        severity: Severity,
+        note: impl ToString,
+    ) -> Diagnostic<FileId> {
+        self.notes.push(Note::new(note).with_severity(severity));
         self
     }
 
+    /// Add some notes to the diagnostic.
+    pub fn with_notes(self, notes: Vec<String>) -> Diagnostic<FileId> {
+        self.with_notes_iter(notes)
+    }
+
     /// Add some notes to the diagnostic.
     pub fn with_notes_iter(
         mut self,
-        notes: impl IntoIterator<Item = String>,
+        notes: impl IntoIterator<Item = impl Into<Note>>,
     ) -> Diagnostic<FileId> {
-        self.notes.extend(notes);
+        self.notes.extend(notes.into_iter().map(Into::into));
         self
     }
 }
+
+impl<FileId: Copy> Diagnostic<FileId> {
+    /// Check that the byte ranges of the labels are valid in their files,
+    /// returning an error for the first label that is not.
+    ///
+    /// A range is valid if it does not start after it ends, does not extend
+    /// past the end of the file, and starts and ends on the boundaries of
+    /// UTF-8 code points. This reads the files with [`Files::source`].
+    ///
+    /// Labels with invalid ranges are clamped to the file by the terminal
+    /// renderer, with a warning note, so checking them up front allows them
+    /// to be reported as the bugs they are instead.
+    ///
+    /// ```rust
+    /// use codespan_reporting::diagnostic::{Diagnostic, Label};
+    /// use codespan_reporting::files::{Error, SimpleFile};
+    ///
+    /// let file = SimpleFile::new("main.fun", "let é = 1;\n");
+    /// let diagnostic = Diagnostic::error().with_labels(vec![
+    ///     Label::primary((), 4..6),
+    ///     Label::secondary((), 5..8),
+    /// ]);
+    ///
+    /// let error = diagnostic.validate(&file).unwrap_err();
+    /// assert_eq!(error.label_index, 1);
+    /// assert!(matches!(error.error, Error::InvalidCharBoundary { given: 5 }));
+    /// ```
+    pub fn validate<'files, F>(&self, files: &'files F) -> Result<(), LabelError>
+    where
+        F: Files<'files, FileId = FileId> + ?Sized,
+    {
+        for (label_index, label) in self.labels.iter().enumerate() {
+            let result = (files.source(label.file_id))
+                .and_then(|source| check_range(source.as_ref(), &label.range));
+            result.map_err(|error| LabelError { label_index, error })?;
+        }
+        Ok(())
+    }
+
+    /// A copy of the diagnostic with the invalid ranges of its labels, as
+    /// found by [`Diagnostic::validate`], clamped to their files, and a
+    /// warning note for each of them. Returns `None` if all of the ranges are
+    /// valid, or if the files cannot be read.
+    pub(crate) fn with_clamped_labels<'files, F>(&self, files: &'files F) -> Option<Self>
+    where
+        F: Files<'files, FileId = FileId> + ?Sized,
+    {
+        let mut clamped: Option<Self> = None;
+        for (label_index, label) in self.labels.iter().enumerate() {
+            let source = match files.source(label.file_id) {
+                Ok(source) => source,
+                Err(_) => continue,
+            };
+            let source = source.as_ref();
+            let error = match check_range(source, &label.range) {
+                Ok(()) => continue,
+                Err(error) => error,
+            };
+
+            let range = clamp_range(source, label.range.clone());
+            let note = alloc::format!(
+                "label {label_index} was clamped from {:?} to {range:?}: {error}",
+                label.range,
+            );
+            let diagnostic = clamped.get_or_insert_with(|| self.clone());
+            diagnostic.labels[label_index].range = range;
+            diagnostic
+                .notes
+                .push(Note::new(note).with_severity(Severity::Warning));
+        }
+        clamped
+    }
+}
+
+/// Check that a byte range is valid in the given source.
+fn check_range(source: &str, range: &Range<usize>) -> Result<(), files::Error> {
+    if range.start > range.end {
+        return Err(files::Error::ReversedRange {
+            start: range.start,
+            end: range.end,
+        });
+    }
+    for given in [range.start, range.end] {
+        if given > source.len() {
+            let max = source.len();
+            return Err(files::Error::IndexTooLarge { given, max });
+        }
+        if !source.is_char_boundary(given) {
+            return Err(files::Error::InvalidCharBoundary { given });
+        }
+    }
+    Ok(())
+}
+
+/// Clamp a byte range to the given source, widening it to the boundaries of
+/// the UTF-8 code points that it starts and ends in. Ranges that end past the
+/// source end with the line they start on, without its line terminator, so
+/// that they do not become multi-line labels.
+fn clamp_range(source: &str, range: Range<usize>) -> Range<usize> {
+    let mut start = usize::min(range.start, source.len());
+    while !source.is_char_boundary(start) {
+        start -= 1;
+    }
+    let mut end = if range.end > source.len() {
+        let line = &source[start..];
+        let line = line.find('\n').map_or(line, |end| &line[..end]);
+        start + line.strip_suffix('\r').unwrap_or(line).len()
+    } else {
+        usize::max(start, range.end)
+    };
+    while !source.is_char_boundary(end) {
+        end += 1;
+    }
+    start..end
+}
+
+/// A label with an invalid byte range, found by [`Diagnostic::validate`].
+#[derive(Debug)]
+pub struct LabelError {
+    /// The index of the label in [`Diagnostic::labels`].
+    pub label_index: usize,
+    /// What is wrong with the range of the label, or the error from reading
+    /// its file.
+    pub error: files::Error,
+}
+
+impl fmt::Display for LabelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid range of label {}: {}",
+            self.label_index, self.error
+        )
+    }
+}
+
+impl error::Error for LabelError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+/// Build a note out of lines of label and value pairs, with the labels
+/// aligned to the right and the values aligned to the left, such as the
+/// types in a type mismatch:
+///
+/// ```rust
+/// use codespan_reporting::diagnostic::aligned_note;
+///
+/// let note = aligned_note(&[("expected type", "`Int`"), ("found type", "`String`")]);
+///
+/// assert_eq!(note, "expected type `Int`\n   found type `String`");
+/// ```
+///
+/// The renderer indents the lines after the first one of a note past the note
+/// bullet, so the lines stay aligned when the note is rendered:
+///
+/// ```text
+/// = expected type `Int`
+///      found type `String`
+/// ```
+///
+/// The labels are aligned by their width in columns, which accounts for wide
+/// Unicode characters.
+pub fn aligned_note(pairs: &[(&str, &str)]) -> String {
+    use unicode_width::UnicodeWidthStr;
+
+    let label_width = pairs.iter().map(|(label, _)| label.width()).max();
+    let label_width = label_width.unwrap_or(0);
+
+    let mut note = String::new();
+    for (index, (label, value)) in pairs.iter().enumerate() {
+        if index > 0 {
+            note.push('\n');
+        }
+        (label.width()..label_width).for_each(|_| note.push(' '));
+        note.push_str(label);
+        note.push(' ');
+        note.push_str(value);
+    }
+    note
+}
+
+/// The exit codes to use for each severity, for command line tools that exit
+/// with a non-zero code when they report errors.
+///
+/// By default, bugs and errors exit with `1`, and every other severity with
+/// `0`:
+///
+/// ```rust
+/// use codespan_reporting::diagnostic::{Diagnostic, ExitCodes};
+///
+/// let diagnostics = [Diagnostic::<()>::warning(), Diagnostic::error()];
+/// assert_eq!(ExitCodes::default().exit_code_for(&diagnostics), 1);
+///
+/// let strict = ExitCodes {
+///     warning: 2,
+///     ..ExitCodes::default()
+/// };
+/// assert_eq!(strict.exit_code_for(&diagnostics[..1]), 2);
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serialization", derive(Serialize, Deserialize))]
+pub struct ExitCodes {
+    /// The exit code to use for bugs.
+    pub bug: i32,
+    /// The exit code to use for errors.
+    pub error: i32,
+    /// The exit code to use for warnings.
+    pub warning: i32,
+    /// The exit code to use for notes.
+    pub note: i32,
+    /// The exit code to use for help messages.
+    pub help: i32,
+}
+
+impl Default for ExitCodes {
+    fn default() -> ExitCodes {
+        ExitCodes {
+            bug: 1,
+            error: 1,
+            warning: 0,
+            note: 0,
+            help: 0,
+        }
+    }
+}
+
+impl ExitCodes {
+    /// The exit code to use for the given severity.
+    pub fn get(&self, severity: Severity) -> i32 {
+        match severity {
+            Severity::Bug => self.bug,
+            Severity::Error => self.error,
+            Severity::Warning => self.warning,
+            Severity::Note => self.note,
+            Severity::Help => self.help,
+        }
+    }
+
+    /// The exit code for the most severe of the diagnostics, or `0` if there
+    /// are no diagnostics.
+    pub fn exit_code_for<FileId>(&self, diagnostics: &[Diagnostic<FileId>]) -> i32 {
+        let severity = diagnostics.iter().map(|diagnostic| diagnostic.severity);
+        severity.max().map_or(0, |severity| self.get(severity))
+    }
+}
+
+/// The exit code for the most severe of the diagnostics, using the
+/// [default exit codes](ExitCodes::default): `1` if there are any bugs or
+/// errors, and `0` otherwise.
+pub fn exit_code_for<FileId>(diagnostics: &[Diagnostic<FileId>]) -> i32 {
+    ExitCodes::default().exit_code_for(diagnostics)
+}
+
+#[cfg(all(test, feature = "serialization"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn notes_serialized_as_plain_strings() {
+        let json = r#"["a plain note",{"severity":"Help","message":"a help note"}]"#;
+        let notes = serde_json::from_str::<Vec<Note>>(json).unwrap();
+        assert_eq!(
+            notes,
+            [
+                Note::new("a plain note"),
+                Note::new("a help note").with_severity(Severity::Help),
+            ],
+        );
+        assert_eq!(serde_json::to_string(&notes).unwrap(), json);
+    }
+}
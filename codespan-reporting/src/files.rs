@@ -9,6 +9,10 @@
 //! - [`SimpleFile`]: For single-file use-cases
 //! - [`SimpleFiles`]: For multi-file use-cases
 //!
+//! Other implementations can be wrapped in a [`CachedFiles`] to cache the line
+//! start locations of their files, or in a [`HyperlinkedFiles`] to link the
+//! locations in their files to URLs of their own.
+//!
 //! These data structures provide a pretty minimal API, however,
 //! so end-users are encouraged to create their own implementations for their
 //! own specific use-cases, such as an implementation that accesses the file
@@ -23,7 +27,10 @@
 //!
 //! [`salsa`]: https://crates.io/crates/salsa
 
-use alloc::vec::Vec;
+use alloc::{borrow::Cow, string::String, vec::Vec};
+#[cfg(feature = "std")]
+use alloc::{format, string::ToString};
+use core::cell::RefCell;
 use core::ops::Range;
 
 #[cfg(feature = "std")]
@@ -46,6 +53,10 @@ pub enum Error {
     ColumnTooLarge { given: usize, max: usize },
     /// The given index is contained in the file, but is not a boundary of a UTF-8 code point.
     InvalidCharBoundary { given: usize },
+    /// The diagnostic does not have a label with the specified index.
+    LabelMissing { given: usize, len: usize },
+    /// The given range starts after it ends.
+    ReversedRange { start: usize, end: usize },
     /// There was a error while doing IO.
     #[cfg(feature = "std")]
     Io(std::io::Error),
@@ -80,6 +91,16 @@ impl core::fmt::Display for Error {
                 write!(f, "invalid column {}, maximum column {}", given, max)
             }
             Error::InvalidCharBoundary { .. } => write!(f, "index is not a code point boundary"),
+            Error::LabelMissing { given, len } => {
+                write!(f, "invalid label {}, number of labels is {}", given, len)
+            }
+            Error::ReversedRange { start, end } => {
+                write!(
+                    f,
+                    "invalid range {}..{}, the start is after the end",
+                    start, end
+                )
+            }
             #[cfg(feature = "std")]
             Error::Io(err) => write!(f, "{}", err),
             Error::FormatError => write!(f, "formatting error"),
@@ -102,6 +123,24 @@ impl error::Error for Error {
 /// A lifetime parameter `'a` is provided to allow any of the returned values to returned by reference.
 /// This is to workaround the lack of higher kinded lifetime parameters.
 /// This can be ignored if this is not needed, however.
+///
+/// # Large files
+///
+/// The renderers only ever access the source of a file through
+/// [`Files::line_source`], one line at a time, and only for the lines that
+/// are displayed: the lines containing labels and the context lines around
+/// them. Implementations for very large files can override
+/// [`Files::line_source`] to read just the requested line, for example by
+/// seeking to the start of the [`Files::line_range`], so that the whole file
+/// never needs to be held in memory. Such an implementation may return an
+/// error from [`Files::source`].
+///
+/// # Trait objects
+///
+/// The trait is object safe, and all of the rendering functions accept
+/// unsized file providers, so diagnostics can be rendered with a trait object
+/// instead of a generic type, such as a file provider that is loaded
+/// dynamically. See [`DynFiles`] for the trait object type to use.
 pub trait Files<'a> {
     /// A unique identifier for files in the file provider. This will be used
     /// for rendering `diagnostic::Label`s in the corresponding source files.
@@ -164,9 +203,10 @@ pub trait Files<'a> {
         line_index: usize,
         byte_index: usize,
     ) -> Result<usize, Error> {
-        let source = self.source(id)?;
-        let line_range = self.line_range(id, line_index)?;
-        let column_index = column_index(source.as_ref(), line_range, byte_index);
+        let line_source = self.line_source(id, line_index)?;
+        let line_start = self.line_range(id, line_index)?.start;
+        let byte_index = byte_index.saturating_sub(line_start);
+        let column_index = column_index(&line_source, 0..line_source.len(), byte_index);
 
         Ok(column_index + 1)
     }
@@ -184,8 +224,129 @@ pub trait Files<'a> {
 
     /// The byte range of line in the source of the file.
     fn line_range(&'a self, id: Self::FileId, line_index: usize) -> Result<Range<usize>, Error>;
+
+    /// The source code of the line at the given line index, covering the byte
+    /// range returned by [`Files::line_range`].
+    ///
+    /// # Note for trait implementors
+    ///
+    /// A default implementation is provided, which copies the line out of
+    /// [`Files::source`], as the source may not be borrowed from the file
+    /// provider. Implementations that store their sources, like
+    /// [`SimpleFiles`], return a slice of the source instead. See
+    /// [the trait documentation](Files#large-files) for when else it is useful
+    /// to override it.
+    fn line_source(&'a self, id: Self::FileId, line_index: usize) -> Result<Cow<'a, str>, Error> {
+        let source = self.source(id)?;
+        let line_range = self.line_range(id, line_index)?;
+
+        Ok(Cow::Owned(slice_line(source.as_ref(), line_range)?.into()))
+    }
+
+    /// The URL that the hyperlink of a location in a file points to, when
+    /// [`Config::render_file_hyperlinks`] is enabled. Returning `None` renders
+    /// the location without a hyperlink.
+    ///
+    /// # Note for trait implementors
+    ///
+    /// A default implementation is provided, which returns `None`, so that
+    /// rendering does not touch the file system. A [`HyperlinkedFiles`] builds
+    /// the URLs with a closure, or links to the files that their names are
+    /// the paths of.
+    ///
+    /// [`Config::render_file_hyperlinks`]: crate::term::Config::render_file_hyperlinks
+    #[allow(unused_variables)]
+    fn location_url(
+        &'a self,
+        id: Self::FileId,
+        line_number: usize,
+        column_number: usize,
+    ) -> Result<Option<String>, Error> {
+        Ok(None)
+    }
+}
+
+/// The line in `source` at `line_range`, for [`Files::line_source`].
+fn slice_line(source: &str, line_range: Range<usize>) -> Result<&str, Error> {
+    match source.get(line_range.clone()) {
+        Some(line) => Ok(line),
+        None if line_range.end > source.len() => Err(Error::IndexTooLarge {
+            given: line_range.end,
+            max: source.len(),
+        }),
+        None => Err(Error::InvalidCharBoundary {
+            given: line_range.start,
+        }),
+    }
+}
+
+/// The `file://` URL of an absolute path, with the characters that are not
+/// allowed in the path of a URL percent-encoded. Returns `None` if the path is
+/// not valid UTF-8. This is the URL that [`HyperlinkedFiles::file_paths`]
+/// links to.
+#[cfg(feature = "std")]
+fn path_url(path: &std::path::Path) -> Option<String> {
+    let path = path.to_str()?;
+    // Paths on Windows use backslashes, and `canonicalize` returns them with
+    // a verbatim prefix, which has no place in a URL.
+    #[cfg(windows)]
+    let path = path
+        .strip_prefix(r"\\?\")
+        .unwrap_or(path)
+        .replace('\\', "/");
+
+    let mut url = String::from("file://");
+    if !path.starts_with('/') {
+        url.push('/');
+    }
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' | b':' => {
+                url.push(char::from(byte))
+            }
+            _ => url.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    Some(url)
 }
 
+/// A file provider as a trait object, for rendering diagnostics without
+/// being generic over the type of the file provider.
+///
+/// The trait object implements [`Files`] for every lifetime, as the methods
+/// borrow the file provider for the lifetime of the trait, which would
+/// otherwise have to match the lifetime of the reference to the trait object.
+/// This means that the implementation must implement [`Files`] for every
+/// lifetime too, so its file ids and names must not borrow data from outside of
+/// it, and neither may the sources that it stores. The `'files` lifetime is
+/// the lifetime of the file provider itself, which is `'static` for a boxed
+/// provider that owns its files.
+///
+/// ```rust
+/// use codespan_reporting::diagnostic::{Diagnostic, Label};
+/// use codespan_reporting::files::{DynFiles, SimpleFiles};
+/// use codespan_reporting::term::{self, Config, DisplayStyle};
+///
+/// let mut files = SimpleFiles::<String, String>::new();
+/// let id = files.add("main.fun".into(), "let x = 1;\n".into());
+/// let files: Box<DynFiles<'static, usize>> = Box::new(files);
+///
+/// let diagnostic = Diagnostic::warning()
+///     .with_message("unused variable")
+///     .with_labels(vec![Label::primary(id, 4..5)]);
+/// let config = Config {
+///     display_style: DisplayStyle::Short,
+///     ..Config::default()
+/// };
+///
+/// assert_eq!(
+///     term::to_string(&config, &*files, &diagnostic).unwrap(),
+///     "main.fun:1:5: warning: unused variable\n",
+/// );
+/// ```
+pub type DynFiles<'files, FileId> =
+    dyn for<'a> Files<'a, FileId = FileId, Name = String, Source = &'a str> + 'files;
+
 /// A user-facing location in a source file.
 ///
 /// Returned by [`Files::location`].
@@ -364,6 +525,11 @@ where
 
         Ok(line_start..next_line_start)
     }
+
+    fn line_source(&self, (): (), line_index: usize) -> Result<Cow<'_, str>, Error> {
+        let line_range = self.line_range((), line_index)?;
+        slice_line(self.source.as_ref(), line_range).map(Cow::Borrowed)
+    }
 }
 
 /// A file database that can store multiple source files.
@@ -424,6 +590,347 @@ where
     fn line_range(&self, file_id: usize, line_index: usize) -> Result<Range<usize>, Error> {
         self.get(file_id)?.line_range((), line_index)
     }
+
+    fn line_source(&self, file_id: usize, line_index: usize) -> Result<Cow<'_, str>, Error> {
+        self.get(file_id)?.line_source((), line_index)
+    }
+}
+
+/// A file database that wraps another one, and caches the line starts of its
+/// files.
+///
+/// The line starts of a file are computed from its [`Files::source`] the first
+/// time that a line of the file is looked up, after which [`Files::line_index`]
+/// and [`Files::line_range`] are a binary search over them. This speeds up
+/// emitting many diagnostics for file databases that find lines by scanning
+/// the source, at the cost of keeping one `usize` for every line of every
+/// file that was looked up, until the cache is cleared with
+/// [`CachedFiles::clear`]. [`SimpleFile`] and [`SimpleFiles`] already compute
+/// the line starts when a file is added, so they do not need to be wrapped.
+///
+/// The other methods are forwarded to the wrapped file database, so that its
+/// line and column numbers are kept. Their default implementations look up
+/// lines through the wrapped file database, not through the cache.
+#[derive(Debug)]
+pub struct CachedFiles<F, FileId> {
+    files: F,
+    /// The line starts of each file, followed by the length of its source.
+    line_starts: RefCell<Vec<(FileId, Vec<usize>)>>,
+}
+
+impl<F, FileId> CachedFiles<F, FileId> {
+    /// Wrap a file database, with an empty cache.
+    pub fn new(files: F) -> CachedFiles<F, FileId> {
+        CachedFiles {
+            files,
+            line_starts: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Get a reference to the wrapped file database.
+    pub fn get_ref(&self) -> &F {
+        &self.files
+    }
+
+    /// Clear the cache, for example after the source of a file has changed.
+    pub fn clear(&mut self) {
+        self.line_starts.get_mut().clear();
+    }
+
+    /// Consume the cache, returning the wrapped file database.
+    pub fn into_inner(self) -> F {
+        self.files
+    }
+}
+
+impl<'a, F> CachedFiles<F, F::FileId>
+where
+    F: Files<'a>,
+{
+    /// Call `f` with the cached line starts of the file, computing them first
+    /// if they are not cached yet.
+    fn with_line_starts<T>(
+        &'a self,
+        id: F::FileId,
+        f: impl FnOnce(&[usize]) -> T,
+    ) -> Result<T, Error> {
+        let cache = self.line_starts.borrow();
+        if let Some((_, line_starts)) = cache.iter().find(|(file_id, _)| *file_id == id) {
+            return Ok(f(line_starts));
+        }
+        drop(cache);
+
+        let source = self.files.source(id)?;
+        let source = source.as_ref();
+        let mut line_starts = line_starts(source).collect::<Vec<_>>();
+        line_starts.push(source.len());
+        let result = f(&line_starts);
+        self.line_starts.borrow_mut().push((id, line_starts));
+        Ok(result)
+    }
+}
+
+impl<'a, F> Files<'a> for CachedFiles<F, F::FileId>
+where
+    F: Files<'a>,
+{
+    type FileId = F::FileId;
+    type Name = F::Name;
+    type Source = F::Source;
+
+    fn name(&'a self, id: Self::FileId) -> Result<Self::Name, Error> {
+        self.files.name(id)
+    }
+
+    fn source(&'a self, id: Self::FileId) -> Result<Self::Source, Error> {
+        self.files.source(id)
+    }
+
+    fn line_index(&'a self, id: Self::FileId, byte_index: usize) -> Result<usize, Error> {
+        self.with_line_starts(id, |line_starts| {
+            let line_starts = &line_starts[..line_starts.len() - 1];
+            (line_starts.binary_search(&byte_index)).unwrap_or_else(|next_line| next_line - 1)
+        })
+    }
+
+    fn line_range(&'a self, id: Self::FileId, line_index: usize) -> Result<Range<usize>, Error> {
+        self.with_line_starts(id, |line_starts| {
+            match (line_starts.get(line_index), line_starts.get(line_index + 1)) {
+                (Some(start), Some(end)) => Ok(*start..*end),
+                _ => Err(Error::LineTooLarge {
+                    given: line_index,
+                    max: line_starts.len() - 2,
+                }),
+            }
+        })?
+    }
+
+    fn line_number(&'a self, id: Self::FileId, line_index: usize) -> Result<usize, Error> {
+        self.files.line_number(id, line_index)
+    }
+
+    fn column_number(
+        &'a self,
+        id: Self::FileId,
+        line_index: usize,
+        byte_index: usize,
+    ) -> Result<usize, Error> {
+        self.files.column_number(id, line_index, byte_index)
+    }
+
+    fn location(&'a self, id: Self::FileId, byte_index: usize) -> Result<Location, Error> {
+        self.files.location(id, byte_index)
+    }
+
+    fn line_source(&'a self, id: Self::FileId, line_index: usize) -> Result<Cow<'a, str>, Error> {
+        self.files.line_source(id, line_index)
+    }
+
+    fn location_url(
+        &'a self,
+        id: Self::FileId,
+        line_number: usize,
+        column_number: usize,
+    ) -> Result<Option<String>, Error> {
+        self.files.location_url(id, line_number, column_number)
+    }
+}
+
+/// A file database that wraps another one, and builds the URLs of
+/// [`Files::location_url`] with a closure, from the id of the file and the line
+/// and column number of the location.
+///
+/// ```rust
+/// use codespan_reporting::files::{Files, HyperlinkedFiles, SimpleFiles};
+///
+/// let mut files = SimpleFiles::new();
+/// let id = files.add("src/main.rs", "fn main() {}\n");
+/// let files = HyperlinkedFiles::new(files, |id: &usize, line, column| {
+///     Some(format!("https://example.com/files/{id}#L{line}C{column}"))
+/// });
+///
+/// assert_eq!(
+///     files.location_url(id, 1, 4).unwrap().as_deref(),
+///     Some("https://example.com/files/0#L1C4"),
+/// );
+/// ```
+///
+/// With the `std` feature, [`HyperlinkedFiles::file_paths`] links the
+/// locations to the files on disk instead.
+pub struct HyperlinkedFiles<F, U> {
+    files: F,
+    url: U,
+}
+
+impl<F, U> HyperlinkedFiles<F, U> {
+    /// Wrap a file database, building the URLs of its locations with `url`.
+    pub fn new(files: F, url: U) -> HyperlinkedFiles<F, U> {
+        HyperlinkedFiles { files, url }
+    }
+
+    /// Get a reference to the wrapped file database.
+    pub fn get_ref(&self) -> &F {
+        &self.files
+    }
+
+    /// Consume the wrapper, returning the wrapped file database.
+    pub fn into_inner(self) -> F {
+        self.files
+    }
+}
+
+impl<F: core::fmt::Debug, U> core::fmt::Debug for HyperlinkedFiles<F, U> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("HyperlinkedFiles")
+            .field("files", &self.files)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<'a, F, U> Files<'a> for HyperlinkedFiles<F, U>
+where
+    F: Files<'a>,
+    U: Fn(&F::FileId, usize, usize) -> Option<String>,
+{
+    type FileId = F::FileId;
+    type Name = F::Name;
+    type Source = F::Source;
+
+    fn name(&'a self, id: Self::FileId) -> Result<Self::Name, Error> {
+        self.files.name(id)
+    }
+
+    fn source(&'a self, id: Self::FileId) -> Result<Self::Source, Error> {
+        self.files.source(id)
+    }
+
+    fn line_index(&'a self, id: Self::FileId, byte_index: usize) -> Result<usize, Error> {
+        self.files.line_index(id, byte_index)
+    }
+
+    fn line_number(&'a self, id: Self::FileId, line_index: usize) -> Result<usize, Error> {
+        self.files.line_number(id, line_index)
+    }
+
+    fn column_number(
+        &'a self,
+        id: Self::FileId,
+        line_index: usize,
+        byte_index: usize,
+    ) -> Result<usize, Error> {
+        self.files.column_number(id, line_index, byte_index)
+    }
+
+    fn location(&'a self, id: Self::FileId, byte_index: usize) -> Result<Location, Error> {
+        self.files.location(id, byte_index)
+    }
+
+    fn line_range(&'a self, id: Self::FileId, line_index: usize) -> Result<Range<usize>, Error> {
+        self.files.line_range(id, line_index)
+    }
+
+    fn line_source(&'a self, id: Self::FileId, line_index: usize) -> Result<Cow<'a, str>, Error> {
+        self.files.line_source(id, line_index)
+    }
+
+    fn location_url(
+        &'a self,
+        id: Self::FileId,
+        line_number: usize,
+        column_number: usize,
+    ) -> Result<Option<String>, Error> {
+        Ok((self.url)(&id, line_number, column_number))
+    }
+}
+
+/// The URLs of [`HyperlinkedFiles::file_paths`], which link to the files that
+/// the names of the files are the paths of.
+#[cfg(feature = "std")]
+#[derive(Copy, Clone, Debug)]
+pub struct FilePathUrls;
+
+#[cfg(feature = "std")]
+impl<F> HyperlinkedFiles<F, FilePathUrls> {
+    /// Wrap a file database whose file names are paths to the files on disk,
+    /// linking the locations in them to the `file://` URL of the absolute
+    /// path of the file, with the line and column number in the fragment.
+    ///
+    /// The path is resolved for every location that is linked to, and
+    /// locations in files that do not exist are not linked.
+    ///
+    /// ```rust
+    /// use codespan_reporting::files::{Files, HyperlinkedFiles, SimpleFiles};
+    ///
+    /// let mut files = SimpleFiles::new();
+    /// let id = files.add("does/not/exist.rs", "fn main() {}\n");
+    /// let files = HyperlinkedFiles::file_paths(files);
+    ///
+    /// assert_eq!(files.location_url(id, 1, 4).unwrap(), None);
+    /// ```
+    pub fn file_paths(files: F) -> HyperlinkedFiles<F, FilePathUrls> {
+        HyperlinkedFiles::new(files, FilePathUrls)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<'a, F> Files<'a> for HyperlinkedFiles<F, FilePathUrls>
+where
+    F: Files<'a>,
+{
+    type FileId = F::FileId;
+    type Name = F::Name;
+    type Source = F::Source;
+
+    fn name(&'a self, id: Self::FileId) -> Result<Self::Name, Error> {
+        self.files.name(id)
+    }
+
+    fn source(&'a self, id: Self::FileId) -> Result<Self::Source, Error> {
+        self.files.source(id)
+    }
+
+    fn line_index(&'a self, id: Self::FileId, byte_index: usize) -> Result<usize, Error> {
+        self.files.line_index(id, byte_index)
+    }
+
+    fn line_number(&'a self, id: Self::FileId, line_index: usize) -> Result<usize, Error> {
+        self.files.line_number(id, line_index)
+    }
+
+    fn column_number(
+        &'a self,
+        id: Self::FileId,
+        line_index: usize,
+        byte_index: usize,
+    ) -> Result<usize, Error> {
+        self.files.column_number(id, line_index, byte_index)
+    }
+
+    fn location(&'a self, id: Self::FileId, byte_index: usize) -> Result<Location, Error> {
+        self.files.location(id, byte_index)
+    }
+
+    fn line_range(&'a self, id: Self::FileId, line_index: usize) -> Result<Range<usize>, Error> {
+        self.files.line_range(id, line_index)
+    }
+
+    fn line_source(&'a self, id: Self::FileId, line_index: usize) -> Result<Cow<'a, str>, Error> {
+        self.files.line_source(id, line_index)
+    }
+
+    fn location_url(
+        &'a self,
+        id: Self::FileId,
+        line_number: usize,
+        column_number: usize,
+    ) -> Result<Option<String>, Error> {
+        let name = self.files.name(id)?.to_string();
+        let url = std::fs::canonicalize(name)
+            .ok()
+            .and_then(|path| path_url(&path))
+            .map(|url| format!("{url}#{line_number}:{column_number}"));
+        Ok(url)
+    }
 }
 
 #[cfg(test)]
@@ -460,4 +967,156 @@ mod test {
 
         assert_eq!(line_sources, ["foo\n", "bar\r\n", "\n", "baz"]);
     }
+
+    #[test]
+    fn line_sources_are_borrowed() {
+        let mut files = SimpleFiles::new();
+        let id = files.add("test", TEST_SOURCE);
+
+        for (line_index, expected) in ["foo\n", "bar\r\n", "\n", "baz"].iter().enumerate() {
+            let line_source = files.line_source(id, line_index).unwrap();
+            assert!(matches!(line_source, Cow::Borrowed(line) if line == *expected));
+        }
+    }
+
+    /// A file database that finds lines by scanning the source, and counts
+    /// how often the source is read. Its lines are numbered from 101, like
+    /// after a `#line 101` directive.
+    struct ScanningFile {
+        source_reads: core::cell::Cell<usize>,
+    }
+
+    impl Files<'_> for ScanningFile {
+        type FileId = ();
+        type Name = &'static str;
+        type Source = &'static str;
+
+        fn name(&self, (): ()) -> Result<&'static str, Error> {
+            Ok("test")
+        }
+
+        fn source(&self, (): ()) -> Result<&'static str, Error> {
+            self.source_reads.set(self.source_reads.get() + 1);
+            Ok(TEST_SOURCE)
+        }
+
+        fn line_index(&self, (): (), byte_index: usize) -> Result<usize, Error> {
+            let source = self.source(())?;
+            Ok(super::line_starts(source)
+                .filter(|start| *start <= byte_index)
+                .count()
+                - 1)
+        }
+
+        fn line_range(&self, (): (), line_index: usize) -> Result<Range<usize>, Error> {
+            let source = self.source(())?;
+            let mut line_starts = super::line_starts(source)
+                .chain([source.len()])
+                .skip(line_index);
+            match (line_starts.next(), line_starts.next()) {
+                (Some(start), Some(end)) => Ok(start..end),
+                _ => Err(Error::LineTooLarge {
+                    given: line_index,
+                    max: 3,
+                }),
+            }
+        }
+
+        fn line_number(&self, (): (), line_index: usize) -> Result<usize, Error> {
+            Ok(line_index + 101)
+        }
+
+        fn line_source(&self, (): (), line_index: usize) -> Result<Cow<'_, str>, Error> {
+            let line_range = self.line_range((), line_index)?;
+            Ok(Cow::Borrowed(&self.source(())?[line_range]))
+        }
+    }
+
+    #[test]
+    fn cached_line_starts() {
+        let file = ScanningFile {
+            source_reads: core::cell::Cell::new(0),
+        };
+        let simple_file = SimpleFile::new("test", TEST_SOURCE);
+        let mut cached_file = CachedFiles::new(file);
+
+        for byte_index in 0..=TEST_SOURCE.len() + 1 {
+            assert_eq!(
+                cached_file.line_index((), byte_index).unwrap(),
+                simple_file.line_index((), byte_index).unwrap(),
+            );
+        }
+        for line_index in 0..4 {
+            assert_eq!(
+                cached_file.line_range((), line_index).unwrap(),
+                simple_file.line_range((), line_index).unwrap(),
+            );
+        }
+        assert!(matches!(
+            cached_file.line_range((), 4),
+            Err(Error::LineTooLarge { given: 4, max: 3 })
+        ));
+        assert_eq!(cached_file.get_ref().source_reads.get(), 1);
+
+        cached_file.clear();
+        cached_file.line_index((), 0).unwrap();
+        assert_eq!(cached_file.get_ref().source_reads.get(), 2);
+    }
+
+    #[test]
+    fn cached_files_forward_line_numbers() {
+        let file = ScanningFile {
+            source_reads: core::cell::Cell::new(0),
+        };
+        let cached_file = CachedFiles::new(file);
+
+        assert_eq!(cached_file.line_number((), 1).unwrap(), 102);
+        assert_eq!(
+            cached_file.location((), 5).unwrap(),
+            Location {
+                line_number: 102,
+                column_number: 2,
+            },
+        );
+        assert!(matches!(
+            cached_file.line_source((), 1).unwrap(),
+            Cow::Borrowed("bar\r\n"),
+        ));
+    }
+
+    #[test]
+    #[cfg(all(unix, feature = "std"))]
+    fn path_urls_are_percent_encoded() {
+        assert_eq!(
+            path_url(std::path::Path::new("/home/me/my project/#1 [draft].rs")).unwrap(),
+            "file:///home/me/my%20project/%231%20%5Bdraft%5D.rs",
+        );
+        assert_eq!(
+            path_url(std::path::Path::new("/tmp/café.rs")).unwrap(),
+            "file:///tmp/caf%C3%A9.rs",
+        );
+    }
+
+    #[test]
+    fn location_urls_by_default() {
+        let file = SimpleFile::new("Cargo.toml", TEST_SOURCE);
+
+        assert_eq!(file.location_url((), 1, 1).unwrap(), None);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn location_urls_of_file_paths() {
+        let path = std::env::temp_dir().join(format!("codespan-{}.rs", std::process::id()));
+        std::fs::write(&path, TEST_SOURCE).unwrap();
+        let name = path.to_str().unwrap();
+        let files = HyperlinkedFiles::file_paths(SimpleFile::new(name, TEST_SOURCE));
+        let url = files.location_url((), 2, 3).unwrap();
+        let expected = path_url(&std::fs::canonicalize(&path).unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(url, Some(format!("{expected}#2:3")));
+        // The file no longer exists
+        assert_eq!(files.location_url((), 2, 3).unwrap(), None);
+    }
 }
@@ -0,0 +1,220 @@
+//! [GitLab Code Quality] back-end for emitting diagnostics.
+//!
+//! GitLab shows the code quality issues reported by a pipeline in its merge
+//! request widgets. Each diagnostic is emitted as an issue:
+//!
+//! - The message becomes the `description`, with the notes appended to it,
+//!   separated by newlines.
+//! - The diagnostic code becomes the `check_name`, which GitLab requires.
+//!   Diagnostics without a code are named `diagnostic`.
+//! - The [`Severity`] is mapped to the `severity` of the issue, with bugs
+//!   being `critical`, errors being `major`, warnings being `minor`, and notes
+//!   and help messages being `info`.
+//! - The first primary label becomes the `location`, or the first label if
+//!   there is no primary label. Diagnostics without any labels are skipped,
+//!   because GitLab requires a location in a file.
+//! - The `fingerprint` is a hash of the code, message, path and line, so that
+//!   it stays the same across runs as long as these do.
+//!
+//! [GitLab Code Quality]: https://docs.gitlab.com/ci/testing/code_quality/
+
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+use serde::Serialize;
+use std::io::Write;
+
+use crate::diagnostic::{Diagnostic, LabelStyle, Severity};
+use crate::files::{Error, Files};
+
+#[derive(Serialize)]
+struct Issue<'a> {
+    description: String,
+    check_name: &'a str,
+    fingerprint: String,
+    severity: &'static str,
+    location: Location,
+}
+
+#[derive(Serialize)]
+struct Location {
+    path: String,
+    lines: Lines,
+}
+
+#[derive(Serialize)]
+struct Lines {
+    begin: usize,
+}
+
+/// The `check_name` of diagnostics without a code.
+const DEFAULT_CHECK_NAME: &str = "diagnostic";
+
+fn severity(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Bug => "critical",
+        Severity::Error => "major",
+        Severity::Warning => "minor",
+        Severity::Note | Severity::Help => "info",
+    }
+}
+
+/// A 64-bit FNV-1a hash of the given fields. Unlike the hashers in the
+/// standard library, this is guaranteed to be stable across Rust versions.
+fn fingerprint(fields: &[&str]) -> String {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+
+    let mut hash = OFFSET_BASIS;
+    for field in fields {
+        // Separate the fields, so that moving text between them changes the hash.
+        for byte in field.bytes().chain([0]) {
+            hash ^= u64::from(byte);
+            hash = hash.wrapping_mul(PRIME);
+        }
+    }
+
+    format!("{hash:016x}")
+}
+
+fn issue<'files, 'diagnostic, F: Files<'files> + ?Sized>(
+    files: &'files F,
+    diagnostic: &'diagnostic Diagnostic<F::FileId>,
+) -> Result<Option<Issue<'diagnostic>>, Error> {
+    let primary_label = (diagnostic.labels.iter()).find(|label| label.style == LabelStyle::Primary);
+    let label = match primary_label.or_else(|| diagnostic.labels.first()) {
+        Some(label) => label,
+        None => return Ok(None),
+    };
+
+    let path = files.name(label.file_id)?.to_string();
+    let line = files.line_number(
+        label.file_id,
+        files.line_index(label.file_id, label.range.start)?,
+    )?;
+    let code = diagnostic.code.as_deref().filter(|code| !code.is_empty());
+
+    let mut description = diagnostic.message.clone();
+    for note in &diagnostic.notes {
+        description.push('\n');
+        description.push_str(&note.message);
+    }
+
+    Ok(Some(Issue {
+        description,
+        check_name: code.unwrap_or(DEFAULT_CHECK_NAME),
+        fingerprint: fingerprint(&[
+            code.unwrap_or_default(),
+            &diagnostic.message,
+            &path,
+            &line.to_string(),
+        ]),
+        severity: severity(diagnostic.severity),
+        location: Location {
+            path,
+            lines: Lines { begin: line },
+        },
+    }))
+}
+
+/// Emit the diagnostics as a GitLab Code Quality report, which is a JSON array
+/// of issues.
+///
+/// The return value covers all error cases. These error case can arise if:
+/// * a file was removed from the file database.
+/// * a file was changed so that it is too small to have an index
+/// * IO fails
+pub fn render_gitlab_codequality<'files, F: Files<'files> + ?Sized>(
+    writer: &mut dyn Write,
+    files: &'files F,
+    diagnostics: &[Diagnostic<F::FileId>],
+) -> Result<(), Error> {
+    let mut issues = Vec::new();
+    for diagnostic in diagnostics {
+        issues.extend(issue(files, diagnostic)?);
+    }
+
+    serde_json::to_writer_pretty(&mut *writer, &issues).map_err(|err| Error::Io(err.into()))?;
+    writeln!(writer)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use super::*;
+
+    use crate::diagnostic::Label;
+    use crate::files::SimpleFiles;
+
+    #[test]
+    fn code_quality_report() {
+        let mut files = SimpleFiles::new();
+        let id = files.add("src/main.fun", "(+ 1\n(+ test \"\"))\n");
+
+        let diagnostics = [
+            Diagnostic::error()
+                .with_code("E0001")
+                .with_message("unexpected type in `+` application")
+                .with_labels(vec![
+                    Label::secondary(id, 5..7),
+                    Label::primary(id, 13..15).with_message("expected `Int` but found `String`"),
+                ])
+                .with_notes(vec!["expected type `Int`".into()]),
+            Diagnostic::warning()
+                .with_message("unused expression")
+                .with_labels(vec![Label::primary(id, 0..4)]),
+            Diagnostic::note()
+                .with_message("defined here")
+                .with_labels(vec![Label::secondary(id, 8..12)]),
+            Diagnostic::help().with_message("consider adding a type annotation"),
+        ];
+
+        let mut buffer = Vec::new();
+        render_gitlab_codequality(&mut buffer, &files, &diagnostics).unwrap();
+
+        let value: serde_json::Value = serde_json::from_slice(&buffer).unwrap();
+        assert_eq!(
+            value,
+            serde_json::json!([
+                {
+                    "description": "unexpected type in `+` application\nexpected type `Int`",
+                    "check_name": "E0001",
+                    "fingerprint": fingerprint(&[
+                        "E0001",
+                        "unexpected type in `+` application",
+                        "src/main.fun",
+                        "2",
+                    ]),
+                    "severity": "major",
+                    "location": { "path": "src/main.fun", "lines": { "begin": 2 } },
+                },
+                {
+                    "description": "unused expression",
+                    "check_name": "diagnostic",
+                    "fingerprint": fingerprint(&["", "unused expression", "src/main.fun", "1"]),
+                    "severity": "minor",
+                    "location": { "path": "src/main.fun", "lines": { "begin": 1 } },
+                },
+                {
+                    "description": "defined here",
+                    "check_name": "diagnostic",
+                    "fingerprint": fingerprint(&["", "defined here", "src/main.fun", "2"]),
+                    "severity": "info",
+                    "location": { "path": "src/main.fun", "lines": { "begin": 2 } },
+                },
+            ]),
+        );
+    }
+
+    #[test]
+    fn fingerprints_are_stable() {
+        assert_eq!(fingerprint(&[]), "cbf29ce484222325");
+        assert_eq!(fingerprint(&["a"]), fingerprint(&["a"]));
+        assert_ne!(fingerprint(&["ab", "c"]), fingerprint(&["a", "bc"]));
+    }
+}
@@ -0,0 +1,284 @@
+//! HTML back-end for emitting diagnostics.
+//!
+//! Diagnostics are rendered in the same way as by the terminal back-end, but
+//! inside a `<pre class="cs-diagnostics">` element. Instead of colors, each
+//! styled part of the output is wrapped in a `<span>` with a class named after
+//! the corresponding field of [`Styles`], prefixed with `cs-`, such as
+//! `cs-header-error`, `cs-line-number` or `cs-secondary-label`. This leaves
+//! the colors up to the stylesheet of the page, and [`DEFAULT_CSS`] can be
+//! used as a starting point.
+//!
+//! The source text and messages are HTML-escaped, and box-drawing characters
+//! are written as is, so the page should be served as UTF-8.
+//!
+//! [`Styles`]: crate::term::Styles
+
+use alloc::vec::Vec;
+
+use std::io::{self, Write};
+
+use crate::diagnostic::{Diagnostic, LabelStyle, Severity};
+use crate::files::{Error, Files};
+use crate::term::{self, Config, WriteStyle};
+
+/// A stylesheet for the classes used by [`render_html`], using the same
+/// colors as the default styles of the terminal back-end.
+pub const DEFAULT_CSS: &str = "\
+.cs-diagnostics { font-family: monospace; }
+.cs-header-bug, .cs-header-error { color: #ff5555; font-weight: bold; }
+.cs-header-warning { color: #ffff55; font-weight: bold; }
+.cs-header-note { color: #55ff55; font-weight: bold; }
+.cs-header-help { color: #55ffff; font-weight: bold; }
+.cs-header-message { font-weight: bold; }
+.cs-primary-label-bug, .cs-primary-label-error { color: #aa0000; }
+.cs-primary-label-warning { color: #aa5500; }
+.cs-primary-label-note { color: #00aa00; }
+.cs-primary-label-help { color: #00aaaa; }
+.cs-secondary-label, .cs-line-number, .cs-source-border, .cs-note-bullet { color: #0000aa; }
+.cs-span-background { background-color: #555555; }
+.cs-whitespace, .cs-context-line { opacity: 0.5; }
+.cs-wavy-underline { text-decoration: underline wavy; }
+.cs-suggestion-insertion { color: #00aa00; }
+.cs-suggestion-deletion { color: #aa0000; }
+";
+
+/// Escape the characters of `text` that are special in HTML.
+fn write_escaped(writer: &mut dyn Write, text: &[u8]) -> io::Result<()> {
+    let mut start = 0;
+    for (index, byte) in text.iter().enumerate() {
+        let escaped = match byte {
+            b'&' => "&amp;",
+            b'<' => "&lt;",
+            b'>' => "&gt;",
+            b'"' => "&quot;",
+            b'\'' => "&#39;",
+            _ => continue,
+        };
+        writer.write_all(&text[start..index])?;
+        writer.write_all(escaped.as_bytes())?;
+        start = index + 1;
+    }
+    writer.write_all(&text[start..])
+}
+
+/// A writer that escapes the rendered output, and wraps styled parts of it
+/// in `<span>` elements.
+///
+/// Styles that add to the current style, such as the span background, are
+/// nested inside the spans that are already open, and all of them are closed
+/// when the style is reset. Spans are closed and reopened around hyperlinks,
+/// so that the elements are always properly nested.
+struct HtmlWriter<'a> {
+    writer: &'a mut dyn Write,
+    classes: Vec<&'static str>,
+}
+
+impl HtmlWriter<'_> {
+    fn open_spans(&mut self) -> io::Result<()> {
+        for class in &self.classes {
+            write!(self.writer, "<span class=\"{class}\">")?;
+        }
+        Ok(())
+    }
+
+    fn close_spans(&mut self) -> io::Result<()> {
+        for _ in &self.classes {
+            write!(self.writer, "</span>")?;
+        }
+        Ok(())
+    }
+
+    fn set_class(&mut self, class: &'static str) -> io::Result<()> {
+        self.reset()?;
+        self.add_class(class)
+    }
+
+    fn add_class(&mut self, class: &'static str) -> io::Result<()> {
+        self.classes.push(class);
+        write!(self.writer, "<span class=\"{class}\">")
+    }
+}
+
+impl Write for HtmlWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        write_escaped(self.writer, buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+impl WriteStyle for HtmlWriter<'_> {
+    fn set_header(&mut self, severity: Severity) -> io::Result<()> {
+        self.set_class(match severity {
+            Severity::Bug => "cs-header-bug",
+            Severity::Error => "cs-header-error",
+            Severity::Warning => "cs-header-warning",
+            Severity::Note => "cs-header-note",
+            Severity::Help => "cs-header-help",
+        })
+    }
+
+    fn set_header_message(&mut self) -> io::Result<()> {
+        self.set_class("cs-header-message")
+    }
+
+    fn set_line_number(&mut self) -> io::Result<()> {
+        self.set_class("cs-line-number")
+    }
+
+    fn set_note_bullet(&mut self) -> io::Result<()> {
+        self.set_class("cs-note-bullet")
+    }
+
+    fn set_source_border(&mut self) -> io::Result<()> {
+        self.set_class("cs-source-border")
+    }
+
+    fn set_label(&mut self, severity: Severity, label_style: LabelStyle) -> io::Result<()> {
+        self.set_class(match (label_style, severity) {
+            (LabelStyle::Primary, Severity::Bug) => "cs-primary-label-bug",
+            (LabelStyle::Primary, Severity::Error) => "cs-primary-label-error",
+            (LabelStyle::Primary, Severity::Warning) => "cs-primary-label-warning",
+            (LabelStyle::Primary, Severity::Note) => "cs-primary-label-note",
+            (LabelStyle::Primary, Severity::Help) => "cs-primary-label-help",
+            (LabelStyle::Secondary, _) => "cs-secondary-label",
+        })
+    }
+
+    fn reset(&mut self) -> io::Result<()> {
+        self.close_spans()?;
+        self.classes.clear();
+        Ok(())
+    }
+
+    fn set_span_background(&mut self) -> io::Result<()> {
+        self.add_class("cs-span-background")
+    }
+
+    fn set_whitespace(&mut self) -> io::Result<()> {
+        self.add_class("cs-whitespace")
+    }
+
+    fn set_wavy_underline(&mut self) -> io::Result<()> {
+        self.add_class("cs-wavy-underline")
+    }
+
+    fn supports_wavy_underline(&self) -> bool {
+        true
+    }
+
+    fn set_context_line(&mut self) -> io::Result<()> {
+        self.set_class("cs-context-line")
+    }
+
+    fn set_suggestion_insertion(&mut self) -> io::Result<()> {
+        self.set_class("cs-suggestion-insertion")
+    }
+
+    fn set_suggestion_deletion(&mut self) -> io::Result<()> {
+        self.set_class("cs-suggestion-deletion")
+    }
+
+    fn start_hyperlink(&mut self, url: &str) -> io::Result<()> {
+        self.close_spans()?;
+        write!(self.writer, "<a href=\"")?;
+        write_escaped(self.writer, url.as_bytes())?;
+        write!(self.writer, "\">")?;
+        self.open_spans()
+    }
+
+    fn end_hyperlink(&mut self) -> io::Result<()> {
+        self.close_spans()?;
+        write!(self.writer, "</a>")?;
+        self.open_spans()
+    }
+}
+
+/// Emit the diagnostics as HTML, inside a single `<pre>` element, using the
+/// given config and files. Batches of diagnostics are emitted as with
+/// [`term::emit_batch`].
+///
+/// The return value covers all error cases. These error case can arise if:
+/// * a file was removed from the file database.
+/// * a file was changed so that it is too small to have an index
+/// * IO fails
+pub fn render_html<'files, F: Files<'files> + ?Sized>(
+    writer: &mut dyn Write,
+    config: &Config,
+    files: &'files F,
+    diagnostics: &[Diagnostic<F::FileId>],
+) -> Result<(), Error> {
+    writeln!(writer, "<pre class=\"cs-diagnostics\">")?;
+    let mut html_writer = HtmlWriter {
+        writer: &mut *writer,
+        classes: Vec::new(),
+    };
+    term::emit_batch(&mut html_writer, config, files, diagnostics)?;
+    html_writer.reset()?;
+    writeln!(writer, "</pre>")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{string::String, vec, vec::Vec};
+
+    use super::*;
+
+    use crate::diagnostic::Label;
+    use crate::files::SimpleFiles;
+
+    #[test]
+    fn html_output() {
+        let mut files = SimpleFiles::new();
+        let id = files.add("main.fun", "if a < b && c {\n");
+
+        let diagnostics = [Diagnostic::error()
+            .with_message("expected `Bool`, found `<Int>`")
+            .with_labels(vec![Label::primary(id, 3..8).with_message("`a < b`")])];
+
+        let mut buffer = Vec::new();
+        render_html(&mut buffer, &Config::default(), &files, &diagnostics).unwrap();
+
+        assert_eq!(
+            String::from_utf8(buffer).unwrap(),
+            r#"<pre class="cs-diagnostics">
+<span class="cs-header-error">error</span><span class="cs-header-message">: expected `Bool`, found `&lt;Int&gt;`</span>
+  <span class="cs-source-border">┌─</span> main.fun:1:4
+  <span class="cs-source-border">│</span>
+<span class="cs-line-number">1</span> <span class="cs-source-border">│</span> if <span class="cs-primary-label-error">a &lt; b</span> &amp;&amp; c {
+  <span class="cs-source-border">│</span>    <span class="cs-primary-label-error">^^^^^</span> <span class="cs-primary-label-error">`a &lt; b`</span>
+
+</pre>
+"#,
+        );
+    }
+
+    #[test]
+    fn hyperlinks_are_nested_in_spans() {
+        let mut buffer = Vec::new();
+        let mut writer = HtmlWriter {
+            writer: &mut buffer,
+            classes: Vec::new(),
+        };
+        writer.set_header(Severity::Error).unwrap();
+        write!(writer, "error[").unwrap();
+        writer
+            .start_hyperlink("https://example.com/?a=1&b=2")
+            .unwrap();
+        write!(writer, "E0001").unwrap();
+        writer.end_hyperlink().unwrap();
+        write!(writer, "]").unwrap();
+        writer.reset().unwrap();
+
+        assert_eq!(
+            String::from_utf8(buffer).unwrap(),
+            "<span class=\"cs-header-error\">error[</span>\
+             <a href=\"https://example.com/?a=1&amp;b=2\"><span class=\"cs-header-error\">E0001</span></a>\
+             <span class=\"cs-header-error\">]</span>",
+        );
+    }
+}
@@ -0,0 +1,277 @@
+//! JSON back-end for emitting diagnostics.
+//!
+//! This is intended for tools that consume diagnostics programmatically,
+//! where parsing the output of the [`term`] back-end would be brittle. Each
+//! diagnostic is serialized to a stable JSON object:
+//!
+//! ```json
+//! {
+//!   "severity": "error",
+//!   "code": "E0001",
+//!   "message": "unexpected type in `+` application",
+//!   "labels": [
+//!     {
+//!       "style": "primary",
+//!       "file_id": 0,
+//!       "file_name": "test",
+//!       "range": { "start": 24, "end": 26 },
+//!       "start": { "line": 2, "column": 9 },
+//!       "end": { "line": 2, "column": 11 },
+//!       "message": "expected `Int` but found `String`"
+//!     }
+//!   ],
+//!   "notes": ["expected type `Int`\n   found type `String`"]
+//! }
+//! ```
+//!
+//! The byte ranges are exactly the ones stored in the [`Label`]s, so they can
+//! be cross-referenced with the output of the [`term`] back-end. No styling is
+//! ever emitted, regardless of the writer.
+//!
+//! [`term`]: crate::term
+//! [`Label`]: crate::diagnostic::Label
+
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::ops::Range;
+
+use serde::Serialize;
+use std::io::Write;
+
+use crate::diagnostic::{Diagnostic, LabelStyle, Severity};
+use crate::files::{Error, Files};
+
+/// A serializable view of a [`Diagnostic`].
+#[derive(Serialize)]
+struct JsonDiagnostic<'diagnostic, FileId> {
+    severity: &'static str,
+    code: Option<&'diagnostic str>,
+    message: &'diagnostic str,
+    labels: Vec<JsonLabel<'diagnostic, FileId>>,
+    notes: Vec<&'diagnostic str>,
+}
+
+/// A serializable view of a [`Label`](crate::diagnostic::Label).
+#[derive(Serialize)]
+struct JsonLabel<'diagnostic, FileId> {
+    style: &'static str,
+    file_id: FileId,
+    file_name: String,
+    range: Range<usize>,
+    start: JsonLocation,
+    end: JsonLocation,
+    message: &'diagnostic str,
+}
+
+/// A user-facing location, using the same numbering as the [`Files`] database.
+#[derive(Serialize)]
+struct JsonLocation {
+    line: usize,
+    column: usize,
+}
+
+impl<'diagnostic, FileId> JsonDiagnostic<'diagnostic, FileId>
+where
+    FileId: Copy + PartialEq,
+{
+    fn new<'files>(
+        files: &'files (impl Files<'files, FileId = FileId> + ?Sized),
+        diagnostic: &'diagnostic Diagnostic<FileId>,
+    ) -> Result<JsonDiagnostic<'diagnostic, FileId>, Error>
+    where
+        FileId: 'files,
+    {
+        let labels = diagnostic
+            .labels
+            .iter()
+            .map(|label| {
+                let start = files.location(label.file_id, label.range.start)?;
+                let end = files.location(label.file_id, label.range.end)?;
+
+                Ok(JsonLabel {
+                    style: match label.style {
+                        LabelStyle::Primary => "primary",
+                        LabelStyle::Secondary => "secondary",
+                    },
+                    file_id: label.file_id,
+                    file_name: files.name(label.file_id)?.to_string(),
+                    range: label.range.clone(),
+                    start: JsonLocation {
+                        line: start.line_number,
+                        column: start.column_number,
+                    },
+                    end: JsonLocation {
+                        line: end.line_number,
+                        column: end.column_number,
+                    },
+                    message: &label.message,
+                })
+            })
+            .collect::<Result<_, Error>>()?;
+
+        Ok(JsonDiagnostic {
+            severity: severity_name(diagnostic.severity),
+            code: diagnostic.code.as_deref(),
+            message: &diagnostic.message,
+            labels,
+            notes: (diagnostic.notes.iter())
+                .map(|note| note.message.as_str())
+                .collect(),
+        })
+    }
+}
+
+fn severity_name(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Bug => "bug",
+        Severity::Error => "error",
+        Severity::Warning => "warning",
+        Severity::Note => "note",
+        Severity::Help => "help",
+    }
+}
+
+fn write_json(writer: &mut dyn Write, value: &impl Serialize) -> Result<(), Error> {
+    serde_json::to_writer_pretty(&mut *writer, value).map_err(|err| Error::Io(err.into()))?;
+    writeln!(writer)?;
+    Ok(())
+}
+
+/// Emit a single diagnostic as a JSON object, followed by a newline.
+///
+/// The return value covers all error cases. These error case can arise if:
+/// * a file was removed from the file database.
+/// * a file was changed so that it is too small to have an index
+/// * IO fails
+pub fn render_json<'files, F: Files<'files> + ?Sized>(
+    writer: &mut dyn Write,
+    files: &'files F,
+    diagnostic: &Diagnostic<F::FileId>,
+) -> Result<(), Error>
+where
+    F::FileId: Serialize,
+{
+    write_json(writer, &JsonDiagnostic::new(files, diagnostic)?)
+}
+
+/// Emit a batch of diagnostics as a JSON array, followed by a newline.
+///
+/// See [`render_json`] for the possible errors.
+pub fn render_json_batch<'files, F: Files<'files> + ?Sized>(
+    writer: &mut dyn Write,
+    files: &'files F,
+    diagnostics: &[Diagnostic<F::FileId>],
+) -> Result<(), Error>
+where
+    F::FileId: Serialize,
+{
+    let diagnostics = diagnostics
+        .iter()
+        .map(|diagnostic| JsonDiagnostic::new(files, diagnostic))
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    write_json(writer, &diagnostics)
+}
+
+/// Emit a single diagnostic as a JSON object on a single line, and flush the
+/// writer, for streaming diagnostics as [JSON Lines](https://jsonlines.org/)
+/// while they are produced.
+///
+/// Unlike [`render_json`], the object is not pretty-printed, so every
+/// diagnostic takes up exactly one line, and consumers can parse the output
+/// incrementally. See [`render_json`] for the possible errors.
+pub fn render_jsonl<'files, F: Files<'files> + ?Sized>(
+    writer: &mut dyn Write,
+    files: &'files F,
+    diagnostic: &Diagnostic<F::FileId>,
+) -> Result<(), Error>
+where
+    F::FileId: Serialize,
+{
+    let diagnostic = JsonDiagnostic::new(files, diagnostic)?;
+    serde_json::to_writer(&mut *writer, &diagnostic).map_err(|err| Error::Io(err.into()))?;
+    writeln!(writer)?;
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{vec, vec::Vec};
+
+    use super::*;
+
+    use crate::diagnostic::Label;
+    use crate::files::SimpleFiles;
+
+    fn test_diagnostic() -> (SimpleFiles<&'static str, &'static str>, Diagnostic<usize>) {
+        let mut files = SimpleFiles::new();
+        let id = files.add("test", "(+ 1\n(+ test \"\"))\n");
+
+        let diagnostic = Diagnostic::error()
+            .with_code("E0001")
+            .with_message("unexpected type in `+` application")
+            .with_labels(vec![
+                Label::primary(id, 13..15).with_message("expected `Int` but found `String`")
+            ])
+            .with_notes(vec!["expected type `Int`\n   found type `String`".into()]);
+
+        (files, diagnostic)
+    }
+
+    #[test]
+    fn single_diagnostic() {
+        let (files, diagnostic) = test_diagnostic();
+        let mut buffer = Vec::new();
+        render_json(&mut buffer, &files, &diagnostic).unwrap();
+
+        let value: serde_json::Value = serde_json::from_slice(&buffer).unwrap();
+        assert_eq!(
+            value,
+            serde_json::json!({
+                "severity": "error",
+                "code": "E0001",
+                "message": "unexpected type in `+` application",
+                "labels": [{
+                    "style": "primary",
+                    "file_id": 0,
+                    "file_name": "test",
+                    "range": { "start": 13, "end": 15 },
+                    "start": { "line": 2, "column": 9 },
+                    "end": { "line": 2, "column": 11 },
+                    "message": "expected `Int` but found `String`",
+                }],
+                "notes": ["expected type `Int`\n   found type `String`"],
+            }),
+        );
+    }
+
+    #[test]
+    fn batch_is_an_array_without_escapes() {
+        let (files, diagnostic) = test_diagnostic();
+        let mut buffer = Vec::new();
+        render_json_batch(&mut buffer, &files, &[diagnostic.clone(), diagnostic]).unwrap();
+
+        assert!(!buffer.contains(&0x1b));
+        let value: serde_json::Value = serde_json::from_slice(&buffer).unwrap();
+        assert_eq!(value.as_array().map(Vec::len), Some(2));
+    }
+
+    #[test]
+    fn lines_of_objects() {
+        let (files, diagnostic) = test_diagnostic();
+        let mut buffer = Vec::new();
+        render_jsonl(&mut buffer, &files, &diagnostic).unwrap();
+        render_jsonl(&mut buffer, &files, &diagnostic).unwrap();
+
+        let lines = core::str::from_utf8(&buffer).unwrap().lines();
+        let values = lines
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(values.len(), 2);
+        assert_eq!(values[0]["message"], "unexpected type in `+` application");
+        assert_eq!(values[0], values[1]);
+    }
+}
@@ -8,6 +8,16 @@ extern crate alloc;
 #[cfg(feature = "std")]
 extern crate std;
 
+#[cfg(feature = "checkstyle")]
+pub mod checkstyle;
 pub mod diagnostic;
 pub mod files;
+#[cfg(feature = "gitlab")]
+pub mod gitlab;
+#[cfg(feature = "html")]
+pub mod html;
+#[cfg(feature = "json")]
+pub mod json;
+#[cfg(feature = "sarif")]
+pub mod sarif;
 pub mod term;
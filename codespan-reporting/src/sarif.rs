@@ -0,0 +1,286 @@
+//! [SARIF 2.1.0] back-end for emitting diagnostics.
+//!
+//! SARIF (Static Analysis Results Interchange Format) is understood by many
+//! code scanning services, such as GitHub code scanning. All diagnostics are
+//! emitted as the `results` of a single run of the given tool:
+//!
+//! - The [`Severity`] is mapped to the `level` of the result, with bugs and
+//!   errors being `error`, warnings being `warning`, and notes and help
+//!   messages being `note`.
+//! - The diagnostic code becomes the `ruleId`.
+//! - Primary labels become the `locations`, and secondary labels become the
+//!   `relatedLocations` of the result.
+//! - The notes are appended to the message, separated by newlines.
+//!
+//! Regions use 1-based line and column numbers, where columns are counted in
+//! Unicode code points.
+//!
+//! [SARIF 2.1.0]: https://docs.oasis-open.org/sarif/sarif/v2.1.0/sarif-v2.1.0.html
+
+use alloc::{
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+
+use serde::Serialize;
+use std::io::Write;
+
+use crate::diagnostic::{Diagnostic, Label, LabelStyle, Severity};
+use crate::files::{Error, Files};
+
+const SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+const VERSION: &str = "2.1.0";
+
+#[derive(Serialize)]
+struct SarifLog<'a> {
+    #[serde(rename = "$schema")]
+    schema: &'static str,
+    version: &'static str,
+    runs: Vec<Run<'a>>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Run<'a> {
+    tool: Tool<'a>,
+    column_kind: &'static str,
+    results: Vec<SarifResult<'a>>,
+}
+
+#[derive(Serialize)]
+struct Tool<'a> {
+    driver: Driver<'a>,
+}
+
+#[derive(Serialize)]
+struct Driver<'a> {
+    name: &'a str,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SarifResult<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rule_id: Option<&'a str>,
+    level: &'static str,
+    message: Message,
+    locations: Vec<SarifLocation<'a>>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    related_locations: Vec<SarifLocation<'a>>,
+}
+
+#[derive(Serialize)]
+struct Message {
+    text: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SarifLocation<'a> {
+    physical_location: PhysicalLocation,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message: Option<LabelMessage<'a>>,
+}
+
+#[derive(Serialize)]
+struct LabelMessage<'a> {
+    text: &'a str,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PhysicalLocation {
+    artifact_location: ArtifactLocation,
+    region: Region,
+}
+
+#[derive(Serialize)]
+struct ArtifactLocation {
+    uri: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Region {
+    start_line: usize,
+    start_column: usize,
+    end_line: usize,
+    end_column: usize,
+}
+
+fn level(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Bug | Severity::Error => "error",
+        Severity::Warning => "warning",
+        Severity::Note | Severity::Help => "note",
+    }
+}
+
+fn location<'files, 'diagnostic, F: Files<'files> + ?Sized>(
+    files: &'files F,
+    label: &'diagnostic Label<F::FileId>,
+) -> Result<SarifLocation<'diagnostic>, Error> {
+    let start = files.location(label.file_id, label.range.start)?;
+    let end = files.location(label.file_id, label.range.end)?;
+
+    Ok(SarifLocation {
+        physical_location: PhysicalLocation {
+            artifact_location: ArtifactLocation {
+                uri: files.name(label.file_id)?.to_string(),
+            },
+            region: Region {
+                start_line: start.line_number,
+                start_column: start.column_number,
+                end_line: end.line_number,
+                end_column: end.column_number,
+            },
+        },
+        message: (!label.message.is_empty()).then_some(LabelMessage {
+            text: &label.message,
+        }),
+    })
+}
+
+fn result<'files, 'diagnostic, F: Files<'files> + ?Sized>(
+    files: &'files F,
+    diagnostic: &'diagnostic Diagnostic<F::FileId>,
+) -> Result<SarifResult<'diagnostic>, Error> {
+    let mut text = diagnostic.message.clone();
+    for note in &diagnostic.notes {
+        text.push('\n');
+        text.push_str(&note.message);
+    }
+
+    let mut locations = Vec::new();
+    let mut related_locations = Vec::new();
+    for label in &diagnostic.labels {
+        match label.style {
+            LabelStyle::Primary => locations.push(location(files, label)?),
+            LabelStyle::Secondary => related_locations.push(location(files, label)?),
+        }
+    }
+
+    Ok(SarifResult {
+        rule_id: diagnostic.code.as_deref().filter(|code| !code.is_empty()),
+        level: level(diagnostic.severity),
+        message: Message { text },
+        locations,
+        related_locations,
+    })
+}
+
+/// Emit the diagnostics as a SARIF 2.1.0 log, reporting a single run of the
+/// tool named `tool_name`.
+///
+/// The return value covers all error cases. These error case can arise if:
+/// * a file was removed from the file database.
+/// * a file was changed so that it is too small to have an index
+/// * IO fails
+pub fn render_sarif<'files, F: Files<'files> + ?Sized>(
+    writer: &mut dyn Write,
+    files: &'files F,
+    tool_name: &str,
+    diagnostics: &[Diagnostic<F::FileId>],
+) -> Result<(), Error> {
+    let results = diagnostics
+        .iter()
+        .map(|diagnostic| result(files, diagnostic))
+        .collect::<Result<_, Error>>()?;
+
+    let log = SarifLog {
+        schema: SCHEMA,
+        version: VERSION,
+        runs: vec![Run {
+            tool: Tool {
+                driver: Driver { name: tool_name },
+            },
+            column_kind: "unicodeCodePoints",
+            results,
+        }],
+    };
+
+    serde_json::to_writer_pretty(&mut *writer, &log).map_err(|err| Error::Io(err.into()))?;
+    writeln!(writer)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use super::*;
+
+    use crate::files::SimpleFiles;
+
+    #[test]
+    fn sarif_log() {
+        let mut files = SimpleFiles::new();
+        let id = files.add("src/main.fun", "(+ 1\n(+ test \"\"))\n");
+
+        let diagnostics = [
+            Diagnostic::error()
+                .with_code("E0001")
+                .with_message("unexpected type in `+` application")
+                .with_labels(vec![
+                    Label::primary(id, 13..15).with_message("expected `Int` but found `String`"),
+                    Label::secondary(id, 5..7),
+                ])
+                .with_notes(vec!["expected type `Int`".into()]),
+            Diagnostic::help().with_message("consider adding a type annotation"),
+        ];
+
+        let mut buffer = Vec::new();
+        render_sarif(&mut buffer, &files, "fun-check", &diagnostics).unwrap();
+
+        let value: serde_json::Value = serde_json::from_slice(&buffer).unwrap();
+        assert_eq!(
+            value,
+            serde_json::json!({
+                "$schema": SCHEMA,
+                "version": "2.1.0",
+                "runs": [{
+                    "tool": { "driver": { "name": "fun-check" } },
+                    "columnKind": "unicodeCodePoints",
+                    "results": [
+                        {
+                            "ruleId": "E0001",
+                            "level": "error",
+                            "message": {
+                                "text": "unexpected type in `+` application\nexpected type `Int`",
+                            },
+                            "locations": [{
+                                "physicalLocation": {
+                                    "artifactLocation": { "uri": "src/main.fun" },
+                                    "region": {
+                                        "startLine": 2,
+                                        "startColumn": 9,
+                                        "endLine": 2,
+                                        "endColumn": 11,
+                                    },
+                                },
+                                "message": { "text": "expected `Int` but found `String`" },
+                            }],
+                            "relatedLocations": [{
+                                "physicalLocation": {
+                                    "artifactLocation": { "uri": "src/main.fun" },
+                                    "region": {
+                                        "startLine": 2,
+                                        "startColumn": 1,
+                                        "endLine": 2,
+                                        "endColumn": 3,
+                                    },
+                                },
+                            }],
+                        },
+                        {
+                            "level": "note",
+                            "message": { "text": "consider adding a type annotation" },
+                            "locations": [],
+                        },
+                    ],
+                }],
+            }),
+        );
+    }
+}
@@ -1,27 +1,50 @@
 //! Terminal back-end for emitting diagnostics.
 
-use crate::diagnostic::Diagnostic;
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::borrow::Borrow;
+use core::ops::Range;
+
+use crate::diagnostic::{Diagnostic, Label, Severity};
 use crate::files::Files;
 
 mod config;
+mod display;
+mod recording;
 mod renderer;
 mod views;
 
 #[cfg(feature = "termcolor")]
 pub use termcolor;
 
-pub use self::config::{Chars, Config, DisplayStyle};
+pub use self::config::{
+    CaretFillStyle, Chars, CodeUrl, Config, ConfigBuilder, DisplayStyle, FileName,
+    LabelMessagePlacement, LineNumberAlignment, RenderWhitespace, SecondaryUnderlineStyle,
+    SeverityCarets, SeverityName, SeverityOrder,
+};
 
 #[cfg(feature = "termcolor")]
-pub use self::config::Styles;
+pub use self::config::{
+    color_choice, downgrade_color_spec, RgbTheme, SourceStyle, StyleAttr, Styles,
+};
 
 #[cfg(feature = "termcolor")]
 pub use config::StylesWriter;
 
-pub use self::renderer::WriteStyle;
+pub use self::display::DiagnosticDisplay;
+pub use self::recording::{Segment, StyleRole, StyledOutput};
+pub use self::renderer::{PlainWriter, TestWriter, WriteStyle};
 
+use self::recording::RecordingWriter;
 pub use self::renderer::Renderer;
-pub use self::views::{RichDiagnostic, ShortDiagnostic};
+use self::renderer::{caret_repeats, char_width, SliceWriter, WidthWriter};
+pub use self::views::{
+    GitHubActionsDiagnostic, GrepDiagnostic, HexdumpDiagnostic, MarkdownDiagnostic, RichDiagnostic,
+    ShortDiagnostic,
+};
 
 /// Emit a diagnostic using the given writer, context, config, and files.
 ///
@@ -29,29 +52,798 @@ pub use self::views::{RichDiagnostic, ShortDiagnostic};
 /// * a file was removed from the file database.
 /// * a file was changed so that it is too small to have an index
 /// * IO fails
+///
+/// The source of the files is only read through [`Files::line_source`], for
+/// the lines that are displayed, so `files` does not need to hold the whole
+/// source of large files in memory.
 pub fn emit<'files, F: Files<'files> + ?Sized, W: WriteStyle>(
     writer: &mut W,
     config: &Config,
     files: &'files F,
     diagnostic: &Diagnostic<F::FileId>,
 ) -> Result<(), super::files::Error> {
-    let mut renderer = Renderer::new(writer, config);
-    match config.display_style {
-        DisplayStyle::Rich => RichDiagnostic::new(diagnostic, config).render(files, &mut renderer),
-        DisplayStyle::Medium => ShortDiagnostic::new(diagnostic, true).render(files, &mut renderer),
-        DisplayStyle::Short => ShortDiagnostic::new(diagnostic, false).render(files, &mut renderer),
+    Renderer::new(writer, config).render(files, diagnostic)
+}
+
+impl Renderer<'_, '_> {
+    /// Render a diagnostic in the [`Config::display_style`] of the renderer's
+    /// config.
+    ///
+    /// The renderer keeps the buffers that it lays out diagnostics in, so
+    /// rendering several diagnostics with the same renderer, as [`emit_batch`]
+    /// does, reuses their allocations.
+    ///
+    /// ```rust
+    /// use codespan_reporting::diagnostic::{Diagnostic, Label};
+    /// use codespan_reporting::files::SimpleFile;
+    /// use codespan_reporting::term::{Config, PlainWriter, Renderer};
+    ///
+    /// let file = SimpleFile::new("test", "let x = 1;\nlet y = 2;\n");
+    /// let diagnostics = [
+    ///     Diagnostic::warning().with_labels(vec![Label::primary((), 4..5)]),
+    ///     Diagnostic::warning().with_labels(vec![Label::primary((), 15..16)]),
+    /// ];
+    ///
+    /// let config = Config::default();
+    /// let mut writer = PlainWriter::new(Vec::new());
+    /// let mut renderer = Renderer::new(&mut writer, &config);
+    /// for diagnostic in &diagnostics {
+    ///     renderer.render(&file, diagnostic)?;
+    /// }
+    /// # Ok::<(), codespan_reporting::files::Error>(())
+    /// ```
+    ///
+    /// See [`emit`] for the possible errors.
+    pub fn render<'files, F: Files<'files> + ?Sized>(
+        &mut self,
+        files: &'files F,
+        diagnostic: &Diagnostic<F::FileId>,
+    ) -> Result<(), super::files::Error> {
+        // Labels with invalid ranges are clamped, with a warning, instead of
+        // failing or rendering garbled output
+        let clamped = diagnostic.with_clamped_labels(files);
+        let diagnostic = clamped.as_ref().unwrap_or(diagnostic);
+
+        let config = self.config();
+        match config.display_style {
+            DisplayStyle::Rich => RichDiagnostic::new(diagnostic, config).render(files, self),
+            DisplayStyle::Medium => ShortDiagnostic::new(diagnostic, true).render(files, self),
+            DisplayStyle::Short => ShortDiagnostic::new(diagnostic, false).render(files, self),
+            DisplayStyle::Markdown => {
+                MarkdownDiagnostic::new(diagnostic, config).render(files, self)
+            }
+            DisplayStyle::GitHubActions => {
+                GitHubActionsDiagnostic::new(diagnostic).render(files, self)
+            }
+            DisplayStyle::Grep => GrepDiagnostic::new(diagnostic).render(files, self),
+        }
     }
 }
 
+/// Emit a diagnostic into a string, without any styling, using the given
+/// config and files.
+///
+/// See [`emit`] for the possible errors.
+pub fn to_string<'files, F: Files<'files> + ?Sized>(
+    config: &Config,
+    files: &'files F,
+    diagnostic: &Diagnostic<F::FileId>,
+) -> Result<String, super::files::Error> {
+    Renderer::render_to_string(config, |renderer| renderer.render(files, diagnostic))
+}
+
+/// Emit a diagnostic into a string, styled with ANSI escape sequences in the
+/// default [`Styles`], using the given config and files.
+///
+/// See [`emit`] for the possible errors.
+#[cfg(feature = "termcolor")]
+pub fn to_ansi_string<'files, F: Files<'files> + ?Sized>(
+    config: &Config,
+    files: &'files F,
+    diagnostic: &Diagnostic<F::FileId>,
+) -> Result<String, super::files::Error> {
+    let mut writer = termcolor::Ansi::new(alloc::vec::Vec::new());
+    emit(&mut writer, config, files, diagnostic)?;
+    Ok(String::from_utf8(writer.into_inner()).expect("rendered output is not valid UTF-8"))
+}
+
+/// Record the output that [`emit`] would write for a diagnostic, using the
+/// given config and files, instead of writing it to a particular back-end.
+///
+/// The resulting [`StyledOutput`] holds the rendered text split into segments,
+/// each tagged with the [`StyleRole`] it is styled for, which can be used to
+/// display the diagnostic in a custom back-end, such as a GUI. It can also be
+/// written to any [`WriteStyle`] later with [`StyledOutput::emit`], which
+/// styles it in the same way as [`emit`].
+///
+/// See [`emit`] for the possible errors.
+pub fn record<'files, F: Files<'files> + ?Sized>(
+    config: &Config,
+    files: &'files F,
+    diagnostic: &Diagnostic<F::FileId>,
+) -> Result<StyledOutput, super::files::Error> {
+    let mut writer = RecordingWriter::new();
+    emit(&mut writer, config, files, diagnostic)?;
+    Ok(writer.into_output())
+}
+
+/// Emit a diagnostic with only one of its labels, the one at `label_index` in
+/// [`Diagnostic::labels`], using the given writer, config, and files.
+///
+/// This renders the header of the diagnostic and the snippet containing the
+/// label, without any of the other labels or the notes, which is useful for
+/// stepping through the labels of a diagnostic one at a time.
+///
+/// Returns [`Error::LabelMissing`] if the diagnostic does not have a label at
+/// `label_index`. See [`emit`] for the other possible errors.
+///
+/// [`Error::LabelMissing`]: super::files::Error::LabelMissing
+pub fn render_label<'files, F: Files<'files> + ?Sized, W: WriteStyle>(
+    writer: &mut W,
+    config: &Config,
+    files: &'files F,
+    diagnostic: &Diagnostic<F::FileId>,
+    label_index: usize,
+) -> Result<(), super::files::Error> {
+    let label = diagnostic
+        .labels
+        .get(label_index)
+        .ok_or(super::files::Error::LabelMissing {
+            given: label_index,
+            len: diagnostic.labels.len(),
+        })?;
+    let focused = Diagnostic {
+        severity: diagnostic.severity,
+        code: diagnostic.code.clone(),
+        message: diagnostic.message.clone(),
+        message_args: diagnostic.message_args.clone(),
+        labels: alloc::vec![label.clone()],
+        notes: alloc::vec::Vec::new(),
+    };
+    emit(writer, config, files, &focused)
+}
+
+/// Emit a diagnostic about binary data, such as a file that is not valid
+/// UTF-8, using the given writer and config. The labels of the diagnostic are
+/// byte ranges of `bytes`, which are shown in a hex dump of 16 bytes per row,
+/// with the offset of each row, and the bytes as ASCII on the right:
+///
+/// ```text
+/// error: invalid magic number
+///          │
+/// 00000000 │ 7f 45 4c 47 02 01 01 00 00 00 00 00 00 00 00 00 │.ELG............│
+///          │    ^^^^^^^^ expected `ELF`
+/// ```
+///
+/// Only the rows containing labels are shown, and the rows in between them
+/// are skipped. The [`Config::display_style`] is ignored.
+///
+/// Returns [`Error::ReversedRange`] if the range of a label ends before it
+/// starts, [`Error::IndexTooLarge`] if a label extends past the end of
+/// `bytes`, or an error if writing fails.
+///
+/// [`Error::ReversedRange`]: super::files::Error::ReversedRange
+/// [`Error::IndexTooLarge`]: super::files::Error::IndexTooLarge
+pub fn render_hexdump<W: WriteStyle>(
+    writer: &mut W,
+    config: &Config,
+    bytes: &[u8],
+    diagnostic: &Diagnostic<()>,
+) -> Result<(), super::files::Error> {
+    HexdumpDiagnostic::new(diagnostic, bytes).render(&mut Renderer::new(writer, config))
+}
+
+/// Measure the width of the widest line that [`emit`] would write for the
+/// diagnostic, in terminal columns, without writing it anywhere.
+///
+/// This accounts for the expansion of tabs and the width of Unicode
+/// characters, and can be used to size the area that diagnostics are displayed
+/// in. Tabs that are written as is, such as in notes, are measured up to the
+/// next tab stop of the terminal, every 8 columns. See [`emit`] for the
+/// possible errors.
+pub fn measured_width<'files, F: Files<'files> + ?Sized>(
+    config: &Config,
+    files: &'files F,
+    diagnostic: &Diagnostic<F::FileId>,
+) -> Result<usize, super::files::Error> {
+    let mut writer = WidthWriter::default();
+    emit(&mut writer, config, files, diagnostic)?;
+    Ok(writer.max_width())
+}
+
+/// Emit a diagnostic in the [`DisplayStyle::Short`] style, without styling,
+/// into the given buffer, returning the number of bytes written.
+///
+/// This is meant for targets with little memory to collect the output in,
+/// such as embedded devices, where `buf` can be a pre-allocated buffer. The
+/// output is written straight into `buf`, but rendering still makes a few
+/// small allocations that do not depend on the length of the output, such as
+/// for the name of the file and the source text of the
+/// [`Diagnostic::message_args`], so an allocator is still required.
+///
+/// If the output does not fit into `buf`, it is truncated: it is cut at the
+/// last whole character that fits, so `&buf[..len]` is always valid UTF-8,
+/// and the rest of the output is discarded. The [`Config::display_style`] is
+/// ignored.
+///
+/// See [`emit`] for the possible errors.
+pub fn write_short<'files, F: Files<'files> + ?Sized>(
+    buf: &mut [u8],
+    config: &Config,
+    files: &'files F,
+    diagnostic: &Diagnostic<F::FileId>,
+) -> Result<usize, super::files::Error> {
+    let mut writer = SliceWriter::new(buf);
+    let mut renderer = Renderer::new(&mut writer, config);
+    ShortDiagnostic::new(diagnostic, false).render(files, &mut renderer)?;
+    Ok(writer.len())
+}
+
+/// The columns that the given byte range of a source line takes up when it is
+/// rendered, as a `(start, end)` pair of zero-based columns, where the end is
+/// exclusive. This is the same computation that places the carets underneath
+/// a label.
+///
+/// Tabs are expanded to the next multiple of `tab_width` columns, and the
+/// width of other characters is their Unicode width, except for control
+/// characters, which take up one column like when they are replaced by
+/// [`Chars::control_glyph`]. Characters that are partially covered by the
+/// range are counted as a whole, so the columns always cover every character
+/// that overlaps the range. An empty range takes up no columns, and starts
+/// at the character containing it. Ranges past the end of the line are
+/// clamped to it.
+///
+/// ```rust
+/// use codespan_reporting::term::byte_range_to_columns;
+///
+/// assert_eq!(byte_range_to_columns("\tlet x = 1;", 5..6, 4), (8, 9));
+/// // `é` is two bytes long, but one column wide
+/// assert_eq!(byte_range_to_columns("café au lait", 3..4, 4), (3, 4));
+/// // `日` is three bytes long, but two columns wide
+/// assert_eq!(byte_range_to_columns("日本", 3..6, 4), (2, 4));
+/// ```
+pub fn byte_range_to_columns(line: &str, range: Range<usize>, tab_width: usize) -> (usize, usize) {
+    let mut start = None;
+    let mut column = 0;
+    for (byte_index, ch) in line.char_indices() {
+        let char_end = byte_index + ch.len_utf8();
+        if start.is_none() && (range.start < char_end) {
+            start = Some(column);
+        }
+        if byte_index >= range.end && start.is_some() {
+            break;
+        }
+        column += char_width(ch, column, tab_width, true);
+    }
+
+    let start = start.unwrap_or(column);
+    match range.is_empty() {
+        true => (start, start),
+        false => (start, column),
+    }
+}
+
+/// Render the carets of a label on its own, without the source line or the
+/// gutter, so that they can be placed underneath a line that is displayed
+/// elsewhere, such as in an editor:
+///
+/// ```text
+///         ^^ expected `Int`
+/// ```
+///
+/// The carets are aligned to `range`, a byte range within `line`, as with
+/// [`byte_range_to_columns`], using [`Config::tab_width`] to expand tabs.
+/// Empty ranges are marked with a single caret. The carets are drawn with
+/// [`Chars::single_primary_caret`], repeated as often as it fits, and
+/// followed by the message if it is not empty. The returned string has no
+/// trailing newline.
+///
+/// ```rust
+/// use codespan_reporting::term::{render_carets, Config};
+///
+/// let carets = render_carets("\tlet x = 1;", 5..6, "unused", &Config::default());
+/// assert_eq!(carets, "        ^ unused");
+/// ```
+pub fn render_carets(line: &str, range: Range<usize>, message: &str, config: &Config) -> String {
+    let (start, end) = byte_range_to_columns(line, range, config.tab_width);
+    let caret = &config.chars.single_primary_caret;
+    let (caret_count, padding) = caret_repeats(caret, end - start);
+
+    let mut output = String::new();
+    (0..start).for_each(|_| output.push(' '));
+    (0..caret_count).for_each(|_| output.push_str(caret));
+    if !message.is_empty() {
+        (0..padding).for_each(|_| output.push(' '));
+        output.push(' ');
+        output.push_str(message);
+    }
+    output
+}
+
+/// Emit a batch of diagnostics in order, using the given writer, config, and
+/// files.
+///
+/// If [`Config::deduplicate`] is enabled, runs of adjacent diagnostics with the
+/// same severity, code, message, and label locations are emitted once, with the
+/// number of diagnostics in the run appended to the message:
+///
+/// ```text
+/// error: expected expression (repeated 147 times)
+/// ```
+///
+/// Diagnostics with a severity below [`Config::min_severity`] are skipped, as
+/// are all but the first diagnostic with [`Config::first_only`]. If
+/// [`Config::emit_summary`] is enabled, the batch is followed by a summary of
+/// the number of errors and warnings that were emitted.
+///
+/// See [`emit`] for the possible errors.
+pub fn emit_batch<'files, F: Files<'files> + ?Sized, W: WriteStyle>(
+    writer: &mut W,
+    config: &Config,
+    files: &'files F,
+    diagnostics: &[Diagnostic<F::FileId>],
+) -> Result<(), super::files::Error> {
+    emit_batch_inner(writer, config, files, diagnostics, None)
+}
+
+/// Emit a batch of diagnostics like [`emit_batch`], calling `on_rendered`
+/// after each diagnostic is written, for side effects such as updating a
+/// progress bar.
+///
+/// With the `std` feature, the writer is flushed before `on_rendered` is
+/// called, so that the diagnostic is visible by then. Diagnostics that are
+/// skipped are not passed to `on_rendered`, and runs of diagnostics collapsed
+/// by [`Config::deduplicate`] are passed once, as the first diagnostic of the
+/// run.
+///
+/// See [`emit`] for the possible errors.
+pub fn emit_batch_with<'files, F, W, C>(
+    writer: &mut W,
+    config: &Config,
+    files: &'files F,
+    diagnostics: &[Diagnostic<F::FileId>],
+    mut on_rendered: C,
+) -> Result<(), super::files::Error>
+where
+    F: Files<'files> + ?Sized,
+    W: WriteStyle,
+    C: FnMut(&Diagnostic<F::FileId>),
+{
+    emit_batch_inner(writer, config, files, diagnostics, Some(&mut on_rendered))
+}
+
+/// The callback of [`emit_batch_with`], if any.
+type OnRendered<'a, FileId> = Option<&'a mut dyn FnMut(&Diagnostic<FileId>)>;
+
+fn emit_batch_inner<'files, F: Files<'files> + ?Sized, W: WriteStyle>(
+    writer: &mut W,
+    config: &Config,
+    files: &'files F,
+    diagnostics: &[Diagnostic<F::FileId>],
+    on_rendered: OnRendered<'_, F::FileId>,
+) -> Result<(), super::files::Error> {
+    match &config.severity_order {
+        Some(order) => {
+            let mut sorted = diagnostics.iter().collect::<Vec<_>>();
+            sorted.sort_by_key(|diagnostic| order.rank(diagnostic.severity));
+            emit_sorted_batch(writer, config, files, &sorted, on_rendered)
+        }
+        None => emit_sorted_batch(writer, config, files, diagnostics, on_rendered),
+    }
+}
+
+/// Emit a batch of diagnostics for [`emit_batch`] that is already sorted by
+/// [`Config::severity_order`], grouping them if [`Config::group_by_file`] is
+/// enabled.
+fn emit_sorted_batch<'files, F, W, D>(
+    writer: &mut W,
+    config: &Config,
+    files: &'files F,
+    diagnostics: &[D],
+    mut on_rendered: OnRendered<'_, F::FileId>,
+) -> Result<(), super::files::Error>
+where
+    F: Files<'files> + ?Sized,
+    W: WriteStyle,
+    D: Borrow<Diagnostic<F::FileId>>,
+{
+    // The renderer is shared, so that its buffers are reused between diagnostics
+    let renderer = &mut Renderer::new(writer, config);
+    let mut summary = Summary::default();
+    if !config.group_by_file {
+        emit_run(
+            renderer,
+            config,
+            files,
+            diagnostics,
+            &mut summary,
+            &mut on_rendered,
+        )?;
+        return summary.emit(renderer, config);
+    }
+
+    let mut groups = Vec::<(Option<F::FileId>, Vec<_>)>::new();
+    for diagnostic in diagnostics {
+        let diagnostic = diagnostic.borrow();
+        if is_suppressed(config, &summary, diagnostic) {
+            summary.suppressed += 1;
+            continue;
+        }
+        let file_id = primary_label(diagnostic).map(|label| label.file_id);
+        match groups.iter_mut().find(|(other, _)| *other == file_id) {
+            Some((_, group)) => group.push(diagnostic),
+            None => groups.push((file_id, alloc::vec![diagnostic])),
+        }
+    }
+    // Diagnostics without labels are emitted last, without a file header
+    groups.sort_by_key(|(file_id, _)| file_id.is_none());
+
+    for (file_id, mut group) in groups {
+        if config.first_only && summary.emitted > 0 {
+            summary.suppressed += group.len();
+            continue;
+        }
+        group.sort_by_key(|diagnostic| {
+            let rank = (config.severity_order).map(|order| order.rank(diagnostic.severity));
+            (
+                rank,
+                primary_label(diagnostic).map(|label| label.range.start),
+            )
+        });
+        if let Some(file_id) = file_id {
+            if is_human_readable(config) {
+                summary.emit_separator(renderer, config)?;
+                let name = files.name(file_id)?.to_string();
+                renderer.render_file_header(&name)?;
+                summary.at_group_start = true;
+            }
+        }
+        emit_run(
+            renderer,
+            config,
+            files,
+            &group,
+            &mut summary,
+            &mut on_rendered,
+        )?;
+    }
+    summary.emit(renderer, config)
+}
+
+/// Emit a run of diagnostics for [`emit_batch`], deduplicating them if
+/// [`Config::deduplicate`] is enabled.
+fn emit_run<'files, F, D>(
+    renderer: &mut Renderer<'_, '_>,
+    config: &Config,
+    files: &'files F,
+    diagnostics: &[D],
+    summary: &mut Summary,
+    on_rendered: &mut OnRendered<'_, F::FileId>,
+) -> Result<(), super::files::Error>
+where
+    F: Files<'files> + ?Sized,
+    D: Borrow<Diagnostic<F::FileId>>,
+{
+    if !config.deduplicate {
+        for diagnostic in diagnostics {
+            let diagnostic = diagnostic.borrow();
+            if is_suppressed(config, summary, diagnostic) {
+                summary.suppressed += 1;
+                continue;
+            }
+            summary.emit_separator(renderer, config)?;
+            renderer.render(files, diagnostic)?;
+            summary.count(diagnostic);
+            notify_rendered(renderer, on_rendered, diagnostic)?;
+        }
+        return Ok(());
+    }
+
+    let mut remaining = diagnostics;
+    while let Some(diagnostic) = remaining.first() {
+        let diagnostic = diagnostic.borrow();
+        let count = remaining
+            .iter()
+            .take_while(|other| is_repeated(diagnostic, (*other).borrow()))
+            .count();
+        remaining = &remaining[count..];
+
+        if is_suppressed(config, summary, diagnostic) {
+            summary.suppressed += count;
+            continue;
+        }
+        summary.emit_separator(renderer, config)?;
+        if count == 1 {
+            renderer.render(files, diagnostic)?;
+        } else {
+            let mut diagnostic = diagnostic.clone();
+            diagnostic.message = format!("{} (repeated {count} times)", diagnostic.message);
+            renderer.render(files, &diagnostic)?;
+        }
+        summary.count(diagnostic);
+        notify_rendered(renderer, on_rendered, diagnostic)?;
+    }
+    Ok(())
+}
+
+/// Call the callback of [`emit_batch_with`] for a diagnostic that was just
+/// written, flushing the writer first.
+fn notify_rendered<FileId>(
+    #[cfg_attr(not(feature = "std"), allow(unused_variables))] renderer: &mut Renderer<'_, '_>,
+    on_rendered: &mut OnRendered<'_, FileId>,
+    diagnostic: &Diagnostic<FileId>,
+) -> Result<(), super::files::Error> {
+    if let Some(on_rendered) = on_rendered {
+        #[cfg(feature = "std")]
+        std::io::Write::flush(renderer)?;
+        on_rendered(diagnostic);
+    }
+    Ok(())
+}
+
+/// The label that decides which file a diagnostic is grouped under by
+/// [`Config::group_by_file`]: the first primary label, or the first label if
+/// there are no primary labels.
+fn primary_label<FileId>(diagnostic: &Diagnostic<FileId>) -> Option<&Label<FileId>> {
+    let labels = &diagnostic.labels;
+    (labels.iter())
+        .find(|label| label.style == crate::diagnostic::LabelStyle::Primary)
+        .or_else(|| labels.first())
+}
+
+/// Emit diagnostics as they are produced by an iterator, using the given
+/// writer, config, and files.
+///
+/// Unlike [`emit_batch`], this does not need all of the diagnostics up front,
+/// so they can be reported while a compilation is still running. With the
+/// `std` feature, the writer is flushed after each diagnostic, so that
+/// interactive consumers see it right away. Diagnostics are not deduplicated,
+/// but diagnostics with a severity below [`Config::min_severity`] are skipped,
+/// as are all but the first diagnostic with [`Config::first_only`].
+///
+/// See [`emit`] for the possible errors.
+pub fn render_stream<'files, F, W, I>(
+    writer: &mut W,
+    config: &Config,
+    files: &'files F,
+    diagnostics: I,
+) -> Result<(), super::files::Error>
+where
+    F: Files<'files> + ?Sized,
+    W: WriteStyle,
+    I: IntoIterator,
+    I::Item: Borrow<Diagnostic<F::FileId>>,
+{
+    let renderer = &mut Renderer::new(writer, config);
+    let mut summary = Summary::default();
+    for diagnostic in diagnostics {
+        let diagnostic = diagnostic.borrow();
+        if is_suppressed(config, &summary, diagnostic) {
+            summary.suppressed += 1;
+            continue;
+        }
+        summary.emit_separator(renderer, config)?;
+        renderer.render(files, diagnostic)?;
+        summary.count(diagnostic);
+        #[cfg(feature = "std")]
+        std::io::Write::flush(renderer)?;
+    }
+
+    summary.emit(renderer, config)
+}
+
+/// Whether the diagnostic is skipped by [`emit_batch`] and [`render_stream`],
+/// because its severity is below [`Config::min_severity`], or because a
+/// diagnostic was already emitted with [`Config::first_only`].
+fn is_suppressed<FileId>(
+    config: &Config,
+    summary: &Summary,
+    diagnostic: &Diagnostic<FileId>,
+) -> bool {
+    matches!(config.min_severity, Some(min_severity) if diagnostic.severity < min_severity)
+        || (config.first_only && summary.emitted > 0)
+}
+
+/// Whether the display style is meant to be read by people, rather than by
+/// other tools.
+fn is_human_readable(config: &Config) -> bool {
+    matches!(
+        config.display_style,
+        DisplayStyle::Rich | DisplayStyle::Medium | DisplayStyle::Short,
+    )
+}
+
+/// The number of diagnostics emitted in a batch, including the number of
+/// errors and warnings, and the number of diagnostics suppressed by
+/// [`Config::min_severity`] and [`Config::first_only`], for [`Config::emit_summary`].
+#[derive(Default)]
+struct Summary {
+    emitted: usize,
+    /// Whether a file header was just written by [`Config::group_by_file`],
+    /// which is already separated from the previous diagnostic.
+    at_group_start: bool,
+    errors: usize,
+    warnings: usize,
+    suppressed: usize,
+}
+
+impl Summary {
+    /// Write the [`Config::diagnostic_separator`] if a diagnostic was already
+    /// emitted.
+    fn emit_separator(
+        &self,
+        renderer: &mut Renderer<'_, '_>,
+        config: &Config,
+    ) -> Result<(), super::files::Error> {
+        match config.diagnostic_separator {
+            Some(separator)
+                if self.emitted > 0 && !self.at_group_start && is_human_readable(config) =>
+            {
+                let width = config.max_line_width.unwrap_or(80);
+                renderer.render_separator(separator, width)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn count<FileId>(&mut self, diagnostic: &Diagnostic<FileId>) {
+        self.emitted += 1;
+        self.at_group_start = false;
+        match diagnostic.severity {
+            Severity::Bug | Severity::Error => self.errors += 1,
+            Severity::Warning => self.warnings += 1,
+            Severity::Note | Severity::Help => {}
+        }
+    }
+
+    fn emit(
+        &self,
+        renderer: &mut Renderer<'_, '_>,
+        config: &Config,
+    ) -> Result<(), super::files::Error> {
+        if config.emit_summary && is_human_readable(config) {
+            renderer.render_summary(self.errors, self.warnings, self.suppressed)?;
+        }
+        Ok(())
+    }
+}
+
+/// Whether two diagnostics are considered the same by [`Config::deduplicate`].
+fn is_repeated<FileId: PartialEq>(first: &Diagnostic<FileId>, other: &Diagnostic<FileId>) -> bool {
+    first.severity == other.severity
+        && first.code == other.code
+        && first.message == other.message
+        && first.labels.len() == other.labels.len()
+        && (first.labels.iter().zip(&other.labels)).all(|(first, other)| {
+            first.style == other.style
+                && first.file_id == other.file_id
+                && first.range == other.range
+        })
+}
+
+/// Render the source lines covered by `range`, underlining it as a primary
+/// label, without any styling.
+///
+/// This produces just the gutter-and-caret block of a rich diagnostic, with
+/// the same layout and context lines as [`emit`] would use for `config`:
+///
+/// ```text
+/// 2 │ (+ test "")
+///   │         ^^
+/// ```
+///
+/// See [`emit`] for the possible errors.
+pub fn render_snippet<'files, F: Files<'files> + ?Sized>(
+    files: &'files F,
+    file_id: F::FileId,
+    range: Range<usize>,
+    config: &Config,
+) -> Result<String, super::files::Error> {
+    let diagnostic = Diagnostic::note().with_labels(alloc::vec![Label::primary(file_id, range)]);
+    Renderer::render_to_string(config, |renderer| {
+        RichDiagnostic::new(&diagnostic, config)
+            .without_header()
+            .without_locus()
+            .render(files, renderer)
+    })
+}
+
+/// Lay out two rendered snippets, such as the code before and after a fix, side
+/// by side, in columns divided by [`Chars::multi_left`]:
+///
+/// ```text
+/// 2 │ (+ test "") │ 2 │ (+ test 0)
+///   │         ^^  │   │         ^
+/// ```
+///
+/// The snippets are usually rendered by [`render_snippet`], and the shorter
+/// one is padded with empty lines. If the columns would be wider than
+/// [`Config::max_line_width`], the right snippet is placed below the left one
+/// instead, separated by a line of [`Chars::multi_top`].
+pub fn render_side_by_side(left: &str, right: &str, config: &Config) -> String {
+    use unicode_width::UnicodeWidthStr;
+
+    let column_width = |snippet: &str| snippet.lines().map(|line| line.width()).max();
+    let left_width = column_width(left).unwrap_or(0);
+    let right_width = column_width(right).unwrap_or(0);
+    let divider = config.chars.multi_left;
+
+    let mut output = String::new();
+    let total_width = left_width + 3 + right_width;
+    if matches!(config.max_line_width, Some(max_line_width) if total_width > max_line_width) {
+        output.push_str(left);
+        let separator_width = core::cmp::max(left_width, right_width);
+        (0..separator_width).for_each(|_| output.push(config.chars.multi_top));
+        output.push('\n');
+        output.push_str(right);
+        return output;
+    }
+
+    let mut left_lines = left.lines();
+    let mut right_lines = right.lines();
+    loop {
+        let (left_line, right_line) = match (left_lines.next(), right_lines.next()) {
+            (None, None) => break,
+            (left_line, right_line) => (left_line.unwrap_or(""), right_line.unwrap_or("")),
+        };
+        let padding = left_width - left_line.width();
+        let row = format!("{left_line}{:padding$} {divider} {right_line}", "");
+        output.push_str(row.trim_end());
+        output.push('\n');
+    }
+    output
+}
+
 #[cfg(all(test, feature = "termcolor"))]
 mod tests {
-    use alloc::{vec, vec::Vec};
+    use alloc::{string::ToString, vec, vec::Vec};
 
     use super::*;
 
     use crate::diagnostic::Label;
     use crate::files::SimpleFiles;
 
+    #[test]
+    fn byte_range_to_columns_partial_chars() {
+        // `é` is the bytes 3..5
+        let line = "café\tau lait";
+
+        assert_eq!(byte_range_to_columns(line, 4..5, 4), (3, 4));
+        assert_eq!(byte_range_to_columns(line, 2..4, 4), (2, 4));
+        assert_eq!(byte_range_to_columns(line, 4..4, 4), (3, 3));
+        assert_eq!(byte_range_to_columns(line, 5..6, 4), (4, 8));
+        assert_eq!(byte_range_to_columns(line, 5..6, 0), (4, 4));
+        assert_eq!(byte_range_to_columns(line, 6..100, 4), (8, 15));
+        assert_eq!(byte_range_to_columns(line, 100..100, 4), (15, 15));
+        assert_eq!(byte_range_to_columns("a\x1b[0mb", 1..5, 4), (1, 5));
+    }
+
+    #[test]
+    fn byte_range_to_columns_tabs_after_partial_columns() {
+        // A tab advances to the next tab stop, so it is narrower after text
+        // that does not end at a tab stop
+        assert_eq!(byte_range_to_columns("ab\tc", 3..4, 4), (4, 5));
+        assert_eq!(byte_range_to_columns("abcd\tc", 5..6, 4), (8, 9));
+        assert_eq!(byte_range_to_columns("abcde\tc", 6..7, 4), (8, 9));
+    }
+
+    #[test]
+    fn carets_without_source() {
+        let config = Config::default();
+
+        assert_eq!(
+            render_carets("(+ test \"\")", 8..10, "expected `Int`", &config),
+            "        ^^ expected `Int`",
+        );
+        assert_eq!(render_carets("\t\tx", 2..3, "", &config), "        ^");
+        assert_eq!(render_carets("日本", 3..3, "here", &config), "  ^ here");
+
+        let mut config = config;
+        config.chars.single_primary_caret = "👉".into();
+        assert_eq!(render_carets("let x = 10;", 4..9, "", &config), "    👉👉");
+        assert_eq!(render_carets("let x = 10;", 4..5, "", &config), "    👉");
+    }
+
     #[test]
     fn unsized_emit() {
         let mut files = SimpleFiles::new();
@@ -62,4 +854,63 @@ mod tests {
 
         emit(&mut writer, &Config::default(), &files, &diagnostic).unwrap();
     }
+
+    #[test]
+    fn invalid_label_ranges() {
+        let mut files = SimpleFiles::new();
+        let id = files.add("test", "let é = 1;");
+        let validate = |range: Range<usize>| {
+            let diagnostic = Diagnostic::error()
+                .with_labels(vec![Label::secondary(id, 0..3), Label::primary(id, range)]);
+            diagnostic
+                .validate(&files)
+                .map_err(|error| error.to_string())
+        };
+
+        assert!(validate(4..6).is_ok());
+        assert!(validate(11..11).is_ok());
+        assert_eq!(
+            validate(4..40).unwrap_err(),
+            "invalid range of label 1: invalid index 40, maximum index is 11",
+        );
+        assert_eq!(
+            validate(5..6).unwrap_err(),
+            "invalid range of label 1: index is not a code point boundary",
+        );
+        assert_eq!(
+            validate(Range { start: 8, end: 4 }).unwrap_err(),
+            "invalid range of label 1: invalid range 8..4, the start is after the end",
+        );
+    }
+
+    #[test]
+    fn measured_width_matches_output() {
+        use unicode_width::UnicodeWidthStr;
+
+        let mut files = SimpleFiles::new();
+        let id = files.add("main.fun", "\tlet 名前 = (+ 1 \"two\")\n");
+        let diagnostic = Diagnostic::error()
+            .with_code("E0001")
+            .with_message("unexpected type in `+` application")
+            .with_labels(vec![
+                Label::primary(id, 19..24).with_message("expected `Int`, found `String`"),
+                Label::secondary(id, 5..11).with_message("in this binding"),
+            ])
+            .with_notes(vec!["expected type `Int`".into()]);
+
+        for display_style in [DisplayStyle::Rich, DisplayStyle::Short] {
+            let config = Config {
+                display_style,
+                ..Config::default()
+            };
+
+            let output = to_string(&config, &files, &diagnostic).unwrap();
+            let expected = output.lines().map(str::width).max().unwrap();
+
+            assert_eq!(
+                measured_width(&config, &files, &diagnostic).unwrap(),
+                expected
+            );
+        }
+    }
 }
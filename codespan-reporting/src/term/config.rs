@@ -1,4 +1,7 @@
-use alloc::string::String;
+use alloc::{borrow::Cow, string::String, sync::Arc};
+use core::fmt;
+
+use crate::diagnostic::{LabelStyle, Severity};
 
 #[cfg(feature = "termcolor")]
 use termcolor::WriteColor;
@@ -8,13 +11,18 @@ use super::renderer::WriteStyle;
 
 #[cfg(feature = "termcolor")]
 use {
-    crate::diagnostic::{LabelStyle, Severity},
-    termcolor::{Color, ColorSpec},
+    std::collections::HashMap,
+    termcolor::{Color, ColorChoice, ColorSpec, HyperlinkSpec},
 };
 
 #[cfg(not(feature = "std"))]
 use core::fmt::{Arguments, Result as WriteResult, Write};
 
+#[cfg(feature = "serialization")]
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "std")]
+use std::ffi::OsString;
 #[cfg(feature = "std")]
 use std::io;
 
@@ -26,7 +34,10 @@ pub struct Config {
     ///
     /// [`DisplayStyle::Rich`]: DisplayStyle::Rich
     pub display_style: DisplayStyle,
-    /// Column width of tabs.
+    /// The interval of the tab stops that tabs in source lines advance to.
+    /// Like in editors and terminals, a tab is expanded up to the next
+    /// multiple of this many columns, counted from the start of the line,
+    /// so a tab after a partial column is narrower than `tab_width`.
     /// Defaults to: `4`.
     pub tab_width: usize,
 
@@ -52,6 +63,378 @@ pub struct Config {
     ///
     /// Defaults to: `0`.
     pub after_label_lines: usize,
+    /// The maximum number of lines between two rendered regions of a source
+    /// snippet that are rendered instead of a break, merging the regions into
+    /// one. A single line is always rendered, since a break would take up just
+    /// as much space.
+    ///
+    /// Defaults to: `0`.
+    pub context_merge_threshold: usize,
+    /// Whether to render the locus of source snippets as a terminal hyperlink,
+    /// using the [OSC 8] escape sequence.
+    ///
+    /// The URL of a location is given by [`Files::location_url`], which gives
+    /// none by default. A [`HyperlinkedFiles`] links the locations to URLs of
+    /// its own, or to the files with their names as paths. Hyperlinks are only
+    /// emitted if the writer supports them, as reported by
+    /// `WriteColor::supports_hyperlinks`. Other writers receive the plain
+    /// locus.
+    ///
+    /// Defaults to: `false`.
+    ///
+    /// [OSC 8]: https://gist.github.com/egmontkob/eb114294efbcd5adb1944c9f3cb5feda
+    /// [`Files::location_url`]: crate::files::Files::location_url
+    /// [`HyperlinkedFiles`]: crate::files::HyperlinkedFiles
+    pub render_file_hyperlinks: bool,
+    /// Changes how the name of a file from the [`Files`] database is
+    /// displayed in the locus of a diagnostic, for example to make absolute
+    /// paths relative to the root of a project. The hyperlinks of
+    /// [`Config::render_file_hyperlinks`] are not affected, as they are built
+    /// from the id of the file.
+    ///
+    /// Defaults to: `None`, which displays the names as they are.
+    ///
+    /// [`Files`]: crate::files::Files
+    pub file_name: Option<FileName>,
+    /// The number of spaces to indent every line of the output with,
+    /// including the header, so that diagnostics can be nested inside other
+    /// output. Empty lines are not indented. The margin counts towards
+    /// [`Config::max_line_width`].
+    ///
+    /// Defaults to: `0`.
+    pub left_margin: usize,
+    /// A string to write at the start of every line of the output, including
+    /// empty lines, such as a timestamp, so that diagnostics can be
+    /// interleaved with other logs and still be found with `grep`. The prefix
+    /// is written without styling, before the [`Config::left_margin`], and
+    /// every line is shifted by the same amount, so the carets stay aligned
+    /// with the source. It counts towards [`Config::max_line_width`].
+    ///
+    /// Defaults to: `None`.
+    pub line_prefix: Option<String>,
+    /// Whether to style the bullets of notes like the header of their
+    /// diagnostic, so that they are tinted by its severity, instead of with
+    /// the single style of [`WriteStyle::set_note_bullet`]. Notes with a
+    /// severity of their own are always styled by it.
+    ///
+    /// Defaults to: `false`.
+    ///
+    /// [`WriteStyle::set_note_bullet`]: crate::term::WriteStyle::set_note_bullet
+    pub color_note_bullet_by_severity: bool,
+    /// Whether to leave out the empty border rows that surround the source
+    /// lines of snippets in the [`DisplayStyle::Rich`] style, after the
+    /// locus and before the notes, to make diagnostics more compact:
+    ///
+    /// ```text
+    /// error: unknown builtin: `NATRAL`
+    ///   ┌─ test:1:1
+    /// 1 │ NATRAL
+    ///   │ ^^^^^^ unknown builtin
+    ///   = there is a builtin with a similar name: `NATURAL`
+    /// ```
+    ///
+    /// Defaults to: `false`.
+    pub compact_borders: bool,
+    /// The maximum width of rendered source lines, including the gutter,
+    /// the [`Config::left_margin`], and the [`Config::line_prefix`].
+    ///
+    /// Source lines that are wider than this are wrapped onto continuation
+    /// rows, which repeat the border but not the line number. Labels are
+    /// split between the rows, so that carets stay underneath the source
+    /// they refer to. Tabs count towards the width as expanded using
+    /// [`Config::tab_width`]. At least one character is rendered per row,
+    /// even if the gutter alone is wider than the maximum.
+    ///
+    /// Defaults to: `None`, which never wraps. With the `terminal_size`
+    /// feature, [`Config::auto_width`] sets it to the width of the terminal.
+    pub max_line_width: Option<usize>,
+    /// Whether to highlight the source text covered by labels with a
+    /// background color, in addition to underlining it.
+    ///
+    /// The background is applied on top of the label color, using
+    /// [`WriteStyle::set_span_background`]. Expanded tabs inside of a label are
+    /// highlighted as well.
+    ///
+    /// Defaults to: `false`.
+    ///
+    /// [`WriteStyle::set_span_background`]: crate::term::WriteStyle::set_span_background
+    pub highlight_span_background: bool,
+    /// Styles the source text of snippets by the byte index of each character
+    /// in its file, such as coloring it by the kind of token it is part of.
+    ///
+    /// The style is applied with [`WriteStyle::set_source_style`], and it
+    /// replaces the dimming of [`Config::dim_context_lines`]. The source text
+    /// of primary labels, and of all labels with
+    /// [`Config::highlight_span_background`], keeps the style of the label
+    /// instead, so that spans stand out from the highlighted code.
+    ///
+    /// Defaults to: `None`.
+    ///
+    /// [`WriteStyle::set_source_style`]: crate::term::WriteStyle::set_source_style
+    #[cfg(feature = "termcolor")]
+    pub source_style: Option<SourceStyle>,
+    /// Which whitespace in source lines to render visibly, replacing spaces
+    /// with [`Chars::space_glyph`] and tabs with [`Chars::tab_glyph`].
+    ///
+    /// Defaults to: [`RenderWhitespace::None`].
+    pub render_whitespace: RenderWhitespace,
+    /// Whether [`emit_batch`] collapses runs of adjacent diagnostics that have
+    /// the same severity, code, message, and label locations into the first
+    /// diagnostic of the run, with `(repeated N times)` appended to its
+    /// message.
+    ///
+    /// Defaults to: `false`.
+    ///
+    /// [`emit_batch`]: crate::term::emit_batch
+    pub deduplicate: bool,
+    /// Whether [`emit_batch`] groups the diagnostics by file, writing the name
+    /// of each file once, followed by the diagnostics in it, ordered by the
+    /// start of their labels. Diagnostics are grouped under the file of
+    /// their first primary label, and files are ordered by their first
+    /// diagnostic. Diagnostics without labels are emitted last.
+    ///
+    /// The names of the files are not written with the display styles that
+    /// are meant to be read by other tools, [`DisplayStyle::Markdown`],
+    /// [`DisplayStyle::GitHubActions`] and [`DisplayStyle::Grep`].
+    ///
+    /// Defaults to: `false`.
+    ///
+    /// [`emit_batch`]: crate::term::emit_batch
+    pub group_by_file: bool,
+    /// Whether [`emit_batch`] and [`render_stream`] finish with a summary of
+    /// the number of errors and warnings that they emitted, like rustc:
+    ///
+    /// ```text
+    /// error: aborting due to 3 previous errors; 2 warnings emitted
+    /// ```
+    ///
+    /// Bugs are counted as errors, and runs of diagnostics collapsed by
+    /// [`Config::deduplicate`] are counted once. Nothing is written if there
+    /// are neither errors nor warnings, or with the display styles that are
+    /// meant to be read by other tools, [`DisplayStyle::Markdown`],
+    /// [`DisplayStyle::GitHubActions`] and [`DisplayStyle::Grep`].
+    ///
+    /// Diagnostics suppressed by [`Config::min_severity`] and
+    /// [`Config::first_only`] are counted separately, as
+    /// `4 diagnostics suppressed`.
+    ///
+    /// Defaults to: `false`.
+    ///
+    /// [`emit_batch`]: crate::term::emit_batch
+    /// [`render_stream`]: crate::term::render_stream
+    pub emit_summary: bool,
+    /// The lowest severity of the diagnostics emitted by [`emit_batch`] and
+    /// [`render_stream`], which skip diagnostics with a lower severity. For
+    /// example, with [`Severity::Warning`], notes and help messages are
+    /// skipped.
+    ///
+    /// Defaults to: `None`, which emits all diagnostics.
+    ///
+    /// [`emit_batch`]: crate::term::emit_batch
+    /// [`render_stream`]: crate::term::render_stream
+    pub min_severity: Option<Severity>,
+    /// The order in which [`emit_batch`] emits diagnostics of different
+    /// severities, such as errors before warnings. Diagnostics of the same
+    /// severity keep their order, and with [`Config::group_by_file`], the
+    /// diagnostics of each file are ordered by their severity before their
+    /// location. This does not apply to [`render_stream`], which emits each
+    /// diagnostic as it arrives.
+    ///
+    /// Defaults to: `None`, which emits diagnostics in the order they are
+    /// given in.
+    ///
+    /// [`emit_batch`]: crate::term::emit_batch
+    /// [`render_stream`]: crate::term::render_stream
+    pub severity_order: Option<SeverityOrder>,
+    /// Whether [`emit_batch`] and [`render_stream`] only emit the first
+    /// diagnostic, like `--error-limit=1` in compilers, for failing fast.
+    /// Combined with [`Config::min_severity`], this emits the first diagnostic
+    /// at or above that severity, such as the first error. The diagnostics
+    /// after it are counted as suppressed by [`Config::emit_summary`].
+    ///
+    /// Defaults to: `false`.
+    ///
+    /// [`emit_batch`]: crate::term::emit_batch
+    /// [`render_stream`]: crate::term::render_stream
+    pub first_only: bool,
+    /// The character of a horizontal rule that [`emit_batch`] and
+    /// [`render_stream`] write between diagnostics, styled like the source
+    /// border. The rule spans [`Config::max_line_width`] columns, or 80 if
+    /// there is no maximum, and is not written before the first diagnostic or
+    /// after the last one, nor with the display styles that are meant to be
+    /// read by other tools.
+    ///
+    /// Defaults to: `None`, which separates diagnostics by the empty line that
+    /// ends each of them.
+    ///
+    /// [`emit_batch`]: crate::term::emit_batch
+    /// [`render_stream`]: crate::term::render_stream
+    pub diagnostic_separator: Option<char>,
+    /// Whether to wrap source lines containing right-to-left text in a
+    /// left-to-right override.
+    ///
+    /// Terminals that implement the Unicode bidirectional algorithm display
+    /// right-to-left text, such as Hebrew or Arabic, in reverse, which moves it
+    /// away from the carets that point at it. The override makes them display
+    /// the line in the order the carets are laid out in, which means that
+    /// right-to-left words are displayed with their letters reversed. The
+    /// override is an invisible control character, which other consumers of
+    /// the output may not expect, so it is off by default. Bidirectional
+    /// control characters in the source itself can still affect the display
+    /// of the line.
+    ///
+    /// Defaults to: `false`.
+    pub bidi_override: bool,
+    /// Whether to replace control characters in source lines, other than
+    /// tabs, with [`Chars::control_glyph`].
+    ///
+    /// Terminals interpret control characters instead of displaying them, so
+    /// escape sequences embedded in the source could change the styling of
+    /// the rest of the output, or move the cursor. Replacing them also keeps
+    /// the carets aligned with the source, because each control character is
+    /// then counted as one column wide. Otherwise they are written as is and
+    /// counted as zero columns wide.
+    ///
+    /// Defaults to: `true`.
+    pub sanitize_control_chars: bool,
+    /// Whether to show the column number of the leftmost label starting on a
+    /// line next to its line number in the gutter, for example `2:9 │`.
+    ///
+    /// Columns are counted as they are displayed, so tabs are expanded to the
+    /// next multiple of the [`Config::tab_width`] and wide characters count as
+    /// two columns. The gutter is widened to fit the widest number in the
+    /// snippet.
+    ///
+    /// Defaults to: `false`.
+    pub show_columns: bool,
+    /// The minimum width of the line numbers in the gutter of source snippets.
+    ///
+    /// Setting this to the width of the largest line number in a batch of
+    /// diagnostics keeps the source borders of all snippets aligned, even if
+    /// they come from files with very different lengths.
+    ///
+    /// Defaults to: `0`.
+    pub line_number_min_width: usize,
+    /// How to align line numbers that are narrower than the gutter.
+    ///
+    /// Defaults to: [`LineNumberAlignment::Right`].
+    pub line_number_alignment: LineNumberAlignment,
+    /// Whether to number the lines of source snippets relative to the line of
+    /// the primary label in the file, which is numbered `0`, with the lines
+    /// before and after it numbered `-1`, `+1`, and so on:
+    ///
+    /// ```text
+    ///    ┌─ test:8:9
+    ///    │
+    /// -1 │ (+ 1
+    ///  0 │ (+ test "")
+    ///    │         ^^ expected `Int` but found `String`
+    /// ```
+    ///
+    /// The gutter is widened to fit the signs. The line numbers of suggested
+    /// edits stay absolute.
+    ///
+    /// Defaults to: `false`.
+    pub relative_line_numbers: bool,
+    /// Whether to render without any styling, even if the writer supports it.
+    ///
+    /// All styling and hyperlinks are skipped, so no escape sequences are
+    /// emitted, while still writing to the same writer. This is useful when
+    /// the writer claims to support color, but the output ends up somewhere
+    /// that does not.
+    ///
+    /// Defaults to: `false`.
+    pub force_no_color: bool,
+    /// Whether to show the number of source lines that were skipped between
+    /// the lines of a snippet, next to the [`Chars::source_border_left_break`].
+    ///
+    /// Defaults to: `false`.
+    pub show_omitted_line_count: bool,
+    /// Overrides the name of each severity in diagnostic headers, for example
+    /// to render `fatal[E001]` instead of `bug[E001]`. The styles of the
+    /// headers still depend on the [`Severity`] itself.
+    ///
+    /// Defaults to: `None`, which uses the lowercase name of the severity.
+    pub severity_name: Option<SeverityName>,
+    /// The message of the `help` header above the suggested edits of labels,
+    /// for example to translate it.
+    ///
+    /// ```text
+    /// help: suggested fix
+    ///   ┌─ test:2:9
+    /// ```
+    ///
+    /// Defaults to: `"suggested fix"`.
+    pub suggestion_message: String,
+    /// The maximum width of the notes of a diagnostic, including the gutter
+    /// and the note bullet.
+    ///
+    /// Each line of a note that is wider than this is wrapped between words,
+    /// and the continuation rows keep the leading indentation of the line, so
+    /// that aligned notes stay aligned. Words that are wider than the maximum
+    /// are never split.
+    ///
+    /// Defaults to: `None`, which never wraps.
+    pub note_wrap_width: Option<usize>,
+    /// Builds the URL of the documentation of a diagnostic code. If this
+    /// returns a URL for the code of a diagnostic, the code in its header is
+    /// rendered as a terminal hyperlink to it, when the writer supports
+    /// hyperlinks.
+    ///
+    /// Defaults to: `None`.
+    pub code_url: Option<CodeUrl>,
+    /// Whether to dim source lines that only provide context, because no
+    /// labels cover them. This draws attention to the labeled lines, which
+    /// are rendered at full brightness.
+    ///
+    /// Defaults to: `false`.
+    pub dim_context_lines: bool,
+    /// How to mark the source text covered by single-line labels.
+    ///
+    /// Defaults to: [`CaretFillStyle::Full`].
+    pub caret_fill_style: CaretFillStyle,
+    /// Where to place the message of the last label on a line of source,
+    /// either on the same row as its carets, or below them.
+    ///
+    /// Defaults to: [`LabelMessagePlacement::Inline`].
+    pub label_message_placement: LabelMessagePlacement,
+    /// Whether the messages of labels are all placed below the carets, each
+    /// one connected to the start of its carets by a corner, which keeps lines
+    /// with many labels readable:
+    ///
+    /// ```text
+    /// 4 │     v.push(*r);
+    ///   │     ^^^^^^ --
+    ///   │     │      │
+    ///   │     │      ╰─ first borrow later used here
+    ///   │     ╰─ second mutable borrow occurs here
+    /// ```
+    ///
+    /// The corner is drawn with [`Chars::multi_bottom_left`] and
+    /// [`Chars::multi_bottom`]. This places the message of the last label on
+    /// a line below its carets too, regardless of
+    /// [`Config::label_message_placement`].
+    ///
+    /// Defaults to: `false`.
+    pub dangling_messages: bool,
+    /// How to mark the source text covered by single-line secondary labels.
+    ///
+    /// Defaults to: [`SecondaryUnderlineStyle::Carets`].
+    pub secondary_underline_style: SecondaryUnderlineStyle,
+    /// The maximum number of labels to show for each diagnostic in the
+    /// source snippets. If a diagnostic has more labels, primary labels are
+    /// shown before secondary labels, then labels with a higher
+    /// [`Label::priority`], and then labels that start earlier. The number
+    /// of labels that are left out is shown after the snippets:
+    ///
+    /// ```text
+    ///   = … and 487 more labels
+    /// ```
+    ///
+    /// Defaults to: `None`, which shows all labels.
+    ///
+    /// [`Label::priority`]: crate::diagnostic::Label::priority
+    pub max_labels: Option<usize>,
 }
 
 impl Default for Config {
@@ -64,10 +447,743 @@ impl Default for Config {
             end_context_lines: 1,
             before_label_lines: 0,
             after_label_lines: 0,
+            context_merge_threshold: 0,
+            render_file_hyperlinks: false,
+            color_note_bullet_by_severity: false,
+            compact_borders: false,
+            left_margin: 0,
+            line_prefix: None,
+            max_line_width: None,
+            highlight_span_background: false,
+            #[cfg(feature = "termcolor")]
+            source_style: None,
+            render_whitespace: RenderWhitespace::None,
+            deduplicate: false,
+            group_by_file: false,
+            emit_summary: false,
+            min_severity: None,
+            severity_order: None,
+            first_only: false,
+            diagnostic_separator: None,
+            bidi_override: false,
+            sanitize_control_chars: true,
+            show_columns: false,
+            line_number_min_width: 0,
+            line_number_alignment: LineNumberAlignment::Right,
+            relative_line_numbers: false,
+            force_no_color: false,
+            show_omitted_line_count: false,
+            severity_name: None,
+            suggestion_message: String::from("suggested fix"),
+            note_wrap_width: None,
+            code_url: None,
+            file_name: None,
+            dim_context_lines: false,
+            caret_fill_style: CaretFillStyle::Full,
+            label_message_placement: LabelMessagePlacement::Inline,
+            dangling_messages: false,
+            secondary_underline_style: SecondaryUnderlineStyle::Carets,
+            max_labels: None,
         }
     }
 }
 
+impl Config {
+    /// Start building a configuration from the defaults.
+    ///
+    /// Unlike struct update syntax, the builder does not break when new
+    /// fields are added to [`Config`].
+    ///
+    /// ```rust
+    /// use codespan_reporting::term::{Chars, Config};
+    ///
+    /// let config = Config::builder()
+    ///     .chars(Chars::ascii())
+    ///     .start_context_lines(5)
+    ///     .build();
+    /// ```
+    pub fn builder() -> ConfigBuilder {
+        ConfigBuilder::default()
+    }
+
+    /// Create a configuration from the defaults, adjusted to the terminal
+    /// capabilities described by environment variables:
+    ///
+    /// * `CODESPAN_ASCII`: if set to anything other than an empty string or
+    ///   `0`, [`Config::chars`] is set to [`Chars::ascii()`], for logs that
+    ///   mangle box-drawing characters.
+    /// * `NO_COLOR`: if set to a non-empty string, [`Config::force_no_color`]
+    ///   is enabled, following the [`NO_COLOR`] convention.
+    /// * `CLICOLOR_FORCE`: if set to anything other than an empty string or
+    ///   `0`, [`Config::force_no_color`] is left disabled, even if `NO_COLOR`
+    ///   is set.
+    ///
+    /// That is, `CLICOLOR_FORCE` takes precedence over `NO_COLOR`, as with
+    /// [`color_choice`], which applies the same variables to the writer. The
+    /// environment is only read by these functions, so libraries that use
+    /// [`Config::default()`] are not affected by it.
+    ///
+    /// [`NO_COLOR`]: https://no-color.org/
+    #[cfg(feature = "std")]
+    pub fn from_env() -> Config {
+        Config::from_vars(|name| std::env::var_os(name))
+    }
+
+    /// [`Config::from_env`], reading the environment variables with `var`.
+    #[cfg(feature = "std")]
+    fn from_vars(var: impl Fn(&str) -> Option<OsString>) -> Config {
+        let mut config = Config::default();
+        if env_flag(&var, "CODESPAN_ASCII") {
+            config.chars = Chars::ascii();
+        }
+        config.force_no_color = env_set(&var, "NO_COLOR") && !env_flag(&var, "CLICOLOR_FORCE");
+        config
+    }
+
+    /// Set [`Config::max_line_width`] to the width of the terminal that
+    /// standard output is connected to, so that source lines are wrapped at
+    /// the edge of the terminal. If standard output is not a terminal, for
+    /// example when it is piped to a file, lines are never wrapped.
+    ///
+    /// ```rust
+    /// use codespan_reporting::term::Config;
+    ///
+    /// let config = Config::default().auto_width();
+    /// ```
+    #[cfg(feature = "terminal_size")]
+    pub fn auto_width(mut self) -> Config {
+        self.max_line_width = terminal_size::terminal_size()
+            .map(|(terminal_size::Width(width), _)| usize::from(width));
+        self
+    }
+}
+
+/// Whether the environment variable, as read with `var`, is set to a
+/// non-empty string.
+#[cfg(feature = "std")]
+fn env_set(var: impl Fn(&str) -> Option<OsString>, name: &str) -> bool {
+    var(name).map_or(false, |value| !value.is_empty())
+}
+
+/// Whether the environment variable, as read with `var`, is set to a
+/// non-empty string other than `0`.
+#[cfg(feature = "std")]
+fn env_flag(var: impl Fn(&str) -> Option<OsString>, name: &str) -> bool {
+    var(name).map_or(false, |value| !value.is_empty() && value != "0")
+}
+
+/// Choose whether to color the output of a writer, such as a
+/// `termcolor::StandardStream`, following the [`NO_COLOR`] and
+/// [`CLICOLOR_FORCE`] conventions, in order of precedence:
+///
+/// 1. If `CLICOLOR_FORCE` is set to anything other than an empty string or
+///    `0`, the output is always colored, even if it is not a terminal.
+/// 2. If `NO_COLOR` is set to a non-empty string, the output is never
+///    colored.
+/// 3. If the output is not a terminal, as given by `is_terminal`, it is
+///    never colored.
+/// 4. Otherwise, termcolor decides based on the `TERM` variable.
+///
+/// Use [`Config::from_env`] to also honor `NO_COLOR` when writing to a
+/// writer that was created elsewhere.
+///
+/// ```rust
+/// use std::io::IsTerminal;
+/// use codespan_reporting::term::color_choice;
+/// use codespan_reporting::term::termcolor::StandardStream;
+///
+/// let writer = StandardStream::stderr(color_choice(std::io::stderr().is_terminal()));
+/// # let _ = writer;
+/// ```
+///
+/// [`NO_COLOR`]: https://no-color.org/
+/// [`CLICOLOR_FORCE`]: https://bixense.com/clicolors/
+#[cfg(feature = "termcolor")]
+pub fn color_choice(is_terminal: bool) -> ColorChoice {
+    color_choice_from_vars(is_terminal, |name| std::env::var_os(name))
+}
+
+/// [`color_choice`], reading the environment variables with `var`.
+#[cfg(feature = "termcolor")]
+fn color_choice_from_vars(
+    is_terminal: bool,
+    var: impl Fn(&str) -> Option<OsString>,
+) -> ColorChoice {
+    if env_flag(&var, "CLICOLOR_FORCE") {
+        ColorChoice::Always
+    } else if env_set(&var, "NO_COLOR") || !is_terminal {
+        ColorChoice::Never
+    } else {
+        ColorChoice::Auto
+    }
+}
+
+/// A builder for a [`Config`], created with [`Config::builder`].
+///
+/// Every setting that is not set on the builder keeps its default value.
+#[derive(Clone, Debug, Default)]
+pub struct ConfigBuilder {
+    config: Config,
+}
+
+impl ConfigBuilder {
+    /// Set [`Config::display_style`].
+    pub fn display_style(mut self, display_style: DisplayStyle) -> ConfigBuilder {
+        self.config.display_style = display_style;
+        self
+    }
+
+    /// Set [`Config::tab_width`].
+    pub fn tab_width(mut self, tab_width: usize) -> ConfigBuilder {
+        self.config.tab_width = tab_width;
+        self
+    }
+
+    /// Set [`Config::chars`].
+    pub fn chars(mut self, chars: Chars) -> ConfigBuilder {
+        self.config.chars = chars;
+        self
+    }
+
+    /// Set [`Config::start_context_lines`].
+    pub fn start_context_lines(mut self, start_context_lines: usize) -> ConfigBuilder {
+        self.config.start_context_lines = start_context_lines;
+        self
+    }
+
+    /// Set [`Config::end_context_lines`].
+    pub fn end_context_lines(mut self, end_context_lines: usize) -> ConfigBuilder {
+        self.config.end_context_lines = end_context_lines;
+        self
+    }
+
+    /// Set [`Config::before_label_lines`].
+    pub fn before_label_lines(mut self, before_label_lines: usize) -> ConfigBuilder {
+        self.config.before_label_lines = before_label_lines;
+        self
+    }
+
+    /// Set [`Config::after_label_lines`].
+    pub fn after_label_lines(mut self, after_label_lines: usize) -> ConfigBuilder {
+        self.config.after_label_lines = after_label_lines;
+        self
+    }
+
+    /// Set [`Config::context_merge_threshold`].
+    pub fn context_merge_threshold(mut self, context_merge_threshold: usize) -> ConfigBuilder {
+        self.config.context_merge_threshold = context_merge_threshold;
+        self
+    }
+
+    /// Set [`Config::render_file_hyperlinks`].
+    pub fn render_file_hyperlinks(mut self, render_file_hyperlinks: bool) -> ConfigBuilder {
+        self.config.render_file_hyperlinks = render_file_hyperlinks;
+        self
+    }
+
+    /// Set [`Config::color_note_bullet_by_severity`].
+    pub fn color_note_bullet_by_severity(
+        mut self,
+        color_note_bullet_by_severity: bool,
+    ) -> ConfigBuilder {
+        self.config.color_note_bullet_by_severity = color_note_bullet_by_severity;
+        self
+    }
+
+    /// Set [`Config::compact_borders`].
+    pub fn compact_borders(mut self, compact_borders: bool) -> ConfigBuilder {
+        self.config.compact_borders = compact_borders;
+        self
+    }
+
+    /// Set [`Config::left_margin`].
+    pub fn left_margin(mut self, left_margin: usize) -> ConfigBuilder {
+        self.config.left_margin = left_margin;
+        self
+    }
+
+    /// Set [`Config::line_prefix`].
+    pub fn line_prefix(mut self, line_prefix: Option<String>) -> ConfigBuilder {
+        self.config.line_prefix = line_prefix;
+        self
+    }
+
+    /// Set [`Config::max_line_width`].
+    pub fn max_line_width(mut self, max_line_width: Option<usize>) -> ConfigBuilder {
+        self.config.max_line_width = max_line_width;
+        self
+    }
+
+    /// Set [`Config::highlight_span_background`].
+    pub fn highlight_span_background(mut self, highlight_span_background: bool) -> ConfigBuilder {
+        self.config.highlight_span_background = highlight_span_background;
+        self
+    }
+
+    /// Set [`Config::source_style`].
+    #[cfg(feature = "termcolor")]
+    pub fn source_style(mut self, source_style: Option<SourceStyle>) -> ConfigBuilder {
+        self.config.source_style = source_style;
+        self
+    }
+
+    /// Set [`Config::render_whitespace`].
+    pub fn render_whitespace(mut self, render_whitespace: RenderWhitespace) -> ConfigBuilder {
+        self.config.render_whitespace = render_whitespace;
+        self
+    }
+
+    /// Set [`Config::deduplicate`].
+    pub fn deduplicate(mut self, deduplicate: bool) -> ConfigBuilder {
+        self.config.deduplicate = deduplicate;
+        self
+    }
+
+    /// Set [`Config::group_by_file`].
+    pub fn group_by_file(mut self, group_by_file: bool) -> ConfigBuilder {
+        self.config.group_by_file = group_by_file;
+        self
+    }
+
+    /// Set [`Config::emit_summary`].
+    pub fn emit_summary(mut self, emit_summary: bool) -> ConfigBuilder {
+        self.config.emit_summary = emit_summary;
+        self
+    }
+
+    /// Set [`Config::min_severity`].
+    pub fn min_severity(mut self, min_severity: Option<Severity>) -> ConfigBuilder {
+        self.config.min_severity = min_severity;
+        self
+    }
+
+    /// Set [`Config::severity_order`].
+    pub fn severity_order(mut self, severity_order: Option<SeverityOrder>) -> ConfigBuilder {
+        self.config.severity_order = severity_order;
+        self
+    }
+
+    /// Set [`Config::first_only`].
+    pub fn first_only(mut self, first_only: bool) -> ConfigBuilder {
+        self.config.first_only = first_only;
+        self
+    }
+
+    /// Set [`Config::diagnostic_separator`].
+    pub fn diagnostic_separator(mut self, diagnostic_separator: Option<char>) -> ConfigBuilder {
+        self.config.diagnostic_separator = diagnostic_separator;
+        self
+    }
+
+    /// Set [`Config::bidi_override`].
+    pub fn bidi_override(mut self, bidi_override: bool) -> ConfigBuilder {
+        self.config.bidi_override = bidi_override;
+        self
+    }
+
+    /// Set [`Config::sanitize_control_chars`].
+    pub fn sanitize_control_chars(mut self, sanitize_control_chars: bool) -> ConfigBuilder {
+        self.config.sanitize_control_chars = sanitize_control_chars;
+        self
+    }
+
+    /// Set [`Config::show_columns`].
+    pub fn show_columns(mut self, show_columns: bool) -> ConfigBuilder {
+        self.config.show_columns = show_columns;
+        self
+    }
+
+    /// Set [`Config::line_number_min_width`].
+    pub fn line_number_min_width(mut self, line_number_min_width: usize) -> ConfigBuilder {
+        self.config.line_number_min_width = line_number_min_width;
+        self
+    }
+
+    /// Set [`Config::line_number_alignment`].
+    pub fn line_number_alignment(
+        mut self,
+        line_number_alignment: LineNumberAlignment,
+    ) -> ConfigBuilder {
+        self.config.line_number_alignment = line_number_alignment;
+        self
+    }
+
+    /// Set [`Config::relative_line_numbers`].
+    pub fn relative_line_numbers(mut self, relative_line_numbers: bool) -> ConfigBuilder {
+        self.config.relative_line_numbers = relative_line_numbers;
+        self
+    }
+
+    /// Set [`Config::force_no_color`].
+    pub fn force_no_color(mut self, force_no_color: bool) -> ConfigBuilder {
+        self.config.force_no_color = force_no_color;
+        self
+    }
+
+    /// Set [`Config::show_omitted_line_count`].
+    pub fn show_omitted_line_count(mut self, show_omitted_line_count: bool) -> ConfigBuilder {
+        self.config.show_omitted_line_count = show_omitted_line_count;
+        self
+    }
+
+    /// Set [`Config::severity_name`].
+    pub fn severity_name(mut self, severity_name: Option<SeverityName>) -> ConfigBuilder {
+        self.config.severity_name = severity_name;
+        self
+    }
+
+    /// Set [`Config::suggestion_message`].
+    pub fn suggestion_message(mut self, suggestion_message: String) -> ConfigBuilder {
+        self.config.suggestion_message = suggestion_message;
+        self
+    }
+
+    /// Set [`Config::note_wrap_width`].
+    pub fn note_wrap_width(mut self, note_wrap_width: Option<usize>) -> ConfigBuilder {
+        self.config.note_wrap_width = note_wrap_width;
+        self
+    }
+
+    /// Set [`Config::file_name`].
+    pub fn file_name(mut self, file_name: Option<FileName>) -> ConfigBuilder {
+        self.config.file_name = file_name;
+        self
+    }
+
+    /// Set [`Config::code_url`].
+    pub fn code_url(mut self, code_url: Option<CodeUrl>) -> ConfigBuilder {
+        self.config.code_url = code_url;
+        self
+    }
+
+    /// Set [`Config::dim_context_lines`].
+    pub fn dim_context_lines(mut self, dim_context_lines: bool) -> ConfigBuilder {
+        self.config.dim_context_lines = dim_context_lines;
+        self
+    }
+
+    /// Set [`Config::caret_fill_style`].
+    pub fn caret_fill_style(mut self, caret_fill_style: CaretFillStyle) -> ConfigBuilder {
+        self.config.caret_fill_style = caret_fill_style;
+        self
+    }
+
+    /// Set [`Config::label_message_placement`].
+    pub fn label_message_placement(
+        mut self,
+        label_message_placement: LabelMessagePlacement,
+    ) -> ConfigBuilder {
+        self.config.label_message_placement = label_message_placement;
+        self
+    }
+
+    /// Set [`Config::dangling_messages`].
+    pub fn dangling_messages(mut self, dangling_messages: bool) -> ConfigBuilder {
+        self.config.dangling_messages = dangling_messages;
+        self
+    }
+
+    /// Set [`Config::secondary_underline_style`].
+    pub fn secondary_underline_style(
+        mut self,
+        secondary_underline_style: SecondaryUnderlineStyle,
+    ) -> ConfigBuilder {
+        self.config.secondary_underline_style = secondary_underline_style;
+        self
+    }
+
+    /// Set [`Config::max_labels`].
+    pub fn max_labels(mut self, max_labels: Option<usize>) -> ConfigBuilder {
+        self.config.max_labels = max_labels;
+        self
+    }
+
+    /// Finish building the configuration.
+    pub fn build(self) -> Config {
+        self.config
+    }
+}
+
+/// Maps the name of a file to the name that is displayed in diagnostics.
+#[derive(Clone)]
+pub struct FileName(Arc<FileNameFn>);
+
+type FileNameFn = dyn Fn(&str) -> String + Send + Sync;
+
+impl FileName {
+    /// Create a file name mapping from a closure.
+    pub fn new(f: impl Fn(&str) -> String + Send + Sync + 'static) -> FileName {
+        FileName(Arc::new(f))
+    }
+
+    pub(crate) fn call(&self, name: &str) -> String {
+        (self.0)(name)
+    }
+}
+
+impl fmt::Debug for FileName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("FileName(..)")
+    }
+}
+
+/// Builds the URL of the documentation of a diagnostic code, such as `E0001`.
+/// Returning `None` renders the code without a hyperlink.
+#[derive(Clone)]
+pub struct CodeUrl(Arc<CodeUrlFn>);
+
+type CodeUrlFn = dyn Fn(&str) -> Option<String> + Send + Sync;
+
+impl CodeUrl {
+    /// Create a URL builder from a closure.
+    pub fn new(f: impl Fn(&str) -> Option<String> + Send + Sync + 'static) -> CodeUrl {
+        CodeUrl(Arc::new(f))
+    }
+
+    pub(crate) fn call(&self, code: &str) -> Option<String> {
+        (self.0)(code)
+    }
+}
+
+impl fmt::Debug for CodeUrl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("CodeUrl(..)")
+    }
+}
+
+/// Styles source text, given the name of its file and the byte index of a
+/// character in it. Returning `None` leaves the character unstyled.
+///
+/// ```rust
+/// use codespan_reporting::term::termcolor::{Color, ColorSpec};
+/// use codespan_reporting::term::SourceStyle;
+///
+/// // The byte ranges of the keywords in each file, from a tokenizer
+/// let keywords = vec![("main.fun", 0..3)];
+///
+/// let source_style = SourceStyle::new(move |name, byte_index| {
+///     (keywords.iter())
+///         .any(|(file, range)| *file == name && range.contains(&byte_index))
+///         .then(|| ColorSpec::new().set_fg(Some(Color::Magenta)).clone())
+/// });
+/// ```
+#[cfg(feature = "termcolor")]
+#[derive(Clone)]
+pub struct SourceStyle(Arc<SourceStyleFn>);
+
+#[cfg(feature = "termcolor")]
+type SourceStyleFn = dyn Fn(&str, usize) -> Option<ColorSpec> + Send + Sync;
+
+#[cfg(feature = "termcolor")]
+impl SourceStyle {
+    /// Create a source styler from a closure.
+    pub fn new(
+        f: impl Fn(&str, usize) -> Option<ColorSpec> + Send + Sync + 'static,
+    ) -> SourceStyle {
+        SourceStyle(Arc::new(f))
+    }
+
+    pub(crate) fn call(&self, name: &str, byte_index: usize) -> Option<ColorSpec> {
+        (self.0)(name, byte_index)
+    }
+}
+
+#[cfg(feature = "termcolor")]
+impl fmt::Debug for SourceStyle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SourceStyle(..)")
+    }
+}
+
+/// Names the severity of a diagnostic in its header.
+#[derive(Clone)]
+pub struct SeverityName(Arc<SeverityNameFn>);
+
+type SeverityNameFn = dyn Fn(Severity) -> Cow<'static, str> + Send + Sync;
+
+impl SeverityName {
+    /// Create a severity namer from a closure, which can return either a
+    /// `&'static str` or a `String`, such as a name that is looked up at
+    /// runtime.
+    pub fn new<N: Into<Cow<'static, str>>>(
+        f: impl Fn(Severity) -> N + Send + Sync + 'static,
+    ) -> SeverityName {
+        SeverityName(Arc::new(move |severity| f(severity).into()))
+    }
+
+    pub(crate) fn call(&self, severity: Severity) -> Cow<'static, str> {
+        (self.0)(severity)
+    }
+}
+
+impl fmt::Debug for SeverityName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SeverityName(..)")
+    }
+}
+
+/// How to mark the source text covered by single-line labels.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CaretFillStyle {
+    /// Mark every column with a caret.
+    ///
+    /// ```text
+    /// ^^^^^^^^
+    /// ```
+    Full,
+    /// Only mark the first and last columns with carets, connecting them with
+    /// [`Chars::single_primary_caret_connector`] or
+    /// [`Chars::single_secondary_caret_connector`].
+    ///
+    /// ```text
+    /// ^──────^
+    /// ```
+    Endpoints,
+}
+
+/// Where to place the message of the last label on a line of source, for
+/// [`Config::label_message_placement`].
+///
+/// The messages of the other labels are always placed below the carets, and
+/// so is the message of a last label that overlaps another label.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LabelMessagePlacement {
+    /// Place the message on the same row as the carets.
+    ///
+    /// ```text
+    /// 2 │ (+ test "")
+    ///   │         ^^ expected `Int` but found `String`
+    /// ```
+    Inline,
+    /// Place the message below the carets, aligned to their start.
+    ///
+    /// ```text
+    /// 2 │ (+ test "")
+    ///   │         ^^
+    ///   │         │
+    ///   │         expected `Int` but found `String`
+    /// ```
+    Below,
+    /// Place the message on the same row as the carets if the row fits into
+    /// [`Config::max_line_width`], and below them otherwise.
+    Auto,
+}
+
+/// How to mark the source text covered by single-line secondary labels.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SecondaryUnderlineStyle {
+    /// Mark the labels with [`Chars::single_secondary_caret`] in the row of
+    /// carets underneath the source.
+    ///
+    /// ```text
+    /// 2 │ (+ test "")
+    ///   │ -       ^^
+    /// ```
+    Carets,
+    /// Underline the source text of the labels with a wavy line, on writers
+    /// that support it, which leaves the row of carets to the primary labels.
+    /// Secondary labels with a message, or without any source text, are
+    /// still marked with carets, so that there is something to point their
+    /// message at. Writers without support for wavy underlines, such as
+    /// writers without color, fall back to [`SecondaryUnderlineStyle::Carets`].
+    ///
+    /// See [`WriteStyle::set_wavy_underline`].
+    ///
+    /// [`WriteStyle::set_wavy_underline`]: crate::term::WriteStyle::set_wavy_underline
+    Wavy,
+}
+
+/// Which whitespace in source lines to render visibly.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RenderWhitespace {
+    /// Render whitespace as is.
+    None,
+    /// Only render the spaces and tabs at the end of a line visibly.
+    Trailing,
+    /// Render all spaces and tabs visibly.
+    All,
+}
+
+/// An order of the severities, from the most to the least urgent, for
+/// [`Config::severity_order`].
+///
+/// ```rust
+/// use codespan_reporting::diagnostic::Severity;
+/// use codespan_reporting::term::SeverityOrder;
+///
+/// let order = SeverityOrder::new([
+///     Severity::Bug,
+///     Severity::Error,
+///     Severity::Warning,
+///     Severity::Note,
+///     Severity::Help,
+/// ]);
+/// assert_eq!(order, Some(SeverityOrder::default()));
+///
+/// // Each severity must appear exactly once
+/// let order = SeverityOrder::new([Severity::Error; 5]);
+/// assert_eq!(order, None);
+/// ```
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SeverityOrder([Severity; 5]);
+
+impl SeverityOrder {
+    /// Create an order of the severities, from the most to the least urgent.
+    ///
+    /// Returns `None` unless each severity appears exactly once.
+    pub fn new(severities: [Severity; 5]) -> Option<SeverityOrder> {
+        let is_unique = (severities.iter().enumerate())
+            .all(|(index, severity)| !severities[..index].contains(severity));
+        is_unique.then_some(SeverityOrder(severities))
+    }
+
+    /// The severities, from the most to the least urgent.
+    pub fn severities(&self) -> [Severity; 5] {
+        self.0
+    }
+
+    /// The position of a severity in the order, starting with 0 for the most
+    /// urgent severity.
+    pub fn rank(&self, severity: Severity) -> usize {
+        (self.0.iter())
+            .position(|other| *other == severity)
+            .expect("severity order is missing a severity")
+    }
+}
+
+impl Default for SeverityOrder {
+    /// Bugs, errors, warnings, notes, and then help messages, following the
+    /// order of [`Severity`].
+    fn default() -> SeverityOrder {
+        SeverityOrder([
+            Severity::Bug,
+            Severity::Error,
+            Severity::Warning,
+            Severity::Note,
+            Severity::Help,
+        ])
+    }
+}
+
+/// How line numbers are aligned in the gutter of source snippets.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LineNumberAlignment {
+    /// Align line numbers with the left edge of the gutter.
+    ///
+    /// ```text
+    /// 9   │ let x = 1;
+    /// 10  │ let y = 2;
+    /// ```
+    Left,
+    /// Align line numbers with the source border.
+    ///
+    /// ```text
+    ///  9 │ let x = 1;
+    /// 10 │ let y = 2;
+    /// ```
+    Right,
+}
+
 /// The display style to use when rendering diagnostics.
 #[derive(Clone, Debug)]
 pub enum DisplayStyle {
@@ -104,59 +1220,188 @@ pub enum DisplayStyle {
     /// error[E0002]: Bad config found
     /// ```
     Short,
+    /// Output a diagnostic as Markdown, for embedding in documentation. The
+    /// header is emphasized, the source snippets are rendered without styling
+    /// in a fenced code block, and the notes are rendered as a list.
+    ///
+    /// ````text
+    /// **error[E0001]**: unexpected type in `+` application
+    ///
+    /// ```text
+    ///   ┌─ test:2:9
+    ///   │
+    /// 2 │ (+ test "")
+    ///   │         ^^ expected `Int` but found `String`
+    /// ```
+    ///
+    /// - expected type `Int`\
+    ///   found type `String`
+    /// ````
+    Markdown,
+    /// Output [GitHub Actions workflow commands] that annotate the source,
+    /// with one command for each primary label. Notes are appended to the
+    /// message.
+    ///
+    /// ```text
+    /// ::error file=test,line=2,col=9,endLine=2,endColumn=11,title=E0001::unexpected type in `+` application%0Aexpected type `Int`%0A   found type `String`
+    /// ::error title=E0002::Bad config found
+    /// ```
+    ///
+    /// [GitHub Actions workflow commands]: https://docs.github.com/en/actions/using-workflows/workflow-commands-for-github-actions
+    GitHubActions,
+    /// Output each diagnostic on a single line, located at its first primary
+    /// label, in the format understood by the quickfix lists of editors such
+    /// as Vim. Secondary labels and notes are omitted.
+    ///
+    /// Unlike [`DisplayStyle::Short`], the column number counts the columns
+    /// that the source takes up, with tabs expanded using
+    /// [`Config::tab_width`].
+    ///
+    /// ```text
+    /// test:2:9: error[E0001]: unexpected type in `+` application
+    /// error[E0002]: Bad config found
+    /// ```
+    Grep,
+}
+
+#[cfg(feature = "std")]
+impl DisplayStyle {
+    /// Returns [`DisplayStyle::GitHubActions`] when running in GitHub Actions,
+    /// as indicated by the `GITHUB_ACTIONS` environment variable being set to
+    /// `true`, and `fallback` otherwise.
+    ///
+    /// The environment is never consulted unless this is called.
+    pub fn github_actions_or(fallback: DisplayStyle) -> DisplayStyle {
+        match std::env::var("GITHUB_ACTIONS") {
+            Ok(value) if value == "true" => DisplayStyle::GitHubActions,
+            _ => fallback,
+        }
+    }
 }
 
 /// Styles to use when rendering the diagnostic.
+///
+/// Every style is a [`ColorSpec`], so any of its attributes can be used, and
+/// [`StyleAttr`] can be used to add attributes to related styles at once. Which
+/// attributes are honored depends on the writer:
+///
+/// - ANSI writers, such as [`termcolor::Ansi`] or a [`termcolor::StandardStream`]
+///   on a terminal that supports ANSI escape sequences, honor all attributes.
+///   Terminals that lack support for an attribute, commonly italic or
+///   strikethrough, silently ignore it.
+/// - The legacy Windows console only honors the foreground and background
+///   colors, and the intense attribute.
+/// - Writers without color support, such as [`termcolor::NoColor`], ignore all
+///   styles.
+///
+/// With the `serialization` feature, styles can be loaded from a theme file.
+/// Each [`ColorSpec`] is represented as a table of its attributes, where
+/// colors are either a name like `"red"`, an ANSI 256 color number like
+/// `"208"`, or an RGB triple like `"255,85,85"`. Missing styles keep their
+/// default values.
 #[cfg(feature = "termcolor")]
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serialization", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serialization", serde(default))]
 pub struct Styles {
     /// The style to use when rendering bug headers.
     /// Defaults to `fg:red bold intense`.
+    #[cfg_attr(feature = "serialization", serde(with = "color_spec_serde"))]
     pub header_bug: ColorSpec,
     /// The style to use when rendering error headers.
     /// Defaults to `fg:red bold intense`.
+    #[cfg_attr(feature = "serialization", serde(with = "color_spec_serde"))]
     pub header_error: ColorSpec,
     /// The style to use when rendering warning headers.
     /// Defaults to `fg:yellow bold intense`.
+    #[cfg_attr(feature = "serialization", serde(with = "color_spec_serde"))]
     pub header_warning: ColorSpec,
     /// The style to use when rendering note headers.
     /// Defaults to `fg:green bold intense`.
+    #[cfg_attr(feature = "serialization", serde(with = "color_spec_serde"))]
     pub header_note: ColorSpec,
     /// The style to use when rendering help headers.
     /// Defaults to `fg:cyan bold intense`.
+    #[cfg_attr(feature = "serialization", serde(with = "color_spec_serde"))]
     pub header_help: ColorSpec,
     /// The style to use when the main diagnostic message.
     /// Defaults to `bold intense`.
+    #[cfg_attr(feature = "serialization", serde(with = "color_spec_serde"))]
     pub header_message: ColorSpec,
 
     /// The style to use when rendering bug labels.
     /// Defaults to `fg:red`.
+    #[cfg_attr(feature = "serialization", serde(with = "color_spec_serde"))]
     pub primary_label_bug: ColorSpec,
     /// The style to use when rendering error labels.
     /// Defaults to `fg:red`.
+    #[cfg_attr(feature = "serialization", serde(with = "color_spec_serde"))]
     pub primary_label_error: ColorSpec,
     /// The style to use when rendering warning labels.
     /// Defaults to `fg:yellow`.
+    #[cfg_attr(feature = "serialization", serde(with = "color_spec_serde"))]
     pub primary_label_warning: ColorSpec,
     /// The style to use when rendering note labels.
     /// Defaults to `fg:green`.
+    #[cfg_attr(feature = "serialization", serde(with = "color_spec_serde"))]
     pub primary_label_note: ColorSpec,
     /// The style to use when rendering help labels.
     /// Defaults to `fg:cyan`.
+    #[cfg_attr(feature = "serialization", serde(with = "color_spec_serde"))]
     pub primary_label_help: ColorSpec,
     /// The style to use when rendering secondary labels.
     /// Defaults `fg:blue` (or `fg:cyan` on windows).
+    #[cfg_attr(feature = "serialization", serde(with = "color_spec_serde"))]
     pub secondary_label: ColorSpec,
 
     /// The style to use when rendering the line numbers.
     /// Defaults `fg:blue` (or `fg:cyan` on windows).
+    #[cfg_attr(feature = "serialization", serde(with = "color_spec_serde"))]
     pub line_number: ColorSpec,
     /// The style to use when rendering the source code borders.
     /// Defaults `fg:blue` (or `fg:cyan` on windows).
+    #[cfg_attr(feature = "serialization", serde(with = "color_spec_serde"))]
     pub source_border: ColorSpec,
     /// The style to use when rendering the note bullets.
     /// Defaults `fg:blue` (or `fg:cyan` on windows).
+    #[cfg_attr(feature = "serialization", serde(with = "color_spec_serde"))]
     pub note_bullet: ColorSpec,
+    /// The style to apply on top of the label style when highlighting the
+    /// source text covered by labels, if [`Config::highlight_span_background`]
+    /// is enabled.
+    /// Defaults to `bg:black intense`.
+    #[cfg_attr(feature = "serialization", serde(with = "color_spec_serde"))]
+    pub span_background: ColorSpec,
+    /// The style to apply on top of the current style when rendering
+    /// whitespace visibly, if [`Config::render_whitespace`] is enabled.
+    /// Defaults to `dimmed`.
+    #[cfg_attr(feature = "serialization", serde(with = "color_spec_serde"))]
+    pub whitespace: ColorSpec,
+    /// The style to use when rendering source lines that only provide
+    /// context, if [`Config::dim_context_lines`] is enabled.
+    /// Defaults to `dimmed`.
+    #[cfg_attr(feature = "serialization", serde(with = "color_spec_serde"))]
+    pub context_line: ColorSpec,
+    /// The style to use when rendering the lines a suggestion inserts.
+    /// Defaults to `fg:green`.
+    #[cfg_attr(feature = "serialization", serde(with = "color_spec_serde"))]
+    pub suggestion_insertion: ColorSpec,
+    /// The style to use when rendering the lines a suggestion deletes.
+    /// Defaults to `fg:red`.
+    #[cfg_attr(feature = "serialization", serde(with = "color_spec_serde"))]
+    pub suggestion_deletion: ColorSpec,
+
+    /// Styles that override the severity-based label styles for a given
+    /// label style.
+    ///
+    /// When [`Styles::label`] is asked for the style of a label, an entry in
+    /// this map for that label style always takes precedence. Only if there is
+    /// no entry does it fall back to the `primary_label_*` style for the
+    /// severity of the diagnostic, or to [`Styles::secondary_label`].
+    ///
+    /// Defaults to an empty map.
+    #[cfg_attr(feature = "serialization", serde(with = "color_spec_serde::map"))]
+    pub label_overrides: HashMap<LabelStyle, ColorSpec>,
 }
 
 #[cfg(feature = "termcolor")]
@@ -188,8 +1433,26 @@ impl Styles {
         &self.source_border
     }
 
+    /// The style used to highlight labeled source text, which only sets
+    /// attributes on top of the current style instead of replacing it.
+    pub fn span_background(&self) -> ColorSpec {
+        self.span_background.clone().set_reset(false).clone()
+    }
+
+    /// The style used for whitespace that is rendered visibly, which only sets
+    /// attributes on top of the current style instead of replacing it.
+    pub fn whitespace(&self) -> ColorSpec {
+        self.whitespace.clone().set_reset(false).clone()
+    }
+
     /// The style used to mark a primary or secondary label at a given severity.
+    ///
+    /// See [`Styles::label_overrides`] for how overrides take precedence.
     pub fn label(&self, severity: Severity, label_style: LabelStyle) -> &ColorSpec {
+        if let Some(spec) = self.label_overrides.get(&label_style) {
+            return spec;
+        }
+
         match (label_style, severity) {
             (LabelStyle::Primary, Severity::Bug) => &self.primary_label_bug,
             (LabelStyle::Primary, Severity::Error) => &self.primary_label_error,
@@ -222,6 +1485,268 @@ impl Styles {
             line_number: ColorSpec::new().set_fg(Some(blue)).clone(),
             source_border: ColorSpec::new().set_fg(Some(blue)).clone(),
             note_bullet: ColorSpec::new().set_fg(Some(blue)).clone(),
+            span_background: ColorSpec::new()
+                .set_bg(Some(Color::Black))
+                .set_intense(true)
+                .clone(),
+            whitespace: ColorSpec::new().set_dimmed(true).clone(),
+            context_line: ColorSpec::new().set_dimmed(true).clone(),
+            suggestion_insertion: ColorSpec::new().set_fg(Some(Color::Green)).clone(),
+            suggestion_deletion: ColorSpec::new().set_fg(Some(Color::Red)).clone(),
+
+            label_overrides: HashMap::new(),
+        }
+    }
+}
+
+#[cfg(feature = "termcolor")]
+impl Styles {
+    /// Add an attribute to the styles of all labels with the given label
+    /// style, including the entry in [`Styles::label_overrides`] if there is
+    /// one. For example, secondary labels pointing at deprecated items could
+    /// be struck through.
+    pub fn with_label_attr(mut self, label_style: LabelStyle, attr: StyleAttr) -> Styles {
+        match label_style {
+            LabelStyle::Primary => {
+                attr.apply(&mut self.primary_label_bug);
+                attr.apply(&mut self.primary_label_error);
+                attr.apply(&mut self.primary_label_warning);
+                attr.apply(&mut self.primary_label_note);
+                attr.apply(&mut self.primary_label_help);
+            }
+            LabelStyle::Secondary => attr.apply(&mut self.secondary_label),
+        }
+        if let Some(spec) = self.label_overrides.get_mut(&label_style) {
+            attr.apply(spec);
+        }
+        self
+    }
+}
+
+/// A text attribute that can be added to a [`ColorSpec`].
+#[cfg(feature = "termcolor")]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum StyleAttr {
+    /// Bold text.
+    Bold,
+    /// Intense, or bright, colors.
+    Intense,
+    /// Underlined text.
+    Underline,
+    /// Dimmed text.
+    Dimmed,
+    /// Italic text.
+    Italic,
+    /// Struck through text, emitted as SGR 9 by ANSI writers.
+    Strikethrough,
+}
+
+#[cfg(feature = "termcolor")]
+impl StyleAttr {
+    /// Add the attribute to the given color specification.
+    pub fn apply(self, spec: &mut ColorSpec) {
+        match self {
+            StyleAttr::Bold => spec.set_bold(true),
+            StyleAttr::Intense => spec.set_intense(true),
+            StyleAttr::Underline => spec.set_underline(true),
+            StyleAttr::Dimmed => spec.set_dimmed(true),
+            StyleAttr::Italic => spec.set_italic(true),
+            StyleAttr::Strikethrough => spec.set_strikethrough(true),
+        };
+    }
+
+    /// Whether the attribute is set in the given color specification.
+    pub fn is_set(self, spec: &ColorSpec) -> bool {
+        match self {
+            StyleAttr::Bold => spec.bold(),
+            StyleAttr::Intense => spec.intense(),
+            StyleAttr::Underline => spec.underline(),
+            StyleAttr::Dimmed => spec.dimmed(),
+            StyleAttr::Italic => spec.italic(),
+            StyleAttr::Strikethrough => spec.strikethrough(),
+        }
+    }
+}
+
+#[cfg(feature = "termcolor")]
+impl Styles {
+    /// Styles using 24-bit RGB colors from the given theme.
+    ///
+    /// Headers are rendered in bold using the color for their severity, and
+    /// primary labels use the same color without the bold attribute.
+    ///
+    /// If the writer does not support color, as reported by
+    /// [`WriteColor::supports_color`], the output is rendered without styling.
+    pub fn with_rgb_theme(theme: &RgbTheme) -> Styles {
+        let rgb =
+            |(r, g, b): (u8, u8, u8)| ColorSpec::new().set_fg(Some(Color::Rgb(r, g, b))).clone();
+        let header = |color| rgb(color).set_bold(true).clone();
+
+        Styles {
+            header_bug: header(theme.bug),
+            header_error: header(theme.error),
+            header_warning: header(theme.warning),
+            header_note: header(theme.note),
+            header_help: header(theme.help),
+            header_message: ColorSpec::new().set_bold(true).clone(),
+
+            primary_label_bug: rgb(theme.bug),
+            primary_label_error: rgb(theme.error),
+            primary_label_warning: rgb(theme.warning),
+            primary_label_note: rgb(theme.note),
+            primary_label_help: rgb(theme.help),
+            secondary_label: rgb(theme.secondary_label),
+
+            line_number: rgb(theme.line_number),
+            source_border: rgb(theme.source_border),
+            note_bullet: rgb(theme.source_border),
+            span_background: ColorSpec::new()
+                .set_bg(Some(Color::Black))
+                .set_intense(true)
+                .clone(),
+            whitespace: ColorSpec::new().set_dimmed(true).clone(),
+            context_line: ColorSpec::new().set_dimmed(true).clone(),
+            suggestion_insertion: rgb(theme.note),
+            suggestion_deletion: rgb(theme.error),
+
+            label_overrides: HashMap::new(),
+        }
+    }
+}
+
+#[cfg(feature = "termcolor")]
+impl Styles {
+    /// High-contrast styles, which are WCAG-friendly when used together with
+    /// [`Chars::high_contrast()`].
+    ///
+    /// Everything is rendered in bright colors, and labels are also bold and
+    /// underlined, so that they stand out from the source code even for users
+    /// who have trouble telling colors apart. Together with the different
+    /// caret of each severity in [`Chars::high_contrast()`], color is not the
+    /// only way that information is conveyed, as recommended by [WCAG success
+    /// criterion 1.4.1].
+    ///
+    /// [WCAG success criterion 1.4.1]: https://www.w3.org/WAI/WCAG21/Understanding/use-of-color.html
+    pub fn high_contrast() -> Styles {
+        let bright = |color| {
+            ColorSpec::new()
+                .set_fg(Some(color))
+                .set_intense(true)
+                .clone()
+        };
+        let header = |color| bright(color).set_bold(true).clone();
+        let label = |color| bright(color).set_bold(true).set_underline(true).clone();
+
+        Styles {
+            header_bug: header(Color::Magenta),
+            header_error: header(Color::Red),
+            header_warning: header(Color::Yellow),
+            header_note: header(Color::Green),
+            header_help: header(Color::Cyan),
+            header_message: header(Color::White),
+
+            primary_label_bug: label(Color::Magenta),
+            primary_label_error: label(Color::Red),
+            primary_label_warning: label(Color::Yellow),
+            primary_label_note: label(Color::Green),
+            primary_label_help: label(Color::Cyan),
+            secondary_label: label(Color::White),
+
+            line_number: header(Color::White),
+            source_border: bright(Color::White),
+            note_bullet: header(Color::White),
+            span_background: ColorSpec::new().set_underline(true).clone(),
+            whitespace: ColorSpec::new().set_dimmed(true).clone(),
+            context_line: ColorSpec::new().set_dimmed(true).clone(),
+            suggestion_insertion: header(Color::Green),
+            suggestion_deletion: header(Color::Red),
+
+            label_overrides: HashMap::new(),
+        }
+    }
+}
+
+#[cfg(feature = "termcolor")]
+impl Styles {
+    /// Styles that are safe for users with color blindness, using the colors
+    /// from [`RgbTheme::okabe_ito()`].
+    ///
+    /// This can be combined with any [`Chars`]. To also tell severities apart
+    /// by the shape of their carets, set [`Chars::severity_carets`], for
+    /// example to [`SeverityCarets::distinct()`].
+    pub fn color_blind_safe() -> Styles {
+        Styles::with_rgb_theme(&RgbTheme::okabe_ito())
+    }
+}
+
+/// RGB colors used to build [`Styles`] with [`Styles::with_rgb_theme`].
+#[cfg(feature = "termcolor")]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct RgbTheme {
+    /// The color of bug headers and labels.
+    pub bug: (u8, u8, u8),
+    /// The color of error headers and labels.
+    pub error: (u8, u8, u8),
+    /// The color of warning headers and labels.
+    pub warning: (u8, u8, u8),
+    /// The color of note headers and labels.
+    pub note: (u8, u8, u8),
+    /// The color of help headers and labels.
+    pub help: (u8, u8, u8),
+    /// The color of secondary labels.
+    pub secondary_label: (u8, u8, u8),
+    /// The color of line numbers.
+    pub line_number: (u8, u8, u8),
+    /// The color of source borders and note bullets.
+    pub source_border: (u8, u8, u8),
+}
+
+#[cfg(feature = "termcolor")]
+impl RgbTheme {
+    /// Colors from the [Dracula](https://draculatheme.com/) theme.
+    pub fn dracula() -> RgbTheme {
+        RgbTheme {
+            bug: (255, 121, 198),
+            error: (255, 85, 85),
+            warning: (255, 184, 108),
+            note: (80, 250, 123),
+            help: (139, 233, 253),
+            secondary_label: (189, 147, 249),
+            line_number: (98, 114, 164),
+            source_border: (98, 114, 164),
+        }
+    }
+
+    /// Colors from the [Solarized](https://ethanschoonover.com/solarized/) theme.
+    pub fn solarized() -> RgbTheme {
+        RgbTheme {
+            bug: (211, 54, 130),
+            error: (220, 50, 47),
+            warning: (181, 137, 0),
+            note: (133, 153, 0),
+            help: (42, 161, 152),
+            secondary_label: (38, 139, 210),
+            line_number: (88, 110, 117),
+            source_border: (88, 110, 117),
+        }
+    }
+
+    /// Colors from the [Okabe-Ito](https://jfly.uni-koeln.de/color/) palette,
+    /// which can be told apart with the common forms of color blindness.
+    ///
+    /// Errors are vermillion, warnings orange, bugs purple, and notes and help
+    /// messages are blue-green and sky blue, so that no two severities differ
+    /// only by red and green.
+    pub fn okabe_ito() -> RgbTheme {
+        RgbTheme {
+            bug: (204, 121, 167),
+            error: (213, 94, 0),
+            warning: (230, 159, 0),
+            note: (0, 158, 115),
+            help: (86, 180, 233),
+            secondary_label: (0, 114, 178),
+            line_number: (0, 114, 178),
+            source_border: (0, 114, 178),
         }
     }
 }
@@ -229,10 +1754,104 @@ impl Styles {
 #[cfg(feature = "termcolor")]
 impl Default for Styles {
     fn default() -> Styles {
-        Self::with_blue(Color::Cyan)
+        if cfg!(windows) {
+            Self::with_blue(Color::Cyan)
+        } else {
+            Self::with_blue(Color::Blue)
+        }
+    }
+}
+
+/// The colors of the 16 color palette, as `(color, intense, rgb)`, using the
+/// values of the legacy Windows console.
+#[cfg(feature = "termcolor")]
+const ANSI16_PALETTE: [(Color, bool, (u8, u8, u8)); 16] = [
+    (Color::Black, false, (0, 0, 0)),
+    (Color::Red, false, (128, 0, 0)),
+    (Color::Green, false, (0, 128, 0)),
+    (Color::Yellow, false, (128, 128, 0)),
+    (Color::Blue, false, (0, 0, 128)),
+    (Color::Magenta, false, (128, 0, 128)),
+    (Color::Cyan, false, (0, 128, 128)),
+    (Color::White, false, (192, 192, 192)),
+    (Color::Black, true, (128, 128, 128)),
+    (Color::Red, true, (255, 0, 0)),
+    (Color::Green, true, (0, 255, 0)),
+    (Color::Yellow, true, (255, 255, 0)),
+    (Color::Blue, true, (0, 0, 255)),
+    (Color::Magenta, true, (255, 0, 255)),
+    (Color::Cyan, true, (0, 255, 255)),
+    (Color::White, true, (255, 255, 255)),
+];
+
+/// The RGB value of a color of the 256 color palette.
+#[cfg(feature = "termcolor")]
+fn ansi256_to_rgb(index: u8) -> (u8, u8, u8) {
+    match index {
+        0..=15 => ANSI16_PALETTE[usize::from(index)].2,
+        16..=231 => {
+            let level = |value: u8| if value == 0 { 0 } else { 55 + 40 * value };
+            let index = index - 16;
+            (level(index / 36), level(index / 6 % 6), level(index % 6))
+        }
+        232..=255 => {
+            let gray = 8 + 10 * (index - 232);
+            (gray, gray, gray)
+        }
+    }
+}
+
+/// The color of the 16 color palette that is nearest to the given color, and
+/// whether it is intense. Colors that are already in the palette are returned
+/// as is.
+#[cfg(feature = "termcolor")]
+fn nearest_ansi16(color: Color) -> Option<(Color, bool)> {
+    let (red, green, blue) = match color {
+        Color::Rgb(red, green, blue) => (red, green, blue),
+        Color::Ansi256(index) => ansi256_to_rgb(index),
+        _ => return None,
+    };
+    let distance = |(r, g, b): (u8, u8, u8)| {
+        let square = |a: u8, b: u8| (i32::from(a) - i32::from(b)).pow(2);
+        square(r, red) + square(g, green) + square(b, blue)
+    };
+
+    ANSI16_PALETTE
+        .iter()
+        .min_by_key(|(_, _, rgb)| distance(*rgb))
+        .map(|(color, intense, _)| (*color, *intense))
+}
+
+/// Downgrade the 256 color and truecolor colors of a color spec to the nearest
+/// of the 16 colors supported by legacy Windows consoles.
+///
+/// The foreground color decides whether the spec is intense, because consoles
+/// only support a single intensity for both colors. [`StylesWriter`] uses this
+/// for writers that report that they set colors synchronously, which is the
+/// case for legacy Windows consoles.
+#[cfg(feature = "termcolor")]
+pub fn downgrade_color_spec(spec: &ColorSpec) -> ColorSpec {
+    let mut spec = spec.clone();
+    if let Some((color, intense)) = spec.fg().copied().and_then(nearest_ansi16) {
+        spec.set_fg(Some(color)).set_intense(intense);
+    }
+    if let Some((color, _)) = spec.bg().copied().and_then(nearest_ansi16) {
+        spec.set_bg(Some(color));
     }
+    spec
 }
 
+/// A writer that styles diagnostics with the given [`Styles`].
+///
+/// Any [`WriteColor`] already implements [`WriteStyle`] using
+/// [`Styles::default()`]. Wrapping it in a `StylesWriter` takes precedence
+/// over that: only the colors, resets and hyperlinks of the wrapped writer
+/// are used, never its own [`WriteStyle`] implementation. This makes it
+/// possible to combine a local theme with a custom [`WriteColor`], for example
+/// one that adds timestamps to its output. See also [`WriteStyle::with_styles`].
+///
+/// If the wrapped writer sets colors synchronously, like legacy Windows
+/// consoles, colors are downgraded with [`downgrade_color_spec`].
 #[cfg(feature = "termcolor")]
 pub struct StylesWriter<'a, W> {
     writer: W,
@@ -241,9 +1860,45 @@ pub struct StylesWriter<'a, W> {
 
 #[cfg(feature = "termcolor")]
 impl<'a, W> StylesWriter<'a, W> {
+    /// Create a new writer styling the output of `writer` with `style`.
     pub fn new(writer: W, style: &'a Styles) -> Self {
         Self { writer, style }
     }
+
+    /// Get a reference to the wrapped writer.
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+
+    /// Get a mutable reference to the wrapped writer.
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.writer
+    }
+
+    /// Consume the styles writer, returning the wrapped writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// The escape sequence for a wavy underline, which is supported by most
+/// terminals that support ANSI colors, and ignored by the others. It is reset
+/// like the other styles.
+#[cfg(feature = "termcolor")]
+const WAVY_UNDERLINE: &[u8] = b"\x1b[4:3m";
+
+#[cfg(feature = "termcolor")]
+impl<'a, W: WriteColor> StylesWriter<'a, W> {
+    /// Set the color of the underlying writer, unless it does not support color.
+    fn set_color(&mut self, spec: &ColorSpec) -> io::Result<()> {
+        if !self.writer.supports_color() {
+            return Ok(());
+        }
+        if self.writer.is_synchronous() {
+            return self.writer.set_color(&downgrade_color_spec(spec));
+        }
+        self.writer.set_color(spec)
+    }
 }
 
 #[cfg(feature = "termcolor")]
@@ -265,88 +1920,209 @@ impl Write for StylesWriter<'_, '_> {
         self.writer.write_str(s)
     }
 
-    fn write_char(&mut self, c: char) -> core::fmt::Result {
-        self.writer.write_char(c)
+    fn write_char(&mut self, c: char) -> core::fmt::Result {
+        self.writer.write_char(c)
+    }
+
+    fn write_fmt(&mut self, args: Arguments<'_>) -> core::fmt::Result {
+        self.writer.write_fmt(args)
+    }
+}
+
+#[cfg(feature = "termcolor")]
+impl<'a, W: WriteColor> WriteStyle for StylesWriter<'a, W> {
+    fn set_header(&mut self, severity: Severity) -> io::Result<()> {
+        self.set_color(self.style.header(severity))
+    }
+
+    fn set_header_message(&mut self) -> io::Result<()> {
+        self.set_color(&self.style.header_message)
+    }
+
+    fn set_line_number(&mut self) -> io::Result<()> {
+        self.set_color(&self.style.line_number)
+    }
+
+    fn set_note_bullet(&mut self) -> io::Result<()> {
+        self.set_color(&self.style.note_bullet)
+    }
+
+    fn set_source_border(&mut self) -> io::Result<()> {
+        self.set_color(&self.style.source_border)
+    }
+
+    fn set_label(&mut self, severity: Severity, label_style: LabelStyle) -> io::Result<()> {
+        let spec = self.style.label(severity, label_style);
+        self.set_color(spec)
+    }
+
+    fn set_span_background(&mut self) -> io::Result<()> {
+        self.set_color(&self.style.span_background())
     }
 
-    fn write_fmt(&mut self, args: Arguments<'_>) -> core::fmt::Result {
-        self.writer.write_fmt(args)
+    fn set_whitespace(&mut self) -> io::Result<()> {
+        self.set_color(&self.style.whitespace())
     }
-}
 
-#[cfg(feature = "termcolor")]
-impl<'a, W: WriteColor> WriteStyle for StylesWriter<'a, W> {
-    fn set_header(&mut self, severity: Severity) -> io::Result<()> {
-        self.writer.set_color(self.style.header(severity))
+    fn set_source_style(&mut self, spec: &ColorSpec) -> io::Result<()> {
+        self.set_color(spec)
     }
 
-    fn set_header_message(&mut self) -> io::Result<()> {
-        self.writer.set_color(&self.style.header_message)
+    fn set_wavy_underline(&mut self) -> io::Result<()> {
+        match self.supports_wavy_underline() {
+            true => self.writer.write_all(WAVY_UNDERLINE),
+            false => Ok(()),
+        }
     }
 
-    fn set_line_number(&mut self) -> io::Result<()> {
-        self.writer.set_color(&self.style.line_number)
+    fn supports_wavy_underline(&self) -> bool {
+        self.writer.supports_color() && !self.writer.is_synchronous()
     }
 
-    fn set_note_bullet(&mut self) -> io::Result<()> {
-        self.writer.set_color(&self.style.note_bullet)
+    fn set_context_line(&mut self) -> io::Result<()> {
+        self.set_color(&self.style.context_line)
     }
 
-    fn set_source_border(&mut self) -> io::Result<()> {
-        self.writer.set_color(&self.style.source_border)
+    fn set_suggestion_insertion(&mut self) -> io::Result<()> {
+        self.set_color(&self.style.suggestion_insertion)
     }
 
-    fn set_label(&mut self, severity: Severity, label_style: LabelStyle) -> io::Result<()> {
-        let spec = self.style.label(severity, label_style);
-        self.writer.set_color(spec)
+    fn set_suggestion_deletion(&mut self) -> io::Result<()> {
+        self.set_color(&self.style.suggestion_deletion)
     }
 
     fn reset(&mut self) -> io::Result<()> {
         self.writer.reset()
     }
+
+    fn start_hyperlink(&mut self, url: &str) -> io::Result<()> {
+        if !self.writer.supports_hyperlinks() {
+            return Ok(());
+        }
+        self.writer
+            .set_hyperlink(&HyperlinkSpec::open(url.as_bytes()))
+    }
+
+    fn end_hyperlink(&mut self) -> io::Result<()> {
+        if !self.writer.supports_hyperlinks() {
+            return Ok(());
+        }
+        self.writer.set_hyperlink(&HyperlinkSpec::close())
+    }
+
+    fn is_styled(&self) -> bool {
+        self.writer.supports_color()
+    }
+}
+
+#[cfg(feature = "termcolor")]
+std::thread_local! {
+    /// The styles of the [`WriteStyle`] implementation for any [`WriteColor`],
+    /// built once per thread instead of on every change of style.
+    static DEFAULT_STYLES: Styles = Styles::default();
 }
 
+/// Styles any [`WriteColor`] with [`Styles::default()`].
+///
+/// The styles are built once per thread, and there is no shared theme to
+/// change. Use [`WriteStyle::with_styles`] or [`StylesWriter`] to render with
+/// other styles.
 #[cfg(feature = "termcolor")]
 impl<T> WriteStyle for T
 where
     T: WriteColor,
 {
     fn set_header(&mut self, severity: Severity) -> io::Result<()> {
-        self.set_color(Styles::default().header(severity))
+        DEFAULT_STYLES.with(|styles| self.set_color(styles.header(severity)))
     }
 
     fn set_header_message(&mut self) -> io::Result<()> {
-        self.set_color(&Styles::default().header_message)
+        DEFAULT_STYLES.with(|styles| self.set_color(&styles.header_message))
     }
 
     fn set_line_number(&mut self) -> io::Result<()> {
-        self.set_color(&Styles::default().line_number)
+        DEFAULT_STYLES.with(|styles| self.set_color(&styles.line_number))
     }
 
     fn set_note_bullet(&mut self) -> io::Result<()> {
-        self.set_color(&Styles::default().note_bullet)
+        DEFAULT_STYLES.with(|styles| self.set_color(&styles.note_bullet))
     }
 
     fn set_source_border(&mut self) -> io::Result<()> {
-        self.set_color(&Styles::default().source_border)
+        DEFAULT_STYLES.with(|styles| self.set_color(&styles.source_border))
     }
 
     fn set_label(&mut self, severity: Severity, label_style: LabelStyle) -> io::Result<()> {
-        let styles = Styles::default();
-        let spec = styles.label(severity, label_style);
+        DEFAULT_STYLES.with(|styles| self.set_color(styles.label(severity, label_style)))
+    }
+
+    fn set_span_background(&mut self) -> io::Result<()> {
+        DEFAULT_STYLES.with(|styles| self.set_color(&styles.span_background()))
+    }
+
+    fn set_whitespace(&mut self) -> io::Result<()> {
+        DEFAULT_STYLES.with(|styles| self.set_color(&styles.whitespace()))
+    }
+
+    fn set_source_style(&mut self, spec: &ColorSpec) -> io::Result<()> {
         self.set_color(spec)
     }
 
+    fn set_wavy_underline(&mut self) -> io::Result<()> {
+        match self.supports_wavy_underline() {
+            true => self.write_all(WAVY_UNDERLINE),
+            false => Ok(()),
+        }
+    }
+
+    fn supports_wavy_underline(&self) -> bool {
+        self.supports_color() && !self.is_synchronous()
+    }
+
+    fn set_context_line(&mut self) -> io::Result<()> {
+        DEFAULT_STYLES.with(|styles| self.set_color(&styles.context_line))
+    }
+
+    fn set_suggestion_insertion(&mut self) -> io::Result<()> {
+        DEFAULT_STYLES.with(|styles| self.set_color(&styles.suggestion_insertion))
+    }
+
+    fn set_suggestion_deletion(&mut self) -> io::Result<()> {
+        DEFAULT_STYLES.with(|styles| self.set_color(&styles.suggestion_deletion))
+    }
+
     fn reset(&mut self) -> io::Result<()> {
         self.reset()
     }
+
+    fn start_hyperlink(&mut self, url: &str) -> io::Result<()> {
+        if !self.supports_hyperlinks() {
+            return Ok(());
+        }
+        self.set_hyperlink(&HyperlinkSpec::open(url.as_bytes()))
+    }
+
+    fn end_hyperlink(&mut self) -> io::Result<()> {
+        if !self.supports_hyperlinks() {
+            return Ok(());
+        }
+        self.set_hyperlink(&HyperlinkSpec::close())
+    }
+
+    fn is_styled(&self) -> bool {
+        self.supports_color()
+    }
 }
 
 /// Characters to use when rendering the diagnostic.
 ///
 /// By using [`Chars::ascii()`] you can switch to an ASCII-only format suitable
 /// for rendering on terminals that do not support box drawing characters.
-#[derive(Clone, Debug)]
+/// The [`Chars::rounded()`] and [`Chars::heavy()`] presets use rounded or
+/// heavy box drawing characters instead.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serialization", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serialization", serde(default))]
 pub struct Chars {
     /// The characters to use for the top-left border of the snippet.
     /// Defaults to: `"┌─"` or `"-->"` with [`Chars::ascii()`].
@@ -362,12 +2138,32 @@ pub struct Chars {
     /// Defaults to: `'='`.
     pub note_bullet: char,
 
-    /// The character to use for marking a single-line primary label.
-    /// Defaults to: `'^'`.
-    pub single_primary_caret: char,
-    /// The character to use for marking a single-line secondary label.
-    /// Defaults to: `'-'`.
-    pub single_secondary_caret: char,
+    /// The string to use for marking a single-line primary label.
+    ///
+    /// The caret may be wider than a single column, such as an emoji, in
+    /// which case it is repeated as often as it fits underneath the label,
+    /// and the columns left over are padded with spaces, so that the carets
+    /// stay aligned with the source. Labels that are narrower than the caret
+    /// are still marked with a single caret.
+    ///
+    /// Defaults to: `"^"`.
+    pub single_primary_caret: String,
+    /// The string to use for marking a single-line secondary label, which
+    /// may be wider than a single column, like
+    /// [`Chars::single_primary_caret`].
+    ///
+    /// Defaults to: `"-"`.
+    pub single_secondary_caret: String,
+    /// The character to use for connecting the carets at the ends of a
+    /// single-line primary label, if [`Config::caret_fill_style`] is
+    /// [`CaretFillStyle::Endpoints`].
+    /// Defaults to: `'─'` or `'-'` with [`Chars::ascii()`].
+    pub single_primary_caret_connector: char,
+    /// The character to use for connecting the carets at the ends of a
+    /// single-line secondary label, if [`Config::caret_fill_style`] is
+    /// [`CaretFillStyle::Endpoints`].
+    /// Defaults to: `'─'` or `'-'` with [`Chars::ascii()`].
+    pub single_secondary_caret_connector: char,
 
     /// The character to use for marking the start of a multi-line primary label.
     /// Defaults to: `'^'`.
@@ -400,6 +2196,29 @@ pub struct Chars {
     /// The character to use for the left of a pointer underneath a caret.
     /// Defaults to: `'│'` or `'|'` with [`Chars::ascii()`].
     pub pointer_left: char,
+
+    /// The character to render in place of a space when whitespace is
+    /// rendered visibly, which should be one column wide.
+    /// Defaults to: `'·'` or `'.'` with [`Chars::ascii()`].
+    pub space_glyph: char,
+    /// The character to render at the start of a tab when whitespace is
+    /// rendered visibly, which should be one column wide. The rest of the
+    /// tab is filled with spaces.
+    /// Defaults to: `'→'` or `'>'` with [`Chars::ascii()`].
+    pub tab_glyph: char,
+    /// The character to render in place of control characters in source
+    /// lines, if [`Config::sanitize_control_chars`] is enabled, which should
+    /// be one column wide.
+    /// Defaults to: `'�'` or `'?'` with [`Chars::ascii()`].
+    pub control_glyph: char,
+
+    /// The characters to use for marking primary labels at each severity,
+    /// replacing [`Chars::single_primary_caret`] and the multi-line primary
+    /// carets. This conveys the severity of a diagnostic by the shape of its
+    /// carets, and not only by their color.
+    /// Defaults to: `None`, or [`SeverityCarets::distinct()`] with
+    /// [`Chars::high_contrast()`].
+    pub severity_carets: Option<SeverityCarets>,
 }
 
 impl Default for Chars {
@@ -418,8 +2237,61 @@ impl Chars {
 
             note_bullet: '=',
 
-            single_primary_caret: '^',
-            single_secondary_caret: '-',
+            single_primary_caret: "^".into(),
+            single_secondary_caret: "-".into(),
+            single_primary_caret_connector: '─',
+            single_secondary_caret_connector: '─',
+
+            multi_primary_caret_start: '^',
+            multi_primary_caret_end: '^',
+            multi_secondary_caret_start: '\'',
+            multi_secondary_caret_end: '\'',
+            multi_top_left: '╭',
+            multi_top: '─',
+            multi_bottom_left: '╰',
+            multi_bottom: '─',
+            multi_left: '│',
+
+            pointer_left: '│',
+
+            space_glyph: '·',
+            tab_glyph: '→',
+            control_glyph: '\u{FFFD}',
+
+            severity_carets: None,
+        }
+    }
+
+    /// A character set that uses Unicode box drawing characters, with a
+    /// different caret for primary labels at each severity.
+    ///
+    /// This is meant to be used with [`Styles::high_contrast()`], so that no
+    /// information is conveyed by color alone.
+    ///
+    /// [`Styles::high_contrast()`]: crate::term::Styles::high_contrast
+    pub fn high_contrast() -> Chars {
+        Chars {
+            severity_carets: Some(SeverityCarets::distinct()),
+            ..Chars::box_drawing()
+        }
+    }
+
+    /// A character set that uses Unicode box drawing characters with a rounded
+    /// corner at the start of each snippet, and a dashed border where source
+    /// lines are left out. The corners of multi-line labels are rounded, as
+    /// with [`Chars::box_drawing()`].
+    pub fn rounded() -> Chars {
+        Chars {
+            snippet_start: "╭─".into(),
+            source_border_left: '│',
+            source_border_left_break: '┆',
+
+            note_bullet: '=',
+
+            single_primary_caret: "^".into(),
+            single_secondary_caret: "-".into(),
+            single_primary_caret_connector: '─',
+            single_secondary_caret_connector: '─',
 
             multi_primary_caret_start: '^',
             multi_primary_caret_end: '^',
@@ -432,6 +2304,46 @@ impl Chars {
             multi_left: '│',
 
             pointer_left: '│',
+
+            space_glyph: '·',
+            tab_glyph: '→',
+            control_glyph: '\u{FFFD}',
+
+            severity_carets: None,
+        }
+    }
+
+    /// A character set that uses heavy Unicode box drawing characters.
+    pub fn heavy() -> Chars {
+        Chars {
+            snippet_start: "┏━".into(),
+            source_border_left: '┃',
+            source_border_left_break: '╏',
+
+            note_bullet: '=',
+
+            single_primary_caret: "^".into(),
+            single_secondary_caret: "-".into(),
+            single_primary_caret_connector: '━',
+            single_secondary_caret_connector: '━',
+
+            multi_primary_caret_start: '^',
+            multi_primary_caret_end: '^',
+            multi_secondary_caret_start: '\'',
+            multi_secondary_caret_end: '\'',
+            multi_top_left: '┏',
+            multi_top: '━',
+            multi_bottom_left: '┗',
+            multi_bottom: '━',
+            multi_left: '┃',
+
+            pointer_left: '┃',
+
+            space_glyph: '·',
+            tab_glyph: '→',
+            control_glyph: '\u{FFFD}',
+
+            severity_carets: None,
         }
     }
 
@@ -448,8 +2360,10 @@ impl Chars {
 
             note_bullet: '=',
 
-            single_primary_caret: '^',
-            single_secondary_caret: '-',
+            single_primary_caret: "^".into(),
+            single_secondary_caret: "-".into(),
+            single_primary_caret_connector: '-',
+            single_secondary_caret_connector: '-',
 
             multi_primary_caret_start: '^',
             multi_primary_caret_end: '^',
@@ -462,6 +2376,642 @@ impl Chars {
             multi_left: '|',
 
             pointer_left: '|',
+
+            space_glyph: '.',
+            tab_glyph: '>',
+            control_glyph: '?',
+
+            severity_carets: None,
+        }
+    }
+
+    /// The character to use for marking a primary label at the given
+    /// severity, which is `caret` unless it is replaced by
+    /// [`Chars::severity_carets`].
+    pub(crate) fn primary_caret(&self, severity: Severity, caret: char) -> char {
+        match &self.severity_carets {
+            Some(severity_carets) => severity_carets.get(severity),
+            None => caret,
+        }
+    }
+
+    /// The caret to use for marking a single-line label at the given
+    /// severity, encoding carets replaced by [`Chars::severity_carets`] into
+    /// `buffer`.
+    pub(crate) fn single_caret<'chars>(
+        &'chars self,
+        severity: Severity,
+        label_style: LabelStyle,
+        buffer: &'chars mut [u8; 4],
+    ) -> &'chars str {
+        match label_style {
+            LabelStyle::Primary => match &self.severity_carets {
+                Some(severity_carets) => severity_carets.get(severity).encode_utf8(buffer),
+                None => &self.single_primary_caret,
+            },
+            LabelStyle::Secondary => &self.single_secondary_caret,
+        }
+    }
+}
+
+/// Characters to use for marking primary labels at each severity.
+///
+/// These are used by [`Chars::high_contrast()`], and can be set on any other
+/// character set with [`Chars::severity_carets`]:
+///
+/// ```rust
+/// use codespan_reporting::diagnostic::{Diagnostic, Label};
+/// use codespan_reporting::files::SimpleFile;
+/// use codespan_reporting::term::{self, Chars, Config, SeverityCarets};
+///
+/// let mut chars = Chars::ascii();
+/// chars.severity_carets = Some(SeverityCarets::distinct());
+/// let config = Config::builder().chars(chars).build();
+///
+/// let file = SimpleFile::new("main.fun", "let x = 1;\n");
+/// let diagnostic = Diagnostic::warning().with_labels(vec![Label::primary((), 4..5)]);
+/// let output = term::to_string(&config, &file, &diagnostic).unwrap();
+/// assert!(output.contains("|     ~\n"));
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serialization", derive(Serialize, Deserialize))]
+pub struct SeverityCarets {
+    /// The caret to use for bugs.
+    pub bug: char,
+    /// The caret to use for errors.
+    pub error: char,
+    /// The caret to use for warnings.
+    pub warning: char,
+    /// The caret to use for notes.
+    pub note: char,
+    /// The caret to use for help messages.
+    pub help: char,
+}
+
+impl SeverityCarets {
+    /// Carets that are distinct from each other and from the default
+    /// secondary caret: `'!'` for bugs, `'^'` for errors, `'~'` for warnings,
+    /// `'*'` for notes, and `'+'` for help messages.
+    pub fn distinct() -> SeverityCarets {
+        SeverityCarets {
+            bug: '!',
+            error: '^',
+            warning: '~',
+            note: '*',
+            help: '+',
+        }
+    }
+
+    /// The caret to use for the given severity.
+    pub fn get(&self, severity: Severity) -> char {
+        match severity {
+            Severity::Bug => self.bug,
+            Severity::Error => self.error,
+            Severity::Warning => self.warning,
+            Severity::Note => self.note,
+            Severity::Help => self.help,
+        }
+    }
+}
+
+/// Serialization of [`ColorSpec`]s, for use with `#[serde(with = "...")]`.
+#[cfg(all(feature = "termcolor", feature = "serialization"))]
+mod color_spec_serde {
+    use alloc::format;
+    use alloc::string::{String, ToString};
+    use serde::{de, ser, Deserialize, Deserializer, Serialize, Serializer};
+    use termcolor::{Color, ColorSpec};
+
+    /// A [`ColorSpec`], with every attribute that differs from
+    /// [`ColorSpec::new`] being optional.
+    #[derive(Serialize, Deserialize)]
+    #[serde(default)]
+    struct SerdeColorSpec {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        fg: Option<SerdeColor>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        bg: Option<SerdeColor>,
+        #[serde(skip_serializing_if = "is_false")]
+        bold: bool,
+        #[serde(skip_serializing_if = "is_false")]
+        intense: bool,
+        #[serde(skip_serializing_if = "is_false")]
+        underline: bool,
+        #[serde(skip_serializing_if = "is_false")]
+        dimmed: bool,
+        #[serde(skip_serializing_if = "is_false")]
+        italic: bool,
+        #[serde(skip_serializing_if = "is_false")]
+        strikethrough: bool,
+        #[serde(skip_serializing_if = "is_true")]
+        reset: bool,
+    }
+
+    fn is_false(value: &bool) -> bool {
+        !*value
+    }
+
+    fn is_true(value: &bool) -> bool {
+        *value
+    }
+
+    impl Default for SerdeColorSpec {
+        fn default() -> SerdeColorSpec {
+            SerdeColorSpec::from(&ColorSpec::new())
+        }
+    }
+
+    impl From<&ColorSpec> for SerdeColorSpec {
+        fn from(spec: &ColorSpec) -> SerdeColorSpec {
+            SerdeColorSpec {
+                fg: spec.fg().copied().map(SerdeColor),
+                bg: spec.bg().copied().map(SerdeColor),
+                bold: spec.bold(),
+                intense: spec.intense(),
+                underline: spec.underline(),
+                dimmed: spec.dimmed(),
+                italic: spec.italic(),
+                strikethrough: spec.strikethrough(),
+                reset: spec.reset(),
+            }
+        }
+    }
+
+    impl From<SerdeColorSpec> for ColorSpec {
+        fn from(spec: SerdeColorSpec) -> ColorSpec {
+            ColorSpec::new()
+                .set_fg(spec.fg.map(|color| color.0))
+                .set_bg(spec.bg.map(|color| color.0))
+                .set_bold(spec.bold)
+                .set_intense(spec.intense)
+                .set_underline(spec.underline)
+                .set_dimmed(spec.dimmed)
+                .set_italic(spec.italic)
+                .set_strikethrough(spec.strikethrough)
+                .set_reset(spec.reset)
+                .clone()
+        }
+    }
+
+    /// A [`Color`], represented in the format accepted by its `FromStr`
+    /// implementation.
+    struct SerdeColor(Color);
+
+    impl Serialize for SerdeColor {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let name = match self.0 {
+                Color::Black => "black".to_string(),
+                Color::Blue => "blue".to_string(),
+                Color::Green => "green".to_string(),
+                Color::Red => "red".to_string(),
+                Color::Cyan => "cyan".to_string(),
+                Color::Magenta => "magenta".to_string(),
+                Color::Yellow => "yellow".to_string(),
+                Color::White => "white".to_string(),
+                Color::Ansi256(n) => n.to_string(),
+                Color::Rgb(r, g, b) => format!("{r},{g},{b}"),
+                _ => return Err(ser::Error::custom("unsupported color")),
+            };
+            serializer.serialize_str(&name)
+        }
+    }
+
+    impl<'de> Deserialize<'de> for SerdeColor {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<SerdeColor, D::Error> {
+            let name = String::deserialize(deserializer)?;
+            match name.parse() {
+                Ok(color) => Ok(SerdeColor(color)),
+                Err(err) => Err(de::Error::custom(err)),
+            }
+        }
+    }
+
+    pub fn serialize<S: Serializer>(spec: &ColorSpec, serializer: S) -> Result<S::Ok, S::Error> {
+        SerdeColorSpec::from(spec).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<ColorSpec, D::Error> {
+        SerdeColorSpec::deserialize(deserializer).map(ColorSpec::from)
+    }
+
+    /// Serialization of maps with [`ColorSpec`] values.
+    pub mod map {
+        use super::SerdeColorSpec;
+        use core::hash::Hash;
+        use serde::{Deserialize, Deserializer, Serialize, Serializer};
+        use std::collections::HashMap;
+        use termcolor::ColorSpec;
+
+        pub fn serialize<K, S>(
+            map: &HashMap<K, ColorSpec>,
+            serializer: S,
+        ) -> Result<S::Ok, S::Error>
+        where
+            K: Serialize,
+            S: Serializer,
+        {
+            serializer.collect_map(
+                map.iter()
+                    .map(|(key, spec)| (key, SerdeColorSpec::from(spec))),
+            )
+        }
+
+        pub fn deserialize<'de, K, D>(deserializer: D) -> Result<HashMap<K, ColorSpec>, D::Error>
+        where
+            K: Deserialize<'de> + Eq + Hash,
+            D: Deserializer<'de>,
+        {
+            let map = HashMap::<K, SerdeColorSpec>::deserialize(deserializer)?;
+            Ok(map
+                .into_iter()
+                .map(|(key, spec)| (key, spec.into()))
+                .collect())
+        }
+    }
+}
+
+#[cfg(all(test, feature = "termcolor"))]
+mod tests {
+    use alloc::vec::Vec;
+    use std::io::Write as _;
+
+    use super::*;
+
+    #[test]
+    fn builder_overrides_defaults() {
+        let config = Config::builder()
+            .display_style(DisplayStyle::Short)
+            .tab_width(2)
+            .chars(Chars::ascii())
+            .start_context_lines(5)
+            .build();
+
+        assert!(matches!(config.display_style, DisplayStyle::Short));
+        assert_eq!(config.tab_width, 2);
+        assert_eq!(config.chars.snippet_start, "-->");
+        assert_eq!(config.start_context_lines, 5);
+        assert_eq!(
+            config.end_context_lines,
+            Config::default().end_context_lines
+        );
+    }
+
+    #[test]
+    fn config_from_env() {
+        let cases = [
+            (None, None, None, false, false),
+            (Some("1"), None, None, true, false),
+            (Some("0"), Some(""), None, false, false),
+            (None, Some("1"), None, false, true),
+            (None, Some("1"), Some("0"), false, true),
+            (None, Some("1"), Some("1"), false, false),
+        ];
+        for (ascii, no_color, clicolor_force, is_ascii, force_no_color) in cases {
+            let vars = [
+                ("CODESPAN_ASCII", ascii),
+                ("NO_COLOR", no_color),
+                ("CLICOLOR_FORCE", clicolor_force),
+            ];
+            let var = |name: &str| {
+                let value = vars.iter().find(|(var_name, _)| *var_name == name)?.1;
+                value.map(OsString::from)
+            };
+
+            let config = Config::from_vars(var);
+            assert_eq!(config.chars.snippet_start == "-->", is_ascii);
+            assert_eq!(config.force_no_color, force_no_color);
+
+            // The color choice is tested here too, as both read the same variables
+            let expected = match (clicolor_force, force_no_color) {
+                (Some("1"), _) => ColorChoice::Always,
+                (_, true) => ColorChoice::Never,
+                _ => ColorChoice::Auto,
+            };
+            assert_eq!(color_choice_from_vars(true, var), expected);
+            match expected {
+                ColorChoice::Always => {
+                    assert_eq!(color_choice_from_vars(false, var), ColorChoice::Always)
+                }
+                _ => assert_eq!(color_choice_from_vars(false, var), ColorChoice::Never),
+            }
+        }
+    }
+
+    #[cfg(feature = "serialization")]
+    #[test]
+    fn styles_round_trip() {
+        let mut styles = Styles::with_rgb_theme(&RgbTheme::dracula());
+        styles.header_message.set_italic(true).set_underline(true);
+        styles.note_bullet = ColorSpec::new()
+            .set_fg(Some(Color::Ansi256(208)))
+            .set_bg(Some(Color::Magenta))
+            .set_reset(false)
+            .clone();
+        styles.label_overrides.insert(
+            LabelStyle::Secondary,
+            ColorSpec::new().set_strikethrough(true).clone(),
+        );
+
+        let json = serde_json::to_string(&styles).unwrap();
+        assert_eq!(serde_json::from_str::<Styles>(&json).unwrap(), styles);
+    }
+
+    #[cfg(feature = "serialization")]
+    #[test]
+    fn partial_styles() {
+        let styles: Styles = serde_json::from_str(
+            r#"{ "header_error": { "fg": "255,0,0", "bold": true }, "note_bullet": { "fg": "green" } }"#,
+        )
+        .unwrap();
+
+        let expected = Styles {
+            header_error: ColorSpec::new()
+                .set_fg(Some(Color::Rgb(255, 0, 0)))
+                .set_bold(true)
+                .clone(),
+            note_bullet: ColorSpec::new().set_fg(Some(Color::Green)).clone(),
+            ..Styles::default()
+        };
+        assert_eq!(styles, expected);
+    }
+
+    #[cfg(feature = "serialization")]
+    #[test]
+    fn chars_round_trip() {
+        let chars = Chars::heavy();
+
+        let json = serde_json::to_string(&chars).unwrap();
+        assert_eq!(serde_json::from_str::<Chars>(&json).unwrap(), chars);
+    }
+
+    #[test]
+    fn label_attrs() {
+        let mut styles = Styles::default();
+        styles
+            .label_overrides
+            .insert(LabelStyle::Secondary, ColorSpec::new());
+        let styles = styles
+            .with_label_attr(LabelStyle::Primary, StyleAttr::Underline)
+            .with_label_attr(LabelStyle::Secondary, StyleAttr::Strikethrough);
+
+        for severity in [Severity::Bug, Severity::Error, Severity::Help] {
+            let spec = styles.label(severity, LabelStyle::Primary);
+            assert!(StyleAttr::Underline.is_set(spec));
+            assert!(!StyleAttr::Strikethrough.is_set(spec));
+        }
+        assert!(StyleAttr::Strikethrough.is_set(&styles.secondary_label));
+        assert!(
+            StyleAttr::Strikethrough.is_set(styles.label(Severity::Note, LabelStyle::Secondary))
+        );
+        assert!(!StyleAttr::Underline.is_set(&styles.header_error));
+    }
+
+    #[test]
+    fn high_contrast_labels_do_not_rely_on_color() {
+        let styles = Styles::high_contrast();
+
+        for severity in [
+            Severity::Bug,
+            Severity::Error,
+            Severity::Warning,
+            Severity::Note,
+            Severity::Help,
+        ] {
+            for label_style in [LabelStyle::Primary, LabelStyle::Secondary] {
+                let spec = styles.label(severity, label_style);
+                assert!(StyleAttr::Bold.is_set(spec));
+                assert!(StyleAttr::Underline.is_set(spec));
+                assert!(StyleAttr::Intense.is_set(spec));
+            }
+            assert!(StyleAttr::Bold.is_set(styles.header(severity)));
+        }
+
+        let carets = SeverityCarets::distinct();
+        let mut glyphs = [
+            String::from(carets.bug),
+            String::from(carets.error),
+            String::from(carets.warning),
+            String::from(carets.note),
+            String::from(carets.help),
+            Chars::high_contrast().single_secondary_caret,
+        ];
+        glyphs.sort_unstable();
+        assert!(glyphs.windows(2).all(|pair| pair[0] != pair[1]));
+    }
+
+    #[test]
+    fn strikethrough_is_emitted_as_sgr_9() {
+        let styles =
+            Styles::default().with_label_attr(LabelStyle::Secondary, StyleAttr::Strikethrough);
+        let mut writer = StylesWriter::new(termcolor::Ansi::new(Vec::<u8>::new()), &styles);
+
+        writer
+            .set_label(Severity::Warning, LabelStyle::Secondary)
+            .unwrap();
+
+        let output = writer.writer.into_inner();
+        assert!(output.windows(4).any(|window| window == b"\x1B[9m"));
+    }
+
+    #[test]
+    fn rgb_theme_colors() {
+        let styles = Styles::with_rgb_theme(&RgbTheme::dracula());
+
+        assert_eq!(styles.header_error.fg(), Some(&Color::Rgb(255, 85, 85)));
+        assert!(styles.header_error.bold());
+        assert_eq!(
+            styles.label(Severity::Warning, LabelStyle::Primary).fg(),
+            Some(&Color::Rgb(255, 184, 108)),
+        );
+        assert_eq!(styles.line_number.fg(), Some(&Color::Rgb(98, 114, 164)));
+    }
+
+    #[test]
+    fn color_blind_safe_colors_differ_from_defaults() {
+        let styles = Styles::color_blind_safe();
+        let defaults = Styles::default();
+
+        for severity in [
+            Severity::Bug,
+            Severity::Error,
+            Severity::Warning,
+            Severity::Note,
+            Severity::Help,
+        ] {
+            assert_ne!(styles.header(severity).fg(), defaults.header(severity).fg());
+            assert_ne!(
+                styles.label(severity, LabelStyle::Primary).fg(),
+                defaults.label(severity, LabelStyle::Primary).fg(),
+            );
+        }
+        assert_eq!(styles.header_error.fg(), Some(&Color::Rgb(213, 94, 0)));
+        assert_eq!(styles.header_note.fg(), Some(&Color::Rgb(0, 158, 115)));
+    }
+
+    #[test]
+    fn label_overrides_take_precedence() {
+        let deprecated = ColorSpec::new().set_fg(Some(Color::Magenta)).clone();
+        let mut styles = Styles::default();
+        styles
+            .label_overrides
+            .insert(LabelStyle::Secondary, deprecated.clone());
+
+        for severity in [Severity::Bug, Severity::Error, Severity::Help] {
+            assert_eq!(styles.label(severity, LabelStyle::Secondary), &deprecated);
+            assert_eq!(
+                styles.label(severity, LabelStyle::Primary),
+                Styles::default().label(severity, LabelStyle::Primary),
+            );
+        }
+
+        let mut writer = StylesWriter::new(termcolor::Ansi::new(Vec::<u8>::new()), &styles);
+        writer
+            .set_label(Severity::Error, LabelStyle::Secondary)
+            .unwrap();
+        assert_eq!(writer.writer.into_inner(), b"\x1B[0m\x1B[35m");
+    }
+
+    #[test]
+    fn with_styles_takes_precedence_over_defaults() {
+        let styles = Styles::with_rgb_theme(&RgbTheme::solarized());
+        let mut writer = termcolor::Ansi::new(Vec::<u8>::new()).with_styles(&styles);
+
+        writer.set_header(Severity::Error).unwrap();
+
+        assert_eq!(
+            writer.into_inner().into_inner(),
+            b"\x1B[0m\x1B[1m\x1B[38;2;220;50;47m"
+        );
+    }
+
+    #[test]
+    fn is_styled_follows_color_support() {
+        let styles = Styles::default();
+        let writers: [&dyn WriteStyle; 5] = [
+            &termcolor::Ansi::new(Vec::<u8>::new()),
+            &termcolor::NoColor::new(Vec::<u8>::new()),
+            &StylesWriter::new(termcolor::Ansi::new(Vec::<u8>::new()), &styles),
+            &StylesWriter::new(termcolor::NoColor::new(Vec::<u8>::new()), &styles),
+            &crate::term::PlainWriter::new(Vec::<u8>::new()),
+        ];
+
+        let is_styled = writers.map(|writer| writer.is_styled());
+        assert_eq!(is_styled, [true, false, true, false, false]);
+    }
+
+    #[test]
+    fn wavy_underline_follows_color_support() {
+        let styles = Styles::default();
+        let mut ansi = StylesWriter::new(termcolor::Ansi::new(Vec::<u8>::new()), &styles);
+        let mut no_color = StylesWriter::new(termcolor::NoColor::new(Vec::<u8>::new()), &styles);
+
+        assert!(ansi.supports_wavy_underline());
+        assert!(!no_color.supports_wavy_underline());
+
+        ansi.set_wavy_underline().unwrap();
+        no_color.set_wavy_underline().unwrap();
+        assert_eq!(ansi.into_inner().into_inner(), b"\x1b[4:3m");
+        assert!(no_color.into_inner().into_inner().is_empty());
+    }
+
+    #[test]
+    fn downgrade_to_nearest_ansi16_color() {
+        let downgrade = |color| {
+            let spec = downgrade_color_spec(ColorSpec::new().set_fg(Some(color)));
+            (spec.fg().copied(), spec.intense())
+        };
+
+        assert_eq!(downgrade(Color::Rgb(220, 50, 47)), (Some(Color::Red), true));
+        assert_eq!(
+            downgrade(Color::Rgb(20, 20, 150)),
+            (Some(Color::Blue), false)
+        );
+        assert_eq!(
+            downgrade(Color::Rgb(40, 40, 40)),
+            (Some(Color::Black), false)
+        );
+        assert_eq!(downgrade(Color::Ansi256(14)), (Some(Color::Cyan), true));
+        assert_eq!(downgrade(Color::Ansi256(196)), (Some(Color::Red), true));
+        assert_eq!(downgrade(Color::Ansi256(244)), (Some(Color::Black), true));
+        assert_eq!(downgrade(Color::Yellow), (Some(Color::Yellow), false));
+
+        let spec = downgrade_color_spec(
+            ColorSpec::new()
+                .set_fg(Some(Color::Green))
+                .set_bg(Some(Color::Rgb(255, 255, 255)))
+                .set_bold(true),
+        );
+        assert_eq!(spec.fg(), Some(&Color::Green));
+        assert_eq!(spec.bg(), Some(&Color::White));
+        assert!(spec.bold() && !spec.intense());
+    }
+
+    /// A writer that sets colors synchronously, like a legacy Windows console.
+    struct SynchronousWriter(termcolor::Ansi<Vec<u8>>);
+
+    impl io::Write for SynchronousWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.0.flush()
+        }
+    }
+
+    impl WriteColor for SynchronousWriter {
+        fn supports_color(&self) -> bool {
+            true
+        }
+
+        fn set_color(&mut self, spec: &ColorSpec) -> io::Result<()> {
+            self.0.set_color(spec)
         }
+
+        fn reset(&mut self) -> io::Result<()> {
+            WriteColor::reset(&mut self.0)
+        }
+
+        fn is_synchronous(&self) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    fn rgb_theme_on_synchronous_writer() {
+        let styles = Styles::with_rgb_theme(&RgbTheme::solarized());
+        let mut writer = SynchronousWriter(termcolor::Ansi::new(Vec::new())).with_styles(&styles);
+
+        writer.set_header(Severity::Error).unwrap();
+
+        assert_eq!(
+            writer.into_inner().0.into_inner(),
+            b"\x1B[0m\x1B[1m\x1B[38;5;9m"
+        );
+    }
+
+    #[test]
+    fn rgb_theme_without_color_support() {
+        let styles = Styles::with_rgb_theme(&RgbTheme::solarized());
+        let mut writer = StylesWriter::new(termcolor::NoColor::new(Vec::<u8>::new()), &styles);
+
+        writer.set_header(Severity::Error).unwrap();
+        write!(writer, "error").unwrap();
+        writer.reset().unwrap();
+
+        assert_eq!(writer.writer.into_inner(), b"error");
+    }
+
+    #[test]
+    fn rgb_theme_with_color_support() {
+        let styles = Styles::with_rgb_theme(&RgbTheme::solarized());
+        let mut writer = StylesWriter::new(termcolor::Ansi::new(Vec::<u8>::new()), &styles);
+
+        writer.set_header(Severity::Error).unwrap();
+
+        assert_eq!(
+            writer.writer.into_inner(),
+            b"\x1B[0m\x1B[1m\x1B[38;2;220;50;47m"
+        );
     }
 }
@@ -0,0 +1,102 @@
+//! Displaying diagnostics with [`fmt::Display`], for quick debugging.
+
+use core::fmt;
+
+use crate::diagnostic::Diagnostic;
+use crate::files::Files;
+use crate::term::renderer::{PlainWriter, Renderer};
+use crate::term::Config;
+
+#[cfg(feature = "std")]
+use std::io::{self, Write};
+
+impl<FileId> Diagnostic<FileId> {
+    /// Display the diagnostic, without any styling, using the given files and
+    /// config, which is handy for quick debugging:
+    ///
+    /// ```rust
+    /// use codespan_reporting::diagnostic::{Diagnostic, Label};
+    /// use codespan_reporting::files::SimpleFile;
+    /// use codespan_reporting::term::{Config, DisplayStyle};
+    ///
+    /// let file = SimpleFile::new("main.fun", "let x = 1;\n");
+    /// let diagnostic = Diagnostic::warning()
+    ///     .with_message("unused variable")
+    ///     .with_labels(vec![Label::primary((), 4..5)]);
+    ///
+    /// let config = Config {
+    ///     display_style: DisplayStyle::Short,
+    ///     ..Config::default()
+    /// };
+    /// println!("{}", diagnostic.display(&file, &config));
+    ///
+    /// assert_eq!(
+    ///     diagnostic.display(&file, &config).to_string(),
+    ///     "main.fun:1:5: warning: unused variable\n",
+    /// );
+    /// ```
+    ///
+    /// See [`DiagnosticDisplay`] for how errors are handled.
+    pub fn display<'a, F>(&'a self, files: &'a F, config: &'a Config) -> DiagnosticDisplay<'a, F>
+    where
+        F: Files<'a, FileId = FileId> + ?Sized,
+    {
+        DiagnosticDisplay {
+            diagnostic: self,
+            files,
+            config,
+        }
+    }
+}
+
+/// A diagnostic that is displayed without any styling, returned by
+/// [`Diagnostic::display`].
+///
+/// This renders the diagnostic like [`term::to_string`], but writes it
+/// straight into the formatter. As [`fmt::Display`] cannot report why
+/// rendering failed, an error from reading the files is turned into a
+/// [`fmt::Error`], which makes [`ToString::to_string`] panic. Use
+/// [`term::to_string`] directly to handle the error instead.
+///
+/// [`term::to_string`]: crate::term::to_string
+/// [`ToString::to_string`]: alloc::string::ToString::to_string
+pub struct DiagnosticDisplay<'a, F: Files<'a> + ?Sized> {
+    diagnostic: &'a Diagnostic<F::FileId>,
+    files: &'a F,
+    config: &'a Config,
+}
+
+impl<'a, F: Files<'a> + ?Sized> fmt::Display for DiagnosticDisplay<'a, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut writer = PlainWriter::new(FormatterWriter(f));
+        Renderer::new(&mut writer, self.config)
+            .render(self.files, self.diagnostic)
+            .map_err(|_| fmt::Error)
+    }
+}
+
+/// Adapts a formatter to the writer that the renderer writes to.
+struct FormatterWriter<'a, 'b>(&'a mut fmt::Formatter<'b>);
+
+#[cfg(feature = "std")]
+impl Write for FormatterWriter<'_, '_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let text = core::str::from_utf8(buf)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        self.0
+            .write_str(text)
+            .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(not(feature = "std"))]
+impl fmt::Write for FormatterWriter<'_, '_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.0.write_str(s)
+    }
+}
@@ -0,0 +1,318 @@
+//! A recording of the styled output of rendering a diagnostic, which can be
+//! inspected or written to a back-end later.
+
+use alloc::{string::String, vec::Vec};
+
+use crate::diagnostic::{LabelStyle, Severity};
+use crate::term::WriteStyle;
+
+#[cfg(feature = "std")]
+use std::io::{self, Write};
+#[cfg(feature = "std")]
+type WriteResult = io::Result<()>;
+
+#[cfg(not(feature = "std"))]
+use core::fmt::{Result as WriteResult, Write};
+
+/// The role of a [`Segment`] in a diagnostic, which determines how it is
+/// styled. Each role corresponds to a method of [`WriteStyle`] that replaces
+/// the current style.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum StyleRole {
+    /// Text without any styling, such as the source of unlabeled lines.
+    Plain,
+    /// The severity and code of the header, see [`WriteStyle::set_header`].
+    Header(Severity),
+    /// The message of the header, see [`WriteStyle::set_header_message`].
+    HeaderMessage,
+    /// Line numbers in the gutter, see [`WriteStyle::set_line_number`].
+    LineNumber,
+    /// The bullets of notes, see [`WriteStyle::set_note_bullet`].
+    NoteBullet,
+    /// The border around the source, see [`WriteStyle::set_source_border`].
+    SourceBorder,
+    /// Labeled source text, carets and label messages, see
+    /// [`WriteStyle::set_label`].
+    Label(Severity, LabelStyle),
+    /// Source lines that only provide context, see
+    /// [`WriteStyle::set_context_line`].
+    ContextLine,
+    /// Lines inserted by a suggested edit, see
+    /// [`WriteStyle::set_suggestion_insertion`].
+    SuggestionInsertion,
+    /// Lines deleted by a suggested edit, see
+    /// [`WriteStyle::set_suggestion_deletion`].
+    SuggestionDeletion,
+}
+
+/// A piece of rendered text, with the style it is rendered in.
+///
+/// The text may contain several lines, and adjacent text with the same style
+/// is always merged into a single segment.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Segment {
+    /// The role that the text is styled for.
+    pub role: StyleRole,
+    /// Whether the background of the text is highlighted, see
+    /// [`WriteStyle::set_span_background`].
+    pub span_background: bool,
+    /// Whether the text is visibly rendered whitespace, see
+    /// [`WriteStyle::set_whitespace`].
+    pub whitespace: bool,
+    /// Whether the text is underlined with a wavy line, see
+    /// [`WriteStyle::set_wavy_underline`].
+    pub wavy_underline: bool,
+    /// The URL that the text links to, if any.
+    pub hyperlink: Option<String>,
+    /// The rendered text.
+    pub text: String,
+}
+
+impl Segment {
+    fn plain() -> Segment {
+        Segment {
+            role: StyleRole::Plain,
+            span_background: false,
+            whitespace: false,
+            wavy_underline: false,
+            hyperlink: None,
+            text: String::new(),
+        }
+    }
+
+    fn has_style_of(&self, other: &Segment) -> bool {
+        self.role == other.role
+            && self.span_background == other.span_background
+            && self.whitespace == other.whitespace
+            && self.wavy_underline == other.wavy_underline
+    }
+}
+
+/// The output of rendering a diagnostic, recorded as a sequence of styled
+/// segments with [`record`].
+///
+/// This is the same output that [`emit`] writes to a [`WriteStyle`], so it
+/// can be used to write a custom back-end, such as a GUI, without working out
+/// the layout of the diagnostic again. The terminal and HTML back-ends do not
+/// go through it, and write to their writers directly.
+///
+/// [`record`]: crate::term::record
+/// [`emit`]: crate::term::emit
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct StyledOutput {
+    segments: Vec<Segment>,
+}
+
+impl StyledOutput {
+    /// The segments of the rendered output, in order.
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    /// Consume the recorded output, returning its segments.
+    pub fn into_segments(self) -> Vec<Segment> {
+        self.segments
+    }
+
+    /// The rendered output without any styling.
+    pub fn text(&self) -> String {
+        self.segments.iter().map(|segment| &*segment.text).collect()
+    }
+
+    /// Write the segments to the given writer, applying their styles.
+    ///
+    /// Each role replaces the style of the previous segment, and the writer
+    /// is reset for plain segments and at the end.
+    pub fn emit(&self, writer: &mut dyn WriteStyle) -> WriteResult {
+        let mut previous: Option<&Segment> = None;
+        for segment in &self.segments {
+            let previous_hyperlink = previous.and_then(|previous| previous.hyperlink.as_ref());
+            if previous_hyperlink != segment.hyperlink.as_ref() {
+                if previous_hyperlink.is_some() {
+                    writer.end_hyperlink()?;
+                }
+                if let Some(url) = &segment.hyperlink {
+                    writer.start_hyperlink(url)?;
+                }
+            }
+            if !matches!(previous, Some(previous) if previous.has_style_of(segment)) {
+                match segment.role {
+                    StyleRole::Plain => writer.reset()?,
+                    StyleRole::Header(severity) => writer.set_header(severity)?,
+                    StyleRole::HeaderMessage => writer.set_header_message()?,
+                    StyleRole::LineNumber => writer.set_line_number()?,
+                    StyleRole::NoteBullet => writer.set_note_bullet()?,
+                    StyleRole::SourceBorder => writer.set_source_border()?,
+                    StyleRole::Label(severity, label_style) => {
+                        writer.set_label(severity, label_style)?
+                    }
+                    StyleRole::ContextLine => writer.set_context_line()?,
+                    StyleRole::SuggestionInsertion => writer.set_suggestion_insertion()?,
+                    StyleRole::SuggestionDeletion => writer.set_suggestion_deletion()?,
+                }
+                if segment.span_background {
+                    writer.set_span_background()?;
+                }
+                if segment.whitespace {
+                    writer.set_whitespace()?;
+                }
+                if segment.wavy_underline {
+                    writer.set_wavy_underline()?;
+                }
+            }
+            write!(writer, "{}", segment.text)?;
+            previous = Some(segment);
+        }
+        if let Some(previous) = previous {
+            if previous.hyperlink.is_some() {
+                writer.end_hyperlink()?;
+            }
+            if !previous.has_style_of(&Segment::plain()) {
+                writer.reset()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A writer that records the rendered output into a [`StyledOutput`].
+#[derive(Debug)]
+pub(crate) struct RecordingWriter {
+    output: StyledOutput,
+    /// The style of the text written next, with empty text.
+    current: Segment,
+}
+
+impl RecordingWriter {
+    pub(crate) fn new() -> RecordingWriter {
+        RecordingWriter {
+            output: StyledOutput::default(),
+            current: Segment::plain(),
+        }
+    }
+
+    pub(crate) fn into_output(self) -> StyledOutput {
+        self.output
+    }
+
+    fn push_str(&mut self, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+        match self.output.segments.last_mut() {
+            Some(last)
+                if last.has_style_of(&self.current) && last.hyperlink == self.current.hyperlink =>
+            {
+                last.text.push_str(text)
+            }
+            _ => {
+                let mut segment = self.current.clone();
+                segment.text.push_str(text);
+                self.output.segments.push(segment);
+            }
+        }
+    }
+
+    fn set_role(&mut self, role: StyleRole) -> WriteResult {
+        self.reset()?;
+        self.current.role = role;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+impl Write for RecordingWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let text = core::str::from_utf8(buf)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        self.push_str(text);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(not(feature = "std"))]
+impl Write for RecordingWriter {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        self.push_str(s);
+        Ok(())
+    }
+}
+
+impl WriteStyle for RecordingWriter {
+    fn set_header(&mut self, severity: Severity) -> WriteResult {
+        self.set_role(StyleRole::Header(severity))
+    }
+
+    fn set_header_message(&mut self) -> WriteResult {
+        self.set_role(StyleRole::HeaderMessage)
+    }
+
+    fn set_line_number(&mut self) -> WriteResult {
+        self.set_role(StyleRole::LineNumber)
+    }
+
+    fn set_note_bullet(&mut self) -> WriteResult {
+        self.set_role(StyleRole::NoteBullet)
+    }
+
+    fn set_source_border(&mut self) -> WriteResult {
+        self.set_role(StyleRole::SourceBorder)
+    }
+
+    fn set_label(&mut self, severity: Severity, label_style: LabelStyle) -> WriteResult {
+        self.set_role(StyleRole::Label(severity, label_style))
+    }
+
+    fn reset(&mut self) -> WriteResult {
+        self.current.role = StyleRole::Plain;
+        self.current.span_background = false;
+        self.current.whitespace = false;
+        self.current.wavy_underline = false;
+        Ok(())
+    }
+
+    fn set_span_background(&mut self) -> WriteResult {
+        self.current.span_background = true;
+        Ok(())
+    }
+
+    fn set_whitespace(&mut self) -> WriteResult {
+        self.current.whitespace = true;
+        Ok(())
+    }
+
+    fn set_wavy_underline(&mut self) -> WriteResult {
+        self.current.wavy_underline = true;
+        Ok(())
+    }
+
+    fn supports_wavy_underline(&self) -> bool {
+        true
+    }
+
+    fn set_context_line(&mut self) -> WriteResult {
+        self.set_role(StyleRole::ContextLine)
+    }
+
+    fn set_suggestion_insertion(&mut self) -> WriteResult {
+        self.set_role(StyleRole::SuggestionInsertion)
+    }
+
+    fn set_suggestion_deletion(&mut self) -> WriteResult {
+        self.set_role(StyleRole::SuggestionDeletion)
+    }
+
+    fn start_hyperlink(&mut self, url: &str) -> WriteResult {
+        self.current.hyperlink = Some(String::from(url));
+        Ok(())
+    }
+
+    fn end_hyperlink(&mut self) -> WriteResult {
+        self.current.hyperlink = None;
+        Ok(())
+    }
+}
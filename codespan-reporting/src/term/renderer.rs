@@ -1,9 +1,22 @@
-use alloc::string::String;
+use alloc::{
+    borrow::Cow,
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
 use core::ops::Range;
 
-use crate::diagnostic::{LabelStyle, Severity};
+use crate::diagnostic::{LabelStyle, Note, Severity};
 use crate::files::{Error, Location};
-use crate::term::{Chars, Config};
+use crate::term::{
+    CaretFillStyle, Chars, Config, LabelMessagePlacement, LineNumberAlignment, RenderWhitespace,
+    SecondaryUnderlineStyle,
+};
+
+#[cfg(feature = "termcolor")]
+use crate::term::{Styles, StylesWriter};
+#[cfg(feature = "termcolor")]
+use termcolor::{ColorSpec, WriteColor};
 
 #[cfg(feature = "std")]
 use std::io::{self, Write};
@@ -13,6 +26,21 @@ type WriteResult = io::Result<()>;
 #[cfg(not(feature = "std"))]
 use core::fmt::{Arguments, Result as WriteResult, Write};
 
+/// Calculate the number of decimal digits in `n`.
+pub(crate) fn count_digits(n: usize) -> usize {
+    n.checked_ilog10().map_or(1, |digits| digits as usize + 1)
+}
+
+/// Calculate the width of a line number in the gutter, which is signed if it
+/// is relative to `origin`.
+pub(crate) fn line_number_width(line_number: usize, origin: Option<usize>) -> usize {
+    match origin {
+        Some(origin) if line_number == origin => 1,
+        Some(origin) => 1 + count_digits(line_number.abs_diff(origin)),
+        None => count_digits(line_number),
+    }
+}
+
 /// A writer that can apply and reset styling for different parts of a diagnostic renderer.
 pub trait WriteStyle: Write {
     fn set_header(&mut self, severity: Severity) -> WriteResult;
@@ -28,6 +56,550 @@ pub trait WriteStyle: Write {
     fn set_label(&mut self, severity: Severity, label_style: LabelStyle) -> WriteResult;
 
     fn reset(&mut self) -> WriteResult;
+
+    /// Highlight the background of labeled source text.
+    ///
+    /// This is called after [`WriteStyle::set_label`] when the source is also
+    /// colored by a label, so it should add to the current style rather than
+    /// replace it. Writers that do not support this should ignore it, which is
+    /// what the default implementation does.
+    fn set_span_background(&mut self) -> WriteResult {
+        Ok(())
+    }
+
+    /// Style source text with the style returned by [`Config::source_style`],
+    /// such as the color of the kind of token it is part of.
+    ///
+    /// This is called before [`WriteStyle::set_whitespace`], which should add
+    /// to it. Writers that do not support this should ignore it, which is what
+    /// the default implementation does.
+    #[cfg(feature = "termcolor")]
+    #[allow(unused_variables)]
+    fn set_source_style(&mut self, spec: &ColorSpec) -> WriteResult {
+        Ok(())
+    }
+
+    /// Dim whitespace that is rendered visibly.
+    ///
+    /// Like [`WriteStyle::set_span_background`], this should add to the
+    /// current style rather than replace it. Writers that do not support this
+    /// should ignore it, which is what the default implementation does.
+    fn set_whitespace(&mut self) -> WriteResult {
+        Ok(())
+    }
+
+    /// Underline the source text of secondary labels with a wavy line, for
+    /// [`SecondaryUnderlineStyle::Wavy`].
+    ///
+    /// Like [`WriteStyle::set_span_background`], this should add to the
+    /// current style rather than replace it. It is only called if
+    /// [`WriteStyle::supports_wavy_underline`] returns `true`.
+    ///
+    /// [`SecondaryUnderlineStyle::Wavy`]: crate::term::SecondaryUnderlineStyle::Wavy
+    fn set_wavy_underline(&mut self) -> WriteResult {
+        Ok(())
+    }
+
+    /// Whether [`WriteStyle::set_wavy_underline`] has any effect on the
+    /// output. Secondary labels are marked with carets instead if it does
+    /// not. The default implementation returns `false`.
+    fn supports_wavy_underline(&self) -> bool {
+        false
+    }
+
+    /// Dim source lines that only provide context, without any labels.
+    ///
+    /// Writers that do not support this should ignore it, which is what the
+    /// default implementation does.
+    fn set_context_line(&mut self) -> WriteResult {
+        Ok(())
+    }
+
+    /// Style the lines inserted by a suggested edit.
+    ///
+    /// Writers that do not support this should ignore it, which is what the
+    /// default implementation does.
+    fn set_suggestion_insertion(&mut self) -> WriteResult {
+        Ok(())
+    }
+
+    /// Style the lines deleted by a suggested edit.
+    ///
+    /// Writers that do not support this should ignore it, which is what the
+    /// default implementation does.
+    fn set_suggestion_deletion(&mut self) -> WriteResult {
+        Ok(())
+    }
+
+    /// Start a hyperlink pointing to `url`.
+    ///
+    /// Writers that do not support hyperlinks should ignore this, which is
+    /// what the default implementation does.
+    #[allow(unused_variables)]
+    fn start_hyperlink(&mut self, url: &str) -> WriteResult {
+        Ok(())
+    }
+
+    /// End a hyperlink previously started with [`WriteStyle::start_hyperlink`].
+    fn end_hyperlink(&mut self) -> WriteResult {
+        Ok(())
+    }
+
+    /// Whether the styling applied to this writer has any effect on its
+    /// output.
+    ///
+    /// Renderers can check this to skip working out how to style their
+    /// output when it would be ignored. Writers that ignore all styling
+    /// should return `false`. The default implementation returns `true`.
+    fn is_styled(&self) -> bool {
+        true
+    }
+
+    /// Wrap this writer in a [`StylesWriter`], styling diagnostics with
+    /// `styles` instead of [`Styles::default()`].
+    ///
+    /// ```rust
+    /// use codespan_reporting::term::termcolor::{ColorChoice, StandardStream};
+    /// use codespan_reporting::term::{Styles, WriteStyle};
+    ///
+    /// let styles = Styles::color_blind_safe();
+    /// let writer = StandardStream::stderr(ColorChoice::Auto);
+    /// let mut writer = writer.lock().with_styles(&styles);
+    /// # let _ = &mut writer;
+    /// ```
+    #[cfg(feature = "termcolor")]
+    fn with_styles(self, styles: &Styles) -> StylesWriter<'_, Self>
+    where
+        Self: Sized + WriteColor,
+    {
+        StylesWriter::new(self, styles)
+    }
+}
+
+/// A writer that renders diagnostics without any styling.
+///
+/// This wraps any [`std::io::Write`] (or [`core::fmt::Write`] when the `std`
+/// feature is disabled), and implements [`WriteStyle`] by ignoring all
+/// styling. Unlike going through `termcolor::NoColor`, this does not require
+/// the `termcolor` feature.
+#[derive(Clone, Debug, Default)]
+pub struct PlainWriter<W> {
+    writer: W,
+}
+
+impl<W> PlainWriter<W> {
+    /// Create a new plain writer wrapping the given writer.
+    pub fn new(writer: W) -> PlainWriter<W> {
+        PlainWriter { writer }
+    }
+
+    /// Get a reference to the wrapped writer.
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+
+    /// Get a mutable reference to the wrapped writer.
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.writer
+    }
+
+    /// Consume the plain writer, returning the wrapped writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+#[cfg(feature = "std")]
+impl<W: Write> Write for PlainWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writer.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+#[cfg(not(feature = "std"))]
+impl<W: Write> Write for PlainWriter<W> {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        self.writer.write_str(s)
+    }
+
+    fn write_char(&mut self, c: char) -> core::fmt::Result {
+        self.writer.write_char(c)
+    }
+
+    fn write_fmt(&mut self, args: Arguments<'_>) -> core::fmt::Result {
+        self.writer.write_fmt(args)
+    }
+}
+
+impl<W: Write> WriteStyle for PlainWriter<W> {
+    fn set_header(&mut self, _severity: Severity) -> WriteResult {
+        Ok(())
+    }
+
+    fn set_header_message(&mut self) -> WriteResult {
+        Ok(())
+    }
+
+    fn set_line_number(&mut self) -> WriteResult {
+        Ok(())
+    }
+
+    fn set_note_bullet(&mut self) -> WriteResult {
+        Ok(())
+    }
+
+    fn set_source_border(&mut self) -> WriteResult {
+        Ok(())
+    }
+
+    fn set_label(&mut self, _severity: Severity, _label_style: LabelStyle) -> WriteResult {
+        Ok(())
+    }
+
+    fn reset(&mut self) -> WriteResult {
+        Ok(())
+    }
+
+    fn is_styled(&self) -> bool {
+        false
+    }
+}
+
+/// A writer that measures the width of the widest line of the rendered output,
+/// in terminal columns, without storing it.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct WidthWriter {
+    line_width: usize,
+    max_width: usize,
+}
+
+impl WidthWriter {
+    /// The width of the widest line written so far.
+    pub(crate) fn max_width(&self) -> usize {
+        usize::max(self.max_width, self.line_width)
+    }
+
+    fn push_str(&mut self, s: &str) {
+        use unicode_width::UnicodeWidthChar;
+
+        for ch in s.chars() {
+            match ch {
+                '\n' => {
+                    self.max_width = self.max_width();
+                    self.line_width = 0;
+                }
+                // Terminals have tab stops every 8 columns.
+                '\t' => self.line_width = (self.line_width / 8 + 1) * 8,
+                _ => self.line_width += ch.width().unwrap_or(0),
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Write for WidthWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let text = core::str::from_utf8(buf)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        self.push_str(text);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(not(feature = "std"))]
+impl Write for WidthWriter {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        self.push_str(s);
+        Ok(())
+    }
+}
+
+impl WriteStyle for WidthWriter {
+    fn set_header(&mut self, _severity: Severity) -> WriteResult {
+        Ok(())
+    }
+
+    fn set_header_message(&mut self) -> WriteResult {
+        Ok(())
+    }
+
+    fn set_line_number(&mut self) -> WriteResult {
+        Ok(())
+    }
+
+    fn set_note_bullet(&mut self) -> WriteResult {
+        Ok(())
+    }
+
+    fn set_source_border(&mut self) -> WriteResult {
+        Ok(())
+    }
+
+    fn set_label(&mut self, _severity: Severity, _label_style: LabelStyle) -> WriteResult {
+        Ok(())
+    }
+
+    fn reset(&mut self) -> WriteResult {
+        Ok(())
+    }
+
+    fn is_styled(&self) -> bool {
+        false
+    }
+}
+
+/// A writer that renders into a fixed-size buffer, without styling.
+///
+/// Output that does not fit into the buffer is discarded, instead of failing,
+/// and the output is cut at the last whole character that fits, so the
+/// written bytes are always valid UTF-8.
+#[derive(Debug)]
+pub(crate) struct SliceWriter<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+    truncated: bool,
+}
+
+impl<'a> SliceWriter<'a> {
+    pub(crate) fn new(buf: &'a mut [u8]) -> SliceWriter<'a> {
+        SliceWriter {
+            buf,
+            len: 0,
+            truncated: false,
+        }
+    }
+
+    /// The number of bytes written into the buffer.
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    fn push_str(&mut self, s: &str) {
+        if self.truncated {
+            return;
+        }
+        let mut end = s.len();
+        if end > self.buf.len() - self.len {
+            self.truncated = true;
+            end = self.buf.len() - self.len;
+            while !s.is_char_boundary(end) {
+                end -= 1;
+            }
+        }
+        self.buf[self.len..][..end].copy_from_slice(&s.as_bytes()[..end]);
+        self.len += end;
+    }
+}
+
+#[cfg(feature = "std")]
+impl Write for SliceWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let text = core::str::from_utf8(buf)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        self.push_str(text);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(not(feature = "std"))]
+impl Write for SliceWriter<'_> {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        self.push_str(s);
+        Ok(())
+    }
+}
+
+impl WriteStyle for SliceWriter<'_> {
+    fn set_header(&mut self, _severity: Severity) -> WriteResult {
+        Ok(())
+    }
+
+    fn set_header_message(&mut self) -> WriteResult {
+        Ok(())
+    }
+
+    fn set_line_number(&mut self) -> WriteResult {
+        Ok(())
+    }
+
+    fn set_note_bullet(&mut self) -> WriteResult {
+        Ok(())
+    }
+
+    fn set_source_border(&mut self) -> WriteResult {
+        Ok(())
+    }
+
+    fn set_label(&mut self, _severity: Severity, _label_style: LabelStyle) -> WriteResult {
+        Ok(())
+    }
+
+    fn reset(&mut self) -> WriteResult {
+        Ok(())
+    }
+
+    fn is_styled(&self) -> bool {
+        false
+    }
+}
+
+/// A writer that captures the rendered output for tests, recording styling as
+/// inline tags instead of escape sequences.
+///
+/// Each style is written as a tag named after the corresponding field of
+/// [`Styles`], such as `<header_error>` or `<secondary_label>`, and every reset
+/// of the style is written as `</>`. Hyperlinks are written as
+/// `<link url>`...`</link>`. This results in deterministic output that is
+/// readable in snapshot tests, and does not depend on the platform:
+///
+/// ```text
+/// <header_error>error[E0001]<header_message>: unexpected type in `+` application</>
+/// ```
+///
+/// [`Styles`]: crate::term::Styles
+#[derive(Clone, Debug, Default)]
+pub struct TestWriter {
+    output: String,
+    is_styled: bool,
+}
+
+impl TestWriter {
+    /// Create a new, empty test writer.
+    pub fn new() -> TestWriter {
+        TestWriter::default()
+    }
+
+    /// Consume the test writer, returning the captured output.
+    pub fn into_string(self) -> String {
+        self.output
+    }
+
+    fn set_style(&mut self, name: &str) -> WriteResult {
+        self.is_styled = true;
+        write!(self, "<{name}>")
+    }
+}
+
+#[cfg(feature = "std")]
+impl Write for TestWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let text = core::str::from_utf8(buf)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        self.output.push_str(text);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(not(feature = "std"))]
+impl Write for TestWriter {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        self.output.push_str(s);
+        Ok(())
+    }
+}
+
+impl WriteStyle for TestWriter {
+    fn set_header(&mut self, severity: Severity) -> WriteResult {
+        self.set_style(match severity {
+            Severity::Bug => "header_bug",
+            Severity::Error => "header_error",
+            Severity::Warning => "header_warning",
+            Severity::Note => "header_note",
+            Severity::Help => "header_help",
+        })
+    }
+
+    fn set_header_message(&mut self) -> WriteResult {
+        self.set_style("header_message")
+    }
+
+    fn set_line_number(&mut self) -> WriteResult {
+        self.set_style("line_number")
+    }
+
+    fn set_note_bullet(&mut self) -> WriteResult {
+        self.set_style("note_bullet")
+    }
+
+    fn set_source_border(&mut self) -> WriteResult {
+        self.set_style("source_border")
+    }
+
+    fn set_label(&mut self, severity: Severity, label_style: LabelStyle) -> WriteResult {
+        self.set_style(match (label_style, severity) {
+            (LabelStyle::Primary, Severity::Bug) => "primary_label_bug",
+            (LabelStyle::Primary, Severity::Error) => "primary_label_error",
+            (LabelStyle::Primary, Severity::Warning) => "primary_label_warning",
+            (LabelStyle::Primary, Severity::Note) => "primary_label_note",
+            (LabelStyle::Primary, Severity::Help) => "primary_label_help",
+            (LabelStyle::Secondary, _) => "secondary_label",
+        })
+    }
+
+    fn reset(&mut self) -> WriteResult {
+        if self.is_styled {
+            self.is_styled = false;
+            write!(self, "</>")?;
+        }
+        Ok(())
+    }
+
+    fn set_span_background(&mut self) -> WriteResult {
+        self.set_style("span_background")
+    }
+
+    fn set_wavy_underline(&mut self) -> WriteResult {
+        self.set_style("wavy_underline")
+    }
+
+    fn supports_wavy_underline(&self) -> bool {
+        true
+    }
+
+    fn set_whitespace(&mut self) -> WriteResult {
+        self.set_style("whitespace")
+    }
+
+    #[cfg(feature = "termcolor")]
+    fn set_source_style(&mut self, _spec: &ColorSpec) -> WriteResult {
+        self.set_style("source_style")
+    }
+
+    fn set_context_line(&mut self) -> WriteResult {
+        self.set_style("context_line")
+    }
+
+    fn set_suggestion_insertion(&mut self) -> WriteResult {
+        self.set_style("suggestion_insertion")
+    }
+
+    fn set_suggestion_deletion(&mut self) -> WriteResult {
+        self.set_style("suggestion_deletion")
+    }
+
+    fn start_hyperlink(&mut self, url: &str) -> WriteResult {
+        write!(self, "<link {url}>")
+    }
+
+    fn end_hyperlink(&mut self) -> WriteResult {
+        write!(self, "</link>")
+    }
 }
 
 /// The 'location focus' of a source code snippet.
@@ -38,16 +610,18 @@ pub struct Locus {
     pub location: Location,
 }
 
-/// Single-line label, with an optional message.
+/// Single-line label, with an optional message and the priority of the
+/// message.
 ///
 /// ```text
 /// ^^^^^^^^^ blah blah
 /// ```
-pub type SingleLabel<'diagnostic> = (LabelStyle, Range<usize>, &'diagnostic str);
+pub type SingleLabel<'diagnostic> = (LabelStyle, Range<usize>, &'diagnostic str, i32);
 
 /// A multi-line label to render.
 ///
 /// Locations are relative to the start of where the source code is rendered.
+#[derive(Copy, Clone)]
 pub enum MultiLabel<'diagnostic> {
     /// Multi-line label top.
     /// The contained value indicates where the label starts.
@@ -78,6 +652,102 @@ pub enum MultiLabel<'diagnostic> {
     Bottom(usize, &'diagnostic str),
 }
 
+/// A file with labels in the snippets of a diagnostic, laid out in
+/// [`SnippetBuffers::files`].
+pub(crate) struct SnippetFile {
+    /// The index of a label in the file, to find its file id.
+    pub(crate) label_index: usize,
+    /// The start of the label that the locus of the file points at.
+    pub(crate) start: usize,
+    /// The location of the locus of the file.
+    pub(crate) location: Location,
+    /// The number of multi-line labels in the file.
+    pub(crate) num_multi_labels: usize,
+    /// The highest style of the labels in the file.
+    pub(crate) max_label_style: LabelStyle,
+}
+
+/// A line of a snippet, laid out in [`SnippetBuffers::lines`].
+#[derive(Copy, Clone)]
+pub(crate) struct SnippetLine {
+    /// The index of the file of the line in [`SnippetBuffers::files`].
+    pub(crate) file: usize,
+    /// The index of the line in its file.
+    pub(crate) line_index: usize,
+    /// The user-facing line number.
+    pub(crate) number: usize,
+    /// Whether the line is rendered, instead of being left out of the snippet.
+    pub(crate) must_render: bool,
+}
+
+/// The key that labels are sorted by in [`SnippetBuffers`]: the index of the
+/// file of their line, the index of their line in the file, and the order that
+/// they were added in.
+pub(crate) type LineKey = (usize, usize, usize);
+
+/// A multi-line label on a line, with the index of its vertical line in the
+/// gutter and its style.
+pub(crate) type LineMultiLabel<'diagnostic> = (usize, LabelStyle, MultiLabel<'diagnostic>);
+
+/// A suggested edit, with the locus and hyperlink of its snippet, the number
+/// of its first line, and the lines before and after applying it.
+pub(crate) type Suggestion = (Locus, Option<String>, usize, String, String);
+
+/// The buffers that the source snippets of a diagnostic are laid out in,
+/// which are kept by the renderer to reuse their allocations for the
+/// following diagnostics.
+///
+/// The lines and labels are flat lists that are sorted by their file and line
+/// once all of the labels are added, instead of a map for each file.
+#[derive(Default)]
+pub(crate) struct SnippetBuffers<'diagnostic> {
+    /// The indices of the labels to show.
+    pub(crate) label_indices: Vec<usize>,
+    /// The files with labels, in the order that they are first referred to.
+    pub(crate) files: Vec<SnippetFile>,
+    /// The lines of the snippets, sorted by file and line index.
+    pub(crate) lines: Vec<SnippetLine>,
+    /// The single-line labels, by the line that they are on.
+    pub(crate) single_labels: Vec<(LineKey, SingleLabel<'diagnostic>)>,
+    /// The multi-line labels, by each line that they are on.
+    pub(crate) multi_labels: Vec<(LineKey, LineMultiLabel<'diagnostic>)>,
+    /// The single-line labels of the line being rendered.
+    pub(crate) line_single_labels: Vec<SingleLabel<'diagnostic>>,
+    /// The multi-line labels of the line being rendered.
+    pub(crate) line_multi_labels: Vec<LineMultiLabel<'diagnostic>>,
+    /// The suggested edits.
+    pub(crate) suggestions: Vec<Suggestion>,
+    /// The name of the file being rendered.
+    pub(crate) name: String,
+}
+
+impl SnippetBuffers<'_> {
+    /// Clear the buffers, keeping their allocations, so that they can hold
+    /// the labels of another diagnostic.
+    pub(crate) fn recycle<'diagnostic>(self) -> SnippetBuffers<'diagnostic> {
+        /// Empty a vector and reuse its allocation for another type with the
+        /// same layout, which only differs by a lifetime here.
+        fn recycle_vec<T, U>(mut vec: Vec<T>) -> Vec<U> {
+            vec.clear();
+            vec.into_iter().map(|_| unreachable!()).collect()
+        }
+
+        let mut name = self.name;
+        name.clear();
+        SnippetBuffers {
+            label_indices: recycle_vec(self.label_indices),
+            files: recycle_vec(self.files),
+            lines: recycle_vec(self.lines),
+            single_labels: recycle_vec(self.single_labels),
+            multi_labels: recycle_vec(self.multi_labels),
+            line_single_labels: recycle_vec(self.line_single_labels),
+            line_multi_labels: recycle_vec(self.line_multi_labels),
+            suggestions: recycle_vec(self.suggestions),
+            name,
+        }
+    }
+}
+
 #[derive(Copy, Clone)]
 enum VerticalBound {
     Top,
@@ -86,6 +756,13 @@ enum VerticalBound {
 
 type Underline = (LabelStyle, VerticalBound);
 
+/// The style of source text from [`Config::source_style`], which can only be
+/// set with the `termcolor` feature.
+#[cfg(feature = "termcolor")]
+type SourceStyleSpec = ColorSpec;
+#[cfg(not(feature = "termcolor"))]
+type SourceStyleSpec = core::convert::Infallible;
+
 /// A renderer of display list entries.
 ///
 /// The following diagram gives an overview of each of the parts of the renderer's output:
@@ -135,6 +812,30 @@ type Underline = (LabelStyle, VerticalBound);
 pub struct Renderer<'writer, 'config> {
     writer: &'writer mut dyn WriteStyle,
     config: &'config Config,
+    /// The column that the source currently being rendered starts at. This is
+    /// non-zero when rendering the continuation of a wrapped source line, so
+    /// that tab stops line up with the start of the line.
+    start_column: usize,
+    /// Whether nothing has been written on the current line yet, so that the
+    /// [`Config::left_margin`] still has to be written before it.
+    at_line_start: bool,
+    /// Whether the [`Config::line_prefix`] has been written on the current
+    /// line. It is written before any styling of the line, so it can come
+    /// before the margin.
+    prefix_written: bool,
+    /// The rows of the note being rendered, which are kept to reuse their
+    /// allocation for the following notes.
+    note_rows: Vec<Range<usize>>,
+    /// The buffers that the snippets of a diagnostic are laid out in, which
+    /// are kept to reuse their allocations for the following diagnostics.
+    snippet_buffers: SnippetBuffers<'static>,
+    /// The line number that the line numbers in the gutter are relative to,
+    /// with [`Config::relative_line_numbers`].
+    line_number_origin: Option<usize>,
+    /// The name of the file that the source being rendered is from, and the
+    /// byte index of the start of the source in it, for
+    /// [`Config::source_style`].
+    source_file: Option<(String, usize)>,
 }
 
 impl<'writer, 'config> Renderer<'writer, 'config> {
@@ -143,13 +844,168 @@ impl<'writer, 'config> Renderer<'writer, 'config> {
         writer: &'writer mut dyn WriteStyle,
         config: &'config Config,
     ) -> Renderer<'writer, 'config> {
-        Renderer { writer, config }
+        Renderer {
+            writer,
+            config,
+            start_column: 0,
+            at_line_start: true,
+            prefix_written: false,
+            note_rows: Vec::new(),
+            snippet_buffers: SnippetBuffers::default(),
+            line_number_origin: None,
+            source_file: None,
+        }
+    }
+
+    /// Take the buffers to lay out the snippets of a diagnostic in, which
+    /// should be given back with [`Renderer::restore_snippet_buffers`].
+    pub(crate) fn take_snippet_buffers<'diagnostic>(&mut self) -> SnippetBuffers<'diagnostic> {
+        core::mem::take(&mut self.snippet_buffers).recycle()
+    }
+
+    /// Give back the buffers taken with [`Renderer::take_snippet_buffers`],
+    /// to reuse their allocations for the following diagnostics.
+    pub(crate) fn restore_snippet_buffers(&mut self, buffers: SnippetBuffers<'_>) {
+        self.snippet_buffers = buffers.recycle();
+    }
+
+    /// Number the lines in the gutter relative to `origin`, or absolutely if
+    /// it is `None`.
+    pub(crate) fn set_line_number_origin(&mut self, origin: Option<usize>) {
+        self.line_number_origin = origin;
+    }
+
+    /// Whether the source is styled with [`Config::source_style`], which
+    /// needs the position of the source in its file.
+    pub(crate) fn has_source_style(&self) -> bool {
+        #[cfg(feature = "termcolor")]
+        return self.config.source_style.is_some();
+        #[cfg(not(feature = "termcolor"))]
+        return false;
+    }
+
+    /// Style the following source as being from the file `name`, starting at
+    /// the byte index `start` in it, or leave it unstyled if `None`.
+    pub(crate) fn set_source_file(&mut self, file: Option<(&str, usize)>) {
+        match file {
+            Some((name, start)) => {
+                let (file_name, file_start) = self.source_file.get_or_insert_with(Default::default);
+                if file_name != name {
+                    file_name.clear();
+                    file_name.push_str(name);
+                }
+                *file_start = start;
+            }
+            None => self.source_file = None,
+        }
+    }
+
+    /// The style of the character at `byte_index` in the source being
+    /// rendered, from [`Config::source_style`].
+    #[cfg(feature = "termcolor")]
+    fn source_style_at(&self, byte_index: usize) -> Option<SourceStyleSpec> {
+        let source_style = self.config.source_style.as_ref()?;
+        let (name, start) = self.source_file.as_ref()?;
+        source_style.call(name, start + byte_index)
+    }
+
+    #[cfg(not(feature = "termcolor"))]
+    fn source_style_at(&self, _byte_index: usize) -> Option<SourceStyleSpec> {
+        None
+    }
+
+    #[cfg(feature = "termcolor")]
+    fn set_source_style_spec(&mut self, spec: &SourceStyleSpec) -> Result<(), Error> {
+        Ok(self.set_source_style(spec)?)
+    }
+
+    #[cfg(not(feature = "termcolor"))]
+    fn set_source_style_spec(&mut self, spec: &SourceStyleSpec) -> Result<(), Error> {
+        match *spec {}
+    }
+
+    /// The config that the renderer renders with.
+    pub(crate) fn config(&self) -> &'config Config {
+        self.config
     }
 
     fn chars(&self) -> &'config Chars {
         &self.config.chars
     }
 
+    /// Call `f` with the writer to apply styling to, unless styling is
+    /// disabled by [`Config::force_no_color`].
+    fn with_style_writer(
+        &mut self,
+        f: impl FnOnce(&mut dyn WriteStyle) -> WriteResult,
+    ) -> WriteResult {
+        match self.config.force_no_color {
+            true => Ok(()),
+            false => f(&mut *self.writer),
+        }
+    }
+
+    /// Apply a style with `f`, after writing the start of the current line,
+    /// so that the line prefix and margin are not styled.
+    fn apply_style(&mut self, f: impl FnOnce(&mut dyn WriteStyle) -> WriteResult) -> WriteResult {
+        self.write_line_start()?;
+        self.with_style_writer(f)
+    }
+
+    /// Write the [`Config::left_margin`] if nothing has been written on the
+    /// current line yet.
+    fn write_left_margin(&mut self) -> WriteResult {
+        if self.at_line_start {
+            self.at_line_start = false;
+            write!(
+                self.writer,
+                "{:width$}",
+                "",
+                width = self.config.left_margin
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Write the [`Config::line_prefix`] if it has not been written on the
+    /// current line yet, and nothing else has been written on it either.
+    fn write_line_prefix(&mut self) -> WriteResult {
+        match &self.config.line_prefix {
+            Some(prefix) if self.at_line_start && !self.prefix_written => {
+                self.prefix_written = true;
+                write!(self.writer, "{prefix}")
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Write the [`Config::line_prefix`] and the [`Config::left_margin`]
+    /// before styling the start of a line, so that they are left unstyled.
+    fn write_line_start(&mut self) -> WriteResult {
+        self.write_line_prefix()?;
+        self.write_left_margin()
+    }
+
+    /// Render into a string without any styling, using a fresh renderer with
+    /// the same config.
+    pub(crate) fn render_to_string(
+        config: &Config,
+        render: impl FnOnce(&mut Renderer<'_, '_>) -> Result<(), Error>,
+    ) -> Result<String, Error> {
+        #[cfg(feature = "std")]
+        {
+            let mut writer = PlainWriter::new(Vec::new());
+            render(&mut Renderer::new(&mut writer, config))?;
+            Ok(String::from_utf8(writer.into_inner()).expect("rendered output is not valid UTF-8"))
+        }
+        #[cfg(not(feature = "std"))]
+        {
+            let mut writer = PlainWriter::new(String::new());
+            render(&mut Renderer::new(&mut writer, config))?;
+            Ok(writer.into_inner())
+        }
+    }
+
     /// Diagnostic header, with severity, code, and message.
     ///
     /// ```text
@@ -161,6 +1017,26 @@ impl<'writer, 'config> Renderer<'writer, 'config> {
         severity: Severity,
         code: Option<&str>,
         message: &str,
+    ) -> Result<(), Error> {
+        self.render_header_with_args(locus, severity, code, message, &[])
+    }
+
+    /// Diagnostic header, with the references to `args` in the message
+    /// replaced by them, and styled like primary labels. See
+    /// [`Diagnostic::message_args`] for the syntax of the references.
+    ///
+    /// ```text
+    /// error: cannot add `"2"` to `1`
+    /// ```
+    ///
+    /// [`Diagnostic::message_args`]: crate::diagnostic::Diagnostic::message_args
+    pub fn render_header_with_args(
+        &mut self,
+        locus: Option<&Locus>,
+        severity: Severity,
+        code: Option<&str>,
+        message: &str,
+        args: &[String],
     ) -> Result<(), Error> {
         // Write locus
         //
@@ -178,13 +1054,7 @@ impl<'writer, 'config> Renderer<'writer, 'config> {
         // error
         // ```
         self.set_header(severity)?;
-        match severity {
-            Severity::Bug => write!(self, "bug")?,
-            Severity::Error => write!(self, "error")?,
-            Severity::Warning => write!(self, "warning")?,
-            Severity::Help => write!(self, "help")?,
-            Severity::Note => write!(self, "note")?,
-        }
+        write!(self, "{}", self.severity_name(severity))?;
 
         // Write error code
         //
@@ -192,20 +1062,119 @@ impl<'writer, 'config> Renderer<'writer, 'config> {
         // [E0001]
         // ```
         if let Some(code) = &code.filter(|code| !code.is_empty()) {
-            write!(self, "[{code}]")?;
+            let url = (self.config.code_url.as_ref()).and_then(|code_url| code_url.call(code));
+            match url {
+                Some(url) => {
+                    write!(self, "[")?;
+                    self.start_hyperlink(&url)?;
+                    write!(self, "{code}")?;
+                    self.end_hyperlink()?;
+                    write!(self, "]")?;
+                }
+                None => write!(self, "[{code}]")?,
+            }
+        }
+
+        // Write diagnostic message
+        //
+        // ```text
+        // : unexpected type in `+` application
+        // ```
+        self.set_header_message()?;
+        write!(self, ": ")?;
+        if args.is_empty() {
+            // Messages without any spans are left as they are
+            write!(self, "{message}")?;
+        } else {
+            for part in message_parts(message, args) {
+                match part {
+                    MessagePart::Text(text) => write!(self, "{text}")?,
+                    MessagePart::Arg(arg) => {
+                        self.set_label(severity, LabelStyle::Primary)?;
+                        write!(self, "{arg}")?;
+                        self.set_header_message()?;
+                    }
+                }
+            }
+        }
+        self.reset()?;
+
+        writeln!(self)?;
+
+        Ok(())
+    }
+
+    /// Summary of the number of errors and warnings that were emitted, and
+    /// the number of diagnostics that were suppressed.
+    ///
+    /// ```text
+    /// error: aborting due to 3 previous errors; 2 warnings emitted
+    /// warning: 1 warning emitted; 4 diagnostics suppressed
+    /// ```
+    ///
+    /// Nothing is written if all counts are zero.
+    pub fn render_summary(
+        &mut self,
+        errors: usize,
+        warnings: usize,
+        suppressed: usize,
+    ) -> Result<(), Error> {
+        let mut parts = Vec::new();
+        match errors {
+            0 => {}
+            1 => parts.push("aborting due to 1 previous error".to_string()),
+            _ => parts.push(format!("aborting due to {errors} previous errors")),
         }
+        match warnings {
+            0 => {}
+            1 => parts.push("1 warning emitted".to_string()),
+            _ => parts.push(format!("{warnings} warnings emitted")),
+        }
+        match suppressed {
+            0 => {}
+            1 => parts.push("1 diagnostic suppressed".to_string()),
+            _ => parts.push(format!("{suppressed} diagnostics suppressed")),
+        }
+
+        let severity = match (errors, warnings) {
+            (0, 0) => Severity::Note,
+            (0, _) => Severity::Warning,
+            (_, _) => Severity::Error,
+        };
+        match parts.is_empty() {
+            true => Ok(()),
+            false => self.render_header(None, severity, None, &parts.join("; ")),
+        }
+    }
 
-        // Write diagnostic message
-        //
-        // ```text
-        // : unexpected type in `+` application
-        // ```
+    /// Name of the file that the following diagnostics are grouped under, for
+    /// [`Config::group_by_file`].
+    ///
+    /// ```text
+    /// src/main.fun
+    /// ```
+    pub fn render_file_header(&mut self, name: &str) -> Result<(), Error> {
+        let name = match &self.config.file_name {
+            Some(file_name) => file_name.call(name),
+            None => name.to_string(),
+        };
         self.set_header_message()?;
-        write!(self, ": {message}")?;
+        write!(self, "{name}")?;
         self.reset()?;
-
         writeln!(self)?;
+        Ok(())
+    }
 
+    /// Horizontal rule between diagnostics.
+    ///
+    /// ```text
+    /// ────────────────────────────────────────
+    /// ```
+    pub fn render_separator(&mut self, separator: char, width: usize) -> Result<(), Error> {
+        self.set_source_border()?;
+        (0..width).try_for_each(|_| write!(self, "{separator}"))?;
+        self.reset()?;
+        writeln!(self)?;
         Ok(())
     }
 
@@ -220,10 +1189,13 @@ impl<'writer, 'config> Renderer<'writer, 'config> {
     /// ```text
     /// ┌─ test:2:9
     /// ```
+    ///
+    /// The locus is a hyperlink to `url`, if it is given.
     pub fn render_snippet_start(
         &mut self,
         outer_padding: usize,
         locus: &Locus,
+        url: Option<&str>,
     ) -> Result<(), Error> {
         self.outer_gutter(outer_padding)?;
 
@@ -232,7 +1204,14 @@ impl<'writer, 'config> Renderer<'writer, 'config> {
         self.reset()?;
 
         write!(self, " ")?;
-        self.snippet_locus(locus)?;
+        match url {
+            Some(url) => {
+                self.start_hyperlink(url)?;
+                self.snippet_locus(locus)?;
+                self.end_hyperlink()?;
+            }
+            None => self.snippet_locus(locus)?,
+        }
 
         writeln!(self)?;
 
@@ -245,6 +1224,15 @@ impl<'writer, 'config> Renderer<'writer, 'config> {
     /// 10 │   │ muffin. Halvah croissant candy canes bonbon candy. Apple pie jelly
     ///    │ ╭─│─────────^
     /// ```
+    ///
+    /// If [`Config::max_line_width`] is set, lines that are too wide are
+    /// wrapped onto continuation rows, with the labels split between them:
+    ///
+    /// ```text
+    /// 10 │   │ muffin. Halvah croissant candy canes bonbon
+    ///    │ ╭─│─────────^
+    ///    │ │ │  candy. Apple pie jelly
+    /// ```
     #[allow(clippy::too_many_arguments)]
     pub fn render_snippet_source(
         &mut self,
@@ -259,6 +1247,208 @@ impl<'writer, 'config> Renderer<'writer, 'config> {
         // Trim trailing newlines, linefeeds, and null chars from source, if they exist.
         // FIXME: Use the number of trimmed placeholders when rendering single line carets
         let source = source.trim_end_matches(['\n', '\r', '\0'].as_ref());
+        let trailing_whitespace = source.trim_end_matches([' ', '\t'].as_ref()).len();
+        let column_number = match self.config.show_columns {
+            true => self.label_column_number(source, single_labels, multi_labels),
+            false => None,
+        };
+        // Lines that only provide context are dimmed as a whole, even if they
+        // are wrapped.
+        let is_context = self.config.dim_context_lines
+            && !source.is_empty()
+            && single_labels.is_empty()
+            && multi_labels.is_empty();
+
+        let chunks = match self.max_source_width(outer_padding, num_multi_labels) {
+            Some(max_width) => self.wrap_source(source, max_width),
+            None => Vec::new(),
+        };
+        if chunks.len() <= 1 {
+            return self.render_snippet_source_row(
+                outer_padding,
+                Some((line_number, column_number)),
+                source,
+                trailing_whitespace,
+                is_context,
+                severity,
+                single_labels,
+                num_multi_labels,
+                multi_labels,
+            );
+        }
+
+        let last_chunk_index = chunks.len() - 1;
+        for (chunk_index, (chunk, start_column)) in chunks.into_iter().enumerate() {
+            let is_last_chunk = chunk_index == last_chunk_index;
+            // Positions past the end of the line (for carets at the end of
+            // the line) belong to the last chunk.
+            let chunk_end = if is_last_chunk { usize::MAX } else { chunk.end };
+            let contains = |position: usize| chunk.start <= position && position < chunk_end;
+
+            // Clip the single labels to this chunk. The message is only shown
+            // on the chunk that contains the end of the label.
+            let chunk_single_labels = single_labels
+                .iter()
+                .filter(|(_, range, _, _)| match range.is_empty() {
+                    true => contains(range.start),
+                    false => is_overlapping(range, &(chunk.start..chunk_end)),
+                })
+                .map(|(label_style, range, message, priority)| {
+                    let start = usize::max(range.start, chunk.start) - chunk.start;
+                    let end = usize::min(range.end, chunk_end) - chunk.start;
+                    let message = if range.end <= chunk_end { *message } else { "" };
+                    (*label_style, start..end, message, *priority)
+                })
+                .collect::<Vec<_>>();
+
+            // Multi-line labels start or end on the chunk that contains their
+            // caret, and continue on the left of the chunks in between.
+            let chunk_multi_labels = multi_labels
+                .iter()
+                .filter_map(|(label_index, label_style, label)| {
+                    let label = match label {
+                        MultiLabel::Top(start) if contains(*start) => {
+                            MultiLabel::Top(start - chunk.start)
+                        }
+                        MultiLabel::Top(start) if *start < chunk.start => MultiLabel::Left,
+                        MultiLabel::Top(_) => return None,
+                        MultiLabel::Left => MultiLabel::Left,
+                        MultiLabel::Bottom(end, message) if contains(*end) => {
+                            MultiLabel::Bottom(end - chunk.start, message)
+                        }
+                        MultiLabel::Bottom(end, _) if *end >= chunk_end => MultiLabel::Left,
+                        MultiLabel::Bottom(..) => return None,
+                    };
+                    Some((*label_index, *label_style, label))
+                })
+                .collect::<Vec<_>>();
+
+            self.start_column = start_column;
+            if let Some((_, file_start)) = &mut self.source_file {
+                *file_start += chunk.start;
+            }
+            let result = self.render_snippet_source_row(
+                outer_padding,
+                (chunk_index == 0).then_some((line_number, column_number)),
+                &source[chunk.clone()],
+                trailing_whitespace.saturating_sub(chunk.start),
+                is_context,
+                severity,
+                &chunk_single_labels,
+                num_multi_labels,
+                &chunk_multi_labels,
+            );
+            self.start_column = 0;
+            if let Some((_, file_start)) = &mut self.source_file {
+                *file_start -= chunk.start;
+            }
+            result?;
+        }
+
+        Ok(())
+    }
+
+    /// The user-facing column number of the leftmost label starting on the
+    /// given source line, counting tabs and wide characters by the columns they
+    /// take up when rendered. Returns `None` if no labels start on the line.
+    pub(crate) fn label_column_number(
+        &self,
+        source: &str,
+        single_labels: &[SingleLabel<'_>],
+        multi_labels: &[(usize, LabelStyle, MultiLabel<'_>)],
+    ) -> Option<usize> {
+        let single_starts = single_labels.iter().map(|(_, range, _, _)| range.start);
+        let multi_starts = multi_labels.iter().filter_map(|(_, _, label)| match label {
+            MultiLabel::Top(start) => Some(*start),
+            MultiLabel::Left | MultiLabel::Bottom(..) => None,
+        });
+        let start = single_starts.chain(multi_starts).min()?;
+
+        Some(self.column_number(source, start))
+    }
+
+    /// The user-facing column number of the given byte index in a source
+    /// line, counting tabs and wide characters by the columns they take up
+    /// when rendered.
+    pub(crate) fn column_number(&self, source: &str, byte_index: usize) -> usize {
+        let column = self
+            .char_metrics(source.char_indices())
+            .take_while(|(metrics, _)| metrics.byte_index < byte_index)
+            .map(|(metrics, _)| metrics.unicode_width)
+            .sum::<usize>();
+        column + 1
+    }
+
+    /// The number of columns left for source on a row of a snippet when
+    /// [`Config::max_line_width`] is set, after the line prefix, the left
+    /// margin, the outer gutter, the left border, the inner gutter, and the
+    /// space before the source.
+    fn max_source_width(&self, outer_padding: usize, num_multi_labels: usize) -> Option<usize> {
+        use unicode_width::UnicodeWidthStr;
+
+        let prefix_width = self
+            .config
+            .line_prefix
+            .as_deref()
+            .map_or(0, |prefix| prefix.width());
+        let gutter_width = prefix_width
+            + self.config.left_margin
+            + outer_padding
+            + 1
+            + 1
+            + 2 * num_multi_labels
+            + 1;
+        let max_line_width = self.config.max_line_width?;
+        Some(max_line_width.saturating_sub(gutter_width))
+    }
+
+    /// Split a line of source code into chunks that are at most `max_width`
+    /// columns wide, returning the byte range and starting column of each
+    /// chunk. Every chunk contains at least one character.
+    fn wrap_source(&self, source: &str, max_width: usize) -> Vec<(Range<usize>, usize)> {
+        let mut chunks = Vec::new();
+        let mut chunk_start = 0;
+        let mut chunk_start_column = 0;
+        let mut column = 0;
+
+        for (metrics, _) in self.char_metrics(source.char_indices()) {
+            if metrics.byte_index > chunk_start
+                && column + metrics.unicode_width > chunk_start_column + max_width
+            {
+                chunks.push((chunk_start..metrics.byte_index, chunk_start_column));
+                chunk_start = metrics.byte_index;
+                chunk_start_column = column;
+            }
+            column += metrics.unicode_width;
+        }
+        chunks.push((chunk_start..source.len(), chunk_start_column));
+
+        chunks
+    }
+
+    /// A single row of source code, which is either a whole line, or a chunk
+    /// of a wrapped line. The line number, and the column number of the leftmost label
+    /// if [`Config::show_columns`] is enabled, are only rendered on the first
+    /// row of a line. Whitespace from the byte index `trailing_whitespace`
+    /// onwards is at the end of the line.
+    #[allow(clippy::too_many_arguments)]
+    fn render_snippet_source_row(
+        &mut self,
+        outer_padding: usize,
+        line_number: Option<(usize, Option<usize>)>,
+        source: &str,
+        trailing_whitespace: usize,
+        is_context: bool,
+        severity: Severity,
+        single_labels: &[SingleLabel<'_>],
+        num_multi_labels: usize,
+        multi_labels: &[(usize, LabelStyle, MultiLabel<'_>)],
+    ) -> Result<(), Error> {
+        // Whether secondary labels are underlined in the source text, instead
+        // of being marked with carets
+        let is_wavy_secondary = self.config.secondary_underline_style
+            == SecondaryUnderlineStyle::Wavy
+            && self.supports_wavy_underline();
 
         // Write source line
         //
@@ -267,7 +1457,12 @@ impl<'writer, 'config> Renderer<'writer, 'config> {
         // ```
         {
             // Write outer gutter (with line number) and border
-            self.outer_gutter_number(line_number, outer_padding)?;
+            match line_number {
+                Some((line_number, column_number)) => {
+                    self.outer_gutter_number(line_number, column_number, outer_padding)?;
+                }
+                None => self.outer_gutter(outer_padding)?,
+            }
             self.border_left()?;
 
             // Write inner gutter (with multi-line continuations on the left if necessary)
@@ -294,42 +1489,145 @@ impl<'writer, 'config> Renderer<'writer, 'config> {
 
             // Write source text
             write!(self, " ")?;
+
+            // Terminals that implement the Unicode bidirectional algorithm
+            // would display right-to-left text in reverse, away from the
+            // carets, so optionally force the source into left-to-right order.
+            let is_bidi_override =
+                self.config.bidi_override && source.chars().any(is_right_to_left);
+            if is_bidi_override {
+                write!(self, "{LEFT_TO_RIGHT_OVERRIDE}")?;
+            }
+
+            if is_context {
+                self.set_context_line()?;
+            }
+
             let mut in_primary = false;
+            let mut in_label = false;
+            let mut in_whitespace = false;
+            let mut in_wavy = false;
+            let mut in_source_style = None;
             for (metrics, ch) in self.char_metrics(source.char_indices()) {
                 let column_range = metrics.byte_index..(metrics.byte_index + ch.len_utf8());
 
-                // Check if we are overlapping a primary label
-                let is_primary = single_labels.iter().any(|(ls, range, _)| {
-                    *ls == LabelStyle::Primary && is_overlapping(range, &column_range)
-                }) || multi_labels.iter().any(|(_, ls, label)| {
-                    *ls == LabelStyle::Primary
-                        && match label {
-                            MultiLabel::Top(start) => column_range.start >= *start,
-                            MultiLabel::Left => true,
-                            MultiLabel::Bottom(start, _) => column_range.end <= *start,
-                        }
-                });
+                // Check if we are overlapping a label, and if it is a primary label
+                let mut is_label = false;
+                let mut is_primary = false;
+                let mut is_wavy = false;
+                for (ls, range, _, _) in single_labels {
+                    if is_overlapping(range, &column_range) {
+                        is_label = true;
+                        is_primary |= *ls == LabelStyle::Primary;
+                        is_wavy |= *ls == LabelStyle::Secondary && is_wavy_secondary;
+                    }
+                }
+                for (_, ls, label) in multi_labels {
+                    let is_overlapping = match label {
+                        MultiLabel::Top(start) => column_range.start >= *start,
+                        MultiLabel::Left => true,
+                        MultiLabel::Bottom(start, _) => column_range.end <= *start,
+                    };
+                    if is_overlapping {
+                        is_label = true;
+                        is_primary |= *ls == LabelStyle::Primary;
+                    }
+                }
+                let is_label = is_label && self.config.highlight_span_background;
+
+                // Check if this is whitespace that should be rendered visibly
+                let is_whitespace = matches!(ch, ' ' | '\t')
+                    && match self.config.render_whitespace {
+                        RenderWhitespace::None => false,
+                        RenderWhitespace::Trailing => metrics.byte_index >= trailing_whitespace,
+                        RenderWhitespace::All => true,
+                    };
+
+                // The style of labels takes precedence over the source style
+                let source_style = match is_primary || is_label || is_wavy {
+                    true => None,
+                    false => self.source_style_at(column_range.start),
+                };
 
-                // Set the source color if we are in a primary label
-                if is_primary && !in_primary {
-                    self.set_label(severity, LabelStyle::Primary)?;
-                    in_primary = true;
-                } else if !is_primary && in_primary {
-                    self.reset()?;
-                    in_primary = false;
+                // Set the source color if we are in a primary label, the
+                // background color if we are highlighting labels, and dim
+                // whitespace that is rendered visibly
+                if is_primary != in_primary
+                    || is_label != in_label
+                    || is_whitespace != in_whitespace
+                    || is_wavy != in_wavy
+                    || source_style != in_source_style
+                {
+                    let was_styled = in_source_style.is_some();
+                    if in_primary || in_label || in_whitespace || in_wavy || was_styled {
+                        self.reset()?;
+                        if is_context {
+                            self.set_context_line()?;
+                        }
+                    }
+                    if let Some(spec) = &source_style {
+                        self.set_source_style_spec(spec)?;
+                    }
+                    if is_primary {
+                        self.set_label(severity, LabelStyle::Primary)?;
+                    }
+                    if is_label {
+                        self.set_span_background()?;
+                    }
+                    if is_whitespace {
+                        self.set_whitespace()?;
+                    }
+                    if is_wavy {
+                        self.set_wavy_underline()?;
+                    }
+                    in_primary = is_primary;
+                    in_label = is_label;
+                    in_whitespace = is_whitespace;
+                    in_wavy = is_wavy;
+                    in_source_style = source_style;
                 }
 
+                // Write the character, expanding tabs and substituting visible
+                // whitespace. The glyphs take up a single column, so that the
+                // carets stay aligned with the source.
                 match ch {
+                    '\t' if is_whitespace && metrics.unicode_width > 0 => {
+                        write!(self, "{}", self.chars().tab_glyph)?;
+                        (1..metrics.unicode_width).try_for_each(|_| write!(self, " "))?;
+                    }
+                    ' ' if is_whitespace => write!(self, "{}", self.chars().space_glyph)?,
                     '\t' => (0..metrics.unicode_width).try_for_each(|_| write!(self, " "))?,
-                    _ => write!(self, "{ch}")?,
+                    _ => self.source_char(ch)?,
                 }
             }
-            if in_primary {
+            let was_styled = in_source_style.is_some();
+            if in_primary || in_label || in_whitespace || in_wavy || was_styled || is_context {
                 self.reset()?;
             }
+            if is_bidi_override {
+                write!(self, "{POP_DIRECTIONAL_FORMATTING}")?;
+            }
             writeln!(self)?;
         }
 
+        // Secondary labels that are underlined in the source text do not need
+        // carets, unless they have a message to point at them.
+        let caret_labels;
+        let single_labels = match is_wavy_secondary {
+            true => {
+                caret_labels = (single_labels.iter())
+                    .filter(|(label_style, range, message, _)| {
+                        *label_style == LabelStyle::Primary
+                            || range.is_empty()
+                            || !message.is_empty()
+                    })
+                    .cloned()
+                    .collect::<Vec<_>>();
+                &caret_labels[..]
+            }
+            false => single_labels,
+        };
+
         // Write single labels underneath source
         //
         // ```text
@@ -385,7 +1683,7 @@ impl<'writer, 'config> Renderer<'writer, 'config> {
             let mut trailing_label = None;
 
             for (label_index, label) in single_labels.iter().enumerate() {
-                let (_, range, message) = label;
+                let (_, range, message, _) = label;
                 if !message.is_empty() {
                     num_messages += 1;
                 }
@@ -400,20 +1698,44 @@ impl<'writer, 'config> Renderer<'writer, 'config> {
                     }
                 }
             }
-            if let Some((trailing_label_index, (_, trailing_range, _))) = trailing_label {
+            if let Some((trailing_label_index, (_, trailing_range, _, _))) = trailing_label {
                 // Check to see if the trailing label candidate overlaps any of
                 // the other labels on the current line.
                 if single_labels
                     .iter()
                     .enumerate()
                     .filter(|(label_index, _)| *label_index != trailing_label_index)
-                    .any(|(_, (_, range, _))| is_overlapping(trailing_range, range))
+                    .any(|(_, (_, range, _, _))| is_label_overlapping(trailing_range, range))
                 {
                     // If it does, we'll instead want to render it below the
                     // carets along with the other hanging labels.
                     trailing_label = None;
                 }
             }
+            if let Some((_, (_, trailing_range, message, _))) = trailing_label {
+                // Dangling messages are all placed below the carets
+                let is_inline = !self.config.dangling_messages
+                    && match self.config.label_message_placement {
+                        LabelMessagePlacement::Inline => true,
+                        LabelMessagePlacement::Below => false,
+                        LabelMessagePlacement::Auto => {
+                            let max_width = self.max_source_width(outer_padding, num_multi_labels);
+                            max_width.map_or(true, |max_width| {
+                                use unicode_width::UnicodeWidthStr;
+
+                                // Wrapped rows are measured from the start of
+                                // their chunk. Empty labels are still marked
+                                // with a caret.
+                                let caret_end = self.column_number(source, trailing_range.end) - 1
+                                    + usize::from(trailing_range.is_empty());
+                                caret_end + 1 + message.width() <= max_width
+                            })
+                        }
+                    };
+                if !is_inline {
+                    trailing_label = None;
+                }
+            }
 
             // Write a line of carets
             //
@@ -426,6 +1748,11 @@ impl<'writer, 'config> Renderer<'writer, 'config> {
             write!(self, " ")?;
 
             let mut previous_label_style = None;
+            // The label whose carets are being written, the number of columns
+            // that they span so far, and whether they start at the start of
+            // the label. The carets are written once the label ends, so that
+            // carets that are wider than a column can be fitted into them.
+            let mut caret_run: Option<(&SingleLabel<'_>, usize, bool)> = None;
             let placeholder_metrics = Metrics {
                 byte_index: source.len(),
                 unicode_width: 1,
@@ -441,13 +1768,32 @@ impl<'writer, 'config> Renderer<'writer, 'config> {
                 // ```
                 .chain(core::iter::once((placeholder_metrics, '\0')))
             {
-                // Find the current label style at this column
+                // Find the current label at this column
                 let column_range = metrics.byte_index..(metrics.byte_index + ch.len_utf8());
-                let current_label_style = single_labels
+                let current_label = single_labels
                     .iter()
-                    .filter(|(_, range, _)| is_overlapping(range, &column_range))
-                    .map(|(label_style, _, _)| *label_style)
-                    .max_by_key(label_priority_key);
+                    .filter(|(_, range, _, _)| is_marked(range, &column_range))
+                    .max_by_key(|(label_style, _, _, _)| label_priority_key(label_style));
+                let current_label_style = current_label.map(|(label_style, _, _, _)| *label_style);
+
+                // Finish the carets of the previous label, in its style
+                if let Some((label, columns, is_start)) = caret_run {
+                    if !matches!(current_label, Some(current) if core::ptr::eq(current, label)) {
+                        let is_end = label.1.end <= column_range.start;
+                        let is_followed = current_label.is_some()
+                            || column_range.start < max_label_end
+                            || trailing_label.is_some();
+                        self.single_carets(
+                            severity,
+                            label.0,
+                            columns,
+                            is_followed,
+                            is_start,
+                            is_end,
+                        )?;
+                        caret_run = None;
+                    }
+                }
 
                 // Update writer style if necessary
                 if previous_label_style != current_label_style {
@@ -461,26 +1807,45 @@ impl<'writer, 'config> Renderer<'writer, 'config> {
                     }
                 }
 
-                let caret_ch = match current_label_style {
-                    Some(LabelStyle::Primary) => Some(self.chars().single_primary_caret),
-                    Some(LabelStyle::Secondary) => Some(self.chars().single_secondary_caret),
+                match current_label {
+                    // Mark zero-width labels with a single caret, even if the
+                    // character after them is wider
+                    Some((label_style, range, _, _)) if range.is_empty() => {
+                        let mut buffer = [0; 4];
+                        let caret =
+                            (self.chars()).single_caret(severity, *label_style, &mut buffer);
+                        write!(self, "{caret}")?;
+                        if column_range.end <= max_label_end {
+                            (caret_width(caret)..metrics.unicode_width)
+                                .try_for_each(|_| write!(self, " "))?;
+                        }
+                    }
+                    Some(label) => match &mut caret_run {
+                        Some((_, columns, _)) => *columns += metrics.unicode_width,
+                        None => {
+                            let is_start = column_range.start <= label.1.start;
+                            caret_run = Some((label, metrics.unicode_width, is_start));
+                        }
+                    },
                     // Only print padding if we are before the end of the last single line caret
-                    None if metrics.byte_index < max_label_end => Some(' '),
-                    None => None,
-                };
-                if let Some(caret_ch) = caret_ch {
-                    // FIXME: improve rendering of carets between character boundaries
-                    (0..metrics.unicode_width).try_for_each(|_| write!(self, "{caret_ch}",))?;
+                    None if metrics.byte_index < max_label_end => {
+                        (0..metrics.unicode_width).try_for_each(|_| write!(self, " "))?;
+                    }
+                    None => {}
                 }
 
                 previous_label_style = current_label_style;
             }
+            if let Some((label, columns, is_start)) = caret_run {
+                let is_followed = trailing_label.is_some();
+                self.single_carets(severity, label.0, columns, is_followed, is_start, true)?;
+            }
             // Reset style if it was previously set
             if previous_label_style.is_some() {
                 self.reset()?;
             }
             // Write first trailing label message
-            if let Some((_, (label_style, _, message))) = trailing_label {
+            if let Some((_, (label_style, _, message, _))) = trailing_label {
                 write!(self, " ")?;
                 self.set_label(severity, *label_style)?;
                 write!(self, "{message}",)?;
@@ -509,8 +1874,8 @@ impl<'writer, 'config> Renderer<'writer, 'config> {
                 self.caret_pointers(
                     severity,
                     max_label_start,
-                    single_labels,
-                    trailing_label,
+                    hanging_labels(single_labels, trailing_label),
+                    None,
                     source.char_indices(),
                 )?;
                 writeln!(self)?;
@@ -522,23 +1887,59 @@ impl<'writer, 'config> Renderer<'writer, 'config> {
                 //   │     first borrow later used by call
                 //   │     help: some help here
                 // ```
-                for (label_style, range, message) in
-                    hanging_labels(single_labels, trailing_label).rev()
+                //
+                // Labels with a higher priority can be listed closer to the
+                // source than labels that start further right. Their messages
+                // are then connected to their carets by a line that passes the
+                // pointers of those labels:
+                //
+                // ```text
+                //   │     ╰─│── first borrow later used by call
+                //   │       first mutable borrow occurs here
+                // ```
+                let num_hanging_labels = hanging_labels(single_labels, trailing_label).count();
+                for (label_index, label) in hanging_labels(single_labels, trailing_label)
+                    .rev()
+                    .enumerate()
                 {
+                    let (label_style, range, message, _) = label;
+                    // The labels whose messages are listed further from the source
+                    let pending_labels = hanging_labels(single_labels, trailing_label)
+                        .take(num_hanging_labels - 1 - label_index);
+                    let crossed_start = (pending_labels.clone())
+                        .map(|(_, range, _, _)| range.start)
+                        .filter(|start| *start > range.start)
+                        .max();
+
                     self.outer_gutter(outer_padding)?;
                     self.border_left()?;
                     self.inner_gutter(severity, num_multi_labels, multi_labels)?;
                     write!(self, " ")?;
-                    self.caret_pointers(
-                        severity,
-                        max_label_start,
-                        single_labels,
-                        trailing_label,
-                        source
-                            .char_indices()
-                            .take_while(|(byte_index, _)| *byte_index < range.start),
-                    )?;
+                    let source_chars = source.char_indices();
+                    match crossed_start {
+                        None => self.caret_pointers(
+                            severity,
+                            max_label_start,
+                            pending_labels,
+                            None,
+                            source_chars.take_while(|(byte_index, _)| *byte_index < range.start),
+                        )?,
+                        Some(crossed_start) => self.caret_pointers(
+                            severity,
+                            max_label_start,
+                            pending_labels,
+                            Some(label),
+                            source_chars.take_while(|(byte_index, _)| *byte_index <= crossed_start),
+                        )?,
+                    }
                     self.set_label(severity, *label_style)?;
+                    if crossed_start.is_some() {
+                        write!(self, "{} ", self.chars().multi_bottom)?;
+                    } else if self.config.dangling_messages {
+                        let chars = self.chars();
+                        let (corner, line) = (chars.multi_bottom_left, chars.multi_bottom);
+                        write!(self, "{corner}{line} ")?;
+                    }
                     write!(self, "{message}",)?;
                     self.reset()?;
                     writeln!(self)?;
@@ -598,87 +1999,535 @@ impl<'writer, 'config> Renderer<'writer, 'config> {
                                 self.inner_gutter_column(severity, underline)?;
                             }
                         }
-                        multi_labels_iter.next();
+                        multi_labels_iter.next();
+                    }
+                    Some((_, _)) | None => self.inner_gutter_column(severity, underline)?,
+                }
+            }
+
+            // Finish the top or bottom caret
+            match bottom_message {
+                None => self.label_multi_top_caret(severity, label_style, source, *range)?,
+                Some(message) => {
+                    self.label_multi_bottom_caret(severity, label_style, source, *range, message)?
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// An empty source line, for providing additional whitespace to source snippets.
+    ///
+    /// ```text
+    /// │ │ │
+    /// ```
+    pub fn render_snippet_empty(
+        &mut self,
+        outer_padding: usize,
+        severity: Severity,
+        num_multi_labels: usize,
+        multi_labels: &[(usize, LabelStyle, MultiLabel<'_>)],
+    ) -> Result<(), Error> {
+        self.outer_gutter(outer_padding)?;
+        self.border_left()?;
+        self.inner_gutter(severity, num_multi_labels, multi_labels)?;
+        writeln!(self)?;
+        Ok(())
+    }
+
+    /// A broken source line, for labeling skipped sections of source.
+    ///
+    /// ```text
+    /// · │ │
+    /// ```
+    ///
+    /// If [`Config::show_omitted_line_count`] is enabled, the number of
+    /// `omitted_lines` is written after it.
+    ///
+    /// ```text
+    /// · │ │ (12 lines omitted)
+    /// ```
+    pub fn render_snippet_break(
+        &mut self,
+        outer_padding: usize,
+        severity: Severity,
+        num_multi_labels: usize,
+        multi_labels: &[(usize, LabelStyle, MultiLabel<'_>)],
+        omitted_lines: usize,
+    ) -> Result<(), Error> {
+        self.outer_gutter(outer_padding)?;
+        self.border_left_break()?;
+        self.inner_gutter(severity, num_multi_labels, multi_labels)?;
+        if self.config.show_omitted_line_count {
+            write!(self, " ")?;
+            self.set_source_border()?;
+            match omitted_lines {
+                1 => write!(self, "(1 line omitted)")?,
+                _ => write!(self, "({omitted_lines} lines omitted)")?,
+            }
+            self.reset()?;
+        }
+        writeln!(self)?;
+        Ok(())
+    }
+
+    /// A row of a hex dump, with the offset of its first byte, followed by
+    /// the bytes in hexadecimal and as ASCII, and a row of carets underneath
+    /// the bytes of each label. The ranges of the labels are relative to the
+    /// start of the row.
+    ///
+    /// ```text
+    /// 00000000 │ 7f 45 4c 47 02 01 01 00 00 00 00 00 00 00 00 00 │.ELG............│
+    ///          │    ^^^^^^^^ expected `ELF`
+    /// ```
+    pub fn render_hexdump_row(
+        &mut self,
+        outer_padding: usize,
+        offset: usize,
+        bytes: &[u8],
+        severity: Severity,
+        single_labels: &[SingleLabel<'_>],
+    ) -> Result<(), Error> {
+        // The style of the label covering each byte, preferring primary labels
+        let label_style = |index: usize| {
+            let labels = single_labels.iter();
+            let labels = labels.filter(|(_, range, _, _)| range.contains(&index));
+            labels.map(|(label_style, _, _, _)| *label_style).min()
+        };
+
+        self.set_line_number()?;
+        write!(self, "{offset:0outer_padding$x}")?;
+        self.reset()?;
+        write!(self, " ")?;
+        self.border_left()?;
+        for index in 0..HEXDUMP_ROW_LEN {
+            write!(self, " ")?;
+            match (bytes.get(index), label_style(index)) {
+                (Some(byte), Some(label_style)) => {
+                    self.set_label(severity, label_style)?;
+                    write!(self, "{byte:02x}")?;
+                    self.reset()?;
+                }
+                (Some(byte), None) => write!(self, "{byte:02x}")?,
+                (None, _) => write!(self, "  ")?,
+            }
+        }
+        write!(self, " ")?;
+        self.border_left()?;
+        for (index, byte) in bytes.iter().enumerate() {
+            let ch = match byte {
+                0x20..=0x7e => char::from(*byte),
+                _ => '.',
+            };
+            match label_style(index) {
+                Some(label_style) => {
+                    self.set_label(severity, label_style)?;
+                    write!(self, "{ch}")?;
+                    self.reset()?;
+                }
+                None => write!(self, "{ch}")?,
+            }
+        }
+        (bytes.len()..HEXDUMP_ROW_LEN).try_for_each(|_| write!(self, " "))?;
+        self.border_left()?;
+        writeln!(self)?;
+
+        // Each byte takes up three columns, including the space before it
+        for (label_style, range, message, _) in single_labels {
+            let mut buffer = [0; 4];
+            let caret = (self.chars()).single_caret(severity, *label_style, &mut buffer);
+            let (caret_count, padding) = caret_repeats(caret, usize::max(3 * range.len(), 2) - 1);
+
+            self.outer_gutter(outer_padding)?;
+            self.border_left()?;
+            write!(self, "{:width$}", "", width = 3 * range.start + 1)?;
+            self.set_label(severity, *label_style)?;
+            (0..caret_count).try_for_each(|_| write!(self, "{caret}"))?;
+            if !message.is_empty() {
+                (0..padding).try_for_each(|_| write!(self, " "))?;
+                write!(self, " {message}")?;
+            }
+            self.reset()?;
+            writeln!(self)?;
+        }
+
+        Ok(())
+    }
+
+    /// The number of labels that were left out of the source snippets,
+    /// because of [`Config::max_labels`].
+    ///
+    /// ```text
+    /// = … and 487 more labels
+    /// ```
+    pub fn render_omitted_labels(
+        &mut self,
+        outer_padding: usize,
+        omitted_labels: usize,
+    ) -> Result<(), Error> {
+        self.outer_gutter(outer_padding)?;
+        self.set_note_bullet()?;
+        write!(self, "{}", self.chars().note_bullet)?;
+        self.reset()?;
+        write!(self, " ")?;
+        self.set_source_border()?;
+        match omitted_labels {
+            1 => write!(self, "… and 1 more label")?,
+            _ => write!(self, "… and {omitted_labels} more labels")?,
+        }
+        self.reset()?;
+        writeln!(self)?;
+        Ok(())
+    }
+
+    /// Additional notes.
+    ///
+    /// ```text
+    /// = expected type `Int`
+    ///      found type `String`
+    /// ```
+    ///
+    /// If [`Config::note_wrap_width`] is set, each line of the note is wrapped
+    /// between words, and the continuation rows keep the indentation of the
+    /// line they belong to.
+    pub fn render_snippet_note(
+        &mut self,
+        outer_padding: usize,
+        message: &str,
+    ) -> Result<(), Error> {
+        self.render_snippet_severity_note(outer_padding, None, message)
+    }
+
+    /// Additional notes, prefixed with the name of the severity of the note if
+    /// it has one. The rows after the first one are aligned with the text
+    /// after the prefix.
+    ///
+    /// ```text
+    /// = help: convert the string to an integer
+    ///         with `parse`
+    /// ```
+    pub fn render_snippet_severity_note(
+        &mut self,
+        outer_padding: usize,
+        severity: Option<Severity>,
+        message: &str,
+    ) -> Result<(), Error> {
+        self.render_note_rows(outer_padding, None, severity, message)
+    }
+
+    /// Additional notes of a diagnostic with the given `diagnostic_severity`,
+    /// as with [`Renderer::render_snippet_severity_note`]. If
+    /// [`Config::color_note_bullet_by_severity`] is enabled, the bullets of
+    /// notes without a severity are styled like the header of the diagnostic.
+    ///
+    /// ```text
+    /// = expected type `Int`
+    /// ```
+    pub fn render_diagnostic_note(
+        &mut self,
+        outer_padding: usize,
+        diagnostic_severity: Severity,
+        severity: Option<Severity>,
+        message: &str,
+    ) -> Result<(), Error> {
+        let bullet_severity = match self.config.color_note_bullet_by_severity {
+            true => Some(diagnostic_severity),
+            false => None,
+        };
+        self.render_note_rows(outer_padding, bullet_severity, severity, message)
+    }
+
+    /// The rows of a note, with the bullet styled like the header of
+    /// `bullet_severity` if the note has no severity of its own.
+    fn render_note_rows(
+        &mut self,
+        outer_padding: usize,
+        bullet_severity: Option<Severity>,
+        severity: Option<Severity>,
+        message: &str,
+    ) -> Result<(), Error> {
+        // The width of the prefix, including the space before it.
+        let prefix_width = severity.map_or(0, |severity| {
+            self.severity_name(severity).chars().count() + 2
+        });
+
+        // The gutter, the bullet, the prefix and the spaces around them come
+        // before the note.
+        let max_width = (self.config.note_wrap_width).map(|note_wrap_width| {
+            note_wrap_width.saturating_sub(outer_padding + 3 + prefix_width)
+        });
+
+        // The prefix of a note with a severity is rendered on its own if the
+        // note is empty.
+        let empty_line = (message.is_empty() && severity.is_some()).then_some("");
+
+        let mut is_first_row = true;
+        for line in message.lines().chain(empty_line) {
+            let indent = &line[..line.len() - line.trim_start().len()];
+            let mut rows = core::mem::take(&mut self.note_rows);
+            self.wrap_note(line, indent, max_width.unwrap_or(usize::MAX), &mut rows);
+
+            for (row_index, row) in rows.iter().cloned().enumerate() {
+                self.outer_gutter(outer_padding)?;
+                if is_first_row {
+                    match severity {
+                        Some(severity) => {
+                            self.set_header(severity)?;
+                            let name = self.severity_name(severity);
+                            write!(self, "{} {name}:", self.chars().note_bullet)?;
+                        }
+                        None => {
+                            match bullet_severity {
+                                Some(bullet_severity) => self.set_header(bullet_severity)?,
+                                None => self.set_note_bullet()?,
+                            }
+                            write!(self, "{}", self.chars().note_bullet)?;
+                        }
                     }
-                    Some((_, _)) | None => self.inner_gutter_column(severity, underline)?,
+                    self.reset()?;
+                    is_first_row = false;
+                } else {
+                    write!(self, "{:width$}", "", width = 1 + prefix_width)?;
                 }
-            }
-
-            // Finish the top or bottom caret
-            match bottom_message {
-                None => self.label_multi_top_caret(severity, label_style, source, *range)?,
-                Some(message) => {
-                    self.label_multi_bottom_caret(severity, label_style, source, *range, message)?
+                // Write row of message
+                match row_index {
+                    0 if line.is_empty() => writeln!(self)?,
+                    0 => writeln!(self, " {}", &line[row])?,
+                    _ => writeln!(self, " {indent}{}", &line[row])?,
                 }
             }
+            self.note_rows = rows;
         }
 
         Ok(())
     }
 
-    /// An empty source line, for providing additional whitespace to source snippets.
+    /// Split a line of a note into rows that are at most `max_width` columns
+    /// wide, breaking it at spaces, and replacing `rows` with the byte range
+    /// of each row. Rows after the first one are indented by `indent`. Every
+    /// row contains at least one word.
+    fn wrap_note(&self, line: &str, indent: &str, max_width: usize, rows: &mut Vec<Range<usize>>) {
+        let width = |text: &str| -> usize {
+            self.char_metrics(text.char_indices())
+                .map(|(metrics, _)| metrics.unicode_width)
+                .sum()
+        };
+        let indent_width = width(indent);
+
+        rows.clear();
+        let mut row_start = 0;
+        let mut row_end = 0;
+        let mut row_width = 0;
+        let mut word_start = 0;
+        for word in line.split(' ') {
+            let word_end = word_start + word.len();
+            if !word.is_empty() {
+                let word_width = width(word);
+                let gap = word_start - row_end;
+                if row_end > row_start && row_width + gap + word_width > max_width {
+                    rows.push(row_start..row_end);
+                    row_start = word_start;
+                    row_width = indent_width + word_width;
+                } else {
+                    row_width += gap + word_width;
+                }
+                row_end = word_end;
+            }
+            word_start = word_end + 1;
+        }
+        rows.push(row_start..row_end);
+    }
+
+    /// The lines changed by a suggested edit, with the lines before the edit
+    /// marked as deleted and the lines after it as inserted. Both are numbered
+    /// starting from `line_number`.
     ///
     /// ```text
-    /// │ │ │
+    /// 2 - (+ test "")
+    /// 2 + (+ test 0)
     /// ```
-    pub fn render_snippet_empty(
+    pub fn render_suggestion_lines(
         &mut self,
         outer_padding: usize,
-        severity: Severity,
-        num_multi_labels: usize,
-        multi_labels: &[(usize, LabelStyle, MultiLabel<'_>)],
+        line_number: usize,
+        deleted: &str,
+        inserted: &str,
     ) -> Result<(), Error> {
-        self.outer_gutter(outer_padding)?;
-        self.border_left()?;
-        self.inner_gutter(severity, num_multi_labels, multi_labels)?;
-        writeln!(self)?;
+        for (line_offset, line) in deleted.lines().enumerate() {
+            self.outer_gutter_number(line_number + line_offset, None, outer_padding)?;
+            self.set_suggestion_deletion()?;
+            write!(self, "- ")?;
+            self.suggestion_line(line)?;
+            self.reset()?;
+            writeln!(self)?;
+        }
+        for (line_offset, line) in inserted.lines().enumerate() {
+            self.outer_gutter_number(line_number + line_offset, None, outer_padding)?;
+            self.set_suggestion_insertion()?;
+            write!(self, "+ ")?;
+            self.suggestion_line(line)?;
+            self.reset()?;
+            writeln!(self)?;
+        }
+
         Ok(())
     }
 
-    /// A broken source line, for labeling skipped sections of source.
+    /// Diagnostic header in Markdown, with the severity and code in bold. The
+    /// message is escaped, except for its code spans.
     ///
     /// ```text
-    /// · │ │
+    /// **error[E0001]**: unexpected type in `+` application
     /// ```
-    pub fn render_snippet_break(
+    pub fn render_markdown_header(
         &mut self,
-        outer_padding: usize,
         severity: Severity,
-        num_multi_labels: usize,
-        multi_labels: &[(usize, LabelStyle, MultiLabel<'_>)],
+        code: Option<&str>,
+        message: &str,
     ) -> Result<(), Error> {
-        self.outer_gutter(outer_padding)?;
-        self.border_left_break()?;
-        self.inner_gutter(severity, num_multi_labels, multi_labels)?;
+        let severity = self.severity_name(severity);
+        match code.filter(|code| !code.is_empty()) {
+            Some(code) => write!(self, "**{severity}[{code}]**: ")?,
+            None => write!(self, "**{severity}**: ")?,
+        }
+        write_markdown_escaped(self, message)?;
         writeln!(self)?;
+
         Ok(())
     }
 
-    /// Additional notes.
+    /// Rendered source snippets in a fenced Markdown code block, preceded by
+    /// an empty line. The fence is made longer than any run of backticks in
+    /// the snippets.
+    ///
+    /// ````text
     ///
     /// ```text
-    /// = expected type `Int`
-    ///      found type `String`
+    ///   ┌─ test:2:9
+    ///   │
+    /// 2 │ (+ test "")
+    ///   │         ^^ expected `Int` but found `String`
     /// ```
-    pub fn render_snippet_note(
+    /// ````
+    pub fn render_markdown_code_block(&mut self, snippets: &str) -> Result<(), Error> {
+        let mut fence_len = 3;
+        let mut backticks = 0;
+        for ch in snippets.chars() {
+            backticks = if ch == '`' { backticks + 1 } else { 0 };
+            fence_len = usize::max(fence_len, backticks + 1);
+        }
+        let fence = "`".repeat(fence_len);
+
+        writeln!(self)?;
+        writeln!(self, "{fence}text")?;
+        write!(self, "{snippets}")?;
+        writeln!(self, "{fence}")?;
+
+        Ok(())
+    }
+
+    /// A note as a Markdown list item, with hard line breaks between the lines
+    /// of the note.
+    ///
+    /// ```text
+    /// - expected type `Int`\
+    ///   found type `String`
+    /// ```
+    pub fn render_markdown_note(&mut self, message: &str) -> Result<(), Error> {
+        self.render_markdown_severity_note(None, message)
+    }
+
+    /// A note as a Markdown list item, with the name of its severity, if any,
+    /// in bold.
+    ///
+    /// ```text
+    /// - **help**: convert the string to an integer
+    /// ```
+    pub fn render_markdown_severity_note(
         &mut self,
-        outer_padding: usize,
+        severity: Option<Severity>,
         message: &str,
     ) -> Result<(), Error> {
-        for (note_line_index, line) in message.lines().enumerate() {
-            self.outer_gutter(outer_padding)?;
-            match note_line_index {
-                0 => {
-                    self.set_note_bullet()?;
-                    write!(self, "{}", self.chars().note_bullet)?;
-                    self.reset()?;
-                }
-                _ => write!(self, " ")?,
+        let mut lines = message.lines().peekable();
+        let mut bullet = "-";
+        if let Some(severity) = severity {
+            write!(self, "- **{}**:", self.severity_name(severity))?;
+            bullet = "";
+            if lines.peek().is_none() {
+                writeln!(self)?;
+            }
+        }
+        while let Some(line) = lines.next() {
+            let line = line.trim_start();
+            match lines.peek() {
+                Some(_) => writeln!(self, "{bullet} {line}\\")?,
+                None => writeln!(self, "{bullet} {line}")?,
             }
-            // Write line of message
-            writeln!(self, " {line}",)?;
+            bullet = " ";
+        }
+
+        Ok(())
+    }
+
+    /// A GitHub Actions workflow command, annotating the source with the
+    /// diagnostic.
+    ///
+    /// ```text
+    /// ::error file=test,line=2,col=9,endLine=2,endColumn=11,title=E0001::unexpected type in `+` application
+    /// ```
+    pub fn render_github_annotation(
+        &mut self,
+        locus: Option<(&Locus, Location)>,
+        severity: Severity,
+        code: Option<&str>,
+        message: &str,
+        notes: &[Note],
+    ) -> Result<(), Error> {
+        let command = match severity {
+            Severity::Bug | Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Note | Severity::Help => "notice",
+        };
+        write!(self, "::{command}")?;
+
+        // Write the parameters of the command
+        //
+        // ```text
+        //  file=test,line=2,col=9,endLine=2,endColumn=11,title=E0001
+        // ```
+        let mut separator = " ";
+        if let Some((locus, end)) = locus {
+            write!(self, " file=")?;
+            write_github_escaped(self, &locus.name, true)?;
+            write!(
+                self,
+                ",line={},col={},endLine={},endColumn={}",
+                locus.location.line_number,
+                locus.location.column_number,
+                end.line_number,
+                end.column_number,
+            )?;
+            separator = ",";
+        }
+        if let Some(code) = code.filter(|code| !code.is_empty()) {
+            write!(self, "{separator}title=")?;
+            write_github_escaped(self, code, true)?;
         }
 
+        // Write the message, followed by the notes on separate lines
+        //
+        // ```text
+        // ::unexpected type in `+` application%0Aexpected type `Int`
+        // ```
+        write!(self, "::")?;
+        write_github_escaped(self, message, false)?;
+        for note in notes {
+            write_github_escaped(self, "\n", false)?;
+            write_github_escaped(self, &note.message, false)?;
+        }
+        writeln!(self)?;
+
         Ok(())
     }
 
@@ -689,19 +2538,14 @@ impl<'writer, 'config> Renderer<'writer, 'config> {
         &self,
         char_indices: impl Iterator<Item = (usize, char)>,
     ) -> impl Iterator<Item = (Metrics, char)> {
-        use unicode_width::UnicodeWidthChar;
-
         let tab_width = self.config.tab_width;
-        let mut unicode_column = 0;
+        let sanitize_control_chars = self.config.sanitize_control_chars;
+        let mut unicode_column = self.start_column;
 
         char_indices.map(move |(byte_index, ch)| {
             let metrics = Metrics {
                 byte_index,
-                unicode_width: match (ch, tab_width) {
-                    ('\t', 0) => 0, // Guard divide-by-zero
-                    ('\t', _) => tab_width - (unicode_column % tab_width),
-                    (ch, _) => ch.width().unwrap_or(0),
-                },
+                unicode_width: char_width(ch, unicode_column, tab_width, sanitize_control_chars),
             };
             unicode_column += metrics.unicode_width;
 
@@ -709,12 +2553,55 @@ impl<'writer, 'config> Renderer<'writer, 'config> {
         })
     }
 
+    /// A line of a suggested edit, with tabs expanded like in source lines.
+    fn suggestion_line(&mut self, line: &str) -> Result<(), Error> {
+        for (metrics, ch) in self.char_metrics(line.char_indices()) {
+            match ch {
+                '\t' => (0..metrics.unicode_width).try_for_each(|_| write!(self, " "))?,
+                _ => self.source_char(ch)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// A character of a source line, other than a tab, which is replaced by
+    /// [`Chars::control_glyph`] if it is a control character and
+    /// [`Config::sanitize_control_chars`] is enabled.
+    fn source_char(&mut self, ch: char) -> Result<(), Error> {
+        match ch {
+            ch if self.config.sanitize_control_chars && ch.is_control() => {
+                write!(self, "{}", self.chars().control_glyph)?;
+            }
+            ch => write!(self, "{ch}")?,
+        }
+        Ok(())
+    }
+
+    /// The name of a severity in headers, as configured by
+    /// [`Config::severity_name`].
+    fn severity_name(&self, severity: Severity) -> Cow<'static, str> {
+        if let Some(severity_name) = &self.config.severity_name {
+            return severity_name.call(severity);
+        }
+
+        Cow::Borrowed(match severity {
+            Severity::Bug => "bug",
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Help => "help",
+            Severity::Note => "note",
+        })
+    }
+
     /// Location focus.
     fn snippet_locus(&mut self, locus: &Locus) -> Result<(), Error> {
+        let name = match &self.config.file_name {
+            Some(file_name) => Cow::Owned(file_name.call(&locus.name)),
+            None => Cow::Borrowed(locus.name.as_str()),
+        };
         write!(
             self,
             "{name}:{line_number}:{column_number}",
-            name = locus.name,
             line_number = locus.location.line_number,
             column_number = locus.location.column_number,
         )?;
@@ -731,10 +2618,34 @@ impl<'writer, 'config> Renderer<'writer, 'config> {
     fn outer_gutter_number(
         &mut self,
         line_number: usize,
+        column_number: Option<usize>,
         outer_padding: usize,
     ) -> Result<(), Error> {
+        // The number is padded by hand, to avoid formatting it into a string
+        let origin = self.line_number_origin;
+        let width = match column_number {
+            Some(column_number) => {
+                line_number_width(line_number, origin) + 1 + count_digits(column_number)
+            }
+            None => line_number_width(line_number, origin),
+        };
+        let padding = outer_padding.saturating_sub(width);
         self.set_line_number()?;
-        write!(self, "{line_number: >outer_padding$}",)?;
+        if self.config.line_number_alignment == LineNumberAlignment::Right {
+            write!(self, "{:padding$}", "")?;
+        }
+        match origin {
+            Some(origin) if line_number > origin => write!(self, "+{}", line_number - origin)?,
+            Some(origin) if line_number < origin => write!(self, "-{}", origin - line_number)?,
+            Some(_) => write!(self, "0")?,
+            None => write!(self, "{line_number}")?,
+        }
+        if let Some(column_number) = column_number {
+            write!(self, ":{column_number}")?;
+        }
+        if self.config.line_number_alignment == LineNumberAlignment::Left {
+            write!(self, "{:padding$}", "")?;
+        }
         self.reset()?;
         write!(self, " ")?;
         Ok(())
@@ -756,34 +2667,107 @@ impl<'writer, 'config> Renderer<'writer, 'config> {
         Ok(())
     }
 
-    /// Write vertical lines pointing to carets.
-    fn caret_pointers(
+    /// Write the carets of a single-line label, spanning the given number of
+    /// columns, which may be only part of the label if it is interrupted by
+    /// another label. Carets that do not fill the columns are padded with
+    /// spaces only if something is written after them on the line.
+    fn single_carets(
+        &mut self,
+        severity: Severity,
+        label_style: LabelStyle,
+        columns: usize,
+        is_followed: bool,
+        is_start: bool,
+        is_end: bool,
+    ) -> Result<(), Error> {
+        let mut buffer = [0; 4];
+        let caret = (self.chars()).single_caret(severity, label_style, &mut buffer);
+        if self.config.caret_fill_style != CaretFillStyle::Endpoints {
+            let (count, padding) = caret_repeats(caret, columns);
+            (0..count).try_for_each(|_| write!(self, "{caret}"))?;
+            if is_followed {
+                (0..padding).try_for_each(|_| write!(self, " "))?;
+            }
+            return Ok(());
+        }
+
+        // Only mark the ends of the label with carets, connecting them
+        //
+        // ```text
+        // ^──────^
+        // ```
+        let connector = match label_style {
+            LabelStyle::Primary => self.chars().single_primary_caret_connector,
+            LabelStyle::Secondary => self.chars().single_secondary_caret_connector,
+        };
+        let width = caret_width(caret);
+        let mut remaining = columns;
+        if is_start {
+            write!(self, "{caret}")?;
+            remaining = remaining.saturating_sub(width);
+        }
+        match is_end && remaining >= width {
+            true => {
+                (width..remaining).try_for_each(|_| write!(self, "{connector}"))?;
+                write!(self, "{caret}")?;
+            }
+            false => (0..remaining).try_for_each(|_| write!(self, "{connector}"))?,
+        }
+        Ok(())
+    }
+
+    /// Write vertical lines pointing to the carets of the labels.
+    ///
+    /// The pointer of the `connector` label, if any, turns right at the start
+    /// of the label, and continues as a line that passes the other pointers.
+    fn caret_pointers<'labels, 'diagnostic: 'labels>(
         &mut self,
         severity: Severity,
         max_label_start: usize,
-        single_labels: &[SingleLabel<'_>],
-        trailing_label: Option<(usize, &SingleLabel<'_>)>,
+        labels: impl Clone + Iterator<Item = &'labels SingleLabel<'diagnostic>>,
+        connector: Option<&SingleLabel<'_>>,
         char_indices: impl Iterator<Item = (usize, char)>,
     ) -> Result<(), Error> {
         for (metrics, ch) in self.char_metrics(char_indices) {
             let column_range = metrics.byte_index..(metrics.byte_index + ch.len_utf8());
-            let label_style = hanging_labels(single_labels, trailing_label)
-                .filter(|(_, range, _)| column_range.contains(&range.start))
-                .map(|(label_style, _, _)| *label_style)
+            let label_style = (labels.clone())
+                .filter(|(_, range, _, _)| column_range.contains(&range.start))
+                .map(|(label_style, _, _, _)| *label_style)
                 .max_by_key(label_priority_key);
+            let connector_style = connector
+                .filter(|(_, range, _, _)| range.start < column_range.end)
+                .map(|(label_style, _, _, _)| *label_style);
 
-            let mut spaces = match label_style {
-                None => 0..metrics.unicode_width,
-                Some(label_style) => {
+            let mut spaces = match (connector, label_style) {
+                (Some((connector_style, range, _, _)), _)
+                    if column_range.contains(&range.start) =>
+                {
+                    self.set_label(severity, *connector_style)?;
+                    write!(self, "{}", self.chars().multi_bottom_left)?;
+                    self.reset()?;
+                    1..metrics.unicode_width
+                }
+                (_, None) => 0..metrics.unicode_width,
+                (_, Some(label_style)) => {
                     self.set_label(severity, label_style)?;
                     write!(self, "{}", self.chars().pointer_left)?;
                     self.reset()?;
                     1..metrics.unicode_width
                 }
             };
-            // Only print padding if we are before the end of the last single line caret
-            if metrics.byte_index <= max_label_start {
-                spaces.try_for_each(|_| write!(self, " "))?;
+            match connector_style {
+                // Continue the line of the connector
+                Some(connector_style) => {
+                    self.set_label(severity, connector_style)?;
+                    let line = self.chars().multi_bottom;
+                    spaces.try_for_each(|_| write!(self, "{line}"))?;
+                    self.reset()?;
+                }
+                // Only print padding if we are before the end of the last single line caret
+                None if metrics.byte_index <= max_label_start => {
+                    spaces.try_for_each(|_| write!(self, " "))?;
+                }
+                None => {}
             }
         }
 
@@ -874,7 +2858,9 @@ impl<'writer, 'config> Renderer<'writer, 'config> {
         }
 
         let caret_start = match label_style {
-            LabelStyle::Primary => self.config.chars.multi_primary_caret_start,
+            LabelStyle::Primary => self
+                .chars()
+                .primary_caret(severity, self.chars().multi_primary_caret_start),
             LabelStyle::Secondary => self.config.chars.multi_secondary_caret_start,
         };
         write!(self, "{caret_start}",)?;
@@ -908,7 +2894,9 @@ impl<'writer, 'config> Renderer<'writer, 'config> {
         }
 
         let caret_end = match label_style {
-            LabelStyle::Primary => self.config.chars.multi_primary_caret_start,
+            LabelStyle::Primary => self
+                .chars()
+                .primary_caret(severity, self.chars().multi_primary_caret_start),
             LabelStyle::Secondary => self.config.chars.multi_secondary_caret_start,
         };
         write!(self, "{caret_end}")?;
@@ -978,22 +2966,40 @@ impl<'writer, 'config> Renderer<'writer, 'config> {
 #[cfg(not(feature = "std"))]
 impl Write for Renderer<'_, '_> {
     fn write_str(&mut self, s: &str) -> core::fmt::Result {
-        self.writer.write_str(s)
-    }
-
-    fn write_char(&mut self, c: char) -> core::fmt::Result {
-        self.writer.write_char(c)
-    }
-
-    fn write_fmt(&mut self, args: Arguments<'_>) -> core::fmt::Result {
-        self.writer.write_fmt(args)
+        if self.config.left_margin == 0 && self.config.line_prefix.is_none() {
+            return self.writer.write_str(s);
+        }
+        for line in s.split_inclusive('\n') {
+            self.write_line_prefix()?;
+            // Empty lines are left without a margin, to avoid trailing whitespace
+            if line != "\n" {
+                self.write_left_margin()?;
+            }
+            self.writer.write_str(line)?;
+            self.at_line_start = line.ends_with('\n');
+            self.prefix_written &= !self.at_line_start;
+        }
+        Ok(())
     }
 }
 
 #[cfg(feature = "std")]
 impl Write for Renderer<'_, '_> {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        self.writer.write(buf)
+        if self.config.left_margin == 0 && self.config.line_prefix.is_none() {
+            return self.writer.write(buf);
+        }
+        for line in buf.split_inclusive(|&byte| byte == b'\n') {
+            self.write_line_prefix()?;
+            // Empty lines are left without a margin, to avoid trailing whitespace
+            if line != b"\n" {
+                self.write_left_margin()?;
+            }
+            self.writer.write_all(line)?;
+            self.at_line_start = line.ends_with(b"\n");
+            self.prefix_written &= !self.at_line_start;
+        }
+        Ok(buf.len())
     }
 
     fn flush(&mut self) -> io::Result<()> {
@@ -1003,38 +3009,253 @@ impl Write for Renderer<'_, '_> {
 
 impl WriteStyle for Renderer<'_, '_> {
     fn set_header(&mut self, severity: Severity) -> WriteResult {
-        self.writer.set_header(severity)
+        self.apply_style(|writer| writer.set_header(severity))
     }
 
     fn set_header_message(&mut self) -> WriteResult {
-        self.writer.set_header_message()
+        self.apply_style(|writer| writer.set_header_message())
     }
 
     fn set_line_number(&mut self) -> WriteResult {
-        self.writer.set_line_number()
+        self.apply_style(|writer| writer.set_line_number())
     }
 
     fn set_note_bullet(&mut self) -> WriteResult {
-        self.writer.set_note_bullet()
+        self.apply_style(|writer| writer.set_note_bullet())
     }
 
     fn set_source_border(&mut self) -> WriteResult {
-        self.writer.set_source_border()
+        self.apply_style(|writer| writer.set_source_border())
     }
 
     fn set_label(&mut self, severity: Severity, label_style: LabelStyle) -> WriteResult {
-        self.writer.set_label(severity, label_style)
+        self.apply_style(|writer| writer.set_label(severity, label_style))
     }
+
     fn reset(&mut self) -> WriteResult {
-        self.writer.reset()
+        self.with_style_writer(|writer| writer.reset())
+    }
+
+    fn set_span_background(&mut self) -> WriteResult {
+        self.apply_style(|writer| writer.set_span_background())
+    }
+
+    #[cfg(feature = "termcolor")]
+    fn set_source_style(&mut self, spec: &ColorSpec) -> WriteResult {
+        self.apply_style(|writer| writer.set_source_style(spec))
+    }
+
+    fn set_whitespace(&mut self) -> WriteResult {
+        self.apply_style(|writer| writer.set_whitespace())
+    }
+
+    fn set_wavy_underline(&mut self) -> WriteResult {
+        self.apply_style(|writer| writer.set_wavy_underline())
+    }
+
+    fn supports_wavy_underline(&self) -> bool {
+        !self.config.force_no_color && self.writer.supports_wavy_underline()
+    }
+
+    fn set_context_line(&mut self) -> WriteResult {
+        self.apply_style(|writer| writer.set_context_line())
+    }
+
+    fn set_suggestion_insertion(&mut self) -> WriteResult {
+        self.apply_style(|writer| writer.set_suggestion_insertion())
+    }
+
+    fn set_suggestion_deletion(&mut self) -> WriteResult {
+        self.apply_style(|writer| writer.set_suggestion_deletion())
+    }
+
+    fn start_hyperlink(&mut self, url: &str) -> WriteResult {
+        self.apply_style(|writer| writer.start_hyperlink(url))
+    }
+
+    fn end_hyperlink(&mut self) -> WriteResult {
+        self.with_style_writer(|writer| writer.end_hyperlink())
+    }
+
+    fn is_styled(&self) -> bool {
+        !self.config.force_no_color && self.writer.is_styled()
     }
 }
 
+/// The number of bytes in each row of a hex dump.
+pub(crate) const HEXDUMP_ROW_LEN: usize = 16;
+
 struct Metrics {
     byte_index: usize,
     unicode_width: usize,
 }
 
+/// Forces the following text to be displayed from left to right.
+const LEFT_TO_RIGHT_OVERRIDE: char = '\u{202D}';
+/// Ends the scope of [`LEFT_TO_RIGHT_OVERRIDE`].
+const POP_DIRECTIONAL_FORMATTING: char = '\u{202C}';
+
+/// The number of columns that a character of a source line takes up, when it
+/// starts at the given column. Tabs extend to the next tab stop, and control
+/// characters are one column wide if they are replaced by
+/// [`Chars::control_glyph`].
+pub(crate) fn char_width(
+    ch: char,
+    column: usize,
+    tab_width: usize,
+    sanitize_control_chars: bool,
+) -> usize {
+    use unicode_width::UnicodeWidthChar;
+
+    match (ch, tab_width) {
+        ('\t', 0) => 0, // Guard divide-by-zero
+        ('\t', _) => tab_width - (column % tab_width),
+        // Replaced by the control glyph
+        (ch, _) if sanitize_control_chars && ch.is_control() => 1,
+        (ch, _) => ch.width().unwrap_or(0),
+    }
+}
+
+/// The number of columns that a caret takes up, which is at least one.
+fn caret_width(caret: &str) -> usize {
+    use unicode_width::UnicodeWidthStr;
+
+    usize::max(caret.width(), 1)
+}
+
+/// The number of times to repeat a caret to mark the given number of
+/// columns, and the number of columns left over, to be padded with spaces.
+/// Carets that are wider than a column are repeated as often as they fit, but
+/// at least once.
+pub(crate) fn caret_repeats(caret: &str, columns: usize) -> (usize, usize) {
+    let width = caret_width(caret);
+    let count = usize::max(columns / width, 1);
+    (count, columns.saturating_sub(count * width))
+}
+
+/// A part of a message that references source spans.
+enum MessagePart<'a> {
+    /// Text of the message itself.
+    Text(&'a str),
+    /// The source text of a referenced span.
+    Arg(&'a str),
+}
+
+/// Split a message into its own text and the source text of the spans that it
+/// references as `{0}`, `{1}`, and so on, unescaping `{{` and `}}`. Messages
+/// without any spans are left as they are.
+fn message_parts<'a>(message: &'a str, args: &'a [String]) -> Vec<MessagePart<'a>> {
+    if args.is_empty() {
+        return alloc::vec![MessagePart::Text(message)];
+    }
+
+    let mut parts = Vec::new();
+    let mut rest = message;
+    while let Some(brace_index) = rest.find(['{', '}']) {
+        let (text, tail) = rest.split_at(brace_index);
+        if !text.is_empty() {
+            parts.push(MessagePart::Text(text));
+        }
+
+        if tail.starts_with("{{") || tail.starts_with("}}") {
+            parts.push(MessagePart::Text(&tail[..1]));
+            rest = &tail[2..];
+            continue;
+        }
+
+        let arg = (tail.strip_prefix('{'))
+            .and_then(|tail| tail.split_once('}'))
+            .filter(|(index, _)| !index.is_empty() && index.bytes().all(|b| b.is_ascii_digit()))
+            .and_then(|(index, tail)| Some((args.get(index.parse::<usize>().ok()?)?, tail)));
+        match arg {
+            Some((arg, tail)) => {
+                parts.push(MessagePart::Arg(arg));
+                rest = tail;
+            }
+            None => {
+                parts.push(MessagePart::Text(&tail[..1]));
+                rest = &tail[1..];
+            }
+        }
+    }
+    if !rest.is_empty() {
+        parts.push(MessagePart::Text(rest));
+    }
+
+    parts
+}
+
+/// The message with the references to source spans replaced by `args`,
+/// without styling them. See [`message_parts`].
+pub(crate) fn interpolate_message(message: &str, args: &[String]) -> String {
+    message_parts(message, args)
+        .into_iter()
+        .map(|part| match part {
+            MessagePart::Text(text) | MessagePart::Arg(text) => text,
+        })
+        .collect()
+}
+
+/// Whether the character is from a right-to-left script, such as Hebrew or
+/// Arabic, or starts right-to-left text.
+fn is_right_to_left(ch: char) -> bool {
+    matches!(
+        ch,
+        '\u{0590}'..='\u{08FF}'
+            | '\u{FB1D}'..='\u{FDFF}'
+            | '\u{FE70}'..='\u{FEFF}'
+            | '\u{10800}'..='\u{10FFF}'
+            | '\u{1E800}'..='\u{1EFFF}'
+            | '\u{202B}'
+            | '\u{202E}'
+            | '\u{2067}'
+    )
+}
+
+/// Write text escaped for use in a GitHub Actions workflow command. Parameter
+/// values additionally need to escape the characters that separate parameters.
+fn write_github_escaped(
+    writer: &mut impl Write,
+    text: &str,
+    is_parameter: bool,
+) -> Result<(), Error> {
+    for ch in text.chars() {
+        match ch {
+            '%' => write!(writer, "%25")?,
+            '\r' => write!(writer, "%0D")?,
+            '\n' => write!(writer, "%0A")?,
+            ':' if is_parameter => write!(writer, "%3A")?,
+            ',' if is_parameter => write!(writer, "%2C")?,
+            _ => write!(writer, "{ch}")?,
+        }
+    }
+    Ok(())
+}
+
+/// Write text escaped for use in a Markdown paragraph, so that characters
+/// such as `*` and `<` are not parsed as formatting or HTML. Code spans are
+/// kept, as backslashes are not escapes inside of them, but a trailing
+/// backtick without a match is escaped.
+fn write_markdown_escaped(writer: &mut impl Write, text: &str) -> Result<(), Error> {
+    let mut unmatched_backticks = text.matches('`').count();
+    let mut in_code_span = false;
+    for ch in text.chars() {
+        match ch {
+            '`' if !in_code_span && unmatched_backticks == 1 => write!(writer, "\\`")?,
+            '`' => {
+                in_code_span = !in_code_span;
+                unmatched_backticks -= 1;
+                write!(writer, "`")?;
+            }
+            '\\' | '*' | '_' | '[' | ']' | '<' | '>' | '&' | '~' | '#' if !in_code_span => {
+                write!(writer, "\\{ch}")?
+            }
+            _ => write!(writer, "{ch}")?,
+        }
+    }
+    Ok(())
+}
+
 /// Check if two ranges overlap
 fn is_overlapping(range0: &Range<usize>, range1: &Range<usize>) -> bool {
     let start = core::cmp::max(range0.start, range1.start);
@@ -1042,6 +3263,26 @@ fn is_overlapping(range0: &Range<usize>, range1: &Range<usize>) -> bool {
     start < end
 }
 
+/// Whether a single-line label should be marked with a caret at the given
+/// range of a source line. Zero-width labels are marked at the character
+/// that follows them.
+fn is_marked(label_range: &Range<usize>, column_range: &Range<usize>) -> bool {
+    match label_range.is_empty() {
+        true => column_range.contains(&label_range.start),
+        false => is_overlapping(label_range, column_range),
+    }
+}
+
+/// Whether the carets of two single-line labels overlap.
+fn is_label_overlapping(range0: &Range<usize>, range1: &Range<usize>) -> bool {
+    match (range0.is_empty(), range1.is_empty()) {
+        (true, true) => range0.start == range1.start,
+        (true, false) => range1.contains(&range0.start),
+        (false, true) => range0.contains(&range1.start),
+        (false, false) => is_overlapping(range0, range1),
+    }
+}
+
 /// For prioritizing primary labels over secondary labels when rendering carets.
 fn label_priority_key(label_style: &LabelStyle) -> u8 {
     match label_style {
@@ -1055,11 +3296,11 @@ fn label_priority_key(label_style: &LabelStyle) -> u8 {
 fn hanging_labels<'labels, 'diagnostic>(
     single_labels: &'labels [SingleLabel<'diagnostic>],
     trailing_label: Option<(usize, &'labels SingleLabel<'diagnostic>)>,
-) -> impl 'labels + DoubleEndedIterator<Item = &'labels SingleLabel<'diagnostic>> {
+) -> impl 'labels + Clone + DoubleEndedIterator<Item = &'labels SingleLabel<'diagnostic>> {
     single_labels
         .iter()
         .enumerate()
-        .filter(|(_, (_, _, message))| !message.is_empty())
+        .filter(|(_, (_, _, message, _))| !message.is_empty())
         .filter(move |(i, _)| trailing_label.map_or(true, |(j, _)| *i != j))
         .map(|(_, label)| label)
 }
@@ -1,24 +1,100 @@
 use alloc::{
     string::{String, ToString},
-    vec,
     vec::Vec,
 };
-use core::ops::Range;
+use core::fmt::Write as _;
 
-use crate::diagnostic::{Diagnostic, LabelStyle};
+use crate::diagnostic::{Diagnostic, LabelStyle, Severity};
 use crate::files::{Error, Files, Location};
-use crate::term::renderer::{Locus, MultiLabel, Renderer, SingleLabel};
+use crate::term::renderer::{
+    count_digits, interpolate_message, line_number_width, LineKey, Locus, MultiLabel, Renderer,
+    SnippetBuffers, SnippetFile, SnippetLine, HEXDUMP_ROW_LEN,
+};
 use crate::term::Config;
 
-/// Calculate the number of decimal digits in `n`.
-fn count_digits(n: usize) -> usize {
-    n.ilog10() as usize + 1
+/// The length of a line without its terminator, such as `\n` or `\r\n`, which
+/// the renderer trims before displaying the line.
+fn line_len<'files, F: Files<'files> + ?Sized>(
+    files: &'files F,
+    file_id: F::FileId,
+    line_index: usize,
+) -> Result<usize, Error> {
+    let source = files.line_source(file_id, line_index)?;
+    Ok(source.trim_end_matches(['\n', '\r', '\0'].as_ref()).len())
+}
+
+/// The locus of a byte index in a file, with the name of the file written
+/// into `name` to reuse its allocation.
+fn locus_in<'files, F: Files<'files> + ?Sized>(
+    files: &'files F,
+    file_id: F::FileId,
+    byte_index: usize,
+    mut name: String,
+) -> Result<Locus, Error> {
+    name.clear();
+    write!(name, "{}", files.name(file_id)?)
+        .expect("a Display implementation returned an error unexpectedly");
+    Ok(Locus {
+        name,
+        location: files.location(file_id, byte_index)?,
+    })
+}
+
+/// Copy the labels of a line into `line_labels`, out of labels that are
+/// sorted by the file and line index they are on.
+fn copy_line_labels<T: Clone>(
+    labels: &[(LineKey, T)],
+    line: &SnippetLine,
+    line_labels: &mut Vec<T>,
+) {
+    let key = (line.file, line.line_index);
+    let start = labels.partition_point(|((file, line_index, _), _)| (*file, *line_index) < key);
+    let end = labels.partition_point(|((file, line_index, _), _)| (*file, *line_index) <= key);
+    line_labels.clear();
+    line_labels.extend(labels[start..end].iter().map(|(_, label)| label.clone()));
+}
+
+/// The source text of the spans referenced by the message of the diagnostic,
+/// as set in [`Diagnostic::message_args`]. Like the source snippets, this is
+/// only read through [`Files::line_source`].
+fn message_args<'files, F: Files<'files> + ?Sized>(
+    files: &'files F,
+    diagnostic: &Diagnostic<F::FileId>,
+) -> Result<Vec<String>, Error>
+where
+    F::FileId: Copy,
+{
+    let mut args = Vec::with_capacity(diagnostic.message_args.len());
+    for (file_id, range) in &diagnostic.message_args {
+        let start_line_index = files.line_index(*file_id, range.start)?;
+        let end_line_index = files.line_index(*file_id, range.end)?;
+
+        let mut arg = String::new();
+        for line_index in start_line_index..=end_line_index {
+            let line_start = files.line_range(*file_id, line_index)?.start;
+            let source = files.line_source(*file_id, line_index)?;
+            let start = range.start.saturating_sub(line_start).min(source.len());
+            let end = range.end.saturating_sub(line_start).min(source.len());
+            match source.get(start..end) {
+                Some(text) => arg.push_str(text),
+                None => {
+                    return Err(Error::InvalidCharBoundary {
+                        given: line_start + start,
+                    })
+                }
+            }
+        }
+        args.push(arg);
+    }
+    Ok(args)
 }
 
 /// Output a richly formatted diagnostic, with source code previews.
 pub struct RichDiagnostic<'diagnostic, 'config, FileId> {
     diagnostic: &'diagnostic Diagnostic<FileId>,
     config: &'config Config,
+    render_header: bool,
+    render_locus: bool,
 }
 
 impl<'diagnostic, 'config, FileId> RichDiagnostic<'diagnostic, 'config, FileId>
@@ -29,7 +105,66 @@ where
         diagnostic: &'diagnostic Diagnostic<FileId>,
         config: &'config Config,
     ) -> RichDiagnostic<'diagnostic, 'config, FileId> {
-        RichDiagnostic { diagnostic, config }
+        RichDiagnostic {
+            diagnostic,
+            config,
+            render_header: true,
+            render_locus: true,
+        }
+    }
+
+    /// Only render the source snippets, without the header, the notes, and
+    /// the trailing empty line.
+    pub(crate) fn without_header(mut self) -> RichDiagnostic<'diagnostic, 'config, FileId> {
+        self.render_header = false;
+        self
+    }
+
+    /// Render the source snippets without their top left border and locus.
+    pub(crate) fn without_locus(mut self) -> RichDiagnostic<'diagnostic, 'config, FileId> {
+        self.render_locus = false;
+        self
+    }
+
+    /// Fill `label_indices` with the indices of the labels to show, in their
+    /// original order. If there are more than [`Config::max_labels`], the
+    /// labels with the highest style, priority, and earliest start are shown.
+    fn shown_labels(&self, label_indices: &mut Vec<usize>) {
+        let labels = &self.diagnostic.labels;
+        label_indices.extend(0..labels.len());
+        let max_labels = match self.config.max_labels {
+            Some(max_labels) if max_labels < labels.len() => max_labels,
+            Some(_) | None => return,
+        };
+
+        label_indices.sort_unstable_by_key(|index| {
+            let label = &labels[*index];
+            (
+                label.style,
+                core::cmp::Reverse(label.priority),
+                label.range.start,
+                *index,
+            )
+        });
+        label_indices.truncate(max_labels);
+        label_indices.sort_unstable();
+    }
+
+    /// The URL that the locus of a snippet links to, if
+    /// [`Config::render_file_hyperlinks`] is enabled.
+    fn location_url<'files>(
+        &self,
+        files: &'files (impl Files<'files, FileId = FileId> + ?Sized),
+        file_id: FileId,
+        location: Location,
+    ) -> Result<Option<String>, Error>
+    where
+        FileId: 'files,
+    {
+        if !self.config.render_file_hyperlinks {
+            return Ok(None);
+        }
+        files.location_url(file_id, location.line_number, location.column_number)
     }
 
     pub fn render<'files>(
@@ -40,53 +175,45 @@ where
     where
         FileId: 'files,
     {
-        use alloc::collections::BTreeMap;
-
-        struct LabeledFile<'diagnostic, FileId> {
-            file_id: FileId,
-            start: usize,
-            name: String,
-            location: Location,
-            num_multi_labels: usize,
-            lines: BTreeMap<usize, Line<'diagnostic>>,
-            max_label_style: LabelStyle,
-        }
-
-        impl<'diagnostic, FileId> LabeledFile<'diagnostic, FileId> {
-            fn get_or_insert_line(
-                &mut self,
-                line_index: usize,
-                line_range: Range<usize>,
-                line_number: usize,
-            ) -> &mut Line<'diagnostic> {
-                self.lines.entry(line_index).or_insert_with(|| Line {
-                    range: line_range,
-                    number: line_number,
-                    single_labels: vec![],
-                    multi_labels: vec![],
-                    // This has to be false by default so we know if it must be rendered by another condition already.
-                    must_render: false,
-                })
-            }
-        }
+        let mut buffers = renderer.take_snippet_buffers();
+        let result = self.render_with_buffers(files, renderer, &mut buffers);
+        renderer.restore_snippet_buffers(buffers);
+        result
+    }
 
-        struct Line<'diagnostic> {
-            number: usize,
-            range: core::ops::Range<usize>,
-            // TODO: How do we reuse these allocations?
-            single_labels: Vec<SingleLabel<'diagnostic>>,
-            multi_labels: Vec<(usize, LabelStyle, MultiLabel<'diagnostic>)>,
-            must_render: bool,
-        }
+    fn render_with_buffers<'files>(
+        &self,
+        files: &'files (impl Files<'files, FileId = FileId> + ?Sized),
+        renderer: &mut Renderer<'_, '_>,
+        buffers: &mut SnippetBuffers<'diagnostic>,
+    ) -> Result<(), Error>
+    where
+        FileId: 'files,
+    {
+        let SnippetBuffers {
+            label_indices,
+            files: snippet_files,
+            lines,
+            single_labels,
+            multi_labels,
+            line_single_labels,
+            line_multi_labels,
+            suggestions,
+            name,
+        } = buffers;
 
-        // TODO: Make this data structure external, to allow for allocation reuse
-        let mut labeled_files = Vec::<LabeledFile<'_, _>>::new();
         // Keep track of the outer padding to use when rendering the
         // snippets of source code.
-        let mut outer_padding = 0;
+        let mut outer_padding = self.config.line_number_min_width;
+
+        let labels = &self.diagnostic.labels;
+        self.shown_labels(label_indices);
+        let omitted_labels = labels.len() - label_indices.len();
 
-        // Group labels by file
-        for label in &self.diagnostic.labels {
+        // Group labels by file. The lines and labels are sorted by their file
+        // and line once all of them are added.
+        for (order, &label_index) in label_indices.iter().enumerate() {
+            let label = &labels[label_index];
             let start_line_index = files.line_index(label.file_id, label.range.start)?;
             let start_line_number = files.line_number(label.file_id, start_line_index)?;
             let start_line_range = files.line_range(label.file_id, start_line_index)?;
@@ -100,44 +227,54 @@ where
             // NOTE: This could be made more efficient by using an associative
             // data structure like a hashmap or B-tree,  but we use a vector to
             // preserve the order that unique files appear in the list of labels.
-            let labeled_file = match labeled_files
-                .iter_mut()
-                .find(|labeled_file| label.file_id == labeled_file.file_id)
+            let file = match (snippet_files.iter())
+                .position(|file| labels[file.label_index].file_id == label.file_id)
             {
-                Some(labeled_file) => {
+                Some(file) => {
                     // another diagnostic also referenced this file
-                    if labeled_file.max_label_style > label.style
-                        || (labeled_file.max_label_style == label.style
-                            && labeled_file.start > label.range.start)
+                    let snippet_file = &mut snippet_files[file];
+                    if snippet_file.max_label_style > label.style
+                        || (snippet_file.max_label_style == label.style
+                            && snippet_file.start > label.range.start)
                     {
                         // this label has a higher style or has the same style but starts earlier
-                        labeled_file.start = label.range.start;
-                        labeled_file.location = files.location(label.file_id, label.range.start)?;
-                        labeled_file.max_label_style = label.style;
+                        snippet_file.start = label.range.start;
+                        snippet_file.location = files.location(label.file_id, label.range.start)?;
+                        snippet_file.max_label_style = label.style;
                     }
-                    labeled_file
+                    file
                 }
                 None => {
                     // no other diagnostic referenced this file yet
-                    labeled_files.push(LabeledFile {
-                        file_id: label.file_id,
+                    snippet_files.push(SnippetFile {
+                        label_index,
                         start: label.range.start,
-                        name: files.name(label.file_id)?.to_string(),
                         location: files.location(label.file_id, label.range.start)?,
                         num_multi_labels: 0,
-                        lines: BTreeMap::new(),
                         max_label_style: label.style,
                     });
-                    // this unwrap should never fail because we just pushed an element
-                    labeled_files
-                        .last_mut()
-                        .expect("just pushed an element that disappeared")
+                    snippet_files.len() - 1
                 }
             };
+            let snippet_file = &mut snippet_files[file];
+
+            // Lines can be added more than once, and are rendered if any of
+            // them must be rendered.
+            let mut add_line = |line_index, number, must_render| {
+                lines.push(SnippetLine {
+                    file,
+                    line_index,
+                    number,
+                    must_render,
+                });
+            };
 
             // insert context lines before label
             // start from 1 because 0 would be the start of the label itself
-            for offset in 1..self.config.before_label_lines + 1 {
+            let before_label_lines = label
+                .before_context
+                .unwrap_or(self.config.before_label_lines);
+            for offset in 1..before_label_lines + 1 {
                 let index = if let Some(index) = start_line_index.checked_sub(offset) {
                     index
                 } else {
@@ -147,10 +284,8 @@ where
                     break;
                 };
 
-                if let Ok(range) = files.line_range(label.file_id, index) {
-                    let line =
-                        labeled_file.get_or_insert_line(index, range, start_line_number - offset);
-                    line.must_render = true;
+                if files.line_range(label.file_id, index).is_ok() {
+                    add_line(index, start_line_number - offset, true);
                 } else {
                     break;
                 }
@@ -158,15 +293,14 @@ where
 
             // insert context lines after label
             // start from 1 because 0 would be the end of the label itself
-            for offset in 1..self.config.after_label_lines + 1 {
+            let after_label_lines = label.after_context.unwrap_or(self.config.after_label_lines);
+            for offset in 1..after_label_lines + 1 {
                 let index = end_line_index
                     .checked_add(offset)
                     .expect("line index too big");
 
-                if let Ok(range) = files.line_range(label.file_id, index) {
-                    let line =
-                        labeled_file.get_or_insert_line(index, range, end_line_number + offset);
-                    line.must_render = true;
+                if files.line_range(label.file_id, index).is_ok() {
+                    add_line(index, end_line_number + offset, true);
                 } else {
                     break;
                 }
@@ -179,36 +313,28 @@ where
                 // 2 │ (+ test "")
                 //   │         ^^ expected `Int` but found `String`
                 // ```
-                let label_start = label.range.start - start_line_range.start;
-                // Ensure that we print at least one caret, even when we
-                // have a zero-length source range.
-                let label_end =
-                    usize::max(label.range.end - start_line_range.start, label_start + 1);
-
-                let line = labeled_file.get_or_insert_line(
-                    start_line_index,
-                    start_line_range,
-                    start_line_number,
-                );
-
-                // Ensure that the single line labels are lexicographically
-                // sorted by the range of source code that they cover.
-                let index = match line.single_labels.binary_search_by(|(_, range, _)| {
-                    // `Range<usize>` doesn't implement `Ord`, so convert to `(usize, usize)`
-                    // to piggyback off its lexicographic comparison implementation.
-                    (range.start, range.end).cmp(&(label_start, label_end))
-                }) {
-                    // If the ranges are the same, order the labels in reverse
-                    // to how they were originally specified in the diagnostic.
-                    // This helps with printing in the renderer.
-                    Ok(index) | Err(index) => index,
-                };
+                // Line terminators are not rendered, so labels that cover them
+                // are clamped to the end of the line, where a `\r\n` is
+                // marked like a `\n`, with a single caret.
+                let line_len = line_len(files, label.file_id, start_line_index)?;
+                let label_start =
+                    core::cmp::min(label.range.start - start_line_range.start, line_len);
+                // Zero-length source ranges are kept empty, so that the
+                // renderer marks them with a single caret.
+                let label_end = core::cmp::min(label.range.end - start_line_range.start, line_len);
 
-                line.single_labels
-                    .insert(index, (label.style, label_start..label_end, &label.message));
+                single_labels.push((
+                    (file, start_line_index, order),
+                    (
+                        label.style,
+                        label_start..label_end,
+                        &label.message,
+                        label.priority,
+                    ),
+                ));
 
                 // If this line is not rendered, the SingleLabel is not visible.
-                line.must_render = true;
+                add_line(start_line_index, start_line_number, true);
             } else {
                 // Multiple lines
                 //
@@ -222,26 +348,19 @@ where
                 //   │ ╰──────────────^ `case` clauses have incompatible types
                 // ```
 
-                let label_index = labeled_file.num_multi_labels;
-                labeled_file.num_multi_labels += 1;
+                let label_index = snippet_file.num_multi_labels;
+                snippet_file.num_multi_labels += 1;
 
                 // First labeled line
                 let label_start = label.range.start - start_line_range.start;
 
-                let start_line = labeled_file.get_or_insert_line(
-                    start_line_index,
-                    start_line_range.clone(),
-                    start_line_number,
-                );
-
-                start_line.multi_labels.push((
-                    label_index,
-                    label.style,
-                    MultiLabel::Top(label_start),
+                multi_labels.push((
+                    (file, start_line_index, order),
+                    (label_index, label.style, MultiLabel::Top(label_start)),
                 ));
 
                 // The first line has to be rendered so the start of the label is visible.
-                start_line.must_render = true;
+                add_line(start_line_index, start_line_number, true);
 
                 // Marked lines
                 //
@@ -251,23 +370,23 @@ where
                 // 7 │ │     _ 0 => "Buzz"
                 // ```
                 for line_index in (start_line_index + 1)..end_line_index {
-                    let line_range = files.line_range(label.file_id, line_index)?;
                     let line_number = files.line_number(label.file_id, line_index)?;
 
                     outer_padding = core::cmp::max(outer_padding, count_digits(line_number));
 
-                    let line = labeled_file.get_or_insert_line(line_index, line_range, line_number);
-
-                    line.multi_labels
-                        .push((label_index, label.style, MultiLabel::Left));
+                    multi_labels.push((
+                        (file, line_index, order),
+                        (label_index, label.style, MultiLabel::Left),
+                    ));
 
                     // The line should be rendered to match the configuration of how much context to show.
-                    line.must_render |=
+                    let must_render =
                         // Is this line part of the context after the start of the label?
                         line_index - start_line_index <= self.config.start_context_lines
                         ||
                         // Is this line part of the context before the end of the label?
                         end_line_index - line_index <= self.config.end_context_lines;
+                    add_line(line_index, line_number, must_render);
                 }
 
                 // Last labeled line
@@ -276,22 +395,138 @@ where
                 // 8 │ │     _ _ => num
                 //   │ ╰──────────────^ `case` clauses have incompatible types
                 // ```
-                let label_end = label.range.end - end_line_range.start;
-
-                let end_line = labeled_file.get_or_insert_line(
-                    end_line_index,
-                    end_line_range,
-                    end_line_number,
-                );
-
-                end_line.multi_labels.push((
-                    label_index,
-                    label.style,
-                    MultiLabel::Bottom(label_end, &label.message),
+                let line_len = line_len(files, label.file_id, end_line_index)?;
+                let label_end = core::cmp::min(label.range.end - end_line_range.start, line_len);
+
+                multi_labels.push((
+                    (file, end_line_index, order),
+                    (
+                        label_index,
+                        label.style,
+                        MultiLabel::Bottom(label_end, &label.message),
+                    ),
                 ));
 
                 // The last line has to be rendered so the end of the label is visible.
-                end_line.must_render = true;
+                add_line(end_line_index, end_line_number, true);
+            }
+        }
+
+        // Merge the lines that were added more than once.
+        lines.sort_unstable_by_key(|line| (line.file, line.line_index));
+        lines.dedup_by(|line, previous| {
+            let is_same = (line.file, line.line_index) == (previous.file, previous.line_index);
+            previous.must_render |= is_same && line.must_render;
+            is_same
+        });
+
+        // Ensure that the single line labels are lexicographically sorted by
+        // their priority, the start of the range of source code that they
+        // cover, and the end of the range. The renderer lists the messages of
+        // the last labels closest to the source.
+        //
+        // If the keys are the same, order the labels in reverse to how they
+        // were originally specified in the diagnostic. This helps with
+        // printing in the renderer.
+        single_labels.sort_unstable_by_key(|((file, line_index, order), label)| {
+            let (_, range, _, priority) = label;
+            let order = core::cmp::Reverse(*order);
+            (*file, *line_index, *priority, range.start, range.end, order)
+        });
+        multi_labels.sort_unstable_by_key(|(key, _)| *key);
+
+        // Relative line numbers are usually narrower than the absolute ones
+        // that the gutter was sized for above.
+        //
+        // ```text
+        // -1 │ (+ 1
+        //  0 │ (+ test "")
+        // ```
+        if self.config.relative_line_numbers {
+            outer_padding = self.config.line_number_min_width;
+            for line in lines.iter().filter(|line| line.must_render) {
+                let origin = Some(snippet_files[line.file].location.line_number);
+                let width = line_number_width(line.number, origin);
+                outer_padding = core::cmp::max(outer_padding, width);
+            }
+        }
+
+        // Suggested edits, with the lines they change before and after
+        // applying them.
+        for &label_index in label_indices.iter() {
+            let label = &labels[label_index];
+            let suggestion = match &label.suggestion {
+                Some(suggestion) if self.render_header => suggestion,
+                Some(_) | None => continue,
+            };
+
+            let start_line_index = files.line_index(label.file_id, suggestion.range.start)?;
+            let end_line_index = files.line_index(label.file_id, suggestion.range.end)?;
+            let line_start = files.line_range(label.file_id, start_line_index)?.start;
+            let line_number = files.line_number(label.file_id, start_line_index)?;
+
+            let mut deleted = String::new();
+            for line_index in start_line_index..=end_line_index {
+                deleted.push_str(&files.line_source(label.file_id, line_index)?);
+            }
+            if suggestion.range.start > suggestion.range.end {
+                return Err(Error::ReversedRange {
+                    start: suggestion.range.start,
+                    end: suggestion.range.end,
+                });
+            }
+            let before = (suggestion.range.start.checked_sub(line_start))
+                .and_then(|start| deleted.get(..start));
+            let after =
+                (suggestion.range.end.checked_sub(line_start)).and_then(|end| deleted.get(end..));
+            let inserted = match (before, after) {
+                (Some(before), Some(after)) => [before, &suggestion.replacement, after].concat(),
+                (None, _) => {
+                    return Err(Error::InvalidCharBoundary {
+                        given: suggestion.range.start,
+                    })
+                }
+                (_, None) => {
+                    return Err(Error::InvalidCharBoundary {
+                        given: suggestion.range.end,
+                    })
+                }
+            };
+
+            let num_lines = usize::max(deleted.lines().count(), inserted.lines().count());
+            let last_line_number = line_number + num_lines.saturating_sub(1);
+            outer_padding = core::cmp::max(outer_padding, count_digits(last_line_number));
+
+            let locus = Locus {
+                name: files.name(label.file_id)?.to_string(),
+                location: files.location(label.file_id, suggestion.range.start)?,
+            };
+            let url = self.location_url(files, label.file_id, locus.location)?;
+            suggestions.push((locus, url, line_number, deleted, inserted));
+        }
+
+        // Widen the gutter to fit the column numbers, if they are shown.
+        //
+        // ```text
+        // 2:9 │ (+ test "")
+        // ```
+        if self.config.show_columns {
+            for line in lines.iter().filter(|line| line.must_render) {
+                let snippet_file = &snippet_files[line.file];
+                let file_id = labels[snippet_file.label_index].file_id;
+                let origin = (self.config.relative_line_numbers)
+                    .then_some(snippet_file.location.line_number);
+                let source = files.line_source(file_id, line.line_index)?;
+                let source = source.trim_end_matches(['\n', '\r', '\0'].as_ref());
+                copy_line_labels(single_labels, line, line_single_labels);
+                copy_line_labels(multi_labels, line, line_multi_labels);
+                let column_number =
+                    renderer.label_column_number(source, line_single_labels, line_multi_labels);
+                if let Some(column_number) = column_number {
+                    let width =
+                        line_number_width(line.number, origin) + 1 + count_digits(column_number);
+                    outer_padding = core::cmp::max(outer_padding, width);
+                }
             }
         }
 
@@ -300,12 +535,15 @@ where
         // ```text
         // error[E0001]: unexpected type in `+` application
         // ```
-        renderer.render_header(
-            None,
-            self.diagnostic.severity,
-            self.diagnostic.code.as_deref(),
-            self.diagnostic.message.as_str(),
-        )?;
+        if self.render_header {
+            renderer.render_header_with_args(
+                None,
+                self.diagnostic.severity,
+                self.diagnostic.code.as_deref(),
+                self.diagnostic.message.as_str(),
+                &message_args(files, self.diagnostic)?,
+            )?;
+        }
 
         // Source snippets
         //
@@ -316,89 +554,107 @@ where
         //   │         ^^ expected `Int` but found `String`
         //   │
         // ```
-        let mut labeled_files = labeled_files.into_iter().peekable();
-        while let Some(labeled_file) = labeled_files.next() {
-            let source = files.source(labeled_file.file_id)?;
-            let source = source.as_ref();
+        let mut remaining_lines = &lines[..];
+        for (file, snippet_file) in snippet_files.iter().enumerate() {
+            let file_id = labels[snippet_file.label_index].file_id;
+            let num_multi_labels = snippet_file.num_multi_labels;
+            let num_lines = remaining_lines.partition_point(|line| line.file == file);
+            let (file_lines, rest) = remaining_lines.split_at(num_lines);
+            remaining_lines = rest;
+
+            renderer.set_line_number_origin(
+                (self.config.relative_line_numbers).then_some(snippet_file.location.line_number),
+            );
+
+            let locus = locus_in(files, file_id, snippet_file.start, core::mem::take(name))?;
 
             // Top left border and locus.
             //
             // ```text
             // ┌─ test:2:9
             // ```
-            if !labeled_file.lines.is_empty() {
-                renderer.render_snippet_start(
-                    outer_padding,
-                    &Locus {
-                        name: labeled_file.name,
-                        location: labeled_file.location,
-                    },
-                )?;
-                renderer.render_snippet_empty(
-                    outer_padding,
-                    self.diagnostic.severity,
-                    labeled_file.num_multi_labels,
-                    &[],
-                )?;
+            if !file_lines.is_empty() && self.render_locus {
+                let url = self.location_url(files, file_id, snippet_file.location)?;
+                renderer.render_snippet_start(outer_padding, &locus, url.as_deref())?;
+                if !self.config.compact_borders {
+                    renderer.render_snippet_empty(
+                        outer_padding,
+                        self.diagnostic.severity,
+                        num_multi_labels,
+                        &[],
+                    )?;
+                }
             }
 
-            let mut lines = labeled_file
-                .lines
-                .iter()
-                .filter(|(_, line)| line.must_render)
-                .peekable();
-
-            while let Some((line_index, line)) = lines.next() {
+            let mut file_lines = file_lines.iter().filter(|line| line.must_render).peekable();
+            while let Some(line) = file_lines.next() {
+                if renderer.has_source_style() {
+                    let line_start = files.line_range(file_id, line.line_index)?.start;
+                    renderer.set_source_file(Some((&locus.name, line_start)));
+                }
+                copy_line_labels(single_labels, line, line_single_labels);
+                copy_line_labels(multi_labels, line, line_multi_labels);
                 renderer.render_snippet_source(
                     outer_padding,
                     line.number,
-                    &source[line.range.clone()],
+                    &files.line_source(file_id, line.line_index)?,
                     self.diagnostic.severity,
-                    &line.single_labels,
-                    labeled_file.num_multi_labels,
-                    &line.multi_labels,
+                    line_single_labels,
+                    num_multi_labels,
+                    line_multi_labels,
                 )?;
 
                 // Check to see if we need to render any intermediate stuff
                 // before rendering the next line.
-                if let Some((next_line_index, next_line)) = lines.peek() {
-                    match next_line_index.checked_sub(*line_index) {
+                if let Some(next_line) = file_lines.peek() {
+                    // A single omitted line is always rendered, as a break
+                    // would take up just as much space.
+                    let merge_threshold = core::cmp::max(1, self.config.context_merge_threshold);
+
+                    match next_line.line_index.checked_sub(line.line_index) {
                         // Consecutive lines
                         Some(1) => {}
-                        // One line between the current line and the next line
-                        Some(2) => {
-                            // Write a source line
-                            let file_id = labeled_file.file_id;
-
-                            // This line was not intended to be rendered initially.
-                            // To render the line right, we have to get back the original labels.
-                            let labels = labeled_file
-                                .lines
-                                .get(&(line_index + 1))
-                                .map_or(&[][..], |line| &line.multi_labels[..]);
-
-                            renderer.render_snippet_source(
-                                outer_padding,
-                                files.line_number(file_id, line_index + 1)?,
-                                &source[files.line_range(file_id, line_index + 1)?],
-                                self.diagnostic.severity,
-                                &[],
-                                labeled_file.num_multi_labels,
-                                labels,
-                            )?;
+                        // Few enough lines between the current line and the next line
+                        Some(distance) if distance - 1 <= merge_threshold => {
+                            // Write the source lines
+                            for line_index in (line.line_index + 1)..next_line.line_index {
+                                // This line was not intended to be rendered initially.
+                                // To render the line right, we have to get back the original labels.
+                                let gap_line = SnippetLine {
+                                    line_index,
+                                    ..*line
+                                };
+                                copy_line_labels(multi_labels, &gap_line, line_multi_labels);
+
+                                if renderer.has_source_style() {
+                                    let line_start = files.line_range(file_id, line_index)?.start;
+                                    renderer.set_source_file(Some((&locus.name, line_start)));
+                                }
+                                renderer.render_snippet_source(
+                                    outer_padding,
+                                    files.line_number(file_id, line_index)?,
+                                    &files.line_source(file_id, line_index)?,
+                                    self.diagnostic.severity,
+                                    &[],
+                                    num_multi_labels,
+                                    line_multi_labels,
+                                )?;
+                            }
                         }
-                        // More than one line between the current line and the next line.
+                        // Too many lines between the current line and the next line.
                         Some(_) | None => {
                             // Source break
                             //
                             // ```text
                             // ·
                             // ```
+                            copy_line_labels(multi_labels, next_line, line_multi_labels);
                             renderer.render_snippet_break(
                                 outer_padding,
                                 self.diagnostic.severity,
-                                labeled_file.num_multi_labels,
-                                &next_line.multi_labels,
+                                num_multi_labels,
+                                line_multi_labels,
+                                next_line.line_index - line.line_index - 1,
                             )?;
                         }
                     }
@@ -407,19 +663,70 @@ where
 
             // Check to see if we should render a trailing border after the
             // final line of the snippet.
-            if labeled_files.peek().is_none() && self.diagnostic.notes.is_empty() {
+            let has_notes = self.render_header && !self.diagnostic.notes.is_empty();
+            if file + 1 == snippet_files.len()
+                && !has_notes
+                && suggestions.is_empty()
+                && omitted_labels == 0
+            {
                 // We don't render a border if we are at the final newline
-                // without trailing notes, because it would end up looking too
+                // without trailing notes or suggestions, because it would end up looking too
                 // spaced-out in combination with the final new line.
+            } else if self.config.compact_borders {
+                // The border is left out to save space.
             } else {
                 // Render the trailing snippet border.
                 renderer.render_snippet_empty(
                     outer_padding,
                     self.diagnostic.severity,
-                    labeled_file.num_multi_labels,
+                    num_multi_labels,
                     &[],
                 )?;
             }
+
+            *name = locus.name;
+        }
+
+        renderer.set_line_number_origin(None);
+        renderer.set_source_file(None);
+
+        // Labels left out because of `Config::max_labels`
+        //
+        // ```text
+        // = … and 487 more labels
+        // ```
+        if omitted_labels > 0 {
+            renderer.render_omitted_labels(outer_padding, omitted_labels)?;
+        }
+
+        if !self.render_header {
+            return Ok(());
+        }
+
+        // Suggested edits
+        //
+        // ```text
+        // help: suggested fix
+        //   ┌─ test:2:9
+        //   │
+        // 2 - (+ test "")
+        // 2 + (+ test 0)
+        //   │
+        // ```
+        let mut suggestions = suggestions.iter().peekable();
+        while let Some((locus, url, line_number, deleted, inserted)) = suggestions.next() {
+            renderer.render_header(None, Severity::Help, None, &self.config.suggestion_message)?;
+            renderer.render_snippet_start(outer_padding, locus, url.as_deref())?;
+            if !self.config.compact_borders {
+                renderer.render_snippet_empty(outer_padding, Severity::Help, 0, &[])?;
+            }
+            renderer.render_suggestion_lines(outer_padding, *line_number, deleted, inserted)?;
+
+            if (suggestions.peek().is_some() || !self.diagnostic.notes.is_empty())
+                && !self.config.compact_borders
+            {
+                renderer.render_snippet_empty(outer_padding, Severity::Help, 0, &[])?;
+            }
         }
 
         // Additional notes
@@ -429,7 +736,155 @@ where
         //      found type `String`
         // ```
         for note in &self.diagnostic.notes {
-            renderer.render_snippet_note(outer_padding, note)?;
+            renderer.render_diagnostic_note(
+                outer_padding,
+                self.diagnostic.severity,
+                note.severity,
+                &note.message,
+            )?;
+        }
+        renderer.render_empty()
+    }
+}
+
+/// Output a diagnostic about binary data, with the labeled bytes in a hex dump.
+///
+/// The ranges of the labels are byte offsets into the data, and only the rows
+/// of the hex dump that contain labels are shown.
+pub struct HexdumpDiagnostic<'diagnostic, 'bytes> {
+    diagnostic: &'diagnostic Diagnostic<()>,
+    bytes: &'bytes [u8],
+}
+
+impl<'diagnostic, 'bytes> HexdumpDiagnostic<'diagnostic, 'bytes> {
+    pub fn new(
+        diagnostic: &'diagnostic Diagnostic<()>,
+        bytes: &'bytes [u8],
+    ) -> HexdumpDiagnostic<'diagnostic, 'bytes> {
+        HexdumpDiagnostic { diagnostic, bytes }
+    }
+
+    pub fn render(&self, renderer: &mut Renderer<'_, '_>) -> Result<(), Error> {
+        let mut buffers = renderer.take_snippet_buffers();
+        let result = self.render_with_buffers(renderer, &mut buffers);
+        renderer.restore_snippet_buffers(buffers);
+        result
+    }
+
+    fn render_with_buffers(
+        &self,
+        renderer: &mut Renderer<'_, '_>,
+        buffers: &mut SnippetBuffers<'diagnostic>,
+    ) -> Result<(), Error> {
+        let SnippetBuffers {
+            label_indices: rows,
+            line_single_labels: row_labels,
+            ..
+        } = buffers;
+        let severity = self.diagnostic.severity;
+        let labels = &self.diagnostic.labels;
+        for label in labels {
+            if label.range.start > label.range.end {
+                return Err(Error::ReversedRange {
+                    start: label.range.start,
+                    end: label.range.end,
+                });
+            }
+            if label.range.end > self.bytes.len() {
+                return Err(Error::IndexTooLarge {
+                    given: label.range.end,
+                    max: self.bytes.len(),
+                });
+            }
+        }
+
+        // Header
+        //
+        // ```text
+        // error: invalid magic number
+        // ```
+        let message = &self.diagnostic.message;
+        let code = self.diagnostic.code.as_deref();
+        renderer.render_header(None, severity, code, message)?;
+
+        // The offsets are at least 8 hexadecimal digits wide
+        let len_bits = usize::BITS - self.bytes.len().leading_zeros();
+        let outer_padding = usize::max((len_bits as usize + 3) / 4, 8);
+
+        // The rows containing the labels, where zero-width labels are in the
+        // row of the byte following them
+        rows.extend(labels.iter().flat_map(|label| {
+            let last = usize::max(label.range.end, label.range.start + 1) - 1;
+            (label.range.start / HEXDUMP_ROW_LEN)..=(last / HEXDUMP_ROW_LEN)
+        }));
+        rows.sort_unstable();
+        rows.dedup();
+
+        // Hex dump
+        //
+        // ```text
+        //          │
+        // 00000000 │ 7f 45 4c 47 02 01 01 00 00 00 00 00 00 00 00 00 │.ELG............│
+        //          │    ^^^^^^^^ expected `ELF`
+        // ```
+        if !rows.is_empty() {
+            renderer.render_snippet_empty(outer_padding, severity, 0, &[])?;
+        }
+        let mut previous_row = None;
+        for &row in rows.iter() {
+            if let Some(previous_row) = previous_row.filter(|previous| row > previous + 1) {
+                let omitted_rows = row - previous_row - 1;
+                renderer.render_snippet_break(outer_padding, severity, 0, &[], omitted_rows)?;
+            }
+            previous_row = Some(row);
+
+            let row_start = row * HEXDUMP_ROW_LEN;
+            let row_end = usize::min(row_start + HEXDUMP_ROW_LEN, self.bytes.len());
+            row_labels.clear();
+            row_labels.extend(
+                labels
+                    .iter()
+                    .filter(|label| match label.range.is_empty() {
+                        true => {
+                            (row_start..row_start + HEXDUMP_ROW_LEN).contains(&label.range.start)
+                        }
+                        false => label.range.start < row_end && row_start < label.range.end,
+                    })
+                    .map(|label| {
+                        let start = usize::max(label.range.start, row_start) - row_start;
+                        let end = usize::min(label.range.end, row_end) - row_start;
+                        let message = match label.range.end <= row_end {
+                            true => label.message.as_str(),
+                            false => "",
+                        };
+                        (label.style, start..end, message, label.priority)
+                    }),
+            );
+
+            renderer.render_hexdump_row(
+                outer_padding,
+                row_start,
+                &self.bytes[row_start..row_end],
+                severity,
+                row_labels,
+            )?;
+        }
+
+        // Additional notes
+        //
+        // ```text
+        // = the file is not an ELF executable
+        // ```
+        if !self.diagnostic.notes.is_empty() {
+            renderer.render_snippet_empty(outer_padding, severity, 0, &[])?;
+        }
+        for note in &self.diagnostic.notes {
+            renderer.render_diagnostic_note(
+                outer_padding,
+                self.diagnostic.severity,
+                note.severity,
+                &note.message,
+            )?;
         }
         renderer.render_empty()
     }
@@ -468,36 +923,68 @@ where
         // ```text
         // test:2:9: error[E0001]: unexpected type in `+` application
         // ```
+        let args = message_args(files, self.diagnostic)?;
+        let mut buffers = renderer.take_snippet_buffers();
         let mut primary_labels_encountered = 0;
         let labels = self.diagnostic.labels.iter();
         for label in labels.filter(|label| label.style == LabelStyle::Primary) {
             primary_labels_encountered += 1;
 
-            renderer.render_header(
-                Some(&Locus {
-                    name: files.name(label.file_id)?.to_string(),
-                    location: files.location(label.file_id, label.range.start)?,
-                }),
+            let locus = locus_in(
+                files,
+                label.file_id,
+                label.range.start,
+                core::mem::take(&mut buffers.name),
+            )?;
+            renderer.render_header_with_args(
+                Some(&locus),
                 self.diagnostic.severity,
                 self.diagnostic.code.as_deref(),
                 self.diagnostic.message.as_str(),
+                &args,
             )?;
+            buffers.name = locus.name;
         }
 
-        // Fallback to printing a non-located header if no primary labels were encountered
+        // Fallback to locating the header at the earliest secondary label in
+        // the first labeled file if no primary labels were encountered, or to
+        // printing a non-located header if there are no labels at all
         //
         // ```text
+        // test:2:9: note: value defined here
         // error[E0002]: Bad config found
         // ```
         if primary_labels_encountered == 0 {
-            renderer.render_header(
-                None,
+            let earliest_label = self.diagnostic.labels.first().and_then(|first| {
+                let labels = self.diagnostic.labels.iter();
+                labels
+                    .filter(|label| label.file_id == first.file_id)
+                    .min_by_key(|label| label.range.start)
+            });
+            let locus = match earliest_label {
+                Some(label) => Some(locus_in(
+                    files,
+                    label.file_id,
+                    label.range.start,
+                    core::mem::take(&mut buffers.name),
+                )?),
+                None => None,
+            };
+
+            renderer.render_header_with_args(
+                locus.as_ref(),
                 self.diagnostic.severity,
                 self.diagnostic.code.as_deref(),
                 self.diagnostic.message.as_str(),
+                &args,
             )?;
+            if let Some(locus) = locus {
+                buffers.name = locus.name;
+            }
         }
 
+        renderer.restore_snippet_buffers(buffers);
+
         if self.show_notes {
             // Additional notes
             //
@@ -506,10 +993,236 @@ where
             //      found type `String`
             // ```
             for note in &self.diagnostic.notes {
-                renderer.render_snippet_note(0, note)?;
+                renderer.render_diagnostic_note(
+                    0,
+                    self.diagnostic.severity,
+                    note.severity,
+                    &note.message,
+                )?;
             }
         }
 
         Ok(())
     }
 }
+
+/// Output a diagnostic as GitHub Actions workflow commands, with one command
+/// for each primary label.
+pub struct GitHubActionsDiagnostic<'diagnostic, FileId> {
+    diagnostic: &'diagnostic Diagnostic<FileId>,
+}
+
+impl<'diagnostic, FileId> GitHubActionsDiagnostic<'diagnostic, FileId>
+where
+    FileId: Copy + PartialEq,
+{
+    pub fn new(
+        diagnostic: &'diagnostic Diagnostic<FileId>,
+    ) -> GitHubActionsDiagnostic<'diagnostic, FileId> {
+        GitHubActionsDiagnostic { diagnostic }
+    }
+
+    pub fn render<'files>(
+        &self,
+        files: &'files (impl Files<'files, FileId = FileId> + ?Sized),
+        renderer: &mut Renderer<'_, '_>,
+    ) -> Result<(), Error>
+    where
+        FileId: 'files,
+    {
+        // Located annotations
+        //
+        // ```text
+        // ::error file=test,line=2,col=9,endLine=2,endColumn=11,title=E0001::unexpected type in `+` application
+        // ```
+        let message = interpolate_message(
+            &self.diagnostic.message,
+            &message_args(files, self.diagnostic)?,
+        );
+        let mut primary_labels_encountered = 0;
+        let labels = self.diagnostic.labels.iter();
+        for label in labels.filter(|label| label.style == LabelStyle::Primary) {
+            primary_labels_encountered += 1;
+
+            let locus = Locus {
+                name: files.name(label.file_id)?.to_string(),
+                location: files.location(label.file_id, label.range.start)?,
+            };
+            let end_location = files.location(label.file_id, label.range.end)?;
+            renderer.render_github_annotation(
+                Some((&locus, end_location)),
+                self.diagnostic.severity,
+                self.diagnostic.code.as_deref(),
+                &message,
+                &self.diagnostic.notes,
+            )?;
+        }
+
+        // Fallback to an annotation without a location if no primary labels were encountered
+        //
+        // ```text
+        // ::error title=E0002::Bad config found
+        // ```
+        if primary_labels_encountered == 0 {
+            renderer.render_github_annotation(
+                None,
+                self.diagnostic.severity,
+                self.diagnostic.code.as_deref(),
+                &message,
+                &self.diagnostic.notes,
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Output a diagnostic as Markdown, with the source snippets in a fenced code
+/// block.
+pub struct MarkdownDiagnostic<'diagnostic, 'config, FileId> {
+    diagnostic: &'diagnostic Diagnostic<FileId>,
+    config: &'config Config,
+}
+
+impl<'diagnostic, 'config, FileId> MarkdownDiagnostic<'diagnostic, 'config, FileId>
+where
+    FileId: Copy + PartialEq,
+{
+    pub fn new(
+        diagnostic: &'diagnostic Diagnostic<FileId>,
+        config: &'config Config,
+    ) -> MarkdownDiagnostic<'diagnostic, 'config, FileId> {
+        MarkdownDiagnostic { diagnostic, config }
+    }
+
+    pub fn render<'files>(
+        &self,
+        files: &'files (impl Files<'files, FileId = FileId> + ?Sized),
+        renderer: &mut Renderer<'_, '_>,
+    ) -> Result<(), Error>
+    where
+        FileId: 'files,
+    {
+        // Header and message
+        //
+        // ```text
+        // **error[E0001]**: unexpected type in `+` application
+        // ```
+        renderer.render_markdown_header(
+            self.diagnostic.severity,
+            self.diagnostic.code.as_deref(),
+            &interpolate_message(
+                &self.diagnostic.message,
+                &message_args(files, self.diagnostic)?,
+            ),
+        )?;
+
+        // Source snippets, rendered without styling so that they can be put
+        // in a code block
+        //
+        // ````text
+        // ```text
+        //   ┌─ test:2:9
+        //   │
+        // 2 │ (+ test "")
+        //   │         ^^ expected `Int` but found `String`
+        // ```
+        // ````
+        //
+        // The margin and the line prefix are written by the outer renderer,
+        // around the code block.
+        let config = Config {
+            left_margin: 0,
+            line_prefix: None,
+            ..self.config.clone()
+        };
+        let snippets = Renderer::render_to_string(&config, |renderer| {
+            RichDiagnostic::new(self.diagnostic, &config)
+                .without_header()
+                .render(files, renderer)
+        })?;
+        if !snippets.is_empty() {
+            renderer.render_markdown_code_block(&snippets)?;
+        }
+
+        // Additional notes
+        //
+        // ```text
+        // - expected type `Int`\
+        //   found type `String`
+        // ```
+        if !self.diagnostic.notes.is_empty() {
+            renderer.render_empty()?;
+        }
+        for note in &self.diagnostic.notes {
+            renderer.render_markdown_severity_note(note.severity, &note.message)?;
+        }
+        renderer.render_empty()
+    }
+}
+
+/// Output a diagnostic on a single line, located at its first primary label.
+pub struct GrepDiagnostic<'diagnostic, FileId> {
+    diagnostic: &'diagnostic Diagnostic<FileId>,
+}
+
+impl<'diagnostic, FileId> GrepDiagnostic<'diagnostic, FileId>
+where
+    FileId: Copy + PartialEq,
+{
+    pub fn new(diagnostic: &'diagnostic Diagnostic<FileId>) -> GrepDiagnostic<'diagnostic, FileId> {
+        GrepDiagnostic { diagnostic }
+    }
+
+    pub fn render<'files>(
+        &self,
+        files: &'files (impl Files<'files, FileId = FileId> + ?Sized),
+        renderer: &mut Renderer<'_, '_>,
+    ) -> Result<(), Error>
+    where
+        FileId: 'files,
+    {
+        // Located header, with the column number counting tabs as they are
+        // displayed
+        //
+        // ```text
+        // test:2:9: error[E0001]: unexpected type in `+` application
+        // ```
+        let mut labels = self.diagnostic.labels.iter();
+        let locus = match labels.find(|label| label.style == LabelStyle::Primary) {
+            Some(label) => {
+                let line_index = files.line_index(label.file_id, label.range.start)?;
+                let line_start = files.line_range(label.file_id, line_index)?.start;
+                let source = files.line_source(label.file_id, line_index)?;
+                let column_number = source
+                    .get(..label.range.start - line_start)
+                    .map(|before| renderer.column_number(&source, before.len()))
+                    .ok_or(Error::InvalidCharBoundary {
+                        given: label.range.start,
+                    })?;
+
+                Some(Locus {
+                    name: files.name(label.file_id)?.to_string(),
+                    location: Location {
+                        line_number: files.line_number(label.file_id, line_index)?,
+                        column_number,
+                    },
+                })
+            }
+            // Header without a location if there are no primary labels
+            //
+            // ```text
+            // error[E0002]: Bad config found
+            // ```
+            None => None,
+        };
+
+        renderer.render_header_with_args(
+            locus.as_ref(),
+            self.diagnostic.severity,
+            self.diagnostic.code.as_deref(),
+            self.diagnostic.message.as_str(),
+            &message_args(files, self.diagnostic)?,
+        )
+    }
+}
@@ -0,0 +1,127 @@
+use core::alloc::{GlobalAlloc, Layout};
+use core::cell::Cell;
+use std::alloc::System;
+
+use codespan_reporting::diagnostic::{Diagnostic, Label};
+use codespan_reporting::files::SimpleFiles;
+use codespan_reporting::term::{self, write_short, Config, DisplayStyle, PlainWriter, Renderer};
+
+/// An allocator that counts the allocations made through it by each thread,
+/// so that the tests can run in parallel.
+struct CountingAllocator;
+
+std::thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|allocations| allocations.set(allocations.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// The number of allocations made by `f` on the current thread.
+fn count_allocations<T>(f: impl FnOnce() -> T) -> (T, usize) {
+    let before = ALLOCATIONS.with(Cell::get);
+    let result = f();
+    (result, ALLOCATIONS.with(Cell::get) - before)
+}
+
+/// The output is written straight into the buffer, so the allocations that
+/// rendering makes do not grow with the length of the output.
+#[test]
+fn write_short_allocations_do_not_depend_on_output() {
+    let mut files = SimpleFiles::new();
+    let id = files.add("test", "let x = λ;\n");
+    let config = Config::default();
+
+    let allocations = |message: &str| {
+        let diagnostic = Diagnostic::error()
+            .with_message(message)
+            .with_labels(vec![Label::primary(id, 8..10)]);
+
+        let mut buf = [0; 4096];
+        let (len, allocations) =
+            count_allocations(|| write_short(&mut buf, &config, &files, &diagnostic).unwrap());
+        assert!(buf[..len].ends_with(format!("{message}\n").as_bytes()));
+        allocations
+    };
+
+    let short = allocations("unexpected `λ`");
+    let long = allocations(&"unexpected `λ` ".repeat(200));
+    assert_eq!(short, long);
+}
+
+/// Diagnostics that are rendered together reuse the buffers of one renderer,
+/// so they allocate less than when they are emitted one at a time.
+#[test]
+fn batches_reuse_allocations() {
+    let mut files = SimpleFiles::new();
+    let id = files.add("test", "let x = 1;\n");
+    let config = Config::default();
+    let diagnostics = (0..20)
+        .map(|_| {
+            Diagnostic::warning()
+                .with_message("unused variable")
+                .with_labels(vec![Label::primary(id, 4..5)])
+                .with_note("remove it")
+        })
+        .collect::<Vec<_>>();
+
+    let mut writer = PlainWriter::new(Vec::with_capacity(1 << 16));
+    let ((), separately) = count_allocations(|| {
+        for diagnostic in &diagnostics {
+            term::emit(&mut writer, &config, &files, diagnostic).unwrap();
+        }
+    });
+    let mut writer = PlainWriter::new(Vec::with_capacity(1 << 16));
+    let ((), together) = count_allocations(|| {
+        term::render_stream(&mut writer, &config, &files, &diagnostics).unwrap();
+    });
+    assert!(
+        together < separately,
+        "{together} allocations for a batch, {separately} one at a time",
+    );
+}
+
+/// Once the buffers of a renderer have grown to fit a diagnostic, rendering
+/// diagnostics like it with the same renderer makes no more allocations.
+#[test]
+fn renderer_reuses_allocations_between_diagnostics() {
+    let mut files = SimpleFiles::new();
+    let id = files.add("test", "let x = 1;\nlet y = x;\n");
+    let diagnostic = Diagnostic::warning()
+        .with_message("unused variable")
+        .with_labels(vec![
+            Label::primary(id, 4..5),
+            Label::secondary(id, 19..20).with_message("used here"),
+        ])
+        .with_note("remove it");
+
+    for display_style in [DisplayStyle::Rich, DisplayStyle::Medium] {
+        let config = Config {
+            display_style: display_style.clone(),
+            ..Config::default()
+        };
+        let mut writer = PlainWriter::new(Vec::with_capacity(1 << 16));
+        let mut renderer = Renderer::new(&mut writer, &config);
+        renderer.render(&files, &diagnostic).unwrap();
+        let ((), allocations) = count_allocations(|| {
+            for _ in 0..100 {
+                renderer.render(&files, &diagnostic).unwrap();
+            }
+        });
+        assert_eq!(
+            allocations, 0,
+            "{allocations} allocations for 100 diagnostics in {display_style:?}",
+        );
+    }
+}
@@ -16,6 +16,7 @@ use termcolor::{ColorSpec, WriteColor};
 /// - Background color as `bg:Color`
 /// - Bold as `bold`
 /// - Underline as `underline`
+/// - Dimmed as `dimmed`
 /// - Intense as `bright`
 ///
 /// For example, the style "intense, bold red foreground" would be printed as:
@@ -114,6 +115,11 @@ impl WriteColor for ColorBuffer {
             write!(self, "underline")?;
         }
 
+        if spec.dimmed() {
+            first = write_first(first, self)?;
+            write!(self, "dimmed")?;
+        }
+
         if spec.intense() {
             first = write_first(first, self)?;
             write!(self, "bright")?;
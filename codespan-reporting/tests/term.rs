@@ -1,6 +1,12 @@
-use codespan_reporting::diagnostic::{Diagnostic, Label};
+#![allow(clippy::incompatible_msrv)]
+
+extern crate alloc;
+
+use codespan_reporting::diagnostic::{Diagnostic, Label, Severity};
 use codespan_reporting::files::{SimpleFile, SimpleFiles};
-use codespan_reporting::term::{Chars, Config, DisplayStyle};
+use codespan_reporting::term::{
+    CaretFillStyle, Chars, Config, DisplayStyle, LineNumberAlignment, RenderWhitespace,
+};
 use std::sync::LazyLock;
 
 mod support;
@@ -87,6 +93,75 @@ macro_rules! test_emit {
                 ..TEST_CONFIG.clone()
             };
 
+            insta::assert_snapshot!(TEST_DATA.emit_no_color(&config));
+        }
+    };
+    (markdown_no_color) => {
+        #[test]
+        fn markdown_no_color() {
+            let config = Config {
+                display_style: DisplayStyle::Markdown,
+                ..TEST_CONFIG.clone()
+            };
+
+            insta::assert_snapshot!(TEST_DATA.emit_no_color(&config));
+        }
+    };
+    (github_actions_no_color) => {
+        #[test]
+        fn github_actions_no_color() {
+            let config = Config {
+                display_style: DisplayStyle::GitHubActions,
+                ..TEST_CONFIG.clone()
+            };
+
+            insta::assert_snapshot!(TEST_DATA.emit_no_color(&config));
+        }
+    };
+    (grep_no_color) => {
+        #[test]
+        fn grep_no_color() {
+            let config = Config {
+                display_style: DisplayStyle::Grep,
+                ..TEST_CONFIG.clone()
+            };
+
+            insta::assert_snapshot!(TEST_DATA.emit_no_color(&config));
+        }
+    };
+    (rich_rounded_no_color) => {
+        #[test]
+        fn rich_rounded_no_color() {
+            let config = Config {
+                display_style: DisplayStyle::Rich,
+                chars: Chars::rounded(),
+                ..TEST_CONFIG.clone()
+            };
+
+            insta::assert_snapshot!(TEST_DATA.emit_no_color(&config));
+        }
+    };
+    (rich_heavy_no_color) => {
+        #[test]
+        fn rich_heavy_no_color() {
+            let config = Config {
+                display_style: DisplayStyle::Rich,
+                chars: Chars::heavy(),
+                ..TEST_CONFIG.clone()
+            };
+
+            insta::assert_snapshot!(TEST_DATA.emit_no_color(&config));
+        }
+    };
+    (rich_high_contrast_no_color) => {
+        #[test]
+        fn rich_high_contrast_no_color() {
+            let config = Config {
+                display_style: DisplayStyle::Rich,
+                chars: Chars::high_contrast(),
+                ..TEST_CONFIG.clone()
+            };
+
             insta::assert_snapshot!(TEST_DATA.emit_no_color(&config));
         }
     };
@@ -117,6 +192,8 @@ mod empty {
     test_emit!(rich_no_color);
     test_emit!(medium_no_color);
     test_emit!(short_no_color);
+    test_emit!(markdown_no_color);
+    test_emit!(github_actions_no_color);
     test_emit!(rich_ascii_no_color);
 }
 
@@ -170,6 +247,8 @@ mod same_line {
     test_emit!(rich_no_color);
     test_emit!(medium_no_color);
     test_emit!(short_no_color);
+    test_emit!(markdown_no_color);
+    test_emit!(github_actions_no_color);
     test_emit!(rich_ascii_no_color);
 }
 
@@ -324,6 +403,8 @@ mod overlapping {
     test_emit!(medium_no_color);
     test_emit!(short_no_color);
     test_emit!(rich_ascii_no_color);
+    test_emit!(rich_rounded_no_color);
+    test_emit!(rich_heavy_no_color);
 }
 
 mod message {
@@ -350,6 +431,7 @@ mod message {
     test_emit!(medium_no_color);
     test_emit!(short_no_color);
     test_emit!(rich_ascii_no_color);
+    test_emit!(grep_no_color);
 }
 
 mod message_and_notes {
@@ -383,9 +465,39 @@ mod message_and_notes {
     test_emit!(rich_no_color);
     test_emit!(medium_no_color);
     test_emit!(short_no_color);
+    test_emit!(markdown_no_color);
+    test_emit!(github_actions_no_color);
     test_emit!(rich_ascii_no_color);
 }
 
+mod header_only {
+    use super::*;
+
+    static TEST_DATA: LazyTestData<'static, SimpleFiles<&'static str, &'static str>> =
+        LazyLock::new(|| {
+            let mut files = SimpleFiles::new();
+            files.add("config.toml", "[package]\nname = \"\"\n");
+
+            let diagnostics = vec![
+                Diagnostic::error()
+                    .with_code("E0002")
+                    .with_message("Bad config found")
+                    .with_notes(vec![
+                        "the package name is empty".to_owned(),
+                        "expected a name like\n`my-package`".to_owned(),
+                    ])
+                    .with_severity_note(Severity::Help, "set `package.name`"),
+                Diagnostic::warning().with_message("3 files were skipped"),
+            ];
+
+            TestData { files, diagnostics }
+        });
+
+    test_emit!(rich_color);
+    test_emit!(rich_no_color);
+    test_emit!(medium_no_color);
+}
+
 mod message_errorcode {
     use super::*;
 
@@ -425,6 +537,7 @@ mod message_errorcode {
 
     test_emit!(rich_no_color);
     test_emit!(short_no_color);
+    test_emit!(github_actions_no_color);
     test_emit!(rich_ascii_no_color);
 }
 
@@ -586,7 +699,65 @@ mod multifile {
     test_emit!(rich_no_color);
     test_emit!(medium_no_color);
     test_emit!(short_no_color);
+    test_emit!(markdown_no_color);
+    test_emit!(github_actions_no_color);
     test_emit!(rich_ascii_no_color);
+    test_emit!(grep_no_color);
+}
+
+mod multifile_primary {
+    use super::*;
+
+    static TEST_DATA: LazyTestData<'static, SimpleFiles<&'static str, String>> =
+        LazyLock::new(|| {
+            let mut files = SimpleFiles::new();
+
+            let definition = unindent::unindent(
+                "
+                        def greet(name : String) : String =
+                            let greeting = \"hello \" ++ name
+                            in greeting
+                    ",
+            );
+            let use_source = unindent::unindent(
+                "
+                        def main : Unit =
+                            print (greet
+                                42)
+                    ",
+            );
+
+            let param_start = definition.find("name").unwrap();
+            let param_end = definition.find(')').unwrap();
+            let body_start = definition.find("let").unwrap();
+            let body_end = definition.rfind("greeting").unwrap() + "greeting".len();
+            let call_start = use_source.find("greet").unwrap();
+            let call_end = call_start + "greet".len();
+            let arg_start = use_source.find('(').unwrap();
+            let arg_end = use_source.find(')').unwrap() + 1;
+
+            let definition_id = files.add("definition.fun", definition);
+            let use_id = files.add("use.fun", use_source);
+
+            let diagnostics = vec![Diagnostic::error()
+                .with_message("type mismatch between definition and use")
+                .with_code("E0308")
+                .with_labels(vec![
+                    Label::primary(use_id, arg_start..arg_end)
+                        .with_message("expected `String`, found `Int`"),
+                    Label::primary(definition_id, param_start..param_end)
+                        .with_message("parameter declared here"),
+                    Label::secondary(definition_id, body_start..body_end)
+                        .with_message("in this body"),
+                    Label::secondary(use_id, call_start..call_end).with_message("in this call"),
+                ])];
+
+            TestData { files, diagnostics }
+        });
+
+    test_emit!(rich_color);
+    test_emit!(rich_no_color);
+    test_emit!(short_no_color);
 }
 
 mod fizz_buzz {
@@ -674,7 +845,11 @@ mod fizz_buzz {
     test_emit!(rich_no_color);
     test_emit!(medium_no_color);
     test_emit!(short_no_color);
+    test_emit!(markdown_no_color);
+    test_emit!(github_actions_no_color);
     test_emit!(rich_ascii_no_color);
+    test_emit!(rich_rounded_no_color);
+    test_emit!(rich_heavy_no_color);
 }
 
 mod multiline_overlapping {
@@ -731,6 +906,8 @@ mod multiline_overlapping {
     test_emit!(medium_no_color);
     test_emit!(short_no_color);
     test_emit!(rich_ascii_no_color);
+    test_emit!(rich_rounded_no_color);
+    test_emit!(rich_heavy_no_color);
 }
 
 mod tabbed {
@@ -799,6 +976,7 @@ mod tabbed {
 
         insta::assert_snapshot!(TEST_DATA.emit_no_color(&config));
     }
+    test_emit!(grep_no_color);
 }
 
 mod tab_columns {
@@ -872,6 +1050,60 @@ mod tab_columns {
     }
 }
 
+mod tab_spans {
+    use super::*;
+
+    static TEST_CONFIG: LazyLock<Config> = LazyLock::new(|| Config {
+        tab_width: 8,
+        ..Config::default()
+    });
+
+    static TEST_DATA: LazyTestData<'static, SimpleFiles<&'static str, String>> =
+        LazyLock::new(|| {
+            let mut files = SimpleFiles::new();
+
+            let source = "\tlet x = 1;\nlet\ty\t=\t2;\n\tif\tx {\n\t\ty\n\t}\n".to_owned();
+            let x_start = source.find('x').unwrap();
+            let y_start = source.find('y').unwrap();
+            let y_end = source.find("2;").unwrap() + 1;
+            let if_start = source.find("if").unwrap();
+            let if_end = source.rfind('}').unwrap() + 1;
+            let file_id = files.add("tabs", source);
+
+            let diagnostics = vec![
+                Diagnostic::warning()
+                    .with_message("span after a leading tab")
+                    .with_labels(vec![
+                        Label::primary(file_id, x_start..x_start + 1).with_message("unused")
+                    ]),
+                Diagnostic::warning()
+                    .with_message("span containing interior tabs")
+                    .with_labels(vec![
+                        Label::primary(file_id, y_start..y_end).with_message("binding")
+                    ]),
+                Diagnostic::warning()
+                    .with_message("multi-line span after tabs")
+                    .with_labels(vec![
+                        Label::primary(file_id, if_start..if_end).with_message("condition")
+                    ]),
+            ];
+
+            TestData { files, diagnostics }
+        });
+
+    test_emit!(rich_no_color);
+
+    #[test]
+    fn tab_width_3_no_color() {
+        let config = Config {
+            tab_width: 3,
+            ..TEST_CONFIG.clone()
+        };
+
+        insta::assert_snapshot!(TEST_DATA.emit_no_color(&config));
+    }
+}
+
 /// Based on:
 /// - https://github.com/TheSamsa/rust/blob/75cf41afb468152611212271bae026948cd3ba46/src/test/ui/codemap_tests/unicode.stderr
 mod unicode {
@@ -1097,14 +1329,36 @@ mod multiline_omit {
         });
 
     test_emit!(rich_no_color);
+    test_emit!(rich_rounded_no_color);
+    test_emit!(rich_heavy_no_color);
+
+    #[test]
+    fn omitted_line_count_color() {
+        let config = Config {
+            show_omitted_line_count: true,
+            ..TEST_CONFIG.clone()
+        };
+
+        insta::assert_snapshot!(TEST_DATA.emit_color(&config));
+    }
+
+    #[test]
+    fn omitted_line_count_no_color() {
+        let config = Config {
+            show_omitted_line_count: true,
+            ..TEST_CONFIG.clone()
+        };
+
+        insta::assert_snapshot!(TEST_DATA.emit_no_color(&config));
+    }
 }
 
 mod surrounding_lines {
     use super::*;
 
     static TEST_CONFIG: LazyLock<Config> = LazyLock::new(|| Config {
-        start_context_lines: 2,
-        end_context_lines: 1,
+        before_label_lines: 2,
+        after_label_lines: 1,
         ..Config::default()
     });
 
@@ -1153,3 +1407,2540 @@ mod surrounding_lines {
 
     test_emit!(rich_no_color);
 }
+
+mod max_line_width {
+    use super::*;
+
+    static TEST_CONFIG: LazyLock<Config> = LazyLock::new(|| Config {
+        max_line_width: Some(40),
+        ..Config::default()
+    });
+
+    static TEST_DATA: LazyTestData<'static, SimpleFiles<&'static str, String>> = LazyLock::new(
+        || {
+            let mut files = SimpleFiles::new();
+
+            let file_id = files.add(
+                "wrapping.rs",
+                [
+                    "fn main() {",
+                    r#"    let message = format!("{} {}", "a very long greeting that does not fit", name);"#,
+                    "\tlet tabbed = some_function(argument_one, argument_two);",
+                    "}",
+                ]
+                .join("\n"),
+            );
+
+            let diagnostics = vec![
+                Diagnostic::error()
+                    .with_message("mismatched types")
+                    .with_code("E0308")
+                    .with_labels(vec![
+                        Label::primary(file_id, 47..87).with_message("expected `usize`"),
+                        Label::secondary(file_id, 89..93).with_message("found `String`"),
+                    ]),
+                Diagnostic::warning()
+                    .with_message("unused arguments")
+                    .with_labels(vec![
+                        Label::primary(file_id, 3..154).with_message("in this function"),
+                        Label::secondary(file_id, 138..150).with_message("not used"),
+                    ]),
+            ];
+
+            TestData { files, diagnostics }
+        },
+    );
+
+    test_emit!(rich_color);
+    test_emit!(rich_no_color);
+    test_emit!(rich_ascii_no_color);
+}
+
+mod span_background {
+    use super::*;
+
+    static TEST_CONFIG: LazyLock<Config> = LazyLock::new(|| Config {
+        highlight_span_background: true,
+        ..Config::default()
+    });
+
+    static TEST_DATA: LazyTestData<'static, SimpleFiles<&'static str, String>> =
+        LazyLock::new(|| {
+            let mut files = SimpleFiles::new();
+
+            let source = "let café =\t\"日本語\";\nlet x = {\n\tcafé\n};\n".to_owned();
+            let file_id = files.add("highlight.rs", source);
+
+            let diagnostics = vec![
+                Diagnostic::error()
+                    .with_message("mismatched types")
+                    .with_labels(vec![
+                        Label::primary(file_id, 11..22).with_message("expected `usize`"),
+                        Label::secondary(file_id, 4..9).with_message("declared here"),
+                    ]),
+                Diagnostic::warning()
+                    .with_message("unnecessary block")
+                    .with_labels(vec![Label::primary(file_id, 33..44)]),
+            ];
+
+            TestData { files, diagnostics }
+        });
+
+    test_emit!(rich_color);
+    test_emit!(rich_no_color);
+}
+
+mod render_whitespace {
+    use super::*;
+
+    static TEST_DATA: LazyTestData<'static, SimpleFiles<&'static str, String>> =
+        LazyLock::new(|| {
+            let mut files = SimpleFiles::new();
+
+            let source = "fn main() {  \n\t  let x\t= 1;\t\n}\n".to_owned();
+            let file_id = files.add("whitespace.rs", source);
+
+            let diagnostics = vec![
+                Diagnostic::warning()
+                    .with_message("trailing whitespace")
+                    .with_labels(vec![
+                        Label::primary(file_id, 11..13),
+                        Label::primary(file_id, 27..28),
+                    ]),
+                Diagnostic::warning()
+                    .with_message("mixed tabs and spaces")
+                    .with_labels(vec![
+                        Label::primary(file_id, 14..17).with_message("indented here"),
+                        Label::secondary(file_id, 22..23).with_message("aligned here"),
+                    ]),
+            ];
+
+            TestData { files, diagnostics }
+        });
+
+    #[test]
+    fn trailing_no_color() {
+        let config = Config {
+            render_whitespace: RenderWhitespace::Trailing,
+            ..TEST_CONFIG.clone()
+        };
+
+        insta::assert_snapshot!(TEST_DATA.emit_no_color(&config));
+    }
+
+    #[test]
+    fn all_no_color() {
+        let config = Config {
+            render_whitespace: RenderWhitespace::All,
+            ..TEST_CONFIG.clone()
+        };
+
+        insta::assert_snapshot!(TEST_DATA.emit_no_color(&config));
+    }
+
+    #[test]
+    fn all_ascii_no_color() {
+        let config = Config {
+            render_whitespace: RenderWhitespace::All,
+            chars: Chars::ascii(),
+            ..TEST_CONFIG.clone()
+        };
+
+        insta::assert_snapshot!(TEST_DATA.emit_no_color(&config));
+    }
+
+    #[test]
+    fn all_color() {
+        let config = Config {
+            render_whitespace: RenderWhitespace::All,
+            ..TEST_CONFIG.clone()
+        };
+
+        insta::assert_snapshot!(TEST_DATA.emit_color(&config));
+    }
+}
+
+mod wide_characters {
+    use super::*;
+
+    static TEST_DATA: LazyTestData<'static, SimpleFiles<&'static str, String>> =
+        LazyLock::new(|| {
+            let mut files = SimpleFiles::new();
+
+            let source = [
+                "let 挨拶 = \"こんにちは\"; // 👍",
+                "let cafe\u{301} = 挨拶 + 👍;",
+                "print(挨拶, 👍);",
+            ]
+            .join("\n");
+            let range = |line: usize, text: &str| {
+                let line_start = source
+                    .split('\n')
+                    .take(line)
+                    .map(|l| l.len() + 1)
+                    .sum::<usize>();
+                let start = line_start + source[line_start..].find(text).unwrap();
+                start..(start + text.len())
+            };
+
+            let greeting = range(0, "\"こんにちは\"");
+            let thumbs_up = range(1, "👍");
+            let combined = range(1, "cafe\u{301}");
+            let multi_line = range(0, "こんにちは").start..range(2, "👍").end;
+            let file_id = files.add("wide.rs", source);
+
+            let diagnostics = vec![
+                Diagnostic::error()
+                    .with_message("mismatched types")
+                    .with_labels(vec![
+                        Label::primary(file_id, thumbs_up)
+                            .with_message("expected `&str`, found emoji"),
+                        Label::secondary(file_id, greeting).with_message("string defined here"),
+                        Label::secondary(file_id, combined).with_message("combining accent"),
+                    ]),
+                Diagnostic::warning()
+                    .with_message("multi-line span")
+                    .with_labels(vec![
+                        Label::primary(file_id, multi_line).with_message("spans wide characters")
+                    ]),
+            ];
+
+            TestData { files, diagnostics }
+        });
+
+    test_emit!(rich_no_color);
+    test_emit!(short_no_color);
+}
+
+mod label_context {
+    use super::*;
+
+    static TEST_CONFIG: LazyLock<Config> = LazyLock::new(|| Config {
+        before_label_lines: 1,
+        after_label_lines: 1,
+        ..Config::default()
+    });
+
+    static TEST_DATA: LazyTestData<'static, SimpleFiles<&'static str, String>> =
+        LazyLock::new(|| {
+            let mut files = SimpleFiles::new();
+
+            let source = (1..=20).map(|i| format!("line {i}\n")).collect::<String>();
+            let line = |n: usize| {
+                let start = source.find(&format!("line {n}\n")).unwrap();
+                start..(start + format!("line {n}").len())
+            };
+            let (line_6, line_10, line_14) = (line(6), line(10), line(14));
+
+            let file_id = files.add("context", source);
+
+            let diagnostics = vec![
+                Diagnostic::error()
+                    .with_message("labels with their own context")
+                    .with_labels(vec![
+                        Label::primary(file_id, line_6.clone())
+                            .with_before_context(3)
+                            .with_after_context(0),
+                        Label::secondary(file_id, line_14.clone())
+                            .with_before_context(0)
+                            .with_after_context(2),
+                    ]),
+                Diagnostic::error()
+                    .with_message("overlapping context is merged")
+                    .with_labels(vec![
+                        Label::primary(file_id, line_6.clone()).with_after_context(2),
+                        Label::secondary(file_id, line_10).with_before_context(1),
+                    ]),
+                Diagnostic::error()
+                    .with_message("config defaults apply to other labels")
+                    .with_labels(vec![
+                        Label::primary(file_id, line_6).with_before_context(0),
+                        Label::secondary(file_id, line_14),
+                    ]),
+            ];
+
+            TestData { files, diagnostics }
+        });
+
+    test_emit!(rich_no_color);
+}
+
+mod secondary_labels_only {
+    use super::*;
+
+    static TEST_DATA: LazyTestData<'static, SimpleFiles<&'static str, String>> =
+        LazyLock::new(|| {
+            let mut files = SimpleFiles::new();
+
+            let file_id = files.add(
+                "secondary.rs",
+                unindent::unindent(
+                    r#"
+                        fn main() {
+                            let value = compute();
+                            println!("{}", value);
+                        }
+                    "#,
+                ),
+            );
+
+            let diagnostics = vec![Diagnostic::note()
+                .with_message("value is used later")
+                .with_labels(vec![
+                    Label::secondary(file_id, 58..63).with_message("used here"),
+                    Label::secondary(file_id, 20..25).with_message("defined here"),
+                    Label::secondary(file_id, 10..67).with_message("in this block"),
+                ])];
+
+            TestData { files, diagnostics }
+        });
+
+    test_emit!(rich_color);
+    test_emit!(rich_no_color);
+    test_emit!(medium_no_color);
+    test_emit!(short_no_color);
+    test_emit!(grep_no_color);
+}
+
+mod right_to_left {
+    use super::*;
+
+    static TEST_DATA: LazyTestData<'static, SimpleFiles<&'static str, String>> =
+        LazyLock::new(|| {
+            let mut files = SimpleFiles::new();
+
+            let source = "let greeting = \"שלום עולם\";\nlet count = 1;\n".to_owned();
+            let start = source.find('"').unwrap();
+            let end = source.rfind('"').unwrap() + 1;
+            let file_id = files.add("hebrew.rs", source);
+
+            let diagnostics = vec![Diagnostic::error()
+                .with_message("mismatched types")
+                .with_labels(vec![
+                    Label::primary(file_id, start..end).with_message("expected `usize`"),
+                    Label::secondary(file_id, 4..12).with_message("declared here"),
+                ])];
+
+            TestData { files, diagnostics }
+        });
+
+    test_emit!(rich_no_color);
+
+    #[test]
+    fn bidi_override_no_color() {
+        let config = Config {
+            bidi_override: true,
+            ..TEST_CONFIG.clone()
+        };
+
+        insta::assert_snapshot!(TEST_DATA.emit_no_color(&config));
+    }
+}
+
+mod show_columns {
+    use super::*;
+
+    static TEST_CONFIG: LazyLock<Config> = LazyLock::new(|| Config {
+        show_columns: true,
+        ..Config::default()
+    });
+
+    static TEST_DATA: LazyTestData<'static, SimpleFiles<&'static str, String>> =
+        LazyLock::new(|| {
+            let mut files = SimpleFiles::new();
+
+            let source = [
+                "fn main() {",
+                "\tlet 名前 = \"codespan\";",
+                "\tlet len = 名前.len();",
+                "\tif len > 10 {",
+                "\t\tprintln!(\"long\");",
+                "\t}",
+                "}",
+                "",
+            ]
+            .join("\n");
+            let name_start = source.find("名前 =").unwrap();
+            let len_start = source.find("名前.len").unwrap();
+            let if_start = source.find("if").unwrap();
+            let if_end = source.rfind('}').unwrap() - 1;
+            let file_id = files.add("names.rs", source);
+
+            let diagnostics = vec![Diagnostic::warning()
+                .with_message("unused comparison")
+                .with_labels(vec![
+                    Label::primary(file_id, len_start..len_start + 12)
+                        .with_message("length taken here"),
+                    Label::secondary(file_id, name_start..name_start + 6)
+                        .with_message("declared here"),
+                    Label::secondary(file_id, if_start..if_end).with_message("in this block"),
+                ])];
+
+            TestData { files, diagnostics }
+        });
+
+    test_emit!(rich_no_color);
+    test_emit!(medium_no_color);
+    test_emit!(short_no_color);
+}
+
+mod line_number_width {
+    use super::*;
+
+    static TEST_CONFIG: LazyLock<Config> = LazyLock::new(|| Config {
+        line_number_min_width: 3,
+        ..Config::default()
+    });
+
+    static TEST_DATA: LazyTestData<'static, SimpleFiles<&'static str, String>> =
+        LazyLock::new(|| {
+            let mut files = SimpleFiles::new();
+
+            let short_id = files.add("short.rs", "let x = 1;\n".to_owned());
+            let long_source = "\n".repeat(119) + "let y = x;\n";
+            let long_start = long_source.find('x').unwrap();
+            let long_id = files.add("long.rs", long_source);
+
+            let diagnostics = vec![
+                Diagnostic::warning()
+                    .with_message("unused variable")
+                    .with_labels(vec![Label::primary(short_id, 4..5)]),
+                Diagnostic::error()
+                    .with_message("cannot find value `x` in this scope")
+                    .with_labels(vec![
+                        Label::primary(long_id, long_start..long_start + 1)
+                            .with_message("not found in this scope"),
+                        Label::secondary(short_id, 4..5).with_message("defined in another file"),
+                    ]),
+            ];
+
+            TestData { files, diagnostics }
+        });
+
+    test_emit!(rich_no_color);
+
+    #[test]
+    fn left_aligned_no_color() {
+        let config = Config {
+            line_number_alignment: LineNumberAlignment::Left,
+            ..TEST_CONFIG.clone()
+        };
+
+        insta::assert_snapshot!(TEST_DATA.emit_no_color(&config));
+    }
+}
+
+mod suggestions {
+    use super::*;
+
+    static TEST_DATA: LazyTestData<'static, SimpleFiles<&'static str, String>> =
+        LazyLock::new(|| {
+            let mut files = SimpleFiles::new();
+
+            let source = unindent::unindent(
+                r#"
+                    (define (add x y)
+                    	(+ x "1"))
+
+                    (define (main)
+                      (add 1 2 3))
+                "#,
+            );
+            let string_start = source.find('"').unwrap();
+            let string_end = source.rfind('"').unwrap() + 1;
+            let extra_start = source.find(" 3").unwrap();
+            let file_id = files.add("add.fun", source);
+
+            let diagnostics = vec![
+                Diagnostic::error()
+                    .with_code("E0001")
+                    .with_message("unexpected type in `+` application")
+                    .with_labels(vec![Label::primary(file_id, string_start..string_end)
+                        .with_message("expected `Int` but found `String`")
+                        .with_suggestion(string_start..string_end, "1")])
+                    .with_notes(vec![
+                        "expected type `Int`\n   found type `String`".to_owned()
+                    ]),
+                Diagnostic::warning()
+                    .with_message("too many arguments")
+                    .with_labels(vec![Label::primary(file_id, extra_start..extra_start + 2)
+                        .with_message("unexpected argument")
+                        .with_suggestion(extra_start..extra_start + 2, "")]),
+            ];
+
+            TestData { files, diagnostics }
+        });
+
+    test_emit!(rich_color);
+    test_emit!(rich_no_color);
+    test_emit!(short_no_color);
+
+    #[test]
+    fn suggestion_message_no_color() {
+        let config = Config {
+            suggestion_message: "replace the argument".to_owned(),
+            ..TEST_CONFIG.clone()
+        };
+
+        insta::assert_snapshot!(TEST_DATA.emit_no_color(&config));
+    }
+}
+
+mod high_contrast {
+    use super::*;
+
+    static TEST_DATA: LazyTestData<'static, SimpleFiles<&'static str, String>> =
+        LazyLock::new(|| {
+            let mut files = SimpleFiles::new();
+
+            let source = unindent::unindent(
+                "
+                    let x = compute(1, 2);
+                    let unused = match x {
+                        0 => \"zero\",
+                        _ => \"many\",
+                    };
+                ",
+            );
+            let call_start = source.find("compute").unwrap();
+            let x_start = source.find('x').unwrap();
+            let unused_start = source.find("unused").unwrap();
+            let match_start = source.find("match").unwrap();
+            let match_end = source.rfind('}').unwrap() + 1;
+            let file_id = files.add("severities.rs", source);
+
+            let diagnostics = vec![
+                Diagnostic::bug()
+                    .with_message("internal error")
+                    .with_labels(vec![Label::primary(file_id, call_start..call_start + 7)]),
+                Diagnostic::error()
+                    .with_message("mismatched types")
+                    .with_labels(vec![
+                        Label::primary(file_id, match_start..match_end)
+                            .with_message("expected integer, found `&str`"),
+                        Label::secondary(file_id, x_start..x_start + 1)
+                            .with_message("defined here"),
+                    ]),
+                Diagnostic::warning()
+                    .with_message("unused variable")
+                    .with_labels(vec![Label::primary(
+                        file_id,
+                        unused_start..unused_start + 6,
+                    )]),
+                Diagnostic::note()
+                    .with_message("called here")
+                    .with_labels(vec![Label::primary(file_id, call_start..call_start + 7)]),
+                Diagnostic::help()
+                    .with_message("prefix the variable with an underscore")
+                    .with_labels(vec![Label::primary(
+                        file_id,
+                        unused_start..unused_start + 6,
+                    )]),
+            ];
+
+            TestData { files, diagnostics }
+        });
+
+    test_emit!(rich_no_color);
+    test_emit!(rich_high_contrast_no_color);
+}
+
+mod severity_name {
+    use super::*;
+    use codespan_reporting::diagnostic::Severity;
+    use codespan_reporting::term::SeverityName;
+
+    static TEST_CONFIG: LazyLock<Config> = LazyLock::new(|| Config {
+        severity_name: Some(SeverityName::new(|severity| match severity {
+            Severity::Bug => "fatal",
+            Severity::Error => "error",
+            Severity::Warning => "lint",
+            Severity::Note | Severity::Help => "info",
+        })),
+        ..Config::default()
+    });
+
+    static TEST_DATA: LazyTestData<'static, SimpleFiles<&'static str, String>> =
+        LazyLock::new(|| {
+            let mut files = SimpleFiles::new();
+
+            let file_id = files.add("query.sql", "SELECT * FROM users;\n".to_owned());
+
+            let diagnostics = vec![
+                Diagnostic::bug()
+                    .with_code("E001")
+                    .with_message("query planner crashed")
+                    .with_labels(vec![Label::primary(file_id, 0..6)]),
+                Diagnostic::warning()
+                    .with_code("L042")
+                    .with_message("avoid selecting all columns")
+                    .with_labels(vec![Label::primary(file_id, 7..8)]),
+                Diagnostic::note().with_message("the query reads a single table"),
+            ];
+
+            TestData { files, diagnostics }
+        });
+
+    test_emit!(rich_color);
+    test_emit!(short_no_color);
+    test_emit!(markdown_no_color);
+}
+
+mod note_wrap_width {
+    use super::*;
+
+    static TEST_CONFIG: LazyLock<Config> = LazyLock::new(|| Config {
+        note_wrap_width: Some(40),
+        ..Config::default()
+    });
+
+    static TEST_DATA: LazyTestData<'static, SimpleFiles<&'static str, String>> =
+        LazyLock::new(|| {
+            let mut files = SimpleFiles::new();
+
+            let file_id = files.add("main.fun", "(+ 1 \"two\")\n".to_owned());
+
+            let diagnostics = vec![Diagnostic::error()
+                .with_message("unexpected type in `+` application")
+                .with_labels(vec![
+                    Label::primary(file_id, 5..10).with_message("expected `Int`")
+                ])
+                .with_notes(vec![
+                    "the arguments of `+` must all be integers, but this one is a string literal"
+                        .to_owned(),
+                    unindent::unindent(
+                        "
+                            expected type `Int` which is the type of integers in this language
+                               found type `String` which is the type of text
+                        ",
+                    ),
+                    "an-unbreakable-word-that-is-wider-than-the-maximum-width".to_owned(),
+                ])];
+
+            TestData { files, diagnostics }
+        });
+
+    test_emit!(rich_no_color);
+}
+
+mod context_merge_threshold {
+    use super::*;
+
+    static TEST_DATA: LazyTestData<'static, SimpleFiles<&'static str, String>> =
+        LazyLock::new(|| {
+            let mut files = SimpleFiles::new();
+
+            let file_id = files.add(
+                "main.fun",
+                unindent::unindent(
+                    "
+                        let x = 1
+                        let a = 2
+                        let b = 3
+                        let c = 4
+                        let d = 5
+                        let y = x
+                    ",
+                ),
+            );
+
+            let diagnostics = vec![Diagnostic::warning()
+                .with_message("unused variable")
+                .with_labels(vec![
+                    Label::secondary(file_id, 4..5).with_message("defined here"),
+                    Label::primary(file_id, 54..55).with_message("never used"),
+                ])];
+
+            TestData { files, diagnostics }
+        });
+
+    test_emit!(rich_no_color);
+
+    #[test]
+    fn below_gap() {
+        let config = Config {
+            context_merge_threshold: 3,
+            ..Config::default()
+        };
+
+        insta::assert_snapshot!(TEST_DATA.emit_no_color(&config));
+    }
+
+    #[test]
+    fn at_gap() {
+        let config = Config {
+            context_merge_threshold: 4,
+            ..Config::default()
+        };
+
+        insta::assert_snapshot!(TEST_DATA.emit_no_color(&config));
+    }
+}
+
+mod dim_context_lines {
+    use super::*;
+
+    static TEST_CONFIG: LazyLock<Config> = LazyLock::new(|| Config {
+        dim_context_lines: true,
+        before_label_lines: 1,
+        after_label_lines: 1,
+        ..Config::default()
+    });
+
+    static TEST_DATA: LazyTestData<'static, SimpleFiles<&'static str, String>> =
+        LazyLock::new(|| {
+            let mut files = SimpleFiles::new();
+
+            let file_id = files.add(
+                "main.fun",
+                unindent::unindent(
+                    "
+                        let x = 1
+                        let y = 2
+                        let z = (+ x
+                            y)
+                        let w = 3
+                    ",
+                ),
+            );
+
+            let diagnostics = vec![Diagnostic::warning()
+                .with_message("unused variable")
+                .with_labels(vec![
+                    Label::primary(file_id, 24..25).with_message("`z` is never used"),
+                    Label::secondary(file_id, 28..39).with_message("computed here"),
+                ])];
+
+            TestData { files, diagnostics }
+        });
+
+    test_emit!(rich_color);
+    test_emit!(rich_no_color);
+}
+
+mod caret_fill_style {
+    use super::*;
+
+    static TEST_CONFIG: LazyLock<Config> = LazyLock::new(|| Config {
+        caret_fill_style: CaretFillStyle::Endpoints,
+        ..Config::default()
+    });
+
+    static TEST_DATA: LazyTestData<'static, SimpleFiles<&'static str, String>> =
+        LazyLock::new(|| {
+            let mut files = SimpleFiles::new();
+
+            let source = format!("let x = f({}, y);\n", "a".repeat(60));
+            let file_id = files.add("main.fun", source);
+
+            let diagnostics = vec![Diagnostic::error()
+                .with_message("argument too long")
+                .with_labels(vec![
+                    Label::primary(file_id, 10..70)
+                        .with_message("this argument is 60 columns wide"),
+                    Label::secondary(file_id, 8..9).with_message("in this call"),
+                    Label::secondary(file_id, 72..73).with_message("next argument"),
+                ])];
+
+            TestData { files, diagnostics }
+        });
+
+    test_emit!(rich_color);
+    test_emit!(rich_no_color);
+    test_emit!(rich_ascii_no_color);
+}
+
+mod label_priority {
+    use super::*;
+
+    fn test_data<'files>(
+        priorities: [i32; 3],
+    ) -> TestData<'files, SimpleFiles<&'static str, String>> {
+        let mut files = SimpleFiles::new();
+
+        let file_id = files.add("main.fun", "let total = price * count;\n".to_owned());
+
+        let diagnostics = vec![Diagnostic::error()
+            .with_message("mismatched types")
+            .with_labels(vec![
+                Label::primary(file_id, 12..25)
+                    .with_message("expected `Int`, found `Float`")
+                    .with_priority(priorities[0]),
+                Label::secondary(file_id, 12..17)
+                    .with_message("`price` is a `Float`")
+                    .with_priority(priorities[1]),
+                Label::secondary(file_id, 20..25)
+                    .with_message("`count` is an `Int`")
+                    .with_priority(priorities[2]),
+            ])];
+
+        TestData { files, diagnostics }
+    }
+
+    #[test]
+    fn default_priority() {
+        let test_data = test_data([0, 0, 0]);
+        insta::assert_snapshot!(test_data.emit_no_color(&Config::default()));
+    }
+
+    #[test]
+    fn higher_priority_is_closer() {
+        let test_data = test_data([0, 1, 0]);
+        insta::assert_snapshot!(test_data.emit_no_color(&Config::default()));
+    }
+
+    #[test]
+    fn priority_crosses_columns() {
+        let test_data = test_data([0, 0, -1]);
+        insta::assert_snapshot!(test_data.emit_no_color(&Config::default()));
+    }
+}
+
+mod zero_width_spans {
+    use super::*;
+
+    static TEST_DATA: LazyTestData<'static, SimpleFile<&'static str, &'static str>> =
+        LazyLock::new(|| {
+            let file = SimpleFile::new("main.fun", "\tlet x = 日本\n");
+
+            let diagnostics = vec![
+                Diagnostic::error()
+                    .with_message("line start")
+                    .with_labels(vec![
+                        Label::primary((), 0..0).with_message("expected `fn` here")
+                    ]),
+                Diagnostic::error()
+                    .with_message("mid-line")
+                    .with_labels(vec![
+                        Label::primary((), 9..9).with_message("expected a string here"),
+                        Label::secondary((), 1..4).with_message("in this binding"),
+                    ]),
+                Diagnostic::error()
+                    .with_message("end of line")
+                    .with_labels(vec![Label::primary((), 15..15).with_message("expected `;`")]),
+            ];
+
+            TestData {
+                files: file,
+                diagnostics,
+            }
+        });
+
+    test_emit!(rich_color);
+    test_emit!(rich_no_color);
+    test_emit!(short_no_color);
+}
+
+mod long_multiline_span {
+    use super::*;
+
+    static TEST_CONFIG: LazyLock<Config> = LazyLock::new(|| Config {
+        start_context_lines: 2,
+        end_context_lines: 1,
+        show_omitted_line_count: true,
+        ..Config::default()
+    });
+
+    static TEST_DATA: LazyTestData<'static, SimpleFiles<&'static str, String>> =
+        LazyLock::new(|| {
+            let mut files = SimpleFiles::new();
+
+            let body = (1..=48)
+                .map(|i| format!("    let x{i} = {i};\n"))
+                .collect::<String>();
+            let source = format!("fn main() {{\n{body}}}\n");
+            let end = source.len() - 1;
+            let file_id = files.add("main.fun", source);
+
+            let diagnostics = vec![Diagnostic::error()
+                .with_message("function body is too long")
+                .with_labels(vec![
+                    Label::primary(file_id, 10..end).with_message("this spans 50 lines")
+                ])];
+
+            TestData { files, diagnostics }
+        });
+
+    test_emit!(rich_no_color);
+    test_emit!(rich_rounded_no_color);
+    test_emit!(rich_heavy_no_color);
+}
+
+mod severity_notes {
+    use super::*;
+
+    static TEST_CONFIG: LazyLock<Config> = LazyLock::new(Config::default);
+
+    static TEST_DATA: LazyTestData<'static, SimpleFile<&'static str, &'static str>> =
+        LazyLock::new(|| {
+            let file = SimpleFile::new("main.fun", "let count = \"3\" + 1;\n");
+
+            let diagnostics = vec![Diagnostic::error()
+                .with_message("mismatched types")
+                .with_labels(vec![
+                    Label::primary((), 12..15).with_message("expected `Int`, found `String`")
+                ])
+                .with_note("expected type `Int`\n   found type `String`")
+                .with_severity_note(Severity::Note, "`+` is only defined for integers")
+                .with_severity_note(Severity::Warning, "strings are never implicitly converted")
+                .with_severity_note(
+                    Severity::Help,
+                    "convert the string to an integer:\n    \"3\".parse()",
+                )];
+
+            TestData {
+                files: file,
+                diagnostics,
+            }
+        });
+
+    test_emit!(rich_color);
+    test_emit!(rich_no_color);
+    test_emit!(medium_color);
+    test_emit!(medium_no_color);
+    test_emit!(short_no_color);
+    test_emit!(markdown_no_color);
+}
+
+mod empty_severity_notes {
+    use super::*;
+
+    static TEST_CONFIG: LazyLock<Config> = LazyLock::new(Config::default);
+
+    static TEST_DATA: LazyTestData<'static, SimpleFile<&'static str, &'static str>> =
+        LazyLock::new(|| {
+            let file = SimpleFile::new("main.fun", "let count = \"3\" + 1;\n");
+
+            let diagnostics = vec![Diagnostic::error()
+                .with_message("mismatched types")
+                .with_labels(vec![
+                    Label::primary((), 12..15).with_message("expected `Int`, found `String`")
+                ])
+                .with_severity_note(Severity::Help, "")
+                .with_note("convert the string with `parse`")];
+
+            TestData {
+                files: file,
+                diagnostics,
+            }
+        });
+
+    test_emit!(rich_no_color);
+    test_emit!(medium_no_color);
+    test_emit!(markdown_no_color);
+}
+
+mod control_chars {
+    use super::*;
+
+    static TEST_DATA: LazyTestData<'static, SimpleFile<&'static str, &'static str>> =
+        LazyLock::new(|| {
+            let file = SimpleFile::new("main.fun", "let s = \"\x1b[31mred\x1b[0m\" + 1;\n");
+
+            let diagnostics = vec![Diagnostic::error()
+                .with_message("mismatched types")
+                .with_labels(vec![
+                    Label::secondary((), 8..23).with_message("this is a `String`"),
+                    Label::primary((), 24..25).with_message("expected `String`, found `Int`"),
+                ])];
+
+            TestData {
+                files: file,
+                diagnostics,
+            }
+        });
+
+    #[test]
+    fn sanitized() {
+        insta::assert_snapshot!(TEST_DATA.emit_no_color(&Config::default()));
+    }
+
+    #[test]
+    fn sanitized_ascii() {
+        let config = Config {
+            chars: Chars::ascii(),
+            ..Config::default()
+        };
+        insta::assert_snapshot!(TEST_DATA.emit_no_color(&config));
+    }
+
+    #[test]
+    fn unsanitized() {
+        let config = Config {
+            sanitize_control_chars: false,
+            ..Config::default()
+        };
+        insta::assert_snapshot!(TEST_DATA.emit_no_color(&config).escape_debug().to_string());
+    }
+}
+
+mod aligned_note {
+    use codespan_reporting::diagnostic::aligned_note;
+
+    use super::*;
+
+    static TEST_CONFIG: LazyLock<Config> = LazyLock::new(Config::default);
+
+    static TEST_DATA: LazyTestData<'static, SimpleFile<&'static str, &'static str>> =
+        LazyLock::new(|| {
+            let file = SimpleFile::new("main.fun", "let count: Int = \"3\";\n");
+
+            let diagnostics = vec![Diagnostic::error()
+                .with_message("mismatched types")
+                .with_labels(vec![
+                    Label::primary((), 17..20).with_message("expected `Int`, found `String`")
+                ])
+                .with_note(aligned_note(&[
+                    ("expected type", "`Int`"),
+                    ("found type", "`String`"),
+                ]))];
+
+            TestData {
+                files: file,
+                diagnostics,
+            }
+        });
+
+    test_emit!(rich_no_color);
+    test_emit!(medium_no_color);
+}
+
+mod crlf_line_endings {
+    use super::*;
+
+    static TEST_CONFIG: LazyLock<Config> = LazyLock::new(Config::default);
+
+    static TEST_DATA: LazyTestData<'static, SimpleFile<&'static str, &'static str>> =
+        LazyLock::new(|| {
+            let file = SimpleFile::new("main.fun", "let x = 1\r\nlet y = x +\r\n    2;\r\n");
+
+            let diagnostics = vec![
+                Diagnostic::error()
+                    .with_message("missing semicolon")
+                    .with_labels(vec![
+                        Label::primary((), 9..9).with_message("expected `;`"),
+                        Label::secondary((), 8..10).with_message("value"),
+                    ]),
+                Diagnostic::error()
+                    .with_message("unexpected line ending")
+                    .with_labels(vec![Label::primary((), 9..10).with_message("here")]),
+                Diagnostic::warning()
+                    .with_message("expression spans lines")
+                    .with_labels(vec![
+                        Label::primary((), 19..31).with_message("this expression"),
+                        Label::secondary((), 21..23).with_message("end of line"),
+                    ]),
+            ];
+
+            TestData {
+                files: file,
+                diagnostics,
+            }
+        });
+
+    test_emit!(rich_no_color);
+    test_emit!(short_no_color);
+}
+
+mod message_args {
+    use super::*;
+
+    static TEST_CONFIG: LazyLock<Config> = LazyLock::new(Config::default);
+
+    static TEST_DATA: LazyTestData<'static, SimpleFile<&'static str, &'static str>> =
+        LazyLock::new(|| {
+            let file = SimpleFile::new("main.fun", "let x = 1 + \"2\";\nlet y = { x };\n");
+
+            let diagnostics = vec![
+                Diagnostic::error()
+                    .with_message("cannot add `{1}` to `{0}`")
+                    .with_message_arg((), 8..9)
+                    .with_message_arg((), 12..15)
+                    .with_labels(vec![
+                        Label::primary((), 12..15).with_message("expected `Int`")
+                    ]),
+                Diagnostic::warning()
+                    .with_message("unnecessary braces in `{{ {0} }}`, {1} is not a span")
+                    .with_message_arg((), 25..30)
+                    .with_labels(vec![Label::primary((), 25..30)]),
+                Diagnostic::note().with_message("messages without spans keep {0} and {{"),
+            ];
+
+            TestData {
+                files: file,
+                diagnostics,
+            }
+        });
+
+    test_emit!(rich_color);
+    test_emit!(short_no_color);
+}
+
+mod max_labels {
+    use super::*;
+
+    static TEST_CONFIG: LazyLock<Config> = LazyLock::new(|| Config {
+        max_labels: Some(3),
+        ..Config::default()
+    });
+
+    static TEST_DATA: LazyTestData<'static, SimpleFile<&'static str, String>> =
+        LazyLock::new(|| {
+            let file = SimpleFile::new(
+                "main.fun",
+                unindent::unindent(
+                    r#"
+                        let a = 1;
+                        let b = 2;
+                        let c = 3;
+                        let d = a + b + c + e;
+                    "#,
+                ),
+            );
+
+            let diagnostics = vec![Diagnostic::error()
+                .with_message("cannot find value `e` in this scope")
+                .with_labels(vec![
+                    Label::secondary((), 4..5).with_message("`a` is defined here"),
+                    Label::secondary((), 15..16).with_message("`b` is defined here"),
+                    Label::secondary((), 26..27)
+                        .with_message("`c` is defined here")
+                        .with_priority(1),
+                    Label::secondary((), 41..42),
+                    Label::primary((), 53..54).with_message("not found in this scope"),
+                ])
+                .with_notes(vec!["`e` is not defined".to_owned()])];
+
+            TestData {
+                files: file,
+                diagnostics,
+            }
+        });
+
+    test_emit!(rich_color);
+    test_emit!(rich_no_color);
+    test_emit!(markdown_no_color);
+}
+
+mod four_digit_line_numbers {
+    use super::*;
+
+    static TEST_DATA: LazyTestData<'static, SimpleFiles<&'static str, String>> =
+        LazyLock::new(|| {
+            let mut files = SimpleFiles::new();
+
+            let source = (1..=1200)
+                .map(|line_number| format!("let x{line_number} = {line_number};\n"))
+                .collect::<String>();
+            let line_start = |line_number: usize| {
+                source
+                    .match_indices('\n')
+                    .nth(line_number - 2)
+                    .map_or(0, |(index, _)| index + 1)
+            };
+            let (line_999, line_1000) = (line_start(999), line_start(1000));
+            let large = files.add("large.fun", source.clone());
+            let small = files.add("small.fun", "let y = x1000;\n".to_owned());
+
+            let diagnostics = vec![
+                Diagnostic::error()
+                    .with_message("mismatched types")
+                    .with_labels(vec![
+                        Label::secondary(large, (line_999 + 4)..(line_999 + 8)),
+                        Label::primary(large, (line_1000 + 4)..(line_1000 + 9))
+                            .with_message("expected `String`"),
+                    ]),
+                Diagnostic::warning()
+                    .with_message("unused variable")
+                    .with_labels(vec![
+                        Label::primary(small, 4..5),
+                        Label::secondary(large, (line_1000 + 4)..(line_1000 + 9)),
+                    ]),
+            ];
+
+            TestData { files, diagnostics }
+        });
+
+    test_emit!(rich_no_color);
+
+    #[test]
+    fn show_columns() {
+        let config = Config {
+            show_columns: true,
+            ..TEST_CONFIG.clone()
+        };
+
+        insta::assert_snapshot!(TEST_DATA.emit_no_color(&config));
+    }
+}
+
+mod compact_borders {
+    use super::*;
+
+    static TEST_CONFIG: LazyLock<Config> = LazyLock::new(|| Config {
+        compact_borders: true,
+        ..Config::default()
+    });
+
+    static TEST_DATA: LazyTestData<'static, SimpleFile<&'static str, String>> =
+        LazyLock::new(|| {
+            let file = SimpleFile::new(
+                "main.fun",
+                unindent::unindent(
+                    r#"
+                        (define (fact n)
+                          (if (= n 0)
+                              "one"
+                              (* n (fact (- n 1)))))
+                    "#,
+                ),
+            );
+
+            let diagnostics = vec![
+                Diagnostic::error()
+                    .with_message("`if` branches have incompatible types")
+                    .with_labels(vec![
+                        Label::secondary((), 19..70).with_message("`if` expression"),
+                        Label::primary((), 37..42).with_message("expected `Int`, found `String`"),
+                    ])
+                    .with_notes(vec!["expected type `Int`".to_owned()]),
+                Diagnostic::warning()
+                    .with_message("unused function")
+                    .with_labels(vec![Label::primary((), 9..13)]),
+            ];
+
+            TestData {
+                files: file,
+                diagnostics,
+            }
+        });
+
+    test_emit!(rich_color);
+    test_emit!(rich_no_color);
+}
+
+mod dangling_messages {
+    use super::*;
+
+    static TEST_CONFIG: LazyLock<Config> = LazyLock::new(|| Config {
+        dangling_messages: true,
+        ..Config::default()
+    });
+
+    static TEST_DATA: LazyTestData<'static, SimpleFile<&'static str, String>> =
+        LazyLock::new(|| {
+            let source = unindent::unindent(
+                r#"
+                    fn main() {
+                        let mut v = vec![1];
+                        let r = &mut v;
+                        v.push(*r);
+                        let (a, b) = (v, r);
+                    }
+                "#,
+            );
+            let at = |text: &str| {
+                let start = source.find(text).unwrap();
+                start..start + text.len()
+            };
+
+            let diagnostics = vec![
+                Diagnostic::error()
+                    .with_message("cannot borrow `v` as mutable more than once at a time")
+                    .with_labels(vec![
+                        Label::secondary((), at("&mut v"))
+                            .with_message("first mutable borrow occurs here"),
+                        Label::primary((), at("v.push"))
+                            .with_message("second mutable borrow occurs here"),
+                        Label::secondary((), at("*r")).with_message("first borrow later used here"),
+                    ]),
+                Diagnostic::warning()
+                    .with_message("unused variables")
+                    .with_labels(vec![
+                        Label::primary((), at("a,")).with_message("`a` is never used"),
+                        Label::primary((), at("b)")).with_message("`b` is never used"),
+                        Label::secondary((), at("(v, r)")).with_message("moved here"),
+                    ]),
+            ];
+
+            TestData {
+                files: SimpleFile::new("main.rs", source),
+                diagnostics,
+            }
+        });
+
+    test_emit!(rich_color);
+    test_emit!(rich_no_color);
+    test_emit!(rich_ascii_no_color);
+}
+
+mod markdown_escaping {
+    use super::*;
+
+    static TEST_CONFIG: LazyLock<Config> = LazyLock::new(Config::default);
+
+    static TEST_DATA: LazyTestData<'static, SimpleFile<&'static str, &'static str>> =
+        LazyLock::new(|| {
+            let source = "let x = a * b_c;\n";
+
+            let diagnostics = vec![
+                Diagnostic::error()
+                    .with_message("cannot multiply `a * b_c` by *2* with <T> & [x]")
+                    .with_labels(vec![Label::primary((), 8..15)]),
+                Diagnostic::warning()
+                    .with_message("unmatched ` in __name__ # 1")
+                    .with_labels(vec![Label::primary((), 4..5)]),
+            ];
+
+            TestData {
+                files: SimpleFile::new("main.rs", source),
+                diagnostics,
+            }
+        });
+
+    test_emit!(markdown_no_color);
+}
+
+mod render_functions {
+    use super::*;
+    use codespan_reporting::files;
+    use codespan_reporting::term::{
+        self, record, render_hexdump, render_label, render_side_by_side, render_snippet,
+        write_short, PlainWriter, StyleRole, TestWriter,
+    };
+    use core::fmt::Write as _;
+
+    #[test]
+    fn snippet_without_header() {
+        let mut files = SimpleFiles::new();
+        let id = files.add("test", "(+ 1\n(+ test \"\"))\n");
+
+        let snippet = render_snippet(&files, id, 13..15, &Config::default()).unwrap();
+        insta::assert_snapshot!(snippet);
+    }
+
+    #[test]
+    fn snippets_side_by_side() {
+        let mut files = SimpleFiles::new();
+        let before = files.add("before", "(+ 1\n(+ test \"\"))\n");
+        let after = files.add("after", "(+ 1\n   (+ test 0)\n)\n");
+        let config = Config {
+            before_label_lines: 1,
+            ..Config::default()
+        };
+        let left = render_snippet(&files, before, 13..15, &config).unwrap();
+        let right = render_snippet(&files, after, 16..17, &config).unwrap();
+
+        insta::assert_snapshot!(render_side_by_side(&left, &right, &config));
+
+        // Snippets that do not fit next to each other are stacked
+        let config = Config {
+            max_line_width: Some(20),
+            ..config
+        };
+        assert_eq!(
+            render_side_by_side(&left, &right, &config),
+            format!("{left}{}\n{right}", "─".repeat(17)),
+        );
+    }
+
+    #[test]
+    fn diagnostic_to_string() {
+        let mut files = SimpleFiles::new();
+        let id = files.add("test", "let x = 1;\n");
+        let diagnostic = Diagnostic::warning()
+            .with_message("unused variable")
+            .with_labels(vec![Label::primary(id, 4..5)]);
+        let config = Config {
+            display_style: DisplayStyle::Short,
+            ..Config::default()
+        };
+
+        insta::assert_snapshot!(term::to_string(&config, &files, &diagnostic).unwrap());
+        assert_eq!(
+            term::to_ansi_string(&config, &files, &diagnostic).unwrap(),
+            "test:1:5: \u{1b}[0m\u{1b}[1m\u{1b}[38;5;11mwarning\u{1b}[0m\u{1b}[1m: unused variable\u{1b}[0m\n",
+        );
+    }
+
+    #[test]
+    fn display_renders_like_to_string() {
+        let mut files = SimpleFiles::new();
+        let id = files.add("test", "(+ test \"\")\n");
+        let diagnostic = Diagnostic::error()
+            .with_message("mismatched types")
+            .with_labels(vec![Label::primary(id, 8..10)]);
+        let config = Config::default();
+
+        assert_eq!(
+            diagnostic.display(&files, &config).to_string(),
+            term::to_string(&config, &files, &diagnostic).unwrap(),
+        );
+
+        // Errors from reading the files cannot be reported through `Display`
+        let diagnostic = diagnostic.with_labels(vec![Label::primary(1, 0..1)]);
+        let mut rendered = String::new();
+        assert!(write!(rendered, "{}", diagnostic.display(&files, &config)).is_err());
+    }
+
+    #[test]
+    fn record_into_segments() {
+        let mut files = SimpleFiles::new();
+        let id = files.add("test", "let x = 1;\n");
+        let diagnostic = Diagnostic::warning()
+            .with_message("unused variable")
+            .with_labels(vec![Label::primary(id, 4..5)]);
+        let config = Config::default();
+
+        let output = record(&config, &files, &diagnostic).unwrap();
+        assert_eq!(
+            output.text(),
+            term::to_string(&config, &files, &diagnostic).unwrap()
+        );
+        assert_eq!(
+            output.segments()[..2]
+                .iter()
+                .map(|segment| (segment.role, &*segment.text))
+                .collect::<Vec<_>>(),
+            [
+                (StyleRole::Header(Severity::Warning), "warning"),
+                (StyleRole::HeaderMessage, ": unused variable"),
+            ],
+        );
+
+        let mut writer = TestWriter::new();
+        output.emit(&mut writer).unwrap();
+        let mut expected = TestWriter::new();
+        term::emit(&mut expected, &config, &files, &diagnostic).unwrap();
+        assert_eq!(writer.into_string(), expected.into_string());
+    }
+
+    #[test]
+    fn focus_on_one_label() {
+        let mut files = SimpleFiles::new();
+        let id = files.add("test", "let x = 1;\nlet x = 2;\n");
+        let diagnostic = Diagnostic::error()
+            .with_message("`x` is defined twice")
+            .with_labels(vec![
+                Label::secondary(id, 4..5).with_message("first definition"),
+                Label::primary(id, 15..16).with_message("redefined here"),
+            ])
+            .with_notes(vec!["rename one of them".into()]);
+        let config = Config::default();
+
+        let mut writer = PlainWriter::new(Vec::<u8>::new());
+        render_label(&mut writer, &config, &files, &diagnostic, 1).unwrap();
+        insta::assert_snapshot!(String::from_utf8(writer.into_inner()).unwrap());
+
+        let mut writer = PlainWriter::new(Vec::<u8>::new());
+        let error = render_label(&mut writer, &config, &files, &diagnostic, 2).unwrap_err();
+        assert!(matches!(
+            error,
+            files::Error::LabelMissing { given: 2, len: 2 }
+        ));
+        assert!(writer.get_ref().is_empty());
+    }
+
+    #[test]
+    fn binary_data_as_hexdump() {
+        let mut bytes = b"\x7fELG\x02\x01\x01".to_vec();
+        bytes.resize(72, 0);
+        bytes[60..64].copy_from_slice(b"main");
+        let diagnostic = Diagnostic::error()
+            .with_message("invalid magic number")
+            .with_labels(vec![
+                Label::primary((), 1..4).with_message("expected `ELF`"),
+                Label::secondary((), 14..18).with_message("entry point"),
+                Label::secondary((), 60..64),
+            ])
+            .with_notes(vec!["the file is not an executable".into()]);
+
+        let mut writer = PlainWriter::new(Vec::<u8>::new());
+        render_hexdump(&mut writer, &Config::default(), &bytes, &diagnostic).unwrap();
+        insta::assert_snapshot!(String::from_utf8(writer.into_inner()).unwrap());
+
+        let diagnostic = Diagnostic::error().with_labels(vec![Label::primary((), 70..73)]);
+        let mut writer = PlainWriter::new(Vec::<u8>::new());
+        let error = render_hexdump(&mut writer, &Config::default(), &bytes, &diagnostic);
+        assert!(matches!(
+            error,
+            Err(files::Error::IndexTooLarge { given: 73, max: 72 })
+        ));
+
+        let label = Label::primary((), core::ops::Range { start: 20, end: 4 });
+        let diagnostic = Diagnostic::error().with_labels(vec![label]);
+        let mut writer = PlainWriter::new(Vec::<u8>::new());
+        let error = render_hexdump(&mut writer, &Config::default(), &bytes, &diagnostic);
+        assert!(matches!(
+            error,
+            Err(files::Error::ReversedRange { start: 20, end: 4 })
+        ));
+    }
+
+    #[test]
+    fn short_diagnostic_into_buffer() {
+        let mut files = SimpleFiles::new();
+        let id = files.add("test", "let x = λ;\n");
+        let diagnostic = Diagnostic::error()
+            .with_message("unexpected `λ`")
+            .with_labels(vec![Label::primary(id, 8..10)]);
+        let config = Config::default();
+
+        let mut buf = [0; 64];
+        let len = write_short(&mut buf, &config, &files, &diagnostic).unwrap();
+        assert_eq!(&buf[..len], "test:1:9: error: unexpected `λ`\n".as_bytes());
+
+        // The output is cut off at the end of the buffer, before any
+        // partially written character
+        let mut buf = [0; 30];
+        let len = write_short(&mut buf, &config, &files, &diagnostic).unwrap();
+        assert_eq!(&buf[..len], "test:1:9: error: unexpected `".as_bytes());
+    }
+
+    #[test]
+    fn reversed_suggestion_range() {
+        let mut files = SimpleFiles::new();
+        let id = files.add("test", "let x = 1;\nlet y = 2;\n");
+        let diagnostic = Diagnostic::error()
+            .with_labels(vec![Label::primary(id, 8..9)
+                .with_suggestion(core::ops::Range { start: 15, end: 8 }, "0")]);
+
+        let mut writer = PlainWriter::new(Vec::<u8>::new());
+        let error = term::emit(&mut writer, &Config::default(), &files, &diagnostic);
+        assert!(matches!(
+            error,
+            Err(files::Error::ReversedRange { start: 15, end: 8 })
+        ));
+    }
+
+    /// A file that can only be read a line at a time, which records the
+    /// indices of the lines that were read.
+    struct LineFiles {
+        lines: Vec<String>,
+        lines_read: core::cell::RefCell<Vec<usize>>,
+    }
+
+    impl<'a> files::Files<'a> for LineFiles {
+        type FileId = ();
+        type Name = &'static str;
+        type Source = &'a str;
+
+        fn name(&self, (): ()) -> Result<&'static str, files::Error> {
+            Ok("large")
+        }
+
+        fn source(&self, (): ()) -> Result<&str, files::Error> {
+            Err(files::Error::FileMissing)
+        }
+
+        fn line_index(&self, (): (), byte_index: usize) -> Result<usize, files::Error> {
+            let mut line_end = 0;
+            for (line_index, line) in self.lines.iter().enumerate() {
+                line_end += line.len();
+                if byte_index < line_end {
+                    return Ok(line_index);
+                }
+            }
+            Ok(self.lines.len() - 1)
+        }
+
+        fn line_range(
+            &self,
+            (): (),
+            line_index: usize,
+        ) -> Result<core::ops::Range<usize>, files::Error> {
+            let line = self
+                .lines
+                .get(line_index)
+                .ok_or(files::Error::LineTooLarge {
+                    given: line_index,
+                    max: self.lines.len() - 1,
+                })?;
+            let line_start = self.lines[..line_index]
+                .iter()
+                .map(String::len)
+                .sum::<usize>();
+            Ok(line_start..(line_start + line.len()))
+        }
+
+        fn line_source(
+            &self,
+            (): (),
+            line_index: usize,
+        ) -> Result<alloc::borrow::Cow<'_, str>, files::Error> {
+            self.lines_read.borrow_mut().push(line_index);
+            self.line_range((), line_index)?;
+            Ok(alloc::borrow::Cow::Owned(self.lines[line_index].clone()))
+        }
+    }
+
+    #[test]
+    fn only_displayed_lines_are_read() {
+        use codespan_reporting::files::Files as _;
+
+        let files = LineFiles {
+            lines: (0..1000).map(|i| format!("let x{i} = {i};\n")).collect(),
+            lines_read: Default::default(),
+        };
+        let start = files.line_range((), 500).unwrap().start;
+        let diagnostic = Diagnostic::error()
+            .with_message("unused variable")
+            .with_labels(vec![Label::primary((), (start + 4)..(start + 8))]);
+        let config = Config {
+            before_label_lines: 2,
+            after_label_lines: 1,
+            ..Config::default()
+        };
+
+        let output = term::to_string(&config, &files, &diagnostic).unwrap();
+
+        let mut lines_read = files.lines_read.into_inner();
+        lines_read.sort_unstable();
+        lines_read.dedup();
+        assert_eq!(lines_read, [498, 499, 500, 501]);
+        insta::assert_snapshot!(output);
+    }
+}
+
+mod batches {
+    use super::*;
+    use codespan_reporting::files;
+    use codespan_reporting::term::{
+        self, emit_batch, emit_batch_with, render_stream, PlainWriter, SeverityOrder,
+    };
+
+    /// Emit a batch of diagnostics into a string.
+    fn emit_batch_to_string<'files, F: files::Files<'files> + ?Sized>(
+        config: &Config,
+        files: &'files F,
+        diagnostics: &[Diagnostic<F::FileId>],
+    ) -> String {
+        let mut writer = PlainWriter::new(Vec::<u8>::new());
+        emit_batch(&mut writer, config, files, diagnostics).unwrap();
+        String::from_utf8(writer.into_inner()).unwrap()
+    }
+
+    #[test]
+    fn grouped_by_file() {
+        let mut files = SimpleFiles::new();
+        let a = files.add("a.fun", "let x = 1;\nlet y = 2;\n");
+        let b = files.add("b.fun", "(+ 1 x)\n");
+        let diagnostics = [
+            Diagnostic::warning()
+                .with_message("unused variable `y`")
+                .with_labels(vec![Label::primary(a, 15..16)]),
+            Diagnostic::error()
+                .with_message("expected `Int`")
+                .with_labels(vec![Label::secondary(a, 4..5), Label::primary(b, 5..6)]),
+            Diagnostic::note().with_message("2 files checked"),
+            Diagnostic::warning()
+                .with_message("unused variable `x`")
+                .with_labels(vec![Label::primary(a, 4..5)]),
+        ];
+        let config = Config {
+            display_style: DisplayStyle::Short,
+            group_by_file: true,
+            ..Config::default()
+        };
+
+        insta::assert_snapshot!(emit_batch_to_string(&config, &files, &diagnostics));
+    }
+
+    #[test]
+    fn sorted_by_severity_order() {
+        let mut files = SimpleFiles::new();
+        let a = files.add("a.fun", "let x = 1;\n");
+        let b = files.add("b.fun", "(+ 1 x)\n");
+        let diagnostics = [
+            Diagnostic::help().with_message("run with `--explain`"),
+            Diagnostic::note()
+                .with_message("`x` is defined here")
+                .with_labels(vec![Label::primary(a, 4..5)]),
+            Diagnostic::error()
+                .with_message("expected `Int`")
+                .with_labels(vec![Label::primary(a, 8..9)]),
+            Diagnostic::warning()
+                .with_message("shadowed variable `x`")
+                .with_labels(vec![Label::primary(b, 5..6)]),
+        ];
+        let order = SeverityOrder::new([
+            Severity::Warning,
+            Severity::Bug,
+            Severity::Error,
+            Severity::Note,
+            Severity::Help,
+        ]);
+        let config = Config {
+            display_style: DisplayStyle::Short,
+            severity_order: order,
+            ..Config::default()
+        };
+
+        insta::assert_snapshot!(
+            "sorted_by_severity_order_ungrouped",
+            emit_batch_to_string(&config, &files, &diagnostics)
+        );
+        let config = Config {
+            group_by_file: true,
+            ..config
+        };
+        insta::assert_snapshot!(
+            "sorted_by_severity_order_grouped",
+            emit_batch_to_string(&config, &files, &diagnostics)
+        );
+    }
+
+    #[test]
+    fn rendered_through_trait_object() {
+        let mut files = SimpleFiles::<String, String>::new();
+        let id = files.add("test".into(), "let x = 1;\nlet y = x;\n".into());
+        let diagnostics = [
+            Diagnostic::warning()
+                .with_message("unused variable")
+                .with_labels(vec![Label::primary(id, 15..16)]),
+            Diagnostic::error()
+                .with_message("unknown file")
+                .with_labels(vec![Label::primary(id + 1, 0..1)]),
+        ];
+        let emit_batch = |files: &files::DynFiles<'_, usize>, diagnostics| {
+            let mut writer = PlainWriter::new(Vec::<u8>::new());
+            let result = emit_batch(&mut writer, &Config::default(), files, diagnostics);
+            result.map(|()| String::from_utf8(writer.into_inner()).unwrap())
+        };
+
+        assert_eq!(
+            emit_batch(&files, &diagnostics[..1]).unwrap(),
+            term::to_string(&Config::default(), &files, &diagnostics[0]).unwrap(),
+        );
+        assert!(matches!(
+            emit_batch(&files, &diagnostics),
+            Err(files::Error::FileMissing),
+        ));
+    }
+
+    #[test]
+    fn deduplicates_adjacent_diagnostics() {
+        let mut files = SimpleFiles::new();
+        let id = files.add("test", "(+ test \"\")\n");
+        let repeated = Diagnostic::error()
+            .with_message("unexpected type")
+            .with_labels(vec![Label::primary(id, 8..10)]);
+        let other = Diagnostic::warning().with_message("unused value");
+        let diagnostics = [
+            repeated.clone(),
+            repeated
+                .clone()
+                .with_notes(vec!["notes are not compared".into()]),
+            repeated.clone(),
+            other.clone(),
+            repeated.clone(),
+            other,
+        ];
+        let config = Config {
+            display_style: DisplayStyle::Short,
+            deduplicate: true,
+            ..Config::default()
+        };
+
+        insta::assert_snapshot!(emit_batch_to_string(&config, &files, &diagnostics));
+    }
+
+    #[test]
+    fn separates_diagnostics() {
+        let files = SimpleFiles::<&str, &str>::new();
+        let config = Config {
+            display_style: DisplayStyle::Short,
+            max_line_width: Some(12),
+            diagnostic_separator: Some('─'),
+            ..Config::default()
+        };
+        let diagnostics = [
+            Diagnostic::error().with_message("unexpected type"),
+            Diagnostic::warning().with_message("unused value"),
+        ];
+
+        let output = emit_batch_to_string(&config, &files, &diagnostics);
+        insta::assert_snapshot!(output);
+
+        let mut writer = PlainWriter::new(Vec::<u8>::new());
+        render_stream(&mut writer, &config, &files, &diagnostics).unwrap();
+        assert_eq!(String::from_utf8(writer.into_inner()).unwrap(), output);
+
+        // A single diagnostic has nothing to be separated from
+        assert_eq!(
+            emit_batch_to_string(&config, &files, &diagnostics[..1]),
+            "error: unexpected type\n",
+        );
+    }
+
+    #[test]
+    fn emits_summary() {
+        let files = SimpleFiles::<&str, &str>::new();
+        let config = Config {
+            display_style: DisplayStyle::Short,
+            deduplicate: true,
+            emit_summary: true,
+            ..Config::default()
+        };
+        let error = Diagnostic::error().with_message("unexpected type");
+        let warning = Diagnostic::warning().with_message("unused value");
+        let note = Diagnostic::note().with_message("see the documentation");
+
+        insta::assert_snapshot!(
+            "emits_summary_notes_only",
+            emit_batch_to_string(&config, &files, core::slice::from_ref(&note))
+        );
+        insta::assert_snapshot!(
+            "emits_summary_one_warning",
+            emit_batch_to_string(&config, &files, core::slice::from_ref(&warning))
+        );
+        let diagnostics = [error.clone(), Diagnostic::bug().with_message("crashed")];
+        insta::assert_snapshot!(
+            "emits_summary_errors_and_bugs",
+            emit_batch_to_string(&config, &files, &diagnostics)
+        );
+        let diagnostics = [error.clone(), error, warning.clone(), note, warning];
+        insta::assert_snapshot!(
+            "emits_summary_repeated_error_and_warnings",
+            emit_batch_to_string(&config, &files, &diagnostics)
+        );
+    }
+
+    #[test]
+    fn skips_diagnostics_below_min_severity() {
+        let files = SimpleFiles::<&str, &str>::new();
+        let diagnostics = [
+            Diagnostic::<usize>::note().with_message("see the documentation"),
+            Diagnostic::warning().with_message("unused value"),
+            Diagnostic::help().with_message("remove the value"),
+            Diagnostic::error().with_message("unexpected type"),
+            Diagnostic::note().with_message("see the documentation"),
+        ];
+        let config = Config {
+            display_style: DisplayStyle::Short,
+            min_severity: Some(Severity::Warning),
+            ..Config::default()
+        };
+
+        insta::assert_snapshot!(
+            "skips_diagnostics_below_min_severity_without_summary",
+            emit_batch_to_string(&config, &files, &diagnostics)
+        );
+        let summary_config = Config {
+            deduplicate: true,
+            emit_summary: true,
+            ..config.clone()
+        };
+        insta::assert_snapshot!(
+            "skips_diagnostics_below_min_severity_with_summary",
+            emit_batch_to_string(&summary_config, &files, &diagnostics)
+        );
+        let config = Config {
+            emit_summary: true,
+            min_severity: Some(Severity::Bug),
+            ..config
+        };
+        insta::assert_snapshot!(
+            "skips_diagnostics_below_min_severity_all_suppressed",
+            emit_batch_to_string(&config, &files, &diagnostics)
+        );
+    }
+
+    #[test]
+    fn emits_first_only() {
+        let files = SimpleFiles::<&str, &str>::new();
+        let diagnostics = [
+            Diagnostic::<usize>::warning().with_message("unused value"),
+            Diagnostic::error().with_message("unexpected type"),
+            Diagnostic::error().with_message("unknown variable"),
+            Diagnostic::note().with_message("see the documentation"),
+        ];
+        let config = Config {
+            display_style: DisplayStyle::Short,
+            first_only: true,
+            ..Config::default()
+        };
+
+        insta::assert_snapshot!(
+            "emits_first_only_first",
+            emit_batch_to_string(&config, &files, &diagnostics)
+        );
+        let config = Config {
+            emit_summary: true,
+            min_severity: Some(Severity::Error),
+            ..config
+        };
+        insta::assert_snapshot!(
+            "emits_first_only_first_error",
+            emit_batch_to_string(&config, &files, &diagnostics)
+        );
+    }
+
+    /// A writer that records the length of its output at every flush.
+    #[derive(Default)]
+    struct FlushRecorder {
+        output: Vec<u8>,
+        flushed_at: Vec<usize>,
+    }
+
+    impl std::io::Write for FlushRecorder {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.output.write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            self.flushed_at.push(self.output.len());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn stream_flushes_after_each_diagnostic() {
+        let mut files = SimpleFiles::new();
+        let id = files.add("test", "(+ test \"\")\n");
+        let diagnostic = Diagnostic::error()
+            .with_message("unexpected type")
+            .with_labels(vec![Label::primary(id, 8..10)]);
+        let config = Config::default();
+
+        let mut writer = PlainWriter::new(FlushRecorder::default());
+        let diagnostics = (0..3).map(|_| diagnostic.clone());
+        render_stream(&mut writer, &config, &files, diagnostics).unwrap();
+
+        let len = term::to_string(&config, &files, &diagnostic).unwrap().len();
+        let recorder = writer.into_inner();
+        assert_eq!(recorder.flushed_at, [len, 2 * len, 3 * len]);
+    }
+
+    #[test]
+    fn calls_back_after_each_diagnostic() {
+        let files = SimpleFiles::<&str, &str>::new();
+        let error = Diagnostic::<usize>::error().with_message("unexpected type");
+        let diagnostics = [
+            error.clone(),
+            error,
+            Diagnostic::note().with_message("see the documentation"),
+            Diagnostic::warning().with_message("unused value"),
+        ];
+        let config = Config {
+            display_style: DisplayStyle::Short,
+            deduplicate: true,
+            min_severity: Some(Severity::Warning),
+            ..Config::default()
+        };
+
+        let mut writer = PlainWriter::new(FlushRecorder::default());
+        let mut rendered = Vec::new();
+        emit_batch_with(&mut writer, &config, &files, &diagnostics, |diagnostic| {
+            rendered.push(diagnostic.message.clone())
+        })
+        .unwrap();
+
+        assert_eq!(rendered, ["unexpected type", "unused value"]);
+        let first = "error: unexpected type (repeated 2 times)\n".len();
+        let second = first + "warning: unused value\n".len();
+        assert_eq!(writer.into_inner().flushed_at, [first, second]);
+    }
+}
+
+mod config_options {
+    use super::*;
+    use codespan_reporting::term::{
+        self, LabelMessagePlacement, PlainWriter, SecondaryUnderlineStyle, SeverityName,
+        SourceStyle, TestWriter,
+    };
+    use termcolor::{Color, ColorSpec};
+
+    /// Emit a diagnostic with every style tagged by its role.
+    fn emit_tagged<'files, F: codespan_reporting::files::Files<'files> + ?Sized>(
+        config: &Config,
+        files: &'files F,
+        diagnostic: &Diagnostic<F::FileId>,
+    ) -> String {
+        let mut writer = TestWriter::new();
+        term::emit(&mut writer, config, files, diagnostic).unwrap();
+        writer.into_string()
+    }
+
+    #[test]
+    fn source_styled_outside_of_labels() {
+        let mut files = SimpleFiles::new();
+        let id = files.add("test", "let x = 1;\nlet y = x;\n");
+        let diagnostic = Diagnostic::warning()
+            .with_message("unused variable")
+            .with_labels(vec![Label::primary(id, 15..16)]);
+
+        // Keywords and identifiers, as a tokenizer would find them
+        let tokens = [0..3, 4..5, 11..14, 15..16, 19..20];
+        let config = Config {
+            before_label_lines: 1,
+            source_style: Some(SourceStyle::new(move |name, byte_index| {
+                assert_eq!(name, "test");
+                (tokens.iter())
+                    .any(|range| range.contains(&byte_index))
+                    .then(|| ColorSpec::new().set_fg(Some(Color::Blue)).clone())
+            })),
+            ..Config::default()
+        };
+
+        insta::assert_snapshot!(emit_tagged(&config, &files, &diagnostic));
+    }
+
+    #[test]
+    fn source_style_under_labels_and_whitespace() {
+        let mut files = SimpleFiles::new();
+        let id = files.add("test", "let x = 1;\nlet y = x;\n");
+        let diagnostic = Diagnostic::warning()
+            .with_message("unused variable")
+            .with_labels(vec![
+                Label::primary(id, 15..16),
+                Label::secondary(id, 11..14),
+            ]);
+        let config = Config {
+            source_style: Some(SourceStyle::new(|_, _| {
+                Some(ColorSpec::new().set_fg(Some(Color::Blue)).clone())
+            })),
+            render_whitespace: RenderWhitespace::All,
+            highlight_span_background: true,
+            ..Config::default()
+        };
+
+        // Labeled source keeps the style of the label, and visible whitespace
+        // is dimmed on top of the source style instead of replacing it
+        let data = TestData {
+            files,
+            diagnostics: vec![diagnostic],
+        };
+        insta::assert_snapshot!(data.emit_color(&config));
+    }
+
+    #[test]
+    fn note_bullets_tinted_by_severity() {
+        let files = SimpleFiles::<&str, &str>::new();
+        let diagnostic = Diagnostic::help()
+            .with_message("consider adding a type annotation")
+            .with_notes(vec!["`x: Int`".into()])
+            .with_severity_note(Severity::Warning, "inference is ambiguous");
+
+        let output = emit_tagged(&Config::default(), &files, &diagnostic);
+        assert!(output.contains("<note_bullet>=</> `x: Int`\n"));
+        let config = Config {
+            color_note_bullet_by_severity: true,
+            ..Config::default()
+        };
+        insta::assert_snapshot!(emit_tagged(&config, &files, &diagnostic));
+    }
+
+    #[test]
+    fn tabs_after_partial_columns() {
+        let mut files = SimpleFiles::new();
+        let id = files.add("test", "ab\tc = 1;\n  \t\td = 2;\n");
+        let diagnostic = Diagnostic::warning()
+            .with_message("unused variables")
+            .with_labels(vec![
+                Label::primary(id, 3..4).with_message("never read"),
+                Label::primary(id, 14..15).with_message("never read"),
+            ]);
+
+        let config = Config {
+            tab_width: 4,
+            ..Config::default()
+        };
+        insta::assert_snapshot!(
+            "tabs_after_partial_columns_tabs",
+            term::to_string(&config, &files, &diagnostic).unwrap()
+        );
+        let config = Config {
+            render_whitespace: RenderWhitespace::All,
+            ..config
+        };
+        insta::assert_snapshot!(
+            "tabs_after_partial_columns_visible_tabs",
+            term::to_string(&config, &files, &diagnostic).unwrap()
+        );
+    }
+
+    #[test]
+    fn left_margin_indents_every_line() {
+        let mut files = SimpleFiles::new();
+        let id = files.add("test", "let x = 1;\n");
+        let diagnostic = Diagnostic::warning()
+            .with_message("unused variable")
+            .with_labels(vec![Label::primary(id, 4..5).with_message("never read")])
+            .with_notes(vec!["remove it".into()]);
+        let config = Config {
+            left_margin: 4,
+            ..Config::default()
+        };
+
+        insta::assert_snapshot!(term::to_string(&config, &files, &diagnostic).unwrap());
+    }
+
+    #[test]
+    fn left_margin_in_markdown() {
+        let mut files = SimpleFiles::new();
+        let id = files.add("test", "let x = 1;\n");
+        let diagnostic = Diagnostic::warning()
+            .with_message("unused variable")
+            .with_labels(vec![Label::primary(id, 4..5).with_message("never read")]);
+        let config = Config {
+            display_style: DisplayStyle::Markdown,
+            left_margin: 4,
+            ..Config::default()
+        };
+
+        insta::assert_snapshot!(term::to_string(&config, &files, &diagnostic).unwrap());
+    }
+
+    #[test]
+    fn clamped_label_ranges() {
+        let mut files = SimpleFiles::new();
+        let id = files.add("test", "let é = 1;");
+        let diagnostic = Diagnostic::error()
+            .with_message("unexpected value")
+            .with_labels(vec![Label::primary(id, 9..40).with_message("here")]);
+
+        insta::assert_snapshot!(term::to_string(&Config::default(), &files, &diagnostic).unwrap());
+    }
+
+    /// Ranges past the end of a file end with the line they start on.
+    #[test]
+    fn clamped_label_ranges_end_on_start_line() {
+        let mut files = SimpleFiles::new();
+        let id = files.add("test", "let x = 1;\r\nlet y = 2;\r\n");
+        let diagnostic = Diagnostic::error()
+            .with_message("unexpected value")
+            .with_labels(vec![Label::primary(id, 4..500).with_message("here")]);
+
+        insta::assert_snapshot!(term::to_string(&Config::default(), &files, &diagnostic).unwrap());
+    }
+
+    #[test]
+    fn line_prefix_starts_every_line() {
+        let mut files = SimpleFiles::new();
+        let id = files.add("test", "let x = 1;\n");
+        let diagnostic = Diagnostic::warning()
+            .with_message("unused variable")
+            .with_labels(vec![Label::primary(id, 4..5).with_message("never read")])
+            .with_notes(vec!["remove it".into()]);
+        let config = Config {
+            line_prefix: Some("[12:00:01] ".into()),
+            left_margin: 2,
+            ..Config::default()
+        };
+
+        insta::assert_snapshot!(
+            "line_prefix_starts_every_line_rich",
+            term::to_string(&config, &files, &diagnostic).unwrap()
+        );
+        // The prefix is written before the styling of a line
+        insta::assert_snapshot!(
+            "line_prefix_starts_every_line_rich_tagged",
+            emit_tagged(&config, &files, &diagnostic)
+        );
+        let config = Config {
+            display_style: DisplayStyle::Markdown,
+            ..config
+        };
+        insta::assert_snapshot!(
+            "line_prefix_starts_every_line_markdown",
+            term::to_string(&config, &files, &diagnostic).unwrap()
+        );
+    }
+
+    #[test]
+    fn relative_line_numbers() {
+        let mut files = SimpleFiles::new();
+        let source: String = (1..=120)
+            .map(|line| format!("let x{line} = {line};\n"))
+            .collect();
+        let start = source.find("let x100 ").unwrap();
+        let id = files.add("test", source);
+        let diagnostic = Diagnostic::warning()
+            .with_message("unused variable")
+            .with_labels(vec![
+                Label::primary(id, start + 4..start + 8).with_message("never read")
+            ]);
+        let config = Config {
+            before_label_lines: 2,
+            after_label_lines: 1,
+            relative_line_numbers: true,
+            ..Config::default()
+        };
+
+        insta::assert_snapshot!(term::to_string(&config, &files, &diagnostic).unwrap());
+    }
+
+    /// A diagnostic with a label message that is 48 columns wide when it is
+    /// placed inline.
+    fn placement_diagnostic(
+        files: &mut SimpleFiles<&'static str, &'static str>,
+    ) -> Diagnostic<usize> {
+        let id = files.add("test", "(+ test \"\")\n");
+        Diagnostic::error()
+            .with_message("mismatched types")
+            .with_labels(vec![
+                Label::primary(id, 8..10).with_message("expected `Int` but found `String`")
+            ])
+    }
+
+    #[test]
+    fn label_message_placement() {
+        let mut files = SimpleFiles::new();
+        let diagnostic = placement_diagnostic(&mut files);
+        let render = |config: &Config| term::to_string(config, &files, &diagnostic).unwrap();
+
+        let inline = render(&Config::default());
+        insta::assert_snapshot!("label_message_placement_inline", inline);
+        let below = render(&Config {
+            label_message_placement: LabelMessagePlacement::Below,
+            ..Config::default()
+        });
+        insta::assert_snapshot!("label_message_placement_below", below);
+
+        // The caret row with the inline message is exactly 48 columns wide
+        let config = Config {
+            label_message_placement: LabelMessagePlacement::Auto,
+            max_line_width: Some(48),
+            ..Config::default()
+        };
+        assert_eq!(render(&config), inline);
+        let config = Config {
+            max_line_width: Some(47),
+            ..config
+        };
+        assert_eq!(render(&config), below);
+    }
+
+    #[test]
+    fn label_message_placement_auto_with_margin() {
+        let mut files = SimpleFiles::new();
+        let diagnostic = placement_diagnostic(&mut files);
+        let render = |config: &Config| term::to_string(config, &files, &diagnostic).unwrap();
+
+        // The margin makes the caret row with the inline message 54 columns wide
+        let config = Config {
+            label_message_placement: LabelMessagePlacement::Auto,
+            max_line_width: Some(48),
+            left_margin: 6,
+            ..Config::default()
+        };
+        insta::assert_snapshot!(
+            "label_message_placement_auto_with_margin_below",
+            render(&config)
+        );
+        let config = Config {
+            max_line_width: Some(54),
+            ..config
+        };
+        insta::assert_snapshot!(
+            "label_message_placement_auto_with_margin_inline",
+            render(&config)
+        );
+    }
+
+    #[test]
+    fn wide_carets_stay_aligned() {
+        let mut files = SimpleFiles::new();
+        let id = files.add("test", "let value = other;\n");
+        let diagnostic = Diagnostic::error()
+            .with_message("mismatched types")
+            .with_labels(vec![
+                Label::primary(id, 4..9),
+                Label::secondary(id, 12..17).with_message("found `String`"),
+            ]);
+
+        let mut config = Config::default();
+        config.chars.single_primary_caret = "👉".into();
+        config.chars.single_secondary_caret = "~~".into();
+        insta::assert_snapshot!(
+            "wide_carets_stay_aligned_repeated",
+            term::to_string(&config, &files, &diagnostic).unwrap()
+        );
+        config.caret_fill_style = CaretFillStyle::Endpoints;
+        insta::assert_snapshot!(
+            "wide_carets_stay_aligned_endpoints",
+            term::to_string(&config, &files, &diagnostic).unwrap()
+        );
+    }
+
+    /// Carets that do not fill their label are not padded at the end of a line.
+    #[test]
+    fn wide_carets_without_trailing_padding() {
+        let mut files = SimpleFiles::new();
+        let id = files.add("test", "let value = 1;\n");
+        let diagnostic = Diagnostic::error()
+            .with_message("unused variable")
+            .with_labels(vec![Label::primary(id, 4..9)]);
+
+        let mut config = Config::default();
+        config.chars.single_primary_caret = "👉".into();
+        let output = term::to_string(&config, &files, &diagnostic).unwrap();
+
+        assert!(output.contains("│     👉👉\n"));
+
+        let diagnostic = Diagnostic::error().with_labels(vec![Label::primary((), 1..3)]);
+        let mut writer = PlainWriter::new(Vec::<u8>::new());
+        term::render_hexdump(&mut writer, &config, b"\x7fELF", &diagnostic).unwrap();
+        let output = String::from_utf8(writer.into_inner()).unwrap();
+        assert!(output.contains("│    👉👉\n"));
+    }
+
+    #[test]
+    fn severity_names_built_at_runtime() {
+        let mut files = SimpleFiles::new();
+        let id = files.add("test", "let x = 1;\n");
+        let diagnostic = Diagnostic::warning()
+            .with_message("unused variable")
+            .with_labels(vec![Label::primary(id, 4..5)]);
+        let names = [(Severity::Warning, String::from("avertissement"))];
+        let config = Config {
+            display_style: DisplayStyle::Short,
+            severity_name: Some(SeverityName::new(move |severity| {
+                let name = names.iter().find(|(s, _)| *s == severity);
+                name.map_or_else(|| format!("{severity:?}"), |(_, name)| name.clone())
+            })),
+            ..Config::default()
+        };
+
+        insta::assert_snapshot!(term::to_string(&config, &files, &diagnostic).unwrap());
+    }
+
+    #[test]
+    fn styles_tagged_by_test_writer() {
+        let mut files = SimpleFiles::new();
+        let id = files.add("test", "(+ test \"\")\n");
+        let diagnostic = Diagnostic::error()
+            .with_code("E0001")
+            .with_message("unexpected type")
+            .with_labels(vec![
+                Label::primary(id, 8..10).with_message("found `String`")
+            ])
+            .with_notes(vec!["expected `Int`".into()]);
+
+        insta::assert_snapshot!(emit_tagged(&Config::default(), &files, &diagnostic));
+    }
+
+    #[test]
+    fn wavy_secondary_underlines() {
+        let mut files = SimpleFiles::new();
+        let id = files.add("test", "(+ test \"\")\n");
+        let diagnostic = Diagnostic::error()
+            .with_message("unexpected type")
+            .with_labels(vec![
+                Label::primary(id, 8..10).with_message("found `String`"),
+                Label::secondary(id, 3..7),
+                Label::secondary(id, 0..1).with_message("in this application"),
+            ]);
+        let config = Config {
+            secondary_underline_style: SecondaryUnderlineStyle::Wavy,
+            ..Config::default()
+        };
+
+        insta::assert_snapshot!(emit_tagged(&config, &files, &diagnostic));
+        // Without styling, the secondary labels are underlined as usual
+        assert_eq!(
+            term::to_string(&config, &files, &diagnostic).unwrap(),
+            term::to_string(&Config::default(), &files, &diagnostic).unwrap(),
+        );
+    }
+
+    #[test]
+    fn plain_writer_matches_no_color() {
+        let mut files = SimpleFiles::new();
+        let id = files.add("test", "(+ test \"\")\n");
+        let diagnostic = Diagnostic::error()
+            .with_code("E0001")
+            .with_message("unexpected type in `+` application")
+            .with_labels(vec![
+                Label::primary(id, 8..10).with_message("expected `Int` but found `String`")
+            ])
+            .with_notes(vec!["expected type `Int`\n   found type `String`".into()]);
+
+        let mut plain = PlainWriter::new(Vec::<u8>::new());
+        term::emit(&mut plain, &Config::default(), &files, &diagnostic).unwrap();
+        let mut no_color = termcolor::NoColor::new(Vec::<u8>::new());
+        term::emit(&mut no_color, &Config::default(), &files, &diagnostic).unwrap();
+
+        assert_eq!(plain.into_inner(), no_color.into_inner());
+    }
+
+    #[test]
+    fn force_no_color_emits_no_escapes() {
+        let mut files = SimpleFiles::new();
+        let id = files.add("test", "(+ test \"\")\n");
+        let diagnostic = Diagnostic::error()
+            .with_code("E0001")
+            .with_message("unexpected type in `+` application")
+            .with_labels(vec![
+                Label::primary(id, 8..10).with_message("expected `Int` but found `String`")
+            ]);
+        let config = Config {
+            force_no_color: true,
+            render_file_hyperlinks: true,
+            ..Config::default()
+        };
+
+        let mut writer = termcolor::Ansi::new(Vec::<u8>::new());
+        term::emit(&mut writer, &config, &files, &diagnostic).unwrap();
+        let output = writer.into_inner();
+
+        assert!(!output.contains(&0x1b));
+        let mut no_color = termcolor::NoColor::new(Vec::<u8>::new());
+        term::emit(&mut no_color, &Config::default(), &files, &diagnostic).unwrap();
+        assert_eq!(output, no_color.into_inner());
+    }
+}
+
+mod hyperlinks {
+    use super::*;
+    use codespan_reporting::files::HyperlinkedFiles;
+    use codespan_reporting::term::{self, CodeUrl, FileName};
+
+    /// Emit a diagnostic in a file whose locations link to URLs with the id
+    /// of the file and the line and column number.
+    fn emit_hyperlinked<W: term::WriteStyle>(mut writer: W, config: &Config) -> W {
+        let mut files = SimpleFiles::new();
+        let id = files.add("test", "(+ test \"\")\n");
+        let files = HyperlinkedFiles::new(files, |id: &usize, line, column| {
+            Some(format!(
+                "https://example.com/{id}?line={line}&column={column}"
+            ))
+        });
+        let diagnostic = Diagnostic::error().with_labels(vec![Label::primary(id, 8..10)]);
+
+        term::emit(&mut writer, config, &files, &diagnostic).unwrap();
+        writer
+    }
+
+    #[test]
+    fn file_hyperlinks() {
+        let config = Config {
+            render_file_hyperlinks: true,
+            ..Config::default()
+        };
+        let writer = emit_hyperlinked(termcolor::Ansi::new(Vec::<u8>::new()), &config);
+        let output = String::from_utf8(writer.into_inner()).unwrap();
+
+        assert!(output
+            .contains("\x1B]8;;https://example.com/0?line=1&column=9\x1B\\test:1:9\x1B]8;;\x1B\\"));
+    }
+
+    #[test]
+    fn file_hyperlinks_not_given_by_default() {
+        let mut files = SimpleFiles::new();
+        let id = files.add("test", "(+ test \"\")\n");
+        let diagnostic = Diagnostic::error().with_labels(vec![Label::primary(id, 8..10)]);
+        let config = Config {
+            render_file_hyperlinks: true,
+            ..Config::default()
+        };
+
+        let mut writer = termcolor::Ansi::new(Vec::<u8>::new());
+        term::emit(&mut writer, &config, &files, &diagnostic).unwrap();
+        let output = String::from_utf8(writer.into_inner()).unwrap();
+
+        assert!(output.contains(" test:1:9\n"));
+        assert!(!output.contains("\x1B]8;"));
+    }
+
+    #[test]
+    fn file_hyperlinks_to_file_paths() {
+        let path = std::env::temp_dir().join(format!("codespan-{}.fun", std::process::id()));
+        std::fs::write(&path, "(+ test \"\")\n").unwrap();
+        let name = path.to_str().unwrap().to_owned();
+        let mut files = SimpleFiles::new();
+        let id = files.add(name.clone(), "(+ test \"\")\n");
+        let files = HyperlinkedFiles::file_paths(files);
+        let diagnostic = Diagnostic::error().with_labels(vec![Label::primary(id, 8..10)]);
+        let config = Config {
+            render_file_hyperlinks: true,
+            ..Config::default()
+        };
+
+        let mut writer = termcolor::Ansi::new(Vec::<u8>::new());
+        term::emit(&mut writer, &config, &files, &diagnostic).unwrap();
+        let output = String::from_utf8(writer.into_inner()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(output.contains("\x1B]8;;file://"));
+        assert!(output.contains(&format!("#1:9\x1B\\{name}:1:9\x1B]8;;\x1B\\")));
+    }
+
+    #[test]
+    fn file_name_mapping_keeps_hyperlinks() {
+        let config = Config {
+            render_file_hyperlinks: true,
+            file_name: Some(FileName::new(|name| format!("./{name}.fun"))),
+            ..Config::default()
+        };
+        let writer = emit_hyperlinked(termcolor::Ansi::new(Vec::<u8>::new()), &config);
+        let output = String::from_utf8(writer.into_inner()).unwrap();
+
+        assert!(output.contains(
+            "\x1B]8;;https://example.com/0?line=1&column=9\x1B\\./test.fun:1:9\x1B]8;;\x1B\\"
+        ));
+    }
+
+    #[test]
+    fn file_hyperlinks_without_color() {
+        let config = Config {
+            render_file_hyperlinks: true,
+            ..Config::default()
+        };
+        let writer = emit_hyperlinked(termcolor::NoColor::new(Vec::<u8>::new()), &config);
+        let output = String::from_utf8(writer.into_inner()).unwrap();
+
+        assert!(output.contains("┌─ test:1:9\n"));
+        assert!(!output.contains('\x1B'));
+    }
+
+    /// Writers that support color but not hyperlinks are styled without them.
+    #[test]
+    fn file_hyperlinks_without_hyperlink_support() {
+        let mut files = SimpleFiles::new();
+        let id = files.add("test", "(+ test \"\")\n");
+        let files = HyperlinkedFiles::new(files, |id: &usize, line, column| {
+            Some(format!(
+                "https://example.com/{id}?line={line}&column={column}"
+            ))
+        });
+        let data = TestData {
+            files,
+            diagnostics: vec![Diagnostic::error().with_labels(vec![Label::primary(id, 8..10)])],
+        };
+        let config = Config {
+            render_file_hyperlinks: true,
+            ..Config::default()
+        };
+        let output = data.emit_color(&config);
+
+        assert!(output.contains("{fg:Blue}┌─{/} test:1:9\n"));
+        assert!(!output.contains("example.com"));
+    }
+
+    #[test]
+    fn code_hyperlinks() {
+        let mut files = SimpleFiles::new();
+        let id = files.add("test", "(+ test \"\")\n");
+        let diagnostic = Diagnostic::error()
+            .with_code("E0001")
+            .with_message("unexpected type")
+            .with_labels(vec![Label::primary(id, 8..10)]);
+        let config = Config {
+            code_url: Some(CodeUrl::new(|code| {
+                Some(format!("https://example.com/errors/{code}"))
+            })),
+            ..Config::default()
+        };
+
+        let mut writer = termcolor::Ansi::new(Vec::<u8>::new());
+        term::emit(&mut writer, &config, &files, &diagnostic).unwrap();
+        let output = String::from_utf8(writer.into_inner()).unwrap();
+        assert!(
+            output.contains("[\x1B]8;;https://example.com/errors/E0001\x1B\\E0001\x1B]8;;\x1B\\]")
+        );
+
+        insta::assert_snapshot!(term::to_string(&config, &files, &diagnostic).unwrap());
+    }
+}